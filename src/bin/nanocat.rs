@@ -0,0 +1,603 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+//
+// Command line interface to scaproust sockets, compatible with the nanocat tool shipped with nanomsg.
+// See http://nanomsg.org/v1.0.0/nanocat.1.html
+
+extern crate scaproust;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use scaproust::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum SocketType {
+    Push,
+    Pull,
+    Pub,
+    Sub,
+    Req,
+    Rep,
+    Surveyor,
+    Respondent,
+    Bus,
+    Pair
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum EchoFormat {
+    NoEcho,
+    Raw,
+    Ascii,
+    Quoted,
+    Msgpack,
+    Hex
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Endpoint {
+    Bind(String),
+    Connect(String)
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    socket_type: Option<SocketType>,
+    endpoints: Vec<Endpoint>,
+    subscriptions: Vec<String>,
+    send_timeout: Option<Duration>,
+    recv_timeout: Option<Duration>,
+    interval: Option<Duration>,
+    delay: Option<Duration>,
+    data: Option<Vec<u8>>,
+    echo_format: EchoFormat
+}
+
+/*****************************************************************************/
+/*                                                                           */
+/* command line                                                              */
+/*                                                                           */
+/*****************************************************************************/
+
+const USAGE: &'static str = "Usage: nanocat SOCKET_TYPE ENDPOINT... [OPTIONS]
+
+Socket types:
+    --push, --pull, --pub, --sub, --req, --rep,
+    --surveyor (--surv), --respondent (--resp), --bus, --pair
+
+Endpoints:
+    --bind ADDR               Bind the socket to ADDR, can be repeated
+    --connect ADDR            Connect the socket to ADDR, can be repeated
+    --bind-local, -L PORT     Bind to tcp://127.0.0.1:PORT
+    --connect-local, -l PORT  Connect to tcp://127.0.0.1:PORT
+
+Socket options:
+    --subscribe PREFIX        Subscribe to PREFIX, can be repeated, default is everything
+    --send-timeout SEC        Fail when a message cannot be sent within SEC seconds
+    --recv-timeout SEC        Exit when no message is received within SEC seconds
+
+Input:
+    --interval, -i SEC        Send the message every SEC seconds
+    --delay, -d SEC           Wait SEC seconds before sending the first message
+    --data, -D DATA           Message to send
+    --file, -F PATH           Send the content of the file at PATH
+
+Output:
+    --format FORMAT           One of raw, ascii, quoted, msgpack or hex
+    --raw                     Dump the messages as they are
+    --ascii, -A               Print printable characters, replace the others with a dot
+    --quoted, -Q              Print the messages as quoted strings
+    --msgpack                 Encode each message as a msgpack binary
+    --hex                     Print the messages as quoted hexadecimal strings";
+
+fn parse_args<I>(args: I) -> Result<Options, String> where I : IntoIterator<Item=String> {
+    let mut options = Options {
+        socket_type: None,
+        endpoints: Vec::new(),
+        subscriptions: Vec::new(),
+        send_timeout: None,
+        recv_timeout: None,
+        interval: None,
+        delay: None,
+        data: None,
+        echo_format: EchoFormat::NoEcho
+    };
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let (name, inline_value) = match arg.find('=') {
+            Some(index) if arg.starts_with("--") => (arg[..index].to_owned(), Some(arg[index + 1..].to_owned())),
+            _ => (arg.clone(), None)
+        };
+        let mut value = || inline_value.clone()
+            .or_else(|| args.next())
+            .ok_or_else(|| format!("option {} requires a value", name));
+
+        match name.as_str() {
+            "--push"                     => try!(set_socket_type(&mut options, SocketType::Push)),
+            "--pull"                     => try!(set_socket_type(&mut options, SocketType::Pull)),
+            "--pub"                      => try!(set_socket_type(&mut options, SocketType::Pub)),
+            "--sub"                      => try!(set_socket_type(&mut options, SocketType::Sub)),
+            "--req"                      => try!(set_socket_type(&mut options, SocketType::Req)),
+            "--rep"                      => try!(set_socket_type(&mut options, SocketType::Rep)),
+            "--surveyor" | "--surv"      => try!(set_socket_type(&mut options, SocketType::Surveyor)),
+            "--respondent" | "--resp"    => try!(set_socket_type(&mut options, SocketType::Respondent)),
+            "--bus"                      => try!(set_socket_type(&mut options, SocketType::Bus)),
+            "--pair"                     => try!(set_socket_type(&mut options, SocketType::Pair)),
+            "--bind"                     => options.endpoints.push(Endpoint::Bind(try!(value()))),
+            "--connect"                  => options.endpoints.push(Endpoint::Connect(try!(value()))),
+            "--bind-local" | "-L"        => options.endpoints.push(Endpoint::Bind(try!(local_url(try!(value()))))),
+            "--connect-local" | "-l"     => options.endpoints.push(Endpoint::Connect(try!(local_url(try!(value()))))),
+            "--subscribe"                => options.subscriptions.push(try!(value())),
+            "--send-timeout"             => options.send_timeout = Some(try!(parse_seconds(try!(value())))),
+            "--recv-timeout"             => options.recv_timeout = Some(try!(parse_seconds(try!(value())))),
+            "--interval" | "-i"          => options.interval = Some(try!(parse_seconds(try!(value())))),
+            "--delay" | "-d"             => options.delay = Some(try!(parse_seconds(try!(value())))),
+            "--data" | "-D"              => try!(set_data(&mut options, try!(value()).into_bytes())),
+            "--file" | "-F"              => try!(set_data(&mut options, try!(read_file(try!(value()))))),
+            "--format"                   => try!(set_echo_format(&mut options, try!(parse_format(try!(value()))))),
+            "--raw"                      => try!(set_echo_format(&mut options, EchoFormat::Raw)),
+            "--ascii" | "-A"             => try!(set_echo_format(&mut options, EchoFormat::Ascii)),
+            "--quoted" | "-Q"            => try!(set_echo_format(&mut options, EchoFormat::Quoted)),
+            "--msgpack"                  => try!(set_echo_format(&mut options, EchoFormat::Msgpack)),
+            "--hex"                      => try!(set_echo_format(&mut options, EchoFormat::Hex)),
+            _ => return Err(format!("unknown option {}", arg))
+        }
+    }
+
+    try!(check_options(&options));
+
+    Ok(options)
+}
+
+fn set_socket_type(options: &mut Options, socket_type: SocketType) -> Result<(), String> {
+    if options.socket_type.is_some() {
+        return Err(String::from("only one socket type can be specified"));
+    }
+    options.socket_type = Some(socket_type);
+    Ok(())
+}
+
+fn set_data(options: &mut Options, data: Vec<u8>) -> Result<(), String> {
+    if options.data.is_some() {
+        return Err(String::from("only one of --data and --file can be specified"));
+    }
+    options.data = Some(data);
+    Ok(())
+}
+
+fn set_echo_format(options: &mut Options, format: EchoFormat) -> Result<(), String> {
+    if options.echo_format != EchoFormat::NoEcho {
+        return Err(String::from("only one output format can be specified"));
+    }
+    options.echo_format = format;
+    Ok(())
+}
+
+fn check_options(options: &Options) -> Result<(), String> {
+    let socket_type = match options.socket_type {
+        Some(x) => x,
+        None => return Err(String::from("a socket type is required"))
+    };
+
+    if options.endpoints.is_empty() {
+        return Err(String::from("at least one --bind or --connect is required"));
+    }
+    if !options.subscriptions.is_empty() && socket_type != SocketType::Sub {
+        return Err(String::from("--subscribe is only valid with --sub"));
+    }
+    if options.data.is_none() && can_only_send(socket_type) {
+        return Err(String::from("--data or --file is required to send messages"));
+    }
+    if options.data.is_some() && can_only_recv(socket_type) {
+        return Err(String::from("--data and --file are not valid with a socket that can only receive"));
+    }
+
+    Ok(())
+}
+
+fn can_only_send(socket_type: SocketType) -> bool {
+    match socket_type {
+        SocketType::Push | SocketType::Pub => true,
+        _ => false
+    }
+}
+
+fn can_only_recv(socket_type: SocketType) -> bool {
+    match socket_type {
+        SocketType::Pull | SocketType::Sub => true,
+        _ => false
+    }
+}
+
+fn local_url(port: String) -> Result<String, String> {
+    match port.parse::<u16>() {
+        Ok(port) => Ok(format!("tcp://127.0.0.1:{}", port)),
+        Err(_) => Err(format!("invalid port {}", port))
+    }
+}
+
+fn parse_seconds(value: String) -> Result<Duration, String> {
+    match value.parse::<f64>() {
+        Ok(secs) if secs >= 0f64 => {
+            let whole = secs.trunc();
+            let nanos = ((secs - whole) * 1_000_000_000f64) as u32;
+
+            Ok(Duration::new(whole as u64, nanos))
+        },
+        _ => Err(format!("invalid duration {}", value))
+    }
+}
+
+fn parse_format(value: String) -> Result<EchoFormat, String> {
+    match value.as_str() {
+        "raw"     => Ok(EchoFormat::Raw),
+        "ascii"   => Ok(EchoFormat::Ascii),
+        "quoted"  => Ok(EchoFormat::Quoted),
+        "msgpack" => Ok(EchoFormat::Msgpack),
+        "hex"     => Ok(EchoFormat::Hex),
+        _ => Err(format!("invalid format {}", value))
+    }
+}
+
+fn read_file(path: String) -> Result<Vec<u8>, String> {
+    let mut buffer = Vec::new();
+
+    File::open(&path)
+        .and_then(|mut file| file.read_to_end(&mut buffer))
+        .map(|_| buffer)
+        .map_err(|e| format!("failed to read {}: {}", path, e))
+}
+
+/*****************************************************************************/
+/*                                                                           */
+/* output                                                                    */
+/*                                                                           */
+/*****************************************************************************/
+
+fn format_message(format: EchoFormat, msg: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(msg.len() + 8);
+
+    match format {
+        EchoFormat::NoEcho => {},
+        EchoFormat::Raw => output.extend_from_slice(msg),
+        EchoFormat::Ascii => {
+            output.extend(msg.iter().map(|&b| if is_printable(b) { b } else { b'.' }));
+            output.push(b'\n');
+        },
+        EchoFormat::Quoted => {
+            output.push(b'"');
+            for &b in msg {
+                match b {
+                    b'"' | b'\\' => output.extend_from_slice(&[b'\\', b]),
+                    b if is_printable(b) => output.push(b),
+                    b => output.extend_from_slice(format!("\\x{:02x}", b).as_bytes())
+                }
+            }
+            output.extend_from_slice(b"\"\n");
+        },
+        EchoFormat::Msgpack => {
+            let len = msg.len();
+
+            if len < 0x100 {
+                output.extend_from_slice(&[0xc4, len as u8]);
+            } else if len < 0x10000 {
+                output.extend_from_slice(&[0xc5, (len >> 8) as u8, len as u8]);
+            } else {
+                output.extend_from_slice(&[0xc6, (len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8, len as u8]);
+            }
+            output.extend_from_slice(msg);
+        },
+        EchoFormat::Hex => {
+            output.push(b'"');
+            for &b in msg {
+                output.extend_from_slice(format!("\\x{:02x}", b).as_bytes());
+            }
+            output.extend_from_slice(b"\"\n");
+        }
+    }
+
+    output
+}
+
+fn is_printable(b: u8) -> bool {
+    b >= 0x20 && b < 0x7f
+}
+
+fn print_message(format: EchoFormat, msg: &[u8]) {
+    let output = format_message(format, msg);
+
+    if output.is_empty() {
+        return;
+    }
+
+    let stdout = io::stdout();
+    let mut handle = stdout.lock();
+    let _ = handle.write_all(&output).and_then(|_| handle.flush());
+}
+
+/*****************************************************************************/
+/*                                                                           */
+/* loops                                                                     */
+/*                                                                           */
+/*****************************************************************************/
+
+fn create_session() -> Session {
+    let builder = SessionBuilder::new().
+        with("tcp", Tcp).
+        with("inproc", Inproc).
+        with("ws", Ws);
+
+    #[cfg(not(windows))]
+    let builder = builder.with("ipc", Ipc);
+
+    builder.build().unwrap_or_else(|e| die(format!("failed to create session: {}", e)))
+}
+
+fn create_socket(session: &mut Session, socket_type: SocketType) -> io::Result<Socket> {
+    match socket_type {
+        SocketType::Push       => session.create_socket::<Push>(),
+        SocketType::Pull       => session.create_socket::<Pull>(),
+        SocketType::Pub        => session.create_socket::<Pub>(),
+        SocketType::Sub        => session.create_socket::<Sub>(),
+        SocketType::Req        => session.create_socket::<Req>(),
+        SocketType::Rep        => session.create_socket::<Rep>(),
+        SocketType::Surveyor   => session.create_socket::<Surveyor>(),
+        SocketType::Respondent => session.create_socket::<Respondent>(),
+        SocketType::Bus        => session.create_socket::<Bus>(),
+        SocketType::Pair       => session.create_socket::<Pair>()
+    }
+}
+
+fn setup_socket(socket: &mut Socket, options: &Options) -> io::Result<()> {
+    try!(socket.set_send_timeout(options.send_timeout));
+    try!(socket.set_recv_timeout(options.recv_timeout));
+
+    if options.socket_type == Some(SocketType::Sub) {
+        if options.subscriptions.is_empty() {
+            try!(socket.set_option(ConfigOption::Subscribe(String::new())));
+        }
+        for subscription in &options.subscriptions {
+            try!(socket.set_option(ConfigOption::Subscribe(subscription.clone())));
+        }
+    }
+
+    for endpoint in &options.endpoints {
+        match *endpoint {
+            Endpoint::Bind(ref url)    => try!(socket.bind(url)),
+            Endpoint::Connect(ref url) => try!(socket.connect(url))
+        };
+    }
+
+    Ok(())
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::TimedOut
+}
+
+/// Sends the data once, or forever at the specified interval.
+fn send_loop(socket: &mut Socket, options: &Options, data: &[u8]) -> io::Result<()> {
+    loop {
+        try!(socket.send(data.to_vec()));
+
+        match options.interval {
+            Some(interval) => thread::sleep(interval),
+            None => return Ok(())
+        }
+    }
+}
+
+/// Prints the received messages until the receive timeout expires.
+fn recv_loop(socket: &mut Socket, options: &Options) -> io::Result<()> {
+    loop {
+        match socket.recv() {
+            Ok(msg) => print_message(options.echo_format, &msg),
+            Err(ref e) if is_timeout(e) => return Ok(()),
+            Err(e) => return Err(e)
+        }
+    }
+}
+
+/// Sends the data, prints the messages received until it is time to send again.
+/// Without interval, the data is sent once and the socket keeps receiving.
+fn rw_loop(socket: &mut Socket, options: &Options, data: &[u8]) -> io::Result<()> {
+    let waits_for_reply = options.socket_type == Some(SocketType::Req);
+    let waits_for_votes = options.socket_type == Some(SocketType::Surveyor);
+
+    loop {
+        let start = Instant::now();
+
+        try!(socket.send(data.to_vec()));
+
+        let interval = match options.interval {
+            Some(interval) => interval,
+            None if waits_for_reply => return socket.recv().map(|msg| print_message(options.echo_format, &msg)),
+            None if waits_for_votes => return votes_loop(socket, options),
+            None => return recv_loop(socket, options)
+        };
+
+        while start.elapsed() < interval {
+            let remaining = interval - start.elapsed();
+            let timeout = match options.recv_timeout {
+                Some(recv_timeout) if recv_timeout < remaining => recv_timeout,
+                _ => remaining
+            };
+
+            try!(socket.set_recv_timeout(Some(timeout)));
+
+            match socket.recv() {
+                Ok(msg) => {
+                    print_message(options.echo_format, &msg);
+
+                    if waits_for_reply {
+                        sleep_until(start, interval);
+                    }
+                },
+                Err(ref e) if waits_for_votes && is_end_of_survey(e) => sleep_until(start, interval),
+                Err(ref e) if is_timeout(e) => {},
+                Err(e) => return Err(e)
+            }
+        }
+    }
+}
+
+/// Prints the votes until the survey deadline expires.
+fn votes_loop(socket: &mut Socket, options: &Options) -> io::Result<()> {
+    loop {
+        match socket.recv() {
+            Ok(msg) => print_message(options.echo_format, &msg),
+            Err(ref e) if is_end_of_survey(e) => return Ok(()),
+            Err(e) => return Err(e)
+        }
+    }
+}
+
+/// Once the deadline has expired, the pending recv times out and the next ones are rejected
+/// by the surveyor, any other error is a genuine failure.
+fn is_end_of_survey(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::TimedOut || err.kind() == io::ErrorKind::NotConnected
+}
+
+fn sleep_until(start: Instant, interval: Duration) {
+    let elapsed = start.elapsed();
+
+    if elapsed < interval {
+        thread::sleep(interval - elapsed);
+    }
+}
+
+/// Prints each received request or survey and answers with the data.
+fn resp_loop(socket: &mut Socket, options: &Options, data: &[u8]) -> io::Result<()> {
+    loop {
+        match socket.recv() {
+            Ok(msg) => print_message(options.echo_format, &msg),
+            Err(ref e) if is_timeout(e) => return Ok(()),
+            Err(e) => return Err(e)
+        }
+
+        try!(socket.send(data.to_vec()));
+    }
+}
+
+fn run(options: &Options) -> io::Result<()> {
+    let socket_type = options.socket_type.expect("socket type should have been checked");
+    let mut session = create_session();
+    let mut socket = try!(create_socket(&mut session, socket_type));
+
+    try!(setup_socket(&mut socket, options));
+
+    if let Some(delay) = options.delay {
+        thread::sleep(delay);
+    }
+
+    match (socket_type, options.data.as_ref()) {
+        (SocketType::Push, Some(data)) |
+        (SocketType::Pub, Some(data))        => send_loop(&mut socket, options, data),
+        (SocketType::Req, Some(data))        |
+        (SocketType::Surveyor, Some(data))   |
+        (SocketType::Bus, Some(data))        |
+        (SocketType::Pair, Some(data))       => rw_loop(&mut socket, options, data),
+        (SocketType::Rep, Some(data))        |
+        (SocketType::Respondent, Some(data)) => resp_loop(&mut socket, options, data),
+        _                                    => recv_loop(&mut socket, options)
+    }
+}
+
+fn die(msg: String) -> ! {
+    let _ = writeln!(io::stderr(), "nanocat: {}", msg);
+    process::exit(1)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    if args.is_empty() || args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        let _ = writeln!(io::stdout(), "{}", USAGE);
+        return;
+    }
+
+    let options = parse_args(args).unwrap_or_else(|e| die(e));
+
+    if let Err(e) = run(&options) {
+        die(format!("{}", e));
+    }
+}
+
+/*****************************************************************************/
+/*                                                                           */
+/* tests                                                                     */
+/*                                                                           */
+/*****************************************************************************/
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_args_should_accept_repeated_endpoints_and_inline_values() {
+        let options = parse_args(args("--sub --connect tcp://a:1 --connect=tcp://b:2 -L 5454 --subscribe x --recv-timeout 1.5 --ascii")).unwrap();
+
+        assert_eq!(Some(SocketType::Sub), options.socket_type);
+        assert_eq!(vec![
+            Endpoint::Connect(String::from("tcp://a:1")),
+            Endpoint::Connect(String::from("tcp://b:2")),
+            Endpoint::Bind(String::from("tcp://127.0.0.1:5454"))], options.endpoints);
+        assert_eq!(vec![String::from("x")], options.subscriptions);
+        assert_eq!(Some(Duration::from_millis(1500)), options.recv_timeout);
+        assert_eq!(EchoFormat::Ascii, options.echo_format);
+    }
+
+    #[test]
+    fn parse_args_should_reject_inconsistent_options() {
+        assert!(parse_args(args("--push --bind tcp://a:1")).is_err());
+        assert!(parse_args(args("--pull --bind tcp://a:1 --data x")).is_err());
+        assert!(parse_args(args("--pair --data x")).is_err());
+        assert!(parse_args(args("--pair --bus --bind tcp://a:1")).is_err());
+        assert!(parse_args(args("--pair --bind tcp://a:1 --ascii --hex")).is_err());
+        assert!(parse_args(args("--pair --bind")).is_err());
+    }
+
+    #[test]
+    fn format_message_should_support_each_echo_format() {
+        let msg = b"a\"b\x01";
+
+        assert_eq!(b"".to_vec(), format_message(EchoFormat::NoEcho, msg));
+        assert_eq!(msg.to_vec(), format_message(EchoFormat::Raw, msg));
+        assert_eq!(b"a\"b.\n".to_vec(), format_message(EchoFormat::Ascii, msg));
+        assert_eq!(b"\"a\\\"b\\x01\"\n".to_vec(), format_message(EchoFormat::Quoted, msg));
+        assert_eq!(b"\xc4\x04a\"b\x01".to_vec(), format_message(EchoFormat::Msgpack, msg));
+        assert_eq!(b"\"\\x61\\x22\\x62\\x01\"\n".to_vec(), format_message(EchoFormat::Hex, msg));
+    }
+
+    #[test]
+    fn format_message_should_use_larger_msgpack_headers_for_larger_messages() {
+        let msg = vec![0u8; 300];
+        let output = format_message(EchoFormat::Msgpack, &msg);
+
+        assert_eq!(&[0xc5, 0x01, 0x2c], &output[..3]);
+        assert_eq!(303, output.len());
+    }
+
+    #[test]
+    fn is_end_of_survey_should_only_match_the_errors_of_an_expired_survey() {
+        assert!(is_end_of_survey(&io::Error::new(io::ErrorKind::TimedOut, "Recv timed out")));
+        assert!(is_end_of_survey(&proto::surv::no_active_survey_io_error()));
+        assert!(!is_end_of_survey(&io::Error::new(io::ErrorKind::Other, "peer closed")));
+        assert!(!is_end_of_survey(&io::Error::new(io::ErrorKind::ConnectionReset, "reset")));
+    }
+}
@@ -0,0 +1,117 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use std::time::Duration;
+
+use xorshift::XorShift;
+
+/// Computes the delays between the reconnection attempts of an endpoint.
+/// The interval starts at `RetryIvl` and is doubled after each attempt until `RetryIvlMax` is reached.
+/// Each delay is randomly picked between half the interval and the interval itself,
+/// so that peers disconnected at the same time do not try to reconnect all together.
+pub struct Backoff {
+    ivl: Duration,
+    ivl_max: Option<Duration>,
+    current: Duration,
+    rng: XorShift
+}
+
+impl Backoff {
+    pub fn new(ivl: Duration, ivl_max: Option<Duration>) -> Backoff {
+        Backoff {
+            ivl: ivl,
+            ivl_max: ivl_max.and_then(|max| if max > ivl { Some(max) } else { None }),
+            current: ivl,
+            rng: XorShift::new()
+        }
+    }
+
+    /// The interval the next delay will be picked from.
+    pub fn get_ivl(&self) -> Duration {
+        self.current
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let ivl = self.current;
+        let half = ivl / 2;
+        let jitter = self.rng.next() % (to_nanos(&(ivl - half)) + 1);
+
+        if let Some(max) = self.ivl_max {
+            let doubled = self.current * 2;
+
+            self.current = if doubled > max { max } else { doubled };
+        }
+
+        half + from_nanos(jitter)
+    }
+
+    pub fn reset(&mut self) {
+        self.current = self.ivl;
+    }
+}
+
+fn to_nanos(duration: &Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64
+}
+
+fn from_nanos(nanos: u64) -> Duration {
+    Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Backoff;
+
+    fn assert_delay_between(delay: Duration, min_ms: u64, max_ms: u64) {
+        assert!(delay >= Duration::from_millis(min_ms), "{:?} < {}ms", delay, min_ms);
+        assert!(delay <= Duration::from_millis(max_ms), "{:?} > {}ms", delay, max_ms);
+    }
+
+    #[test]
+    fn without_max_the_interval_should_not_change() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), None);
+
+        for _ in 0..5 {
+            assert_delay_between(backoff.next_delay(), 50, 100);
+            assert_eq!(Duration::from_millis(100), backoff.get_ivl());
+        }
+    }
+
+    #[test]
+    fn with_max_the_interval_should_double_until_max_is_reached() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Some(Duration::from_millis(500)));
+
+        assert_delay_between(backoff.next_delay(), 50, 100);
+        assert_eq!(Duration::from_millis(200), backoff.get_ivl());
+        assert_delay_between(backoff.next_delay(), 100, 200);
+        assert_eq!(Duration::from_millis(400), backoff.get_ivl());
+        assert_delay_between(backoff.next_delay(), 200, 400);
+        assert_eq!(Duration::from_millis(500), backoff.get_ivl());
+        assert_delay_between(backoff.next_delay(), 250, 500);
+        assert_eq!(Duration::from_millis(500), backoff.get_ivl());
+    }
+
+    #[test]
+    fn max_lower_than_ivl_should_be_ignored() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Some(Duration::from_millis(50)));
+
+        assert_delay_between(backoff.next_delay(), 50, 100);
+        assert_eq!(Duration::from_millis(100), backoff.get_ivl());
+    }
+
+    #[test]
+    fn reset_should_restore_the_initial_interval() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Some(Duration::from_millis(800)));
+
+        backoff.next_delay();
+        backoff.next_delay();
+        backoff.reset();
+
+        assert_eq!(Duration::from_millis(100), backoff.get_ivl());
+    }
+}
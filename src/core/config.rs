@@ -10,6 +10,7 @@ use std::time::Duration;
 use io_error::*;
 
 pub struct Config {
+    pub linger: Duration,
     pub send_timeout: Option<Duration>,
     pub send_priority: u8,
     pub recv_timeout: Option<Duration>,
@@ -17,12 +18,13 @@ pub struct Config {
     pub retry_ivl: Duration,
     pub retry_ivl_max: Option<Duration>,
     pub tcp_no_delay: bool,
-    pub recv_max_size: u64
+    pub recv_max_size: u64,
+    pub recv_peer_info: bool
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ConfigOption {
-    /// Specifies how long the socket should try to send pending outbound messages 
-    /// after `drop` have been called. Default value is 1 second.
+    /// See [Socket::set_linger](struct.Socket.html#method.set_linger).
     Linger(Duration),
 
     /// See [Socket::set_send_timeout](struct.Socket.html#method.set_send_timeout).
@@ -44,13 +46,14 @@ pub enum ConfigOption {
 
     /// For connection-based transports such as TCP, this option specifies how long to wait, 
     /// when connection is broken before trying to re-establish it. 
-    /// Note that actual reconnect interval may be randomised to some extent 
+    /// Note that actual reconnect interval is randomised between half the value and the value itself
     /// to prevent severe reconnection storms. Default value is 0.1 second.
     RetryIvl(Duration),
 
-    /// This option is to be used only in addition to ReconnectInterval option.
+    /// This option is to be used only in addition to RetryIvl option.
     /// It specifies maximum reconnection interval. On each reconnect attempt,
-    /// the previous interval is doubled until ReconnectIntervalMax is reached.
+    /// the previous interval is doubled until RetryIvlMax is reached.
+    /// The interval is reset to RetryIvl once the connection is re-established.
     /// Value of `None` means that no exponential backoff is performed and reconnect interval is based only on ReconnectInterval.
     /// If RetryIvlMax is less than RetryIvl, it is ignored. 
     /// Default value is `None`.
@@ -59,13 +62,53 @@ pub enum ConfigOption {
     /// See [Socket::set_tcp_nodelay](struct.Socket.html#method.set_tcp_nodelay).
     TcpNoDelay(bool),
 
+    /// See [Socket::set_recv_peer_info](struct.Socket.html#method.set_recv_peer_info).
+    RecvPeerInfo(bool),
+
     /// Defined on `Sub` socket. Subscribes for a particular topic.
     /// A single `Sub` socket can handle multiple subscriptions.
     Subscribe(String),
 
     /// Defined on Sub` socket. Unsubscribes from a particular topic.
+    /// A topic subscribed several times stays subscribed until it is unsubscribed as many times.
     Unsubscribe(String),
 
+    /// Defined on `Sub` socket. Subscribes for messages whose topic is exactly the one specified.
+    /// The topic of a message is its body up to the first NUL byte, or the whole body if there is none.
+    SubscribeExact(String),
+
+    /// Defined on `Sub` socket. Unsubscribes from an exact topic.
+    UnsubscribeExact(String),
+
+    /// Defined on `Sub` socket. Subscribes for messages whose topic matches a pattern like `prices.*.eur`.
+    /// The pattern and the topic are split in segments by the `TopicDelimiter`, 
+    /// a `*` segment matches any single segment and a `**` segment matches any number of segments.
+    SubscribePattern(String),
+
+    /// Defined on `Sub` socket. Unsubscribes from a pattern.
+    UnsubscribePattern(String),
+
+    /// Defined on `Sub` socket. Drops the messages starting with the specified topic, 
+    /// even when one of the subscriptions accepts them.
+    Exclude(String),
+
+    /// Defined on `Sub` socket. Removes an exclusion.
+    /// A topic excluded several times stays excluded until it is unexcluded as many times.
+    Unexclude(String),
+
+    /// Defined on `Sub` socket. The byte separating the segments of a topic matched against a pattern.
+    /// It cannot be changed while patterns are subscribed.
+    /// Default value is `b'.'`.
+    TopicDelimiter(u8),
+
+    /// Defined on `Pub` and `Sub` sockets. When enabled on a `Sub` socket, 
+    /// its subscriptions are forwarded upstream to the connected `Pub` sockets.
+    /// When enabled on a `Pub` socket, messages are only sent to the peers 
+    /// whose forwarded subscriptions match them, peers that forward nothing still receive everything.
+    /// Devices pass the subscriptions along when enabled on both of their sockets.
+    /// Default value is false, which is compatible with plain nanomsg peers.
+    SubscriptionForwarding(bool),
+
     /// This option is defined on the Req socket.
     /// If a reply is not received in the specified amount of time, 
     /// the request will be automatically resent. 
@@ -79,9 +122,30 @@ pub enum ConfigOption {
     SurveyDeadline(Duration)
 }
 
+/// Identifies the option to read with [Socket::get_option](struct.Socket.html#method.get_option).
+/// Each name matches the [ConfigOption](enum.ConfigOption.html) variant holding the value.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ConfigOptionName {
+    Linger,
+    SendTimeout,
+    SendPriority,
+    RecvTimeout,
+    RecvPriority,
+    RecvMaxSize,
+    RetryIvl,
+    RetryIvlMax,
+    TcpNoDelay,
+    RecvPeerInfo,
+    TopicDelimiter,
+    SubscriptionForwarding,
+    ReqResendIvl,
+    SurveyDeadline
+}
+
 impl Default for Config {
     fn default() -> Config {
         Config {
+            linger: Duration::from_secs(1),
             send_timeout: None,
             send_priority: 8,
             recv_timeout: None,
@@ -89,7 +153,8 @@ impl Default for Config {
             retry_ivl: Duration::from_millis(100),
             retry_ivl_max: None,
             tcp_no_delay: false,
-            recv_max_size: 1024 * 1024
+            recv_max_size: 1024 * 1024,
+            recv_peer_info: false
         }
     }
 }
@@ -97,6 +162,7 @@ impl Default for Config {
 impl Config {
     pub fn set(&mut self, cfg_opt: ConfigOption) -> Result<()> {
         match cfg_opt {
+            ConfigOption::Linger(linger) => self.linger = linger,
             ConfigOption::SendTimeout(timeout) => self.send_timeout = timeout,
             ConfigOption::SendPriority(priority) => self.send_priority = priority,
             ConfigOption::RecvTimeout(timeout) => self.recv_timeout = timeout,
@@ -105,10 +171,28 @@ impl Config {
             ConfigOption::RetryIvlMax(ivl) => self.retry_ivl_max = ivl,
             ConfigOption::RecvMaxSize(x) => self.recv_max_size = x,
             ConfigOption::TcpNoDelay(x) => self.tcp_no_delay = x,
+            ConfigOption::RecvPeerInfo(x) => self.recv_peer_info = x,
             _ => return Err(invalid_input_io_error("option not supported"))
         }
         Ok(())
     }
+
+    pub fn get(&self, name: ConfigOptionName) -> Result<ConfigOption> {
+        let opt = match name {
+            ConfigOptionName::Linger => ConfigOption::Linger(self.linger),
+            ConfigOptionName::SendTimeout => ConfigOption::SendTimeout(self.send_timeout),
+            ConfigOptionName::SendPriority => ConfigOption::SendPriority(self.send_priority),
+            ConfigOptionName::RecvTimeout => ConfigOption::RecvTimeout(self.recv_timeout),
+            ConfigOptionName::RecvPriority => ConfigOption::RecvPriority(self.recv_priority),
+            ConfigOptionName::RetryIvl => ConfigOption::RetryIvl(self.retry_ivl),
+            ConfigOptionName::RetryIvlMax => ConfigOption::RetryIvlMax(self.retry_ivl_max),
+            ConfigOptionName::RecvMaxSize => ConfigOption::RecvMaxSize(self.recv_max_size),
+            ConfigOptionName::TcpNoDelay => ConfigOption::TcpNoDelay(self.tcp_no_delay),
+            ConfigOptionName::RecvPeerInfo => ConfigOption::RecvPeerInfo(self.recv_peer_info),
+            _ => return Err(invalid_input_io_error("option not supported"))
+        };
+        Ok(opt)
+    }
 }
 
 impl ConfigOption {
@@ -122,8 +206,23 @@ impl ConfigOption {
             ConfigOption::RecvPriority(_) |
             ConfigOption::RetryIvl(_)     |
             ConfigOption::RetryIvlMax(_)  |
-            ConfigOption::TcpNoDelay(_)   => true,
+            ConfigOption::RecvMaxSize(_)  |
+            ConfigOption::TcpNoDelay(_)   |
+            ConfigOption::RecvPeerInfo(_) => true,
             _ => false
         }
     }
-}
\ No newline at end of file
+}
+
+impl ConfigOptionName {
+    #[doc(hidden)]
+    pub fn is_generic(&self) -> bool {
+        match *self {
+            ConfigOptionName::TopicDelimiter         |
+            ConfigOptionName::SubscriptionForwarding |
+            ConfigOptionName::ReqResendIvl           |
+            ConfigOptionName::SurveyDeadline         => false,
+            _ => true
+        }
+    }
+}
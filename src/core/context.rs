@@ -8,7 +8,7 @@ use std::fmt;
 use std::io::Result;
 use std::time::Duration;
 
-use core::{EndpointId, EndpointSpec, Scheduled};
+use core::{EndpointId, ContextId, EndpointSpec, Scheduled};
 use core::network::Network;
 
 pub trait Context : Network + Scheduler + fmt::Debug {
@@ -47,8 +47,14 @@ pub enum Schedulable {
     Rebind(EndpointId, EndpointSpec),
     SendTimeout,
     RecvTimeout,
+    ContextSendTimeout(ContextId),
+    ContextRecvTimeout(ContextId),
     ReqResend,
-    SurveyCancel
+    ContextReqResend(ContextId),
+    RequestTimeout,
+    SurveyCancel,
+    ContextSurveyCancel(ContextId),
+    Linger
 }
 
 impl fmt::Debug for Scheduled {
@@ -5,6 +5,7 @@
 // This file may not be copied, modified, or distributed except according to those terms.
 
 use std::rc::Rc;
+use std::time::Duration;
 
 use super::{EndpointId, Message, EndpointSpec, EndpointDesc};
 use super::context::Context;
@@ -13,6 +14,68 @@ pub enum Request {
     Close(bool)
 }
 
+/// State of an endpoint, as seen by its socket.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EndpointState {
+    /// A connected endpoint is being connected or is performing the handshake.
+    Connecting,
+    /// A connected endpoint is ready to exchange messages, or a bound endpoint is listening.
+    Active,
+    /// The endpoint failed and a new attempt is scheduled, see `RetryIvl`.
+    WaitingToReconnect,
+    /// The endpoint has been closed, or has failed without any attempt to re-establish it.
+    Closed
+}
+
+/// Changes in the connections of a socket, see [Socket::monitor](struct.Socket.html#method.monitor).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EndpointEvent {
+    /// A connected endpoint has completed the handshake, with the address of the peer.
+    Connected(EndpointId, Option<String>),
+    /// A bound endpoint, whose id comes first, has accepted a connection from the given address.
+    /// The connection gets an id of its own, which comes second.
+    Accepted(EndpointId, EndpointId, Option<String>),
+    /// The handshake failed, with the protocol announced by the peer when it could be read.
+    HandshakeFailed(EndpointId, Option<u16>),
+    /// An established connection was lost.
+    Disconnected(EndpointId),
+    /// The endpoint failed and will be re-established after the given delay.
+    ReconnectScheduled(EndpointId, Duration)
+}
+
+/// Describes the connection a message was received from,
+/// see [Socket::set_recv_peer_info](struct.Socket.html#method.set_recv_peer_info).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerInfo {
+    /// Id of the connection, as reported by the monitor events.
+    pub endpoint_id: EndpointId,
+    /// Transport of the connection, like `tcp` or `ipc`.
+    pub scheme: String,
+    /// Address of the remote end, the peer `SocketAddr` for `tcp`, when the transport knows it.
+    pub addr: Option<String>,
+    /// Identity of the peer process, available with `ipc` on linux.
+    pub credentials: Option<PeerCredentials>
+}
+
+/// Identity of the process at the other end of a local connection.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PeerCredentials {
+    pub pid: u32,
+    pub uid: u32,
+    pub gid: u32
+}
+
+impl PeerInfo {
+    pub fn new(eid: EndpointId, scheme: &str) -> PeerInfo {
+        PeerInfo {
+            endpoint_id: eid,
+            scheme: scheme.to_owned(),
+            addr: None,
+            credentials: None
+        }
+    }
+}
+
 pub struct Endpoint {
     id: EndpointId,
     url: Option<String>,
@@ -105,6 +168,12 @@ impl Pipe {
     pub fn get_recv_priority(&self) -> u8 {
         self.0.get_recv_priority()
     }
+    pub fn is_accepted(&self) -> bool {
+        self.0.url.is_none()
+    }
+    pub fn get_url(&self) -> Option<&str> {
+        self.0.url.as_ref().map(|url| url.as_str())
+    }
 }
 
 impl Acceptor {
@@ -120,6 +189,9 @@ impl Acceptor {
     pub fn close(self, network: &mut Context) -> Option<EndpointSpec> {
         self.0.close(network, false)
     }
+    pub fn get_url(&self) -> Option<&str> {
+        self.0.url.as_ref().map(|url| url.as_str())
+    }
     pub fn get_send_priority(&self) -> u8 {
         self.0.get_send_priority()
     }
@@ -12,12 +12,17 @@
 #[doc(hidden)] pub mod endpoint;
 #[doc(hidden)] pub mod device;
 #[doc(hidden)] pub mod probe;
+#[doc(hidden)] pub mod backoff;
+#[doc(hidden)] pub mod stats;
+#[doc(hidden)] pub mod survey;
 
 #[cfg(test)]
 pub mod tests;
 
 use std::fmt;
 
+use self::endpoint::PeerInfo;
+
 #[doc(hidden)]
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub struct Scheduled(usize);
@@ -28,7 +33,7 @@ pub struct Scheduled(usize);
 /*                                                                           */
 /*****************************************************************************/
 
-#[doc(hidden)]
+/// Identifies an endpoint of a socket, see [Endpoint::id](struct.Endpoint.html#method.id).
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub struct EndpointId(usize);
 
@@ -104,6 +109,28 @@ impl From<usize> for SocketId {
     }
 }
 
+/*****************************************************************************/
+/*                                                                           */
+/* ContextId                                                                 */
+/*                                                                           */
+/*****************************************************************************/
+
+/// Identifies a context of a socket, see [Context::id](struct.Context.html#method.id).
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ContextId(usize);
+
+impl fmt::Debug for ContextId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<usize> for ContextId {
+    fn from(value: usize) -> ContextId {
+        ContextId(value)
+    }
+}
+
 /*****************************************************************************/
 /*                                                                           */
 /* DeviceId                                                                  */
@@ -170,28 +197,32 @@ pub struct PollRes {
 #[derive(Default, Debug)]
 pub struct Message {
     pub header: Vec<u8>,
-    pub body: Vec<u8>
+    pub body: Vec<u8>,
+    pub peer: Option<PeerInfo>
 }
 
 impl Message {
     pub fn new() -> Message {
         Message {
             header: Vec::new(),
-            body: Vec::new()
+            body: Vec::new(),
+            peer: None
         }
     }
 
     pub fn from_body(body: Vec<u8>) -> Message {
         Message {
             header: Vec::new(),
-            body: body
+            body: body,
+            peer: None
         }
     }
 
     pub fn from_header_and_body(header: Vec<u8>, body: Vec<u8>) -> Message {
         Message {
             header: header,
-            body: body
+            body: body,
+            peer: None
         }
     }
 
@@ -207,12 +238,23 @@ impl Message {
         &self.body
     }
 
+    /// Describes the connection the message was received from,
+    /// when the socket is configured to record it.
+    pub fn get_peer(&self) -> Option<&PeerInfo> {
+        self.peer.as_ref()
+    }
+
+    pub fn with_peer(mut self, peer: Option<PeerInfo>) -> Message {
+        self.peer = peer;
+        self
+    }
+
     pub fn split(self) -> (Vec<u8>, Vec<u8>) {
         (self.header, self.body)
     }
 
     pub fn without_header(self) -> Message {
-        Message::from_body(self.body)
+        Message::from_body(self.body).with_peer(self.peer)
     }
 }
 
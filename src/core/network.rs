@@ -9,14 +9,21 @@ use std::io::Result;
 
 use super::{EndpointTmpl};
 use core::{SocketId, EndpointId, Message};
+use core::endpoint::PeerCredentials;
 
 pub trait Network {
-    fn connect(&mut self, sid: SocketId, tmpl: &EndpointTmpl) -> Result<EndpointId>;
+    /// Returns the id of the new endpoint, along with the address of the peer, when known.
+    fn connect(&mut self, sid: SocketId, tmpl: &EndpointTmpl) -> Result<(EndpointId, Option<String>)>;
     fn reconnect(&mut self, sid: SocketId, eid: EndpointId, tmpl: &EndpointTmpl) -> Result<()>;
-    fn bind(&mut self, sid: SocketId, tmpl: &EndpointTmpl) -> Result<EndpointId>;
+    /// Returns the id of the new endpoint, along with the address it is actually bound to, when known.
+    fn bind(&mut self, sid: SocketId, tmpl: &EndpointTmpl) -> Result<(EndpointId, Option<String>)>;
     fn rebind(&mut self, sid: SocketId, eid: EndpointId, tmpl: &EndpointTmpl) -> Result<()>;
     fn open(&mut self, eid: EndpointId, remote: bool);
     fn close(&mut self, eid: EndpointId, remote: bool);
+    /// Closes the pipe once the message being sent, if any, is completely written.
+    fn linger(&mut self, eid: EndpointId);
     fn send(&mut self, eid: EndpointId, msg: Rc<Message>);
     fn recv(&mut self, eid: EndpointId);
+    /// Returns the address and the credentials of the peer of a pipe, when the transport knows them.
+    fn get_peer(&self, eid: EndpointId) -> (Option<String>, Option<PeerCredentials>);
 }
\ No newline at end of file
@@ -4,15 +4,18 @@
 // or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
 // This file may not be copied, modified, or distributed except according to those terms.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::mpsc::Sender;
 use std::io;
 use std::boxed::FnBox;
 use std::time::Duration;
 
-use super::{SocketId, EndpointId, Message, EndpointTmpl, EndpointSpec, EndpointDesc, Scheduled };
-use super::endpoint::{Pipe, Acceptor};
-use super::config::{Config, ConfigOption};
+use super::{SocketId, EndpointId, ContextId, Message, EndpointTmpl, EndpointSpec, EndpointDesc, Scheduled };
+use super::endpoint::{Pipe, Acceptor, EndpointState, EndpointEvent, PeerInfo};
+use super::config::{Config, ConfigOption, ConfigOptionName};
+use super::backoff::Backoff;
+use super::stats::Statistics;
+use super::survey::SurveyResponses;
 use super::context::{Context, Schedulable, Event};
 use io_error::*;
 
@@ -22,16 +25,47 @@ pub enum Request {
     Send(Message, bool),
     Recv(bool),
     SetOption(ConfigOption),
+    GetOption(ConfigOptionName),
+    GetTopics(TopicList),
+    GetEndpointState(EndpointId),
+    GetStats,
+    AddNotifier(Box<Notify>),
+    AddMonitor(Sender<EndpointEvent>),
+    OpenContext(Sender<Reply>),
+    CloseContext(ContextId),
+    ContextSend(ContextId, Message),
+    ContextRecv(ContextId),
+    ContextSetOption(ContextId, ConfigOption),
+    Survey(Message, Option<usize>),
+    Exchange(Message, Option<Duration>),
     Close
 }
 
+/// Identifies a list of topics held by a `Sub` socket.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TopicList {
+    Subscriptions,
+    ExactSubscriptions,
+    PatternSubscriptions,
+    Exclusions
+}
+
 pub enum Reply {
     Err(io::Error),
-    Connect(EndpointId),
-    Bind(EndpointId),
+    Connect(EndpointId, Option<String>),
+    Bind(EndpointId, Option<String>),
     Send,
     Recv(Message),
-    SetOption
+    SetOption,
+    GetOption(ConfigOption),
+    GetTopics(Vec<String>),
+    EndpointState(EndpointState),
+    Stats(Statistics),
+    AddNotifier,
+    AddMonitor,
+    OpenContext(ContextId),
+    CloseContext,
+    Survey(SurveyResponses)
 }
 
 pub struct Socket {
@@ -39,8 +73,34 @@ pub struct Socket {
     reply_sender: Sender<Reply>,
     protocol: Box<Protocol>,
     pipes: HashMap<EndpointId, Pipe>,
+    opened_pipes: HashSet<EndpointId>,
+    peers: HashMap<EndpointId, PeerInfo>,
     acceptors: HashMap<EndpointId, Acceptor>,
-    config: Config
+    config: Config,
+    backoffs: HashMap<EndpointId, Backoff>,
+    retries: HashMap<EndpointId, Scheduled>,
+    notifiers: Vec<Box<Notify>>,
+    monitors: Vec<Sender<EndpointEvent>>,
+    linger_timeout: Option<Scheduled>,
+    stats: Statistics,
+    contexts: HashMap<ContextId, OpenedContext>,
+    context_id_seq: usize
+}
+
+/// Reply channel and timeouts of a context, the other settings are shared with the socket.
+struct OpenedContext {
+    reply_sender: Sender<Reply>,
+    send_timeout: Option<Duration>,
+    recv_timeout: Option<Duration>
+}
+
+/// Lets a facade that does not block on the reply channel know when to look at it again.
+/// All the methods are called from the event loop thread.
+pub trait Notify : Send {
+    /// The socket has processed a request or an event, a reply may have been sent.
+    fn on_activity(&self);
+    fn on_can_send(&self, ready: bool);
+    fn on_can_recv(&self, ready: bool);
 }
 
 /*****************************************************************************/
@@ -69,12 +129,40 @@ pub trait Protocol {
     fn is_send_ready(&self) -> bool;
     fn is_recv_ready(&self) -> bool;
 
-    fn set_option(&mut self, _: ConfigOption) -> io::Result<()> {
+    fn set_option(&mut self, _: &mut Context, _: ConfigOption) -> io::Result<()> {
+        Err(invalid_input_io_error("option not supported"))
+    }
+    fn get_option(&self, _: ConfigOptionName) -> io::Result<ConfigOption> {
+        Err(invalid_input_io_error("option not supported"))
+    }
+    /// The topics are sorted, they can only be changed through the protocol options.
+    fn get_topics(&self, _: TopicList) -> io::Result<Vec<String>> {
         Err(invalid_input_io_error("option not supported"))
     }
     fn on_timer_tick(&mut self, _: &mut Context, _: Schedulable) {
     }
+    /// Switches the protocol to raw mode: the headers are exposed to the user and no state machine is enforced.
+    fn set_raw(&mut self) {}
+    /// The number of received messages discarded by the protocol logic.
+    fn get_dropped_msg_count(&self) -> u64 { 0 }
     fn on_device_plugged(&mut self, _: &mut Context) {}
+    /// Creates an independent state machine sharing the pipes of the socket, replying on the given channel.
+    fn open_context(&mut self, _: ContextId, _: Sender<Reply>) -> io::Result<()> {
+        Err(invalid_input_io_error("contexts not supported by the protocol"))
+    }
+    fn close_context(&mut self, _: &mut Context, _: ContextId) {}
+    fn send_in_context(&mut self, _: &mut Context, _: ContextId, _: Message, _: Option<Scheduled>) {}
+    fn on_context_send_timeout(&mut self, _: &mut Context, _: ContextId) {}
+    fn recv_in_context(&mut self, _: &mut Context, _: ContextId, _: Option<Scheduled>) {}
+    fn on_context_recv_timeout(&mut self, _: &mut Context, _: ContextId) {}
+    /// Broadcasts a survey then replies with the responses collected until the deadline.
+    fn survey(&mut self, _: &mut Context, _: Message, _: Option<usize>) -> io::Result<()> {
+        Err(invalid_input_io_error("survey not supported by the protocol"))
+    }
+    /// Sends a request then replies with the matching reply, the timeout covering both steps.
+    fn request(&mut self, _: &mut Context, _: Message, _: Option<Scheduled>) -> io::Result<()> {
+        Err(invalid_input_io_error("request not supported by the protocol"))
+    }
     fn close(&mut self, ctx: &mut Context);
 }
 
@@ -93,8 +181,18 @@ impl Socket {
             reply_sender: reply_tx,
             protocol: proto,
             pipes: HashMap::new(),
+            opened_pipes: HashSet::new(),
+            peers: HashMap::new(),
             acceptors: HashMap::new(),
-            config: Config::default()
+            config: Config::default(),
+            backoffs: HashMap::new(),
+            retries: HashMap::new(),
+            notifiers: Vec::new(),
+            monitors: Vec::new(),
+            linger_timeout: None,
+            stats: Statistics::default(),
+            contexts: HashMap::new(),
+            context_id_seq: 0
         }
     }
 
@@ -153,31 +251,36 @@ impl Socket {
         let tmpl = self.create_endpoint_tmpl(url);
 
         match ctx.connect(self.id, &tmpl) {
-            Ok(id) => self.on_connect_success(ctx, id, tmpl.spec),
+            Ok((id, addr)) => self.on_connect_success(ctx, id, tmpl.spec, addr),
             Err(e) => self.on_connect_error(e)
         };
     }
 
-    fn on_connect_success(&mut self, ctx: &mut Context, eid: EndpointId, spec: EndpointSpec) {
+    fn on_connect_success(&mut self, ctx: &mut Context, eid: EndpointId, spec: EndpointSpec, addr: Option<String>) {
         let pipe = self.connect_pipe(eid, spec);
 
         self.insert_pipe(ctx, eid, pipe);
-        self.send_reply(Reply::Connect(eid));
+        self.send_reply(Reply::Connect(eid, addr));
     }
 
     fn on_connect_error(&mut self, err: io::Error) {
+        self.stats.connect_errors += 1;
         self.send_reply(Reply::Err(err));
     }
 
     fn schedule_reconnect(&mut self, ctx: &mut Context, eid: EndpointId, spec: EndpointSpec) {
         let task = Schedulable::Reconnect(eid, spec);
-        let delay = self.config.retry_ivl;
-        let _ = ctx.schedule(task, delay); 
-        // TODO maybe we should keep track of the scheduled reconnection
-        // In case the facade wants to close the ep somewhere between the error and the timeout
+
+        self.schedule_retry(ctx, eid, task);
     }
 
     pub fn reconnect(&mut self, ctx: &mut Context, eid: EndpointId, spec: EndpointSpec) {
+        if self.retries.remove(&eid).is_none() || self.is_lingering() {
+            return;
+        }
+
+        self.stats.reconnect_attempts += 1;
+
         let pids = self.get_protocol_ids();
         let tmpl = EndpointTmpl {
             pids: pids,
@@ -195,6 +298,7 @@ impl Socket {
     }
 
     fn on_reconnect_error(&mut self, ctx: &mut Context, eid: EndpointId, spec: EndpointSpec) {
+        self.stats.connect_errors += 1;
         self.schedule_reconnect(ctx, eid, spec);
     }
 
@@ -208,33 +312,43 @@ impl Socket {
         let tmpl = self.create_endpoint_tmpl(url);
 
         match ctx.bind(self.id, &tmpl) {
-            Ok(id) => self.on_bind_success(ctx, id, tmpl.spec),
+            Ok((id, addr)) => self.on_bind_success(ctx, id, tmpl.spec, addr),
             Err(e) => self.on_bind_error(e)
         };
     }
 
-    fn on_bind_success(&mut self, ctx: &mut Context, eid: EndpointId, spec: EndpointSpec) {
+    fn on_bind_success(&mut self, ctx: &mut Context, eid: EndpointId, mut spec: EndpointSpec, addr: Option<String>) {
+        // When bound to an ephemeral port, rebinding after an error must reuse the chosen one
+        if let Some(ref addr) = addr {
+            spec.url = bound_url(&spec.url, addr);
+        }
+
         let acceptor = self.connect_acceptor(eid, spec);
 
         acceptor.open(ctx);
 
         self.acceptors.insert(eid, acceptor);
-        self.send_reply(Reply::Bind(eid));
+        self.send_reply(Reply::Bind(eid, addr));
     }
 
     fn on_bind_error(&mut self, err: io::Error) {
+        self.stats.bind_errors += 1;
         self.send_reply(Reply::Err(err));
     }
 
     fn schedule_rebind(&mut self, ctx: &mut Context, eid: EndpointId, spec: EndpointSpec) {
         let task = Schedulable::Rebind(eid, spec);
-        let delay = self.config.retry_ivl;
-        let _ = ctx.schedule(task, delay); 
-        // TODO maybe we should keep track of the scheduled reconnection
-        // In case the facade wants to close the ep somewhere between the error and the timeout
+
+        self.schedule_retry(ctx, eid, task);
     }
 
     pub fn rebind(&mut self, ctx: &mut Context, eid: EndpointId, spec: EndpointSpec) {
+        if self.retries.remove(&eid).is_none() || self.is_lingering() {
+            return;
+        }
+
+        self.stats.reconnect_attempts += 1;
+
         let pids = self.get_protocol_ids();
         let tmpl = EndpointTmpl {
             pids: pids,
@@ -250,10 +364,12 @@ impl Socket {
     fn on_rebind_success(&mut self, ctx: &mut Context, eid: EndpointId, spec: EndpointSpec) {
         let acceptor = Acceptor::from_spec(eid, spec);
 
+        self.reset_retry_delay(eid);
         self.insert_acceptor(ctx, eid, acceptor)
     }
 
     fn on_rebind_error(&mut self, ctx: &mut Context, eid: EndpointId, spec: EndpointSpec) {
+        self.stats.bind_errors += 1;
         self.schedule_rebind(ctx, eid, spec);
     }
 
@@ -265,26 +381,68 @@ impl Socket {
 
     pub fn on_pipe_opened(&mut self, ctx: &mut Context, eid: EndpointId) {
         if let Some(pipe) = self.pipes.remove(&eid) {
+            if !pipe.is_accepted() {
+                let addr = pipe.get_url().map(url_addr);
+
+                self.stats.established_connections += 1;
+                self.publish(EndpointEvent::Connected(eid, addr));
+            }
+            self.insert_peer(ctx, eid, pipe.get_url());
+            self.reset_retry_delay(eid);
+            self.opened_pipes.insert(eid);
             self.protocol.add_pipe(ctx, eid, pipe);
         }
     }
 
-    pub fn on_pipe_accepted(&mut self, ctx: &mut Context, aid: EndpointId, eid: EndpointId) {
+    pub fn on_pipe_accepted(&mut self, ctx: &mut Context, aid: EndpointId, eid: EndpointId, addr: Option<String>) {
         let pipe = self.accept_pipe(aid, eid);
 
-        self.insert_pipe(ctx, eid, pipe);
+        if let Some(url) = self.acceptors.get(&aid).and_then(|acceptor| acceptor.get_url()) {
+            self.peers.insert(eid, PeerInfo::new(eid, url_scheme(url)));
+        }
+
+        self.stats.accepted_connections += 1;
+        self.publish(EndpointEvent::Accepted(aid, eid, addr));
+
+        if self.is_lingering() {
+            let _ = pipe.close(ctx);
+        } else {
+            self.insert_pipe(ctx, eid, pipe);
+        }
     }
 
     pub fn close_pipe(&mut self, ctx: &mut Context, eid: EndpointId) {
         let _ = self.remove_pipe(ctx, eid);
+        self.cancel_retry(ctx, eid);
     }
 
-    pub fn on_pipe_error(&mut self, ctx: &mut Context, eid: EndpointId, _: io::Error) {
-        if let Some(spec) = self.remove_pipe(ctx, eid) {
+    pub fn on_pipe_error(&mut self, ctx: &mut Context, eid: EndpointId, err: io::Error) {
+        if self.pipes.contains_key(&eid) {
+            self.stats.dropped_connections += 1;
+
+            if err.kind() == io::ErrorKind::InvalidData {
+                self.publish(EndpointEvent::HandshakeFailed(eid, get_bad_handshake_peer_id(&err)));
+            }
+        } else if self.opened_pipes.contains(&eid) {
+            self.stats.broken_connections += 1;
+            self.publish(EndpointEvent::Disconnected(eid));
+        }
+
+        let spec = self.remove_pipe(ctx, eid);
+
+        if self.is_lingering() {
+            self.check_linger_end(ctx);
+        } else if let Some(spec) = spec {
             self.schedule_reconnect(ctx, eid, spec);
         }
     }
 
+    pub fn on_pipe_closed(&mut self, ctx: &mut Context, eid: EndpointId) {
+        self.opened_pipes.remove(&eid);
+        self.peers.remove(&eid);
+        self.check_linger_end(ctx);
+    }
+
     fn insert_pipe(&mut self, ctx: &mut Context, eid: EndpointId, pipe: Pipe) {
         pipe.open(ctx);
 
@@ -292,15 +450,36 @@ impl Socket {
     }
 
     fn remove_pipe(&mut self, ctx: &mut Context, eid: EndpointId) -> Option<EndpointSpec> {
+        self.peers.remove(&eid);
+
         if let Some(pipe) = self.pipes.remove(&eid) {
             return pipe.close(ctx)
         }
         if let Some(pipe) = self.protocol.remove_pipe(ctx, eid) {
+            self.opened_pipes.remove(&eid);
             return pipe.close(ctx)
         }
         None
     }
 
+    /// The scheme comes from the url for a connected pipe,
+    /// for an accepted one it has been recorded along with the acceptor url.
+    fn insert_peer(&mut self, ctx: &mut Context, eid: EndpointId, url: Option<&str>) {
+        let mut peer = match url {
+            Some(url) => PeerInfo::new(eid, url_scheme(url)),
+            None => match self.peers.remove(&eid) {
+                Some(peer) => peer,
+                None => return
+            }
+        };
+        let (addr, creds) = ctx.get_peer(eid);
+
+        peer.addr = addr;
+        peer.credentials = creds;
+
+        self.peers.insert(eid, peer);
+    }
+
     fn connect_pipe(&self, eid: EndpointId, spec: EndpointSpec) -> Pipe {
         Pipe::from_spec(eid, spec)
     }
@@ -328,6 +507,8 @@ impl Socket {
 /*****************************************************************************/
 
     pub fn on_acceptor_error(&mut self, ctx: &mut Context, eid: EndpointId, _: io::Error) {
+        self.stats.accept_errors += 1;
+
         if let Some(spec) = self.remove_acceptor(ctx, eid) {
             self.schedule_rebind(ctx, eid, spec);
         }
@@ -335,6 +516,7 @@ impl Socket {
 
     pub fn close_acceptor(&mut self, ctx: &mut Context, eid: EndpointId) {
         let _ = self.remove_acceptor(ctx, eid);
+        self.cancel_retry(ctx, eid);
     }
 
     fn insert_acceptor(&mut self, ctx: &mut Context, eid: EndpointId, acceptor: Acceptor) {
@@ -351,6 +533,93 @@ impl Socket {
         Acceptor::from_spec(eid, spec)
     }
 
+/*****************************************************************************/
+/*                                                                           */
+/* retry                                                                     */
+/*                                                                           */
+/*****************************************************************************/
+
+    fn schedule_retry(&mut self, ctx: &mut Context, eid: EndpointId, task: Schedulable) {
+        let delay = self.next_retry_delay(eid);
+
+        if let Ok(scheduled) = ctx.schedule(task, delay) {
+            self.retries.insert(eid, scheduled);
+            self.publish(EndpointEvent::ReconnectScheduled(eid, delay));
+        }
+    }
+
+    fn cancel_retry(&mut self, ctx: &mut Context, eid: EndpointId) {
+        if let Some(scheduled) = self.retries.remove(&eid) {
+            ctx.cancel(scheduled);
+        }
+        self.backoffs.remove(&eid);
+    }
+
+    fn next_retry_delay(&mut self, eid: EndpointId) -> Duration {
+        let config = &self.config;
+        let backoff = self.backoffs.entry(eid).or_insert_with(|| {
+            Backoff::new(config.retry_ivl, config.retry_ivl_max)
+        });
+
+        backoff.next_delay()
+    }
+
+    fn reset_retry_delay(&mut self, eid: EndpointId) {
+        if let Some(backoff) = self.backoffs.get_mut(&eid) {
+            backoff.reset();
+        }
+    }
+
+    /// The interval the next retry delay of the endpoint will be picked from,
+    /// `None` if the endpoint never failed.
+    pub fn get_retry_ivl(&self, eid: EndpointId) -> Option<Duration> {
+        self.backoffs.get(&eid).map(|backoff| backoff.get_ivl())
+    }
+
+/*****************************************************************************/
+/*                                                                           */
+/* endpoint state                                                            */
+/*                                                                           */
+/*****************************************************************************/
+
+    pub fn get_endpoint_state(&self, eid: EndpointId) -> EndpointState {
+        if self.pipes.contains_key(&eid) {
+            EndpointState::Connecting
+        } else if self.opened_pipes.contains(&eid) || self.acceptors.contains_key(&eid) {
+            EndpointState::Active
+        } else if self.retries.contains_key(&eid) {
+            EndpointState::WaitingToReconnect
+        } else {
+            EndpointState::Closed
+        }
+    }
+
+    pub fn query_endpoint_state(&mut self, _: &mut Context, eid: EndpointId) {
+        let state = self.get_endpoint_state(eid);
+
+        self.send_reply(Reply::EndpointState(state));
+    }
+
+/*****************************************************************************/
+/*                                                                           */
+/* stats                                                                     */
+/*                                                                           */
+/*****************************************************************************/
+
+    pub fn get_stats(&self) -> Statistics {
+        Statistics {
+            current_connections: self.opened_pipes.len() as u64,
+            dropped_messages: self.protocol.get_dropped_msg_count(),
+            .. self.stats
+        }
+    }
+
+    pub fn query_stats(&mut self, _: &mut Context) {
+        let stats = self.get_stats();
+
+        self.send_reply(Reply::Stats(stats));
+    }
+
 /*****************************************************************************/
 /*                                                                           */
 /* send                                                                      */
@@ -371,6 +640,33 @@ impl Socket {
         }
     }
 
+    /// The deadline of the survey bounds the whole operation, so the timeouts of the socket do not apply.
+    pub fn survey(&mut self, ctx: &mut Context, msg: Message, expected: Option<usize>) {
+        #[cfg(debug_assertions)] debug!("[{:?}] survey", ctx);
+        if let Err(e) = self.protocol.survey(ctx, msg, expected) {
+            self.send_reply(Reply::Err(e));
+        }
+    }
+
+    /// The timeout bounds the whole exchange, so the timeouts of the socket do not apply.
+    pub fn request(&mut self, ctx: &mut Context, msg: Message, timeout: Option<Duration>) {
+        #[cfg(debug_assertions)] debug!("[{:?}] request", ctx);
+        let sched = match timeout {
+            Some(delay) => match ctx.schedule(Schedulable::RequestTimeout, delay) {
+                Ok(sched) => Some(sched),
+                Err(e) => return self.send_reply(Reply::Err(e))
+            },
+            None => None
+        };
+
+        if let Err(e) = self.protocol.request(ctx, msg, sched) {
+            if let Some(sched) = sched {
+                ctx.cancel(sched);
+            }
+            self.send_reply(Reply::Err(e));
+        }
+    }
+
     pub fn try_send(&mut self, ctx: &mut Context, msg: Message) {
         #[cfg(debug_assertions)] debug!("[{:?}] try_send", ctx);
         if self.protocol.is_send_ready() {
@@ -382,8 +678,10 @@ impl Socket {
         }
     }
 
-    pub fn on_send_ack(&mut self, ctx: &mut Context, eid: EndpointId) {
+    pub fn on_send_ack(&mut self, ctx: &mut Context, eid: EndpointId, len: usize) {
         #[cfg(debug_assertions)] debug!("[{:?}] send ack from ep {:?}", ctx, eid);
+        self.stats.messages_sent += 1;
+        self.stats.bytes_sent += len as u64;
         self.protocol.on_send_ack(ctx, eid);
     }
 
@@ -434,6 +732,15 @@ impl Socket {
 
     pub fn on_recv_ack(&mut self, ctx: &mut Context, eid: EndpointId, msg: Message) {
         #[cfg(debug_assertions)] debug!("[{:?}] recv ack from ep {:?}", ctx, eid);
+        self.stats.messages_received += 1;
+        self.stats.bytes_received += msg.len() as u64;
+
+        let msg = if self.config.recv_peer_info {
+            msg.with_peer(self.peers.get(&eid).cloned())
+        } else {
+            msg
+        };
+
         self.protocol.on_recv_ack(ctx, eid, msg);
     }
 
@@ -457,11 +764,11 @@ impl Socket {
 /*                                                                           */
 /*****************************************************************************/
 
-    pub fn set_option(&mut self, _: &mut Context, opt: ConfigOption) {
+    pub fn set_option(&mut self, ctx: &mut Context, opt: ConfigOption) {
         let res = if opt.is_generic() {
             self.config.set(opt)
         } else {
-            self.protocol.set_option(opt)
+            self.protocol.set_option(ctx, opt)
         };
         let reply = match res {
             Ok(()) => Reply::SetOption,
@@ -471,6 +778,53 @@ impl Socket {
         self.send_reply(reply);
     }
 
+    pub fn get_option(&mut self, _: &mut Context, name: ConfigOptionName) {
+        let res = if name.is_generic() {
+            self.config.get(name)
+        } else {
+            self.protocol.get_option(name)
+        };
+        let reply = match res {
+            Ok(opt) => Reply::GetOption(opt),
+            Err(e) => Reply::Err(e)
+        };
+
+        self.send_reply(reply);
+    }
+
+    pub fn get_topics(&mut self, _: &mut Context, list: TopicList) {
+        let reply = match self.protocol.get_topics(list) {
+            Ok(topics) => Reply::GetTopics(topics),
+            Err(e) => Reply::Err(e)
+        };
+
+        self.send_reply(reply);
+    }
+
+    pub fn add_notifier(&mut self, _: &mut Context, notifier: Box<Notify>) {
+        notifier.on_can_send(self.protocol.is_send_ready());
+        notifier.on_can_recv(self.protocol.is_recv_ready());
+
+        self.notifiers.push(notifier);
+        self.send_reply(Reply::AddNotifier);
+    }
+
+    pub fn notify<F : Fn(&Notify)>(&self, f: F) {
+        for notifier in &self.notifiers {
+            f(notifier.as_ref());
+        }
+    }
+
+    pub fn add_monitor(&mut self, _: &mut Context, monitor: Sender<EndpointEvent>) {
+        self.monitors.push(monitor);
+        self.send_reply(Reply::AddMonitor);
+    }
+
+    /// Forwards the event to the monitors, forgetting the ones whose receiver has been dropped.
+    fn publish(&mut self, event: EndpointEvent) {
+        self.monitors.retain(|monitor| monitor.send(event.clone()).is_ok());
+    }
+
     pub fn on_timer_tick(&mut self, ctx: &mut Context, task: Schedulable) {
         self.protocol.on_timer_tick(ctx, task)
     }
@@ -479,6 +833,121 @@ impl Socket {
         self.protocol.on_device_plugged(ctx)
     }
 
+/*****************************************************************************/
+/*                                                                           */
+/* contexts                                                                  */
+/*                                                                           */
+/*****************************************************************************/
+
+    /// The context starts with the timeouts of the socket, then they can be changed independently.
+    pub fn open_context(&mut self, _: &mut Context, reply_tx: Sender<Reply>) {
+        let cid = ContextId::from(self.context_id_seq);
+
+        match self.protocol.open_context(cid, reply_tx.clone()) {
+            Ok(()) => {
+                let opened = OpenedContext {
+                    reply_sender: reply_tx,
+                    send_timeout: self.config.send_timeout,
+                    recv_timeout: self.config.recv_timeout
+                };
+
+                self.context_id_seq += 1;
+                self.contexts.insert(cid, opened);
+                self.send_reply(Reply::OpenContext(cid));
+            },
+            Err(e) => self.send_reply(Reply::Err(e))
+        }
+    }
+
+    pub fn close_context(&mut self, ctx: &mut Context, cid: ContextId) {
+        if let Some(opened) = self.contexts.remove(&cid) {
+            self.protocol.close_context(ctx, cid);
+
+            let _ = opened.reply_sender.send(Reply::CloseContext);
+        }
+    }
+
+    fn send_context_reply(&self, cid: ContextId, reply: Reply) {
+        if let Some(opened) = self.contexts.get(&cid) {
+            let _ = opened.reply_sender.send(reply);
+        }
+    }
+
+    pub fn send_in_context(&mut self, ctx: &mut Context, cid: ContextId, msg: Message) {
+        #[cfg(debug_assertions)] debug!("[{:?}] send in context {:?}", ctx, cid);
+        let send_timeout = match self.contexts.get(&cid) {
+            Some(opened) => opened.send_timeout,
+            None => return self.send_reply(Reply::Err(invalid_input_io_error("unknown context")))
+        };
+
+        if let Some(delay) = send_timeout {
+            let task = Schedulable::ContextSendTimeout(cid);
+
+            match ctx.schedule(task, delay) {
+                Ok(timeout) => self.protocol.send_in_context(ctx, cid, msg, Some(timeout)),
+                Err(e) => self.send_context_reply(cid, Reply::Err(e))
+            }
+        } else {
+            self.protocol.send_in_context(ctx, cid, msg, None);
+        }
+    }
+
+    pub fn on_context_send_timeout(&mut self, ctx: &mut Context, cid: ContextId) {
+        #[cfg(debug_assertions)] debug!("[{:?}] send timeout in context {:?}", ctx, cid);
+        self.protocol.on_context_send_timeout(ctx, cid);
+    }
+
+    pub fn recv_in_context(&mut self, ctx: &mut Context, cid: ContextId) {
+        #[cfg(debug_assertions)] debug!("[{:?}] recv in context {:?}", ctx, cid);
+        let recv_timeout = match self.contexts.get(&cid) {
+            Some(opened) => opened.recv_timeout,
+            None => return self.send_reply(Reply::Err(invalid_input_io_error("unknown context")))
+        };
+
+        if let Some(delay) = recv_timeout {
+            let task = Schedulable::ContextRecvTimeout(cid);
+
+            match ctx.schedule(task, delay) {
+                Ok(timeout) => self.protocol.recv_in_context(ctx, cid, Some(timeout)),
+                Err(e) => self.send_context_reply(cid, Reply::Err(e))
+            }
+        } else {
+            self.protocol.recv_in_context(ctx, cid, None);
+        }
+    }
+
+    pub fn on_context_recv_timeout(&mut self, ctx: &mut Context, cid: ContextId) {
+        #[cfg(debug_assertions)] debug!("[{:?}] recv timeout in context {:?}", ctx, cid);
+        self.protocol.on_context_recv_timeout(ctx, cid);
+    }
+
+    pub fn set_context_option(&mut self, _: &mut Context, cid: ContextId, opt: ConfigOption) {
+        let reply = match self.contexts.get_mut(&cid) {
+            Some(opened) => match opt {
+                ConfigOption::SendTimeout(timeout) => {
+                    opened.send_timeout = timeout;
+                    Reply::SetOption
+                },
+                ConfigOption::RecvTimeout(timeout) => {
+                    opened.recv_timeout = timeout;
+                    Reply::SetOption
+                },
+                _ => Reply::Err(invalid_input_io_error("option not supported by contexts"))
+            },
+            None => return self.send_reply(Reply::Err(invalid_input_io_error("unknown context")))
+        };
+
+        self.send_context_reply(cid, reply);
+    }
+
+/*****************************************************************************/
+/*                                                                           */
+/* close                                                                     */
+/*                                                                           */
+/*****************************************************************************/
+
+    /// Pipes that are still writing a message are given `linger` to complete it.
+    /// The socket is only removed once they are all closed or the delay has elapsed.
     pub fn close(&mut self, ctx: &mut Context) {
         for (_, pipe) in self.pipes.drain() {
             pipe.close(ctx);
@@ -486,13 +955,75 @@ impl Socket {
         for (_, acceptor) in self.acceptors.drain() {
             acceptor.close(ctx);
         }
+        for (_, scheduled) in self.retries.drain() {
+            ctx.cancel(scheduled);
+        }
+
+        if self.opened_pipes.is_empty() || self.config.linger == Duration::from_millis(0) {
+            return self.finish_close(ctx);
+        }
+
+        match ctx.schedule(Schedulable::Linger, self.config.linger) {
+            Ok(timeout) => {
+                for eid in &self.opened_pipes {
+                    ctx.linger(*eid);
+                }
+                self.linger_timeout = Some(timeout);
+            },
+            Err(_) => self.finish_close(ctx)
+        }
+    }
+
+    pub fn on_linger_timeout(&mut self, ctx: &mut Context) {
+        if self.linger_timeout.take().is_some() {
+            self.finish_close(ctx);
+        }
+    }
+
+    fn is_lingering(&self) -> bool {
+        self.linger_timeout.is_some()
+    }
 
+    fn check_linger_end(&mut self, ctx: &mut Context) {
+        if self.opened_pipes.is_empty() {
+            if let Some(timeout) = self.linger_timeout.take() {
+                ctx.cancel(timeout);
+                self.finish_close(ctx);
+            }
+        }
+    }
+
+    fn finish_close(&mut self, ctx: &mut Context) {
+        self.opened_pipes.clear();
+        self.peers.clear();
+        self.contexts.clear();
         self.protocol.close(ctx);
 
         ctx.raise(Event::Closed);
     }
 }
 
+fn url_addr(url: &str) -> String {
+    match url.find("://") {
+        Some(index) => url[index + 3..].to_owned(),
+        None => url.to_owned()
+    }
+}
+
+fn url_scheme(url: &str) -> &str {
+    match url.find("://") {
+        Some(index) => &url[..index],
+        None => url
+    }
+}
+
+fn bound_url(url: &str, addr: &str) -> String {
+    match url.find("://") {
+        Some(index) => format!("{}{}", &url[..index + 3], addr),
+        None => addr.to_owned()
+    }
+}
+
 /*****************************************************************************/
 /*                                                                           */
 /* tests                                                                     */
@@ -503,6 +1034,7 @@ impl Socket {
 mod tests {
     use std::fmt;
     use std::rc::Rc;
+    use std::cell::RefCell;
     use std::sync::mpsc;
     use std::io;
     use std::time::Duration;
@@ -510,8 +1042,9 @@ mod tests {
     use super::*;
     use core::network;
     use core::context::*;
-    use core::{SocketId, EndpointId, Message, EndpointTmpl, Scheduled};
-    use core::endpoint::Pipe;
+    use core::{SocketId, EndpointId, Message, EndpointTmpl, EndpointSpec, Scheduled};
+    use core::endpoint::{Pipe, PeerCredentials};
+    use core::tests::*;
 
     struct TestProto;
 
@@ -536,13 +1069,13 @@ mod tests {
     struct FailingNetwork;
 
     impl network::Network for FailingNetwork {
-        fn connect(&mut self, _: SocketId, _: &EndpointTmpl) -> io::Result<EndpointId> {
+        fn connect(&mut self, _: SocketId, _: &EndpointTmpl) -> io::Result<(EndpointId, Option<String>)> {
             Err(other_io_error("FailingNetwork can only fail"))
         }
         fn reconnect(&mut self, _: SocketId, _: EndpointId, _: &EndpointTmpl) -> io::Result<()> {
             Err(other_io_error("FailingNetwork can only fail"))
         }
-        fn bind(&mut self, _: SocketId, _: &EndpointTmpl) -> io::Result<EndpointId> {
+        fn bind(&mut self, _: SocketId, _: &EndpointTmpl) -> io::Result<(EndpointId, Option<String>)> {
             Err(other_io_error("FailingNetwork can only fail"))
         }
         fn rebind(&mut self, _: SocketId, _: EndpointId, _: &EndpointTmpl) -> io::Result<()> {
@@ -552,10 +1085,15 @@ mod tests {
         }
         fn close(&mut self, _: EndpointId, _: bool) {
         }
+        fn linger(&mut self, _: EndpointId) {
+        }
         fn send(&mut self, _: EndpointId, _: Rc<Message>) {
         }
         fn recv(&mut self, _: EndpointId) {
         }
+        fn get_peer(&self, _: EndpointId) -> (Option<String>, Option<PeerCredentials>) {
+            (None, None)
+        }
     }
 
     impl Scheduler for FailingNetwork {
@@ -600,22 +1138,26 @@ mod tests {
     struct WorkingNetwork(EndpointId);
 
     impl network::Network for WorkingNetwork {
-        fn connect(&mut self, _: SocketId, _: &EndpointTmpl) -> io::Result<EndpointId> {
-            Ok(self.0)
+        fn connect(&mut self, _: SocketId, _: &EndpointTmpl) -> io::Result<(EndpointId, Option<String>)> {
+            Ok((self.0, Some(String::from("fake"))))
         }
         fn reconnect(&mut self, _: SocketId, _: EndpointId, _: &EndpointTmpl) -> io::Result<()> {
             Ok(())
         }
-        fn bind(&mut self, _: SocketId, _: &EndpointTmpl) -> io::Result<EndpointId> {
-            Ok(self.0)
+        fn bind(&mut self, _: SocketId, _: &EndpointTmpl) -> io::Result<(EndpointId, Option<String>)> {
+            Ok((self.0, Some(String::from("fake:5454"))))
         }
         fn rebind(&mut self, _: SocketId, _: EndpointId, _: &EndpointTmpl) -> io::Result<()> {
             Ok(())
         }
         fn open(&mut self, _: EndpointId, _: bool) {}
         fn close(&mut self, _: EndpointId, _: bool) {}
+        fn linger(&mut self, _: EndpointId) {}
         fn send(&mut self, _: EndpointId, _: Rc<Message>) {}
         fn recv(&mut self, _: EndpointId) {}
+        fn get_peer(&self, _: EndpointId) -> (Option<String>, Option<PeerCredentials>) {
+            (None, None)
+        }
     }
 
     impl Scheduler for WorkingNetwork {
@@ -651,12 +1193,420 @@ mod tests {
         let reply = rx.recv().expect("Socket should have sent a reply to the connect request");
 
         match reply {
-            Reply::Connect(eid) => {
+            Reply::Connect(eid, addr) => {
                 assert_eq!(EndpointId::from(1), eid);
+                assert_eq!(Some(String::from("fake")), addr);
             },
             _ => {
                 assert!(false, "Socket should have replied an ack to the connect request");
             },
         }
     }
+
+    #[test]
+    fn when_bind_succeeds() {
+        let id = SocketId::from(1);
+        let (tx, rx) = mpsc::channel();
+        let proto = Box::new(TestProto) as Box<Protocol>;
+        let mut network = WorkingNetwork(EndpointId::from(1));
+        let mut socket = Socket::new(id, tx, proto);
+
+        socket.bind(&mut network, String::from("test://fake:0"));
+
+        let reply = rx.recv().expect("Socket should have sent a reply to the bind request");
+
+        match reply {
+            Reply::Bind(eid, addr) => {
+                assert_eq!(EndpointId::from(1), eid);
+                assert_eq!(Some(String::from("fake:5454")), addr);
+            },
+            _ => {
+                assert!(false, "Socket should have replied an ack to the bind request");
+            },
+        }
+    }
+
+    #[test]
+    fn bound_url_keeps_the_scheme() {
+        assert_eq!("tcp://127.0.0.1:5454", super::bound_url("tcp://127.0.0.1:0", "127.0.0.1:5454"));
+        assert_eq!("ws://127.0.0.1:5454/a", super::bound_url("ws://127.0.0.1:0/a", "127.0.0.1:5454/a"));
+        assert_eq!("127.0.0.1:5454", super::bound_url("nonsense", "127.0.0.1:5454"));
+    }
+
+    fn is_closed(evt: &Event) -> bool {
+        match *evt {
+            Event::Closed => true,
+            _ => false
+        }
+    }
+
+    #[test]
+    fn close_without_pipes_should_not_linger() {
+        let (tx, _) = mpsc::channel();
+        let proto = Box::new(TestProto) as Box<Protocol>;
+        let mut socket = Socket::new(SocketId::from(1), tx, proto);
+        let sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(sensor.clone());
+
+        socket.close(&mut ctx);
+
+        assert_eq!(0, sensor.borrow().get_linger_calls().len());
+        assert_eq!(1, sensor.borrow().get_raised_events().len());
+        assert!(is_closed(&sensor.borrow().get_raised_events()[0]));
+    }
+
+    #[test]
+    fn close_should_linger_until_opened_pipes_are_closed() {
+        let (tx, _) = mpsc::channel();
+        let proto = Box::new(TestProto) as Box<Protocol>;
+        let mut socket = Socket::new(SocketId::from(1), tx, proto);
+        let sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(sensor.clone());
+        let eid = EndpointId::from(2);
+
+        socket.on_pipe_accepted(&mut ctx, EndpointId::from(1), eid, None);
+        socket.on_pipe_opened(&mut ctx, eid);
+        ctx.set_schedule_result(Some(Scheduled::from(3)));
+        socket.close(&mut ctx);
+
+        assert_eq!(&[eid], sensor.borrow().get_linger_calls());
+        sensor.borrow().assert_no_event_raised();
+
+        socket.on_pipe_closed(&mut ctx, eid);
+
+        sensor.borrow().assert_one_cancellation(Scheduled::from(3));
+        assert_eq!(1, sensor.borrow().get_raised_events().len());
+        assert!(is_closed(&sensor.borrow().get_raised_events()[0]));
+    }
+
+    #[test]
+    fn close_should_stop_lingering_on_timeout() {
+        let (tx, _) = mpsc::channel();
+        let proto = Box::new(TestProto) as Box<Protocol>;
+        let mut socket = Socket::new(SocketId::from(1), tx, proto);
+        let sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(sensor.clone());
+        let eid = EndpointId::from(2);
+
+        socket.on_pipe_accepted(&mut ctx, EndpointId::from(1), eid, None);
+        socket.on_pipe_opened(&mut ctx, eid);
+        ctx.set_schedule_result(Some(Scheduled::from(3)));
+        socket.close(&mut ctx);
+        socket.on_linger_timeout(&mut ctx);
+
+        assert_eq!(1, sensor.borrow().get_raised_events().len());
+        assert!(is_closed(&sensor.borrow().get_raised_events()[0]));
+
+        socket.on_pipe_closed(&mut ctx, eid);
+
+        assert_eq!(1, sensor.borrow().get_raised_events().len());
+    }
+
+    #[test]
+    fn close_should_not_linger_when_linger_is_zero() {
+        let (tx, _) = mpsc::channel();
+        let proto = Box::new(TestProto) as Box<Protocol>;
+        let mut socket = Socket::new(SocketId::from(1), tx, proto);
+        let sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(sensor.clone());
+        let eid = EndpointId::from(2);
+
+        socket.set_option(&mut ctx, ConfigOption::Linger(Duration::from_millis(0)));
+        socket.on_pipe_accepted(&mut ctx, EndpointId::from(1), eid, None);
+        socket.on_pipe_opened(&mut ctx, eid);
+        socket.close(&mut ctx);
+
+        assert_eq!(0, sensor.borrow().get_linger_calls().len());
+        assert_eq!(1, sensor.borrow().get_raised_events().len());
+    }
+
+    fn assert_delay_between(delay: Duration, min_ms: u64, max_ms: u64) {
+        assert!(delay >= Duration::from_millis(min_ms), "{:?} < {}ms", delay, min_ms);
+        assert!(delay <= Duration::from_millis(max_ms), "{:?} > {}ms", delay, max_ms);
+    }
+
+    fn new_test_spec() -> EndpointSpec {
+        EndpointSpec {
+            url: String::from("test://fake"),
+            desc: new_test_endpoint_desc()
+        }
+    }
+
+    #[test]
+    fn reconnect_delay_should_double_until_retry_ivl_max() {
+        let (tx, _) = mpsc::channel();
+        let proto = Box::new(TestProto) as Box<Protocol>;
+        let mut socket = Socket::new(SocketId::from(1), tx, proto);
+        let sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(sensor.clone());
+        let eid = EndpointId::from(1);
+
+        socket.set_option(&mut ctx, ConfigOption::RetryIvlMax(Some(Duration::from_millis(500))));
+        socket.connect(&mut WorkingNetwork(eid), String::from("test://fake"));
+        assert_eq!(None, socket.get_retry_ivl(eid));
+
+        for _ in 0..4 {
+            ctx.set_schedule_result(Some(Scheduled::from(2)));
+            socket.on_pipe_error(&mut ctx, eid, other_io_error("test"));
+            socket.reconnect(&mut ctx, eid, new_test_spec());
+        }
+
+        let delays = sensor.borrow().get_schedule_calls().to_vec();
+
+        assert_eq!(4, delays.len());
+        assert_delay_between(delays[0], 50, 100);
+        assert_delay_between(delays[1], 100, 200);
+        assert_delay_between(delays[2], 200, 400);
+        assert_delay_between(delays[3], 250, 500);
+        assert_eq!(Some(Duration::from_millis(500)), socket.get_retry_ivl(eid));
+    }
+
+    #[test]
+    fn reconnect_delay_should_be_reset_when_pipe_is_opened() {
+        let (tx, _) = mpsc::channel();
+        let proto = Box::new(TestProto) as Box<Protocol>;
+        let mut socket = Socket::new(SocketId::from(1), tx, proto);
+        let sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(sensor.clone());
+        let eid = EndpointId::from(1);
+
+        socket.set_option(&mut ctx, ConfigOption::RetryIvlMax(Some(Duration::from_millis(500))));
+        socket.connect(&mut WorkingNetwork(eid), String::from("test://fake"));
+        ctx.set_schedule_result(Some(Scheduled::from(2)));
+        socket.on_pipe_error(&mut ctx, eid, other_io_error("test"));
+        socket.reconnect(&mut ctx, eid, new_test_spec());
+        ctx.set_schedule_result(Some(Scheduled::from(2)));
+        socket.on_pipe_error(&mut ctx, eid, other_io_error("test"));
+        socket.reconnect(&mut ctx, eid, new_test_spec());
+        assert_eq!(Some(Duration::from_millis(400)), socket.get_retry_ivl(eid));
+
+        socket.on_pipe_opened(&mut ctx, eid);
+        assert_eq!(Some(Duration::from_millis(100)), socket.get_retry_ivl(eid));
+    }
+
+    #[test]
+    fn closing_a_pipe_waiting_to_reconnect_should_cancel_the_reconnection() {
+        let (tx, _) = mpsc::channel();
+        let proto = Box::new(TestProto) as Box<Protocol>;
+        let mut socket = Socket::new(SocketId::from(1), tx, proto);
+        let sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(sensor.clone());
+        let eid = EndpointId::from(1);
+
+        socket.connect(&mut WorkingNetwork(eid), String::from("test://fake"));
+        ctx.set_schedule_result(Some(Scheduled::from(2)));
+        socket.on_pipe_error(&mut ctx, eid, other_io_error("test"));
+        assert_eq!(EndpointState::WaitingToReconnect, socket.get_endpoint_state(eid));
+
+        socket.close_pipe(&mut ctx, eid);
+        sensor.borrow().assert_one_cancellation(Scheduled::from(2));
+        assert_eq!(EndpointState::Closed, socket.get_endpoint_state(eid));
+        assert_eq!(None, socket.get_retry_ivl(eid));
+
+        socket.reconnect(&mut ctx, eid, new_test_spec());
+        assert_eq!(EndpointState::Closed, socket.get_endpoint_state(eid));
+    }
+
+    #[test]
+    fn closing_an_acceptor_waiting_to_rebind_should_cancel_the_rebind() {
+        let (tx, _) = mpsc::channel();
+        let proto = Box::new(TestProto) as Box<Protocol>;
+        let mut socket = Socket::new(SocketId::from(1), tx, proto);
+        let sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(sensor.clone());
+        let eid = EndpointId::from(1);
+
+        socket.bind(&mut WorkingNetwork(eid), String::from("test://fake:0"));
+        assert_eq!(EndpointState::Active, socket.get_endpoint_state(eid));
+
+        ctx.set_schedule_result(Some(Scheduled::from(2)));
+        socket.on_acceptor_error(&mut ctx, eid, other_io_error("test"));
+        assert_eq!(EndpointState::WaitingToReconnect, socket.get_endpoint_state(eid));
+
+        socket.close_acceptor(&mut ctx, eid);
+        sensor.borrow().assert_one_cancellation(Scheduled::from(2));
+
+        socket.rebind(&mut ctx, eid, new_test_spec());
+        assert_eq!(EndpointState::Closed, socket.get_endpoint_state(eid));
+    }
+
+    #[test]
+    fn endpoint_state_should_follow_the_pipe_lifecycle() {
+        let (tx, _) = mpsc::channel();
+        let proto = Box::new(TestProto) as Box<Protocol>;
+        let mut socket = Socket::new(SocketId::from(1), tx, proto);
+        let sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(sensor.clone());
+        let eid = EndpointId::from(1);
+
+        assert_eq!(EndpointState::Closed, socket.get_endpoint_state(eid));
+
+        socket.connect(&mut WorkingNetwork(eid), String::from("test://fake"));
+        assert_eq!(EndpointState::Connecting, socket.get_endpoint_state(eid));
+
+        ctx.set_schedule_result(Some(Scheduled::from(2)));
+        socket.on_pipe_error(&mut ctx, eid, other_io_error("test"));
+        assert_eq!(EndpointState::WaitingToReconnect, socket.get_endpoint_state(eid));
+
+        socket.reconnect(&mut ctx, eid, new_test_spec());
+        assert_eq!(EndpointState::Connecting, socket.get_endpoint_state(eid));
+
+        socket.on_pipe_opened(&mut ctx, eid);
+        assert_eq!(EndpointState::Active, socket.get_endpoint_state(eid));
+    }
+
+    #[test]
+    fn stats_should_count_the_connections() {
+        let (tx, _) = mpsc::channel();
+        let proto = Box::new(TestProto) as Box<Protocol>;
+        let mut socket = Socket::new(SocketId::from(1), tx, proto);
+        let sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(sensor.clone());
+        let eid = EndpointId::from(1);
+
+        socket.connect(&mut WorkingNetwork(eid), String::from("test://fake"));
+        ctx.set_schedule_result(Some(Scheduled::from(2)));
+        socket.on_pipe_error(&mut ctx, eid, other_io_error("test"));
+        socket.reconnect(&mut ctx, eid, new_test_spec());
+        socket.on_pipe_opened(&mut ctx, eid);
+
+        let stats = socket.get_stats();
+        assert_eq!(1, stats.dropped_connections);
+        assert_eq!(1, stats.reconnect_attempts);
+        assert_eq!(1, stats.established_connections);
+        assert_eq!(1, stats.current_connections);
+
+        ctx.set_schedule_result(Some(Scheduled::from(3)));
+        socket.on_pipe_error(&mut ctx, eid, other_io_error("test"));
+
+        let stats = socket.get_stats();
+        assert_eq!(1, stats.broken_connections);
+        assert_eq!(0, stats.accepted_connections);
+    }
+
+    #[test]
+    fn stats_should_count_the_messages_and_bytes() {
+        let (tx, _) = mpsc::channel();
+        let proto = Box::new(TestProto) as Box<Protocol>;
+        let mut socket = Socket::new(SocketId::from(1), tx, proto);
+        let sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(sensor.clone());
+
+        socket.send(&mut ctx, Message::from_body(vec![1, 2, 3]));
+        socket.on_send_ack(&mut ctx, EndpointId::from(1), 3);
+        socket.on_send_ack(&mut ctx, EndpointId::from(2), 3);
+        socket.on_recv_ack(&mut ctx, EndpointId::from(1), Message::from_body(vec![1, 2, 3, 4]));
+
+        let stats = socket.get_stats();
+        assert_eq!(2, stats.messages_sent);
+        assert_eq!(6, stats.bytes_sent);
+        assert_eq!(1, stats.messages_received);
+        assert_eq!(4, stats.bytes_received);
+    }
+
+    #[test]
+    fn monitor_should_receive_the_pipe_lifecycle_events() {
+        let (tx, _) = mpsc::channel();
+        let proto = Box::new(TestProto) as Box<Protocol>;
+        let mut socket = Socket::new(SocketId::from(1), tx, proto);
+        let sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(sensor.clone());
+        let eid = EndpointId::from(1);
+        let (monitor_tx, monitor_rx) = mpsc::channel();
+
+        socket.add_monitor(&mut ctx, monitor_tx);
+        socket.connect(&mut WorkingNetwork(eid), String::from("test://fake"));
+        ctx.set_schedule_result(Some(Scheduled::from(2)));
+        socket.on_pipe_error(&mut ctx, eid, other_io_error("test"));
+        socket.reconnect(&mut ctx, eid, new_test_spec());
+        socket.on_pipe_opened(&mut ctx, eid);
+        socket.on_pipe_error(&mut ctx, eid, other_io_error("test"));
+
+        let events: Vec<EndpointEvent> = monitor_rx.try_iter().collect();
+
+        assert_eq!(3, events.len());
+        match events[0] {
+            EndpointEvent::ReconnectScheduled(id, _) => assert_eq!(eid, id),
+            ref other => panic!("unexpected event {:?}", other)
+        }
+        assert_eq!(EndpointEvent::Connected(eid, Some(String::from("fake"))), events[1]);
+        assert_eq!(EndpointEvent::Disconnected(eid), events[2]);
+    }
+
+    #[test]
+    fn monitor_should_receive_the_peer_id_when_handshake_fails() {
+        let (tx, _) = mpsc::channel();
+        let proto = Box::new(TestProto) as Box<Protocol>;
+        let mut socket = Socket::new(SocketId::from(1), tx, proto);
+        let sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(sensor.clone());
+        let aid = EndpointId::from(1);
+        let eid = EndpointId::from(2);
+        let (monitor_tx, monitor_rx) = mpsc::channel();
+
+        socket.add_monitor(&mut ctx, monitor_tx);
+        socket.on_pipe_accepted(&mut ctx, aid, eid, Some(String::from("peer")));
+        socket.on_pipe_error(&mut ctx, eid, bad_handshake_io_error(Some(16)));
+
+        let events: Vec<EndpointEvent> = monitor_rx.try_iter().collect();
+
+        assert_eq!(2, events.len());
+        assert_eq!(EndpointEvent::Accepted(aid, eid, Some(String::from("peer"))), events[0]);
+        assert_eq!(EndpointEvent::HandshakeFailed(eid, Some(16)), events[1]);
+    }
+
+    #[test]
+    fn get_option_should_return_the_value_previously_set() {
+        let (tx, rx) = mpsc::channel();
+        let proto = Box::new(TestProto) as Box<Protocol>;
+        let mut socket = Socket::new(SocketId::from(1), tx, proto);
+        let mut ctx = TestContext::with_sensor(Rc::new(RefCell::new(TestContextSensor::default())));
+
+        socket.set_option(&mut ctx, ConfigOption::RecvMaxSize(1024));
+        socket.get_option(&mut ctx, ConfigOptionName::RecvMaxSize);
+        socket.get_option(&mut ctx, ConfigOptionName::Linger);
+        socket.get_option(&mut ctx, ConfigOptionName::ReqResendIvl);
+
+        match rx.recv().unwrap() {
+            Reply::SetOption => {},
+            _ => panic!("Socket should have replied an ack to the set option request")
+        }
+        match rx.recv().unwrap() {
+            Reply::GetOption(opt) => assert_eq!(ConfigOption::RecvMaxSize(1024), opt),
+            _ => panic!("Socket should have replied the option value")
+        }
+        match rx.recv().unwrap() {
+            Reply::GetOption(opt) => assert_eq!(ConfigOption::Linger(Duration::from_secs(1)), opt),
+            _ => panic!("Socket should have replied the option value")
+        }
+        match rx.recv().unwrap() {
+            Reply::Err(e) => assert_eq!(io::ErrorKind::InvalidInput, e.kind()),
+            _ => panic!("Socket should have replied an error for an option not supported by the protocol")
+        }
+    }
+
+    #[test]
+    fn requests_in_an_unknown_context_should_fail() {
+        let (tx, rx) = mpsc::channel();
+        let proto = Box::new(TestProto) as Box<Protocol>;
+        let mut socket = Socket::new(SocketId::from(1), tx, proto);
+        let mut ctx = TestContext::with_sensor(Rc::new(RefCell::new(TestContextSensor::default())));
+
+        socket.send_in_context(&mut ctx, ContextId::from(7), Message::new());
+        socket.recv_in_context(&mut ctx, ContextId::from(7));
+        socket.set_context_option(&mut ctx, ContextId::from(7), ConfigOption::SendTimeout(None));
+
+        match rx.try_recv().unwrap() {
+            Reply::Err(e) => assert_eq!(io::ErrorKind::InvalidInput, e.kind()),
+            _ => panic!("Socket should have replied an error to the send in an unknown context")
+        }
+        match rx.try_recv().unwrap() {
+            Reply::Err(e) => assert_eq!(io::ErrorKind::InvalidInput, e.kind()),
+            _ => panic!("Socket should have replied an error to the recv in an unknown context")
+        }
+        match rx.try_recv().unwrap() {
+            Reply::Err(e) => assert_eq!(io::ErrorKind::InvalidInput, e.kind()),
+            _ => panic!("Socket should have replied an error to the option set in an unknown context")
+        }
+    }
 }
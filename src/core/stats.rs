@@ -0,0 +1,40 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+/// Counters describing the activity of a socket since its creation,
+/// see [Socket::stats](struct.Socket.html#method.stats).
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Statistics {
+    /// Messages written to a peer, a message broadcasted to several peers is counted once per peer.
+    pub messages_sent: u64,
+    /// Bytes written to the peers, header included.
+    pub bytes_sent: u64,
+    /// Messages read from a peer, including the ones dropped by the protocol.
+    pub messages_received: u64,
+    /// Bytes read from the peers, header included.
+    pub bytes_received: u64,
+    /// Connections established by the connected endpoints.
+    pub established_connections: u64,
+    /// Connections accepted by the bound endpoints.
+    pub accepted_connections: u64,
+    /// Connections closed before the handshake completed.
+    pub dropped_connections: u64,
+    /// Connections closed because of an error after the handshake completed.
+    pub broken_connections: u64,
+    /// Failed attempts to connect an endpoint.
+    pub connect_errors: u64,
+    /// Failed attempts to bind an endpoint.
+    pub bind_errors: u64,
+    /// Errors reported by the bound endpoints while accepting connections.
+    pub accept_errors: u64,
+    /// Attempts to reconnect or rebind an endpoint after an error.
+    pub reconnect_attempts: u64,
+    /// Connections currently open, handshake completed.
+    pub current_connections: u64,
+    /// Received messages discarded by the protocol, like subscription mismatches
+    /// or replies to a request or a survey that is no longer pending.
+    pub dropped_messages: u64
+}
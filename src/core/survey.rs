@@ -0,0 +1,17 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use core::Message;
+
+/// Outcome of a survey run to completion,
+/// see [Socket::survey](struct.Socket.html#method.survey).
+#[derive(Debug)]
+pub struct SurveyResponses {
+    /// The responses received before the deadline, in the order they arrived.
+    pub responses: Vec<Message>,
+    /// The number of peers the survey was sent to.
+    pub respondents: usize
+}
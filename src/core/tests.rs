@@ -11,7 +11,7 @@ use std::io::Result;
 use std::time::Duration;
 
 use super::{SocketId, EndpointId, Message, EndpointTmpl, EndpointDesc, Scheduled};
-use super::endpoint::Pipe;
+use super::endpoint::{Pipe, PeerCredentials};
 use super::context::{Context, Scheduler, Schedulable, Event};
 use super::network::Network;
 use io_error;
@@ -32,9 +32,11 @@ pub fn new_test_endpoint_desc() -> EndpointDesc {
 #[derive(Debug)]
 pub struct TestContextSensor {
     close_calls: Vec<(EndpointId, bool)>,
+    linger_calls: Vec<EndpointId>,
     send_calls: Vec<(EndpointId, Rc<Message>)>,
     recv_calls: Vec<EndpointId>,
     raised_events: Vec<Event>,
+    schedule_calls: Vec<Duration>,
     schedule_cancellations: Vec<Scheduled>
 }
 
@@ -42,9 +44,11 @@ impl Default for TestContextSensor {
     fn default() -> TestContextSensor {
         TestContextSensor {
             close_calls: Vec::new(),
+            linger_calls: Vec::new(),
             send_calls: Vec::new(),
             recv_calls: Vec::new(),
             raised_events: Vec::new(),
+            schedule_calls: Vec::new(),
             schedule_cancellations: Vec::new()
         }
     }
@@ -59,6 +63,14 @@ impl TestContextSensor {
         &self.close_calls
     }
 
+    fn push_linger_call(&mut self, eid: EndpointId) {
+        self.linger_calls.push(eid)
+    }
+
+    pub fn get_linger_calls(&self) -> &[EndpointId] {
+        &self.linger_calls
+    }
+
     fn push_send_call(&mut self, eid: EndpointId, msg: Rc<Message>) {
         self.send_calls.push((eid, msg))
     }
@@ -95,6 +107,14 @@ impl TestContextSensor {
         assert_eq!(0, self.raised_events.len());
     }
 
+    fn push_schedule_call(&mut self, delay: Duration) {
+        self.schedule_calls.push(delay)
+    }
+
+    pub fn get_schedule_calls(&self) -> &[Duration] {
+        &self.schedule_calls
+    }
+
     fn push_schedule_cancellation(&mut self, scheduled: Scheduled) {
         self.schedule_cancellations.push(scheduled)
     }
@@ -140,37 +160,48 @@ impl TestContext {
             schedule_result: None
         }
     }
+
+    pub fn set_schedule_result(&mut self, scheduled: Option<Scheduled>) {
+        self.schedule_result = scheduled;
+    }
 }
 
 impl Network for TestContext {
-    fn connect(&mut self, _: SocketId, _: &EndpointTmpl) -> Result<EndpointId> {
+    fn connect(&mut self, _: SocketId, _: &EndpointTmpl) -> Result<(EndpointId, Option<String>)> {
         unimplemented!();
     }
     fn reconnect(&mut self, _: SocketId, _: EndpointId, _: &EndpointTmpl) -> Result<()> {
-        unimplemented!();
+        Ok(())
     }
-    fn bind(&mut self, _: SocketId, _: &EndpointTmpl) -> Result<EndpointId> {
+    fn bind(&mut self, _: SocketId, _: &EndpointTmpl) -> Result<(EndpointId, Option<String>)> {
         unimplemented!();
     }
     fn rebind(&mut self, _: SocketId, _: EndpointId, _: &EndpointTmpl) -> Result<()> {
-        unimplemented!();
+        Ok(())
     }
     fn open(&mut self, _: EndpointId, _: bool) {
-        unimplemented!();
     }
     fn close(&mut self, eid: EndpointId, remote: bool) {
         self.sensor.borrow_mut().push_close_call(eid, remote)
     }
+    fn linger(&mut self, eid: EndpointId) {
+        self.sensor.borrow_mut().push_linger_call(eid)
+    }
     fn send(&mut self, eid: EndpointId, msg: Rc<Message>) {
         self.sensor.borrow_mut().push_send_call(eid, msg)
     }
     fn recv(&mut self, eid: EndpointId) {
         self.sensor.borrow_mut().push_recv_call(eid)
     }
+    fn get_peer(&self, _: EndpointId) -> (Option<String>, Option<PeerCredentials>) {
+        (None, None)
+    }
 }
 
 impl Scheduler for TestContext {
-    fn schedule(&mut self, schedulable: Schedulable, delay: Duration) -> Result<Scheduled> {
+    fn schedule(&mut self, _: Schedulable, delay: Duration) -> Result<Scheduled> {
+        self.sensor.borrow_mut().push_schedule_call(delay);
+
         if let Some(scheduled) = self.schedule_result.take() {
             Ok(scheduled)
         } else {
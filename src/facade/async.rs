@@ -0,0 +1,232 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::io;
+
+use futures::{Future, Stream, Sink, Poll, Async, AsyncSink, StartSend};
+use futures::task::AtomicTask;
+
+use super::socket::Socket;
+use core::Message;
+use core::socket::{Request, Reply, Notify};
+use io_error::*;
+
+/// Readiness of the socket as reported by the event loop, and the tasks to wake up when it changes.
+struct Shared {
+    send_task: AtomicTask,
+    recv_task: AtomicTask,
+    can_send: AtomicBool,
+    recv_ready_count: AtomicUsize
+}
+
+struct Notifier(Arc<Shared>);
+
+impl Notify for Notifier {
+    fn on_activity(&self) {
+        self.0.send_task.notify();
+        self.0.recv_task.notify();
+    }
+    fn on_can_send(&self, ready: bool) {
+        self.0.can_send.store(ready, Ordering::SeqCst);
+
+        if ready {
+            self.0.send_task.notify();
+        }
+    }
+    fn on_can_recv(&self, ready: bool) {
+        if ready {
+            self.0.recv_ready_count.fetch_add(1, Ordering::SeqCst);
+            self.0.recv_task.notify();
+        }
+    }
+}
+
+enum Pending {
+    Send,
+    Recv(usize)
+}
+
+/// Asynchronous version of a [Socket](struct.Socket.html), available with the `async` feature.
+///
+/// Obtained via the socket [into_async](struct.Socket.html#method.into_async) method,
+/// once the socket has been configured and plugged.
+/// Messages are sent through the [Sink](https://docs.rs/futures/0.1/futures/sink/trait.Sink.html) implementation
+/// and received through the [Stream](https://docs.rs/futures/0.1/futures/stream/trait.Stream.html) implementation.
+/// No thread is blocked while waiting, the tasks are woken up by the event loop when the socket becomes ready.
+/// Operations are performed one at a time: a message being sent delays the next receive and the other way round.
+/// Dropping the socket blocks the same way dropping a [Socket](struct.Socket.html) does.
+pub struct AsyncSocket {
+    socket: Socket,
+    shared: Arc<Shared>,
+    pending: Option<Pending>,
+    recv_blocked_at: Option<usize>,
+    sent: Option<io::Result<()>>,
+    received: Option<io::Result<Message>>
+}
+
+impl Socket {
+    /// Converts the socket into an [AsyncSocket](struct.AsyncSocket.html).
+    pub fn into_async(self) -> io::Result<AsyncSocket> {
+        let shared = Arc::new(Shared {
+            send_task: AtomicTask::new(),
+            recv_task: AtomicTask::new(),
+            can_send: AtomicBool::new(true),
+            recv_ready_count: AtomicUsize::new(0)
+        });
+        let notifier = Notifier(shared.clone());
+
+        try!(self.add_notifier(box notifier));
+
+        Ok(AsyncSocket::new(self, shared))
+    }
+}
+
+impl AsyncSocket {
+    fn new(socket: Socket, shared: Arc<Shared>) -> AsyncSocket {
+        AsyncSocket {
+            socket: socket,
+            shared: shared,
+            pending: None,
+            recv_blocked_at: None,
+            sent: None,
+            received: None
+        }
+    }
+
+    /// Sends a buffer, the future yields the socket back once the buffer is sent.
+    pub fn send(self, buffer: Vec<u8>) -> impl Future<Item=AsyncSocket, Error=io::Error> {
+        self.send_msg(Message::from_body(buffer))
+    }
+
+    /// Sends a message, the future yields the socket back once the message is sent.
+    pub fn send_msg(self, msg: Message) -> impl Future<Item=AsyncSocket, Error=io::Error> {
+        Sink::send(self, msg)
+    }
+
+    /// Returns `false` while the reply to the last request has not been received.
+    fn poll_reply(&mut self) -> io::Result<bool> {
+        let pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => return Ok(true)
+        };
+        let reply = match try!(self.socket.try_recv_reply()) {
+            Some(reply) => reply,
+            None => {
+                self.pending = Some(pending);
+                return Ok(false);
+            }
+        };
+
+        match pending {
+            Pending::Send => {
+                self.sent = Some(match reply {
+                    Reply::Send => Ok(()),
+                    Reply::Err(e) => Err(e),
+                    _ => Err(other_io_error("unexpected reply"))
+                });
+                self.shared.send_task.notify();
+            },
+            Pending::Recv(count) => {
+                self.received = match reply {
+                    Reply::Recv(msg) => Some(Ok(msg)),
+                    Reply::Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        self.recv_blocked_at = Some(count);
+                        None
+                    },
+                    Reply::Err(e) => Some(Err(e)),
+                    _ => Some(Err(other_io_error("unexpected reply")))
+                };
+                self.shared.recv_task.notify();
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn check_sent(&mut self) -> io::Result<()> {
+        match self.sent.take() {
+            Some(Err(e)) => Err(e),
+            _ => Ok(())
+        }
+    }
+}
+
+impl Sink for AsyncSocket {
+    type SinkItem = Message;
+    type SinkError = io::Error;
+
+    fn start_send(&mut self, msg: Message) -> StartSend<Message, io::Error> {
+        self.shared.send_task.register();
+
+        let idle = try!(self.poll_reply());
+
+        try!(self.check_sent());
+
+        if !idle || !self.shared.can_send.load(Ordering::SeqCst) {
+            return Ok(AsyncSink::NotReady(msg));
+        }
+
+        try!(self.socket.send_request(Request::Send(msg, false)));
+        self.pending = Some(Pending::Send);
+
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), io::Error> {
+        self.shared.send_task.register();
+
+        try!(self.poll_reply());
+        try!(self.check_sent());
+
+        match self.pending {
+            Some(Pending::Send) => Ok(Async::NotReady),
+            _ => Ok(Async::Ready(()))
+        }
+    }
+}
+
+impl Stream for AsyncSocket {
+    type Item = Message;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Message>, io::Error> {
+        self.shared.recv_task.register();
+
+        loop {
+            if !try!(self.poll_reply()) {
+                return Ok(Async::NotReady);
+            }
+            if let Some(received) = self.received.take() {
+                return received.map(|msg| Async::Ready(Some(msg)));
+            }
+
+            // Once a receive attempt has failed, wait for the socket to report it can receive
+            let count = self.shared.recv_ready_count.load(Ordering::SeqCst);
+            if self.recv_blocked_at == Some(count) {
+                return Ok(Async::NotReady);
+            }
+
+            self.recv_blocked_at = None;
+            try!(self.socket.send_request(Request::Recv(true)));
+            self.pending = Some(Pending::Recv(count));
+        }
+    }
+}
+
+impl Drop for AsyncSocket {
+    fn drop(&mut self) {
+        // The reply to the pending request, if it ever comes, must not be mistaken
+        // for the reply to the close request, so the socket is closed right here,
+        // and the reply channel drained until the event loop disconnects it.
+        if self.pending.take().is_some() {
+            let _ = self.socket.send_request(Request::Close);
+
+            while self.socket.recv_reply().is_ok() {}
+        }
+    }
+}
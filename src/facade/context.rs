@@ -0,0 +1,179 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use std::sync::mpsc;
+use std::io;
+use std::time::Duration;
+
+use super::*;
+use reactor;
+use core::{SocketId, ContextId, Message};
+use core::socket::{Request, Reply};
+use core::config::ConfigOption;
+use io_error::*;
+
+#[doc(hidden)]
+pub type ReplyReceiver = mpsc::Receiver<Reply>;
+
+#[doc(hidden)]
+pub struct RequestSender {
+    req_tx: EventLoopRequestSender,
+    socket_id: SocketId
+}
+
+impl RequestSender {
+    pub fn new(tx: EventLoopRequestSender, sid: SocketId) -> RequestSender {
+        RequestSender {
+            req_tx: tx,
+            socket_id: sid
+        }
+    }
+    fn send(&self, req: Request) -> io::Result<()> {
+        self.req_tx.send(reactor::Request::Socket(self.socket_id, req)).map_err(from_send_error)
+    }
+}
+
+/// Context of a socket.
+///
+/// Obtained via the socket [open_context](struct.Socket.html#method.open_context) method.
+/// A context runs its own instance of the protocol state machine, while sharing the endpoints of the socket.
+/// With `Req`, each context has a request in flight, its own request id and resend timer,
+/// and receives the reply matching its request, whatever the order the replies come in.
+/// With `Rep`, each context receives a request and saves its backtrace,
+/// so several requests can be served at the same time and replied to in any order.
+/// With `Surveyor`, each context runs its own survey, with its own survey id and deadline,
+/// and receives the responses to that survey, so surveys can overlap.
+/// Contexts can be moved to other threads, so a single socket can serve many concurrent exchanges.
+/// They stop working once the socket is dropped, and `drop(Context)` closes the context.
+pub struct Context {
+    request_sender: RequestSender,
+    reply_receiver: ReplyReceiver,
+    id: ContextId
+}
+
+impl Context {
+    #[doc(hidden)]
+    pub fn new(request_tx: RequestSender, reply_rx: ReplyReceiver, id: ContextId) -> Context {
+        Context {
+            request_sender: request_tx,
+            reply_receiver: reply_rx,
+            id: id
+        }
+    }
+
+    /// Identifies the context among the ones opened on its socket.
+    pub fn id(&self) -> ContextId {
+        self.id
+    }
+
+/*****************************************************************************/
+/*                                                                           */
+/* send                                                                      */
+/*                                                                           */
+/*****************************************************************************/
+
+    /// Sends a buffer, starting a new exchange in this context.
+    pub fn send(&mut self, buffer: Vec<u8>) -> io::Result<()> {
+        self.send_msg(Message::from_body(buffer))
+    }
+
+    /// Sends a message, starting a new exchange in this context.
+    pub fn send_msg(&mut self, msg: Message) -> io::Result<()> {
+        let request = Request::ContextSend(self.id, msg);
+
+        self.call(request, |reply| self.on_send_reply(reply))
+    }
+
+    fn on_send_reply(&self, reply: Reply) -> io::Result<()> {
+        match reply {
+            Reply::Send => Ok(()),
+            Reply::Err(e) => Err(e),
+            _ => self.unexpected_reply()
+        }
+    }
+
+/*****************************************************************************/
+/*                                                                           */
+/* recv                                                                      */
+/*                                                                           */
+/*****************************************************************************/
+
+    /// Receives a buffer belonging to the exchange of this context.
+    pub fn recv(&mut self) -> io::Result<Vec<u8>> {
+        self.recv_msg().map(|msg| msg.into())
+    }
+
+    /// Receives a message belonging to the exchange of this context.
+    pub fn recv_msg(&mut self) -> io::Result<Message> {
+        let request = Request::ContextRecv(self.id);
+
+        self.call(request, |reply| self.on_recv_reply(reply))
+    }
+
+    fn on_recv_reply(&self, reply: Reply) -> io::Result<Message> {
+        match reply {
+            Reply::Recv(msg) => Ok(msg),
+            Reply::Err(e) => Err(e),
+            _ => self.unexpected_reply()
+        }
+    }
+
+/*****************************************************************************/
+/*                                                                           */
+/* options                                                                   */
+/*                                                                           */
+/*****************************************************************************/
+
+    /// Sets the timeout for send operation in this context.
+    /// Initial value is the one of the socket when the context was opened.
+    pub fn set_send_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.set_option(ConfigOption::SendTimeout(timeout))
+    }
+
+    /// Sets the timeout for recv operation in this context.
+    /// Initial value is the one of the socket when the context was opened.
+    pub fn set_recv_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.set_option(ConfigOption::RecvTimeout(timeout))
+    }
+
+    fn set_option(&mut self, cfg_opt: ConfigOption) -> io::Result<()> {
+        let request = Request::ContextSetOption(self.id, cfg_opt);
+
+        self.call(request, |reply| self.on_set_option_reply(reply))
+    }
+
+    fn on_set_option_reply(&self, reply: Reply) -> io::Result<()> {
+        match reply {
+            Reply::SetOption => Ok(()),
+            Reply::Err(e) => Err(e),
+            _ => self.unexpected_reply()
+        }
+    }
+
+/*****************************************************************************/
+/*                                                                           */
+/* backend                                                                   */
+/*                                                                           */
+/*****************************************************************************/
+
+    fn call<T, F : FnOnce(Reply) -> io::Result<T>>(&self, request: Request, process: F) -> io::Result<T> {
+        self.execute_request(request).and_then(process)
+    }
+
+    fn execute_request(&self, request: Request) -> io::Result<Reply> {
+        self.request_sender.send(request).and_then(|_| self.reply_receiver.receive())
+    }
+
+    fn unexpected_reply<T>(&self) -> io::Result<T> {
+        Err(other_io_error("unexpected reply"))
+    }
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        let _ = self.execute_request(Request::CloseContext(self.id));
+    }
+}
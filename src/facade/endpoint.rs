@@ -36,19 +36,47 @@ impl RequestSender {
 ///   
 /// Obtained via the socket [bind](struct.Socket.html#method.bind) or 
 /// [connect](struct.Socket.html#method.connect) methods.  
-/// Can only be used to close an endpoint.  
+/// Can be used to close an endpoint and to find out the address it resolved to.  
 /// Note that `drop(Endpoint)` will **NOT** close it.
 pub struct Endpoint {
     request_sender: RequestSender,
-    remote: bool
+    remote: bool,
+    addr: Option<String>
 }
 
 impl Endpoint {
     #[doc(hidden)]
-    pub fn new(request_tx: RequestSender, remote: bool) -> Endpoint {
+    pub fn new(request_tx: RequestSender, remote: bool, addr: Option<String>) -> Endpoint {
         Endpoint {
             request_sender: request_tx,
-            remote: remote
+            remote: remote,
+            addr: addr
+        }
+    }
+
+    /// Identifies the endpoint in the events reported by the socket [monitor](struct.Socket.html#method.monitor).
+    pub fn id(&self) -> EndpointId {
+        self.request_sender.id
+    }
+
+    /// Address a bound endpoint is actually listening on, without the scheme.
+    /// This is how the port picked by the system can be found after binding to port `0`.
+    /// Returns `None` for connected endpoints.
+    pub fn local_addr(&self) -> Option<&str> {
+        if self.remote {
+            None
+        } else {
+            self.addr.as_ref().map(|addr| addr.as_str())
+        }
+    }
+
+    /// Address of the peer a connected endpoint was initially connected to, without the scheme.
+    /// Returns `None` for bound endpoints.
+    pub fn peer_addr(&self) -> Option<&str> {
+        if self.remote {
+            self.addr.as_ref().map(|addr| addr.as_str())
+        } else {
+            None
         }
     }
 
@@ -0,0 +1,92 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use std::cell::Cell;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+
+use core::socket::Notify;
+
+/// A pair of connected unix sockets, the reading end is readable while the signal is raised.
+pub struct Signal {
+    reader: UnixStream,
+    writer: UnixStream,
+    raised: Cell<bool>
+}
+
+impl Signal {
+    pub fn new() -> io::Result<Signal> {
+        let (reader, writer) = try!(UnixStream::pair());
+
+        try!(reader.set_nonblocking(true));
+        try!(writer.set_nonblocking(true));
+
+        Ok(Signal {
+            reader: reader,
+            writer: writer,
+            raised: Cell::new(false)
+        })
+    }
+
+    pub fn as_raw_fd(&self) -> RawFd {
+        self.reader.as_raw_fd()
+    }
+
+    pub fn set(&self, raised: bool) {
+        if raised == self.raised.get() {
+            return;
+        }
+
+        if raised {
+            let _ = (&self.writer).write(&[1]);
+        } else {
+            let mut buffer = [0u8; 16];
+
+            while let Ok(n) = (&self.reader).read(&mut buffer) {
+                if n == 0 {
+                    break;
+                }
+            }
+        }
+
+        self.raised.set(raised);
+    }
+}
+
+/// Reflects the readiness of a socket on file descriptors that external event loops can wait on.
+pub struct FdNotifier {
+    send: Signal,
+    recv: Signal
+}
+
+impl FdNotifier {
+    pub fn new() -> io::Result<FdNotifier> {
+        Ok(FdNotifier {
+            send: try!(Signal::new()),
+            recv: try!(Signal::new())
+        })
+    }
+
+    pub fn send_fd(&self) -> RawFd {
+        self.send.as_raw_fd()
+    }
+
+    pub fn recv_fd(&self) -> RawFd {
+        self.recv.as_raw_fd()
+    }
+}
+
+impl Notify for FdNotifier {
+    fn on_activity(&self) {}
+    fn on_can_send(&self, ready: bool) {
+        self.send.set(ready)
+    }
+    fn on_can_recv(&self, ready: bool) {
+        self.recv.set(ready)
+    }
+}
+
@@ -7,8 +7,13 @@
 pub mod session;
 pub mod socket;
 pub mod endpoint;
+pub mod context;
 pub mod device;
 pub mod probe;
+#[cfg(feature = "async")]
+pub mod async;
+#[cfg(unix)]
+pub mod fd;
 
 use std::sync::mpsc;
 use std::io;
@@ -8,6 +8,7 @@ use std::collections::HashMap;
 use std::io;
 use std::thread;
 use std::sync::mpsc;
+use std::time::Duration;
 
 use mio;
 
@@ -15,6 +16,7 @@ use super::*;
 use transport::Transport;
 use reactor;
 use reactor::dispatcher;
+use reactor::timer::TimerConfig;
 use core::session::{Request, Reply};
 use core::socket::{Protocol, ProtocolCtor};
 use core;
@@ -49,14 +51,16 @@ impl RequestSender {
 /// Creates the session and starts the I/O thread.
 #[derive(Default)]
 pub struct SessionBuilder {
-    transports: HashMap<String, Box<Transport + Send>>
+    transports: HashMap<String, Box<Transport + Send>>,
+    timer: TimerConfig
 }
 
 impl SessionBuilder {
 
     pub fn new() -> SessionBuilder {
         SessionBuilder {
-            transports: HashMap::new()
+            transports: HashMap::new(),
+            timer: TimerConfig::default()
         }
     }
 
@@ -66,13 +70,47 @@ impl SessionBuilder {
         self
     }
 
+    /// Sets the resolution of the timer driving the send, recv and protocol timeouts.
+    /// Deadlines are rounded up to the next tick, default value is 25ms.
+    /// The timer wheel counts in milliseconds, see `with_high_resolution_timer` for shorter deadlines.
+    pub fn with_timer_tick(mut self, tick: Duration) -> SessionBuilder {
+        self.timer.tick = tick;
+        self
+    }
+
+    /// Sets the number of slots of the timer wheel, default value is 1024.
+    /// The wheel covers `tick * slots` before deadlines start sharing slots with later ones.
+    pub fn with_timer_slots(mut self, slots: usize) -> SessionBuilder {
+        self.timer.slots = slots;
+        self
+    }
+
+    /// Sets the maximum number of pending timeouts, default value is 8192.
+    /// Operations needing a timeout fail once it is reached.
+    /// With the high resolution timer, this is only the initially reserved size.
+    pub fn with_timer_capacity(mut self, capacity: usize) -> SessionBuilder {
+        self.timer.capacity = capacity;
+        self
+    }
+
+    /// Replaces the timer wheel by a timer honouring the exact deadlines, down to the microsecond.
+    /// The I/O thread busy polls during the last millisecond before each deadline,
+    /// trading some CPU for latency. The tick and slots settings are then ignored.
+    pub fn with_high_resolution_timer(mut self) -> SessionBuilder {
+        self.timer.high_resolution = true;
+        self
+    }
+
     pub fn build(self) -> io::Result<Session> {
+        if !self.timer.high_resolution && self.timer.tick < Duration::from_millis(1) {
+            return Err(invalid_input_io_error("timer tick must be at least 1ms"));
+        }
 
         let (reply_tx, reply_rx) = mpsc::channel();
         let (request_tx, request_rx) = mio::channel::channel();
         let session = Session::new(RequestSender::new(request_tx), reply_rx);
 
-        thread::spawn(move || dispatcher::Dispatcher::dispatch(self.transports, request_rx, reply_tx));
+        thread::spawn(move || dispatcher::Dispatcher::dispatch(self.transports, self.timer, request_rx, reply_tx));
 
         Ok(session)
     }
@@ -113,6 +151,21 @@ impl Session {
         self.call(request, |reply| self.on_create_socket_reply(reply))
     }
 
+    /// Creates a raw socket with the specified protocol.
+    /// Raw sockets do not enforce the protocol state machine: requests are not resent,
+    /// surveys have no deadline and a reply can be sent at any time.
+    /// Instead, the routing information is exposed in the [header](struct.Message.html#method.get_header)
+    /// of received messages and must be set in the header of sent messages.
+    /// This is the building block of custom devices and brokers.
+    pub fn create_raw_socket<T>(&mut self) -> io::Result<socket::Socket>
+    where T : Protocol + From<mpsc::Sender<core::socket::Reply>> + 'static
+    {
+        let protocol_ctor = Session::create_raw_protocol_ctor::<T>();
+        let request = Request::CreateSocket(protocol_ctor);
+
+        self.call(request, |reply| self.on_create_socket_reply(reply))
+    }
+
     fn create_protocol_ctor<T>() -> ProtocolCtor 
     where T : Protocol + From<mpsc::Sender<core::socket::Reply>> + 'static
     {
@@ -121,6 +174,18 @@ impl Session {
         })
     }
 
+    fn create_raw_protocol_ctor<T>() -> ProtocolCtor 
+    where T : Protocol + From<mpsc::Sender<core::socket::Reply>> + 'static
+    {
+        Box::new(move |sender: mpsc::Sender<core::socket::Reply>| {
+            let mut protocol = T::from(sender);
+
+            protocol.set_raw();
+
+            Box::new(protocol) as Box<Protocol>
+        })
+    }
+
     fn on_create_socket_reply(&self, reply: Reply) -> io::Result<socket::Socket> {
         match reply {
             Reply::SocketCreated(id, rx) => {
@@ -7,12 +7,17 @@
 use std::sync::mpsc;
 use std::io;
 use std::time::Duration;
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
 
 use super::*;
 use reactor;
 use core::{SocketId, Message, PollReq};
-use core::socket::{Request, Reply};
-use core::config::ConfigOption;
+use core::socket::{Request, Reply, Notify, TopicList};
+use core::endpoint::{EndpointState, EndpointEvent};
+use core::stats::Statistics;
+use core::survey::SurveyResponses;
+use core::config::{ConfigOption, ConfigOptionName};
 use core;
 use io_error::*;
 
@@ -35,6 +40,9 @@ impl RequestSender {
     fn child_sender(&self, eid: core::EndpointId) -> endpoint::RequestSender {
         endpoint::RequestSender::new(self.req_tx.clone(), self.socket_id, eid)
     }
+    fn context_sender(&self) -> context::RequestSender {
+        context::RequestSender::new(self.req_tx.clone(), self.socket_id)
+    }
     fn send(&self, req: Request) -> io::Result<()> {
         self.req_tx.send(reactor::Request::Socket(self.socket_id, req)).map_err(from_send_error)
     }
@@ -46,7 +54,9 @@ impl RequestSender {
 /// Applications can have more than one Socket open at a time.
 pub struct Socket {
     request_sender: RequestSender,
-    reply_receiver: ReplyReceiver
+    reply_receiver: ReplyReceiver,
+    #[cfg(unix)]
+    fds: Option<(RawFd, RawFd)>
 }
 
 impl Socket {
@@ -54,7 +64,9 @@ impl Socket {
     pub fn new(request_tx: RequestSender, reply_rx: ReplyReceiver) -> Socket {
         Socket {
             request_sender: request_tx,
-            reply_receiver: reply_rx
+            reply_receiver: reply_rx,
+            #[cfg(unix)]
+            fds: None
         }
     }
 
@@ -95,9 +107,9 @@ impl Socket {
 
     fn on_connect_reply(&self, reply: Reply) -> io::Result<endpoint::Endpoint> {
         match reply {
-            Reply::Connect(id) => {
+            Reply::Connect(id, addr) => {
                 let request_tx = self.request_sender.child_sender(id);
-                let ep = endpoint::Endpoint::new(request_tx, true);
+                let ep = endpoint::Endpoint::new(request_tx, true, addr);
                 
                 Ok(ep)
             },
@@ -127,9 +139,9 @@ impl Socket {
 
     fn on_bind_reply(&self, reply: Reply) -> io::Result<endpoint::Endpoint> {
         match reply {
-            Reply::Bind(id) => {
+            Reply::Bind(id, addr) => {
                 let request_tx = self.request_sender.child_sender(id);
-                let ep = endpoint::Endpoint::new(request_tx, false);
+                let ep = endpoint::Endpoint::new(request_tx, false, addr);
                 
                 Ok(ep)
             },
@@ -138,6 +150,21 @@ impl Socket {
         }
     }
 
+    /// Tells whether the endpoint is connecting, active, waiting to be re-established or closed.
+    pub fn get_endpoint_state(&mut self, endpoint: &endpoint::Endpoint) -> io::Result<EndpointState> {
+        let request = Request::GetEndpointState(endpoint.id());
+
+        self.call(request, |reply| self.on_get_endpoint_state_reply(reply))
+    }
+
+    fn on_get_endpoint_state_reply(&self, reply: Reply) -> io::Result<EndpointState> {
+        match reply {
+            Reply::EndpointState(state) => Ok(state),
+            Reply::Err(e) => Err(e),
+            _ => self.unexpected_reply()
+        }
+    }
+
 /*****************************************************************************/
 /*                                                                           */
 /* send                                                                      */
@@ -216,6 +243,66 @@ impl Socket {
         }
     }
 
+/*****************************************************************************/
+/*                                                                           */
+/* exchange                                                                  */
+/*                                                                           */
+/*****************************************************************************/
+
+    /// Sends a request and receives its reply, the reply is the one matching the request id.
+    /// The request is resent like with send, until the timeout elapses, and the timeouts of the socket do not apply.
+    /// Only supported by `Req` sockets, and not in raw mode.
+    pub fn request(&mut self, buffer: Vec<u8>, timeout: Option<Duration>) -> io::Result<Vec<u8>> {
+        self.request_msg(Message::from_body(buffer), timeout).map(|msg| msg.into())
+    }
+
+    /// Sends a request message and receives its reply, see the request method.
+    pub fn request_msg(&mut self, msg: Message, timeout: Option<Duration>) -> io::Result<Message> {
+        let request = Request::Exchange(msg, timeout);
+
+        self.call(request, |reply| self.on_recv_reply(reply))
+    }
+
+    /// Receives the requests, passes them to the handler and sends back what it returns, until one of the steps fails.
+    /// Meant for `Rep` sockets: other protocols report an error when receiving or replying is not allowed.
+    pub fn serve<F>(&mut self, mut handler: F) -> io::Result<()> where F : FnMut(Message) -> io::Result<Message> {
+        loop {
+            let request = try!(self.recv_msg());
+            let reply = try!(handler(request));
+
+            try!(self.send_msg(reply));
+        }
+    }
+
+/*****************************************************************************/
+/*                                                                           */
+/* survey                                                                    */
+/*                                                                           */
+/*****************************************************************************/
+
+    /// Sends a survey and waits for the `SurveyDeadline` to collect the responses,
+    /// along with the number of peers the survey was sent to.
+    /// Stops early once `expected` responses arrived, or once every peer has answered.
+    /// Only supported by `Surveyor` sockets, and not in raw mode.
+    pub fn survey(&mut self, buffer: Vec<u8>, expected: Option<usize>) -> io::Result<SurveyResponses> {
+        self.survey_msg(Message::from_body(buffer), expected)
+    }
+
+    /// Sends a survey message, see the survey method.
+    pub fn survey_msg(&mut self, msg: Message, expected: Option<usize>) -> io::Result<SurveyResponses> {
+        let request = Request::Survey(msg, expected);
+
+        self.call(request, |reply| self.on_survey_reply(reply))
+    }
+
+    fn on_survey_reply(&self, reply: Reply) -> io::Result<SurveyResponses> {
+        match reply {
+            Reply::Survey(result) => Ok(result),
+            Reply::Err(e)         => Err(e),
+            _ => self.unexpected_reply()
+        }
+    }
+
 /*****************************************************************************/
 /*                                                                           */
 /* options                                                                   */
@@ -264,6 +351,22 @@ impl Socket {
         self.set_option(ConfigOption::TcpNoDelay(value))
     }
 
+    /// This option, when set to `true`, makes each received message describe the connection it comes from,
+    /// see [Message::get_peer](struct.Message.html#method.get_peer).
+    /// The description holds the endpoint id, the transport scheme and the remote address,
+    /// plus the peer process credentials for `ipc` on linux.
+    /// Default value is `false`.
+    pub fn set_recv_peer_info(&mut self, value: bool) -> io::Result<()> {
+        self.set_option(ConfigOption::RecvPeerInfo(value))
+    }
+
+    /// Sets how long the socket should try to send pending outbound messages after `drop` have been called.
+    /// Dropping the socket blocks until they are sent or the duration elapses.
+    /// Zero duration means that pending messages are discarded. Default value is 1 second.
+    pub fn set_linger(&mut self, linger: Duration) -> io::Result<()> {
+        self.set_option(ConfigOption::Linger(linger))
+    }
+
     /// Sets a socket option.
     /// See [ConfigOption](core/config/enum.ConfigOption.html) to get the list of options.
     pub fn set_option(&mut self, cfg_opt: ConfigOption) -> io::Result<()> {
@@ -280,6 +383,177 @@ impl Socket {
         }
     }
 
+    /// Reads the current value of a socket option, including the protocol specific ones.
+    /// See [ConfigOptionName](core/config/enum.ConfigOptionName.html) to get the list of options.
+    pub fn get_option(&mut self, name: ConfigOptionName) -> io::Result<ConfigOption> {
+        let request = Request::GetOption(name);
+
+        self.call(request, |reply| self.on_get_option_reply(reply))
+    }
+
+    fn on_get_option_reply(&self, reply: Reply) -> io::Result<ConfigOption> {
+        match reply {
+            Reply::GetOption(opt) => Ok(opt),
+            Reply::Err(e)         => Err(e),
+            _ => self.unexpected_reply()
+        }
+    }
+
+    /// Lists the topics subscribed by a `Sub` socket, sorted.
+    /// Subscriptions are changed with the `Subscribe` and `Unsubscribe` options.
+    pub fn get_subscriptions(&mut self) -> io::Result<Vec<String>> {
+        self.get_topics(TopicList::Subscriptions)
+    }
+
+    /// Lists the exact topics subscribed by a `Sub` socket, sorted.
+    /// They are changed with the `SubscribeExact` and `UnsubscribeExact` options.
+    pub fn get_exact_subscriptions(&mut self) -> io::Result<Vec<String>> {
+        self.get_topics(TopicList::ExactSubscriptions)
+    }
+
+    /// Lists the patterns subscribed by a `Sub` socket, sorted.
+    /// They are changed with the `SubscribePattern` and `UnsubscribePattern` options.
+    pub fn get_pattern_subscriptions(&mut self) -> io::Result<Vec<String>> {
+        self.get_topics(TopicList::PatternSubscriptions)
+    }
+
+    /// Lists the topics excluded by a `Sub` socket, sorted.
+    /// They are changed with the `Exclude` and `Unexclude` options.
+    pub fn get_exclusions(&mut self) -> io::Result<Vec<String>> {
+        self.get_topics(TopicList::Exclusions)
+    }
+
+    fn get_topics(&mut self, list: TopicList) -> io::Result<Vec<String>> {
+        let request = Request::GetTopics(list);
+
+        self.call(request, |reply| self.on_get_topics_reply(reply))
+    }
+
+    fn on_get_topics_reply(&self, reply: Reply) -> io::Result<Vec<String>> {
+        match reply {
+            Reply::GetTopics(topics) => Ok(topics),
+            Reply::Err(e)            => Err(e),
+            _ => self.unexpected_reply()
+        }
+    }
+
+/*****************************************************************************/
+/*                                                                           */
+/* stats                                                                     */
+/*                                                                           */
+/*****************************************************************************/
+
+    /// Retrieves the counters describing the activity of the socket since its creation.
+    pub fn stats(&mut self) -> io::Result<Statistics> {
+        self.call(Request::GetStats, |reply| self.on_stats_reply(reply))
+    }
+
+    fn on_stats_reply(&self, reply: Reply) -> io::Result<Statistics> {
+        match reply {
+            Reply::Stats(stats) => Ok(stats),
+            Reply::Err(e)       => Err(e),
+            _ => self.unexpected_reply()
+        }
+    }
+
+/*****************************************************************************/
+/*                                                                           */
+/* monitor                                                                   */
+/*                                                                           */
+/*****************************************************************************/
+
+    /// Creates a channel reporting the connections established, accepted and lost by the socket endpoints.
+    /// Only the events happening after this call are reported, dropping the receiver stops the reporting.
+    pub fn monitor(&mut self) -> io::Result<mpsc::Receiver<EndpointEvent>> {
+        let (tx, rx) = mpsc::channel();
+
+        self.call(Request::AddMonitor(tx), |reply| self.on_monitor_reply(reply, rx))
+    }
+
+    fn on_monitor_reply(&self, reply: Reply, rx: mpsc::Receiver<EndpointEvent>) -> io::Result<mpsc::Receiver<EndpointEvent>> {
+        match reply {
+            Reply::AddMonitor => Ok(rx),
+            Reply::Err(e)     => Err(e),
+            _ => self.unexpected_reply()
+        }
+    }
+
+/*****************************************************************************/
+/*                                                                           */
+/* contexts                                                                  */
+/*                                                                           */
+/*****************************************************************************/
+
+    /// Opens a [context](struct.Context.html) on the socket, to run an exchange concurrently with the other contexts.
+    /// Only `Req`, `Rep` and `Surveyor` sockets support contexts, and not in raw mode.
+    pub fn open_context(&mut self) -> io::Result<context::Context> {
+        let (tx, rx) = mpsc::channel();
+
+        self.call(Request::OpenContext(tx), |reply| self.on_open_context_reply(reply, rx))
+    }
+
+    fn on_open_context_reply(&self, reply: Reply, rx: context::ReplyReceiver) -> io::Result<context::Context> {
+        match reply {
+            Reply::OpenContext(id) => Ok(context::Context::new(self.request_sender.context_sender(), rx, id)),
+            Reply::Err(e)          => Err(e),
+            _ => self.unexpected_reply()
+        }
+    }
+
+/*****************************************************************************/
+/*                                                                           */
+/* readiness                                                                 */
+/*                                                                           */
+/*****************************************************************************/
+
+    /// Retrieves a file descriptor that is readable when a message can be sent to the socket.
+    /// It is meant to be waited on by external event loops, and must never be read from, written to or closed.
+    /// Its lifetime is bound to the socket's one.
+    /// This is the equivalent of nanomsg's `NN_SNDFD` option.
+    #[cfg(unix)]
+    pub fn send_fd(&mut self) -> io::Result<RawFd> {
+        self.get_fds().map(|(send_fd, _)| send_fd)
+    }
+
+    /// Retrieves a file descriptor that is readable when a message can be received from the socket.
+    /// It is meant to be waited on by external event loops, and must never be read from, written to or closed.
+    /// Its lifetime is bound to the socket's one.
+    /// This is the equivalent of nanomsg's `NN_RCVFD` option.
+    #[cfg(unix)]
+    pub fn recv_fd(&mut self) -> io::Result<RawFd> {
+        self.get_fds().map(|(_, recv_fd)| recv_fd)
+    }
+
+    #[cfg(unix)]
+    fn get_fds(&mut self) -> io::Result<(RawFd, RawFd)> {
+        if let Some(fds) = self.fds {
+            return Ok(fds);
+        }
+
+        let notifier = try!(fd::FdNotifier::new());
+        let fds = (notifier.send_fd(), notifier.recv_fd());
+
+        try!(self.add_notifier(box notifier));
+
+        self.fds = Some(fds);
+        Ok(fds)
+    }
+
+    #[doc(hidden)]
+    pub fn add_notifier(&self, notifier: Box<Notify>) -> io::Result<()> {
+        let request = Request::AddNotifier(notifier);
+
+        self.call(request, |reply| self.on_add_notifier_reply(reply))
+    }
+
+    fn on_add_notifier_reply(&self, reply: Reply) -> io::Result<()> {
+        match reply {
+            Reply::AddNotifier => Ok(()),
+            Reply::Err(e) => Err(e),
+            _ => self.unexpected_reply()
+        }
+    }
+
 /*****************************************************************************/
 /*                                                                           */
 /* backend                                                                   */
@@ -294,14 +568,25 @@ impl Socket {
         self.send_request(request).and_then(|_| self.recv_reply())
     }
 
-    fn send_request(&self, request: Request) -> io::Result<()> {
+    #[doc(hidden)]
+    pub fn send_request(&self, request: Request) -> io::Result<()> {
         self.request_sender.send(request)
     }
 
-    fn recv_reply(&self) -> io::Result<Reply> {
+    #[doc(hidden)]
+    pub fn recv_reply(&self) -> io::Result<Reply> {
         self.reply_receiver.receive()
     }
 
+    #[doc(hidden)]
+    pub fn try_recv_reply(&self) -> io::Result<Option<Reply>> {
+        match self.reply_receiver.try_recv() {
+            Ok(reply) => Ok(Some(reply)),
+            Err(mpsc::TryRecvError::Empty) => Ok(None),
+            Err(mpsc::TryRecvError::Disconnected) => Err(other_io_error("evt channel closed"))
+        }
+    }
+
     fn unexpected_reply<T>(&self) -> io::Result<T> {
         Err(other_io_error("unexpected reply"))
     }
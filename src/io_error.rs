@@ -5,6 +5,7 @@
 // This file may not be copied, modified, or distributed except according to those terms.
 
 use std::error;
+use std::fmt;
 use std::io;
 
 use mio;
@@ -25,10 +26,43 @@ pub fn invalid_input_io_error<E>(msg: E) -> io::Error where E: Into<Box<error::E
     io::Error::new(io::ErrorKind::InvalidInput, msg)
 }
 
+pub fn addr_in_use_io_error<E>(msg: E) -> io::Error where E: Into<Box<error::Error + Send + Sync>> {
+    io::Error::new(io::ErrorKind::AddrInUse, msg)
+}
+
 pub fn timedout_io_error<E>(msg: E) -> io::Error where E: Into<Box<error::Error + Send + Sync>> {
     io::Error::new(io::ErrorKind::TimedOut, msg)
 }
 
+/// Error raised when the peer announces a protocol that is not the expected one.
+#[derive(Debug)]
+pub struct BadHandshake {
+    pub peer_id: Option<u16>
+}
+
+impl fmt::Display for BadHandshake {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "received bad handshake")
+    }
+}
+
+impl error::Error for BadHandshake {
+    fn description(&self) -> &str {
+        "received bad handshake"
+    }
+}
+
+pub fn bad_handshake_io_error(peer_id: Option<u16>) -> io::Error {
+    invalid_data_io_error(BadHandshake { peer_id: peer_id })
+}
+
+/// The protocol announced by the peer, when the error is caused by a bad handshake.
+pub fn get_bad_handshake_peer_id(err: &io::Error) -> Option<u16> {
+    err.get_ref()
+        .and_then(|e| e.downcast_ref::<BadHandshake>())
+        .and_then(|e| e.peer_id)
+}
+
 pub fn from_send_error<T>(send_error: mio::channel::SendError<T>) -> io::Error {
     match send_error {
         mio::channel::SendError::Io(e) => e,
@@ -10,7 +10,7 @@
 //! # Goals
 //!
 //! * Support for all of nanomsg's protocols.
-//! * Support for TCP and IPC transports.
+//! * Support for TCP, IPC, INPROC, WS and TLS transports.
 //! * Idiomatic rust API first, mimic the original C API second.
 //! * Extensibility: allow user code to define additional protocols and transports
 //!
@@ -24,6 +24,8 @@
 //! To plug the sockets, use the [connect](struct.Socket.html#method.connect) and [bind](struct.Socket.html#method.bind) socket methods.  
 //! Finally, use the socket methods [send](struct.Socket.html#method.send) and
 //! [recv](struct.Socket.html#method.recv) to exchange messages between sockets.  
+//! To write a custom device or broker, [raw sockets](struct.Session.html#method.create_raw_socket) expose the routing information in the message header.  
+//! With the `async` feature, a socket can be [converted](struct.Socket.html#method.into_async) into a futures `Sink` and `Stream`.  
 //! When in doubts, please refer to the [nanomsg manual](http://nanomsg.org/v1.0.0/nanomsg.7.html).  
 //!
 //! # Example
@@ -55,6 +57,7 @@
 #![feature(fnbox)]
 #![feature(stmt_expr_attributes)]
 #![feature(conservative_impl_trait)]
+#![cfg_attr(test, feature(test))]
 
 //#![feature(plugin)]
 //#![plugin(clippy)]
@@ -68,6 +71,14 @@ extern crate byteorder;
 extern crate mio;
 extern crate mio_uds;
 extern crate time;
+#[cfg(target_os = "linux")]
+extern crate libc;
+#[cfg(feature = "tls")]
+extern crate openssl;
+#[cfg(feature = "async")]
+extern crate futures;
+#[cfg(test)]
+extern crate test;
 
 pub mod core;
 pub mod proto;
@@ -81,6 +92,8 @@ mod facade;
 #[doc(hidden)]
 mod sequence;
 #[doc(hidden)]
+mod xorshift;
+#[doc(hidden)]
 mod io_error;
 
 pub use facade::session::SessionBuilder;
@@ -89,14 +102,25 @@ pub use facade::socket::Socket;
 pub use facade::device::Device;
 pub use facade::probe::Probe;
 pub use facade::endpoint::Endpoint;
+pub use facade::context::Context;
+#[cfg(feature = "async")]
+pub use facade::async::AsyncSocket;
+pub use core::endpoint::{EndpointState, EndpointEvent, PeerInfo, PeerCredentials};
+pub use core::{EndpointId, ContextId};
+pub use core::stats::Statistics;
+pub use core::survey::SurveyResponses;
 pub use core::Message;
 pub use core::PollReq;
 pub use core::PollRes;
-pub use core::config::ConfigOption;
+pub use core::config::{ConfigOption, ConfigOptionName};
 
 pub use transport::tcp::Tcp;
 #[cfg(not(windows))]
 pub use transport::ipc::Ipc;
+pub use transport::inproc::Inproc;
+pub use transport::ws::Ws;
+#[cfg(feature = "tls")]
+pub use transport::tls::{Tls, TlsConfig};
 
 pub use proto::pair::Pair;
 pub use proto::publ::Pub;
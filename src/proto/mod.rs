@@ -18,12 +18,24 @@ pub mod resp;
 pub mod bus;
 
 mod priolist;
+mod subscription;
+mod pattern;
+mod trie;
 
 use core::Scheduled;
 
 #[doc(hidden)]
 pub type Timeout = Option<Scheduled>;
 
+/// The backtrace added by intermediate devices ends with the request or survey id,
+/// the only item having its most significant bit set.
+fn get_backtrace_len(payload: &[u8]) -> Option<usize> {
+    payload.chunks(4)
+        .take_while(|item| item.len() == 4)
+        .position(|item| item[0] & 0x80 != 0)
+        .map(|index| (index + 1) * 4)
+}
+
 /// **One-to-one protocol**   
 ///   
 /// Pair protocol is the simplest and least scalable scalability protocol. 
@@ -59,7 +71,9 @@ pub const PAIR:       u16 = (    16)    ;
 /// If the socket is subscribed to multiple topics, 
 /// message matching any of them will be delivered to the user.
 /// Since the filtering is performed on the Subscriber side, 
-/// all the messages from Publisher will be sent over the transport layer.
+/// all the messages from Publisher will be sent over the transport layer,
+/// unless [`ConfigOption::SubscriptionForwarding`](../enum.ConfigOption.html#variant.SubscriptionForwarding) 
+/// is enabled on both sides, in which case the Publisher only sends the messages a Subscriber is subscribed to.
 /// The entire message, including the topic, is delivered to the user.  
 ///   
 /// This socket is used to distribute messages to multiple destinations. Receive operation is not defined.
@@ -72,6 +86,9 @@ pub const PUB:        u16 = (2 * 16)    ;
 /// Only messages that the socket is subscribed to are received. 
 /// When the socket is created there are no subscriptions 
 /// and thus no messages will be received. 
+/// Besides the prefix subscriptions, messages can be filtered by exact topic or by pattern,
+/// and exclusions drop the messages a subscription would accept, 
+/// see [`ConfigOption::SubscribePattern`](../enum.ConfigOption.html#variant.SubscribePattern) for example.
 /// Send operation is not defined on this socket.
 pub const SUB:        u16 = (2 * 16) + 1;
 
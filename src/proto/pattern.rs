@@ -0,0 +1,144 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+const TERMINATOR: u8 = 0;
+const ONE_SEGMENT: &'static [u8] = b"*";
+const ANY_SEGMENTS: &'static [u8] = b"**";
+
+/// The topic of a message is its body up to the first NUL byte, or the whole body.
+pub fn topic_of(body: &[u8]) -> &[u8] {
+    match body.iter().position(|&b| b == TERMINATOR) {
+        Some(end) => &body[..end],
+        None => body
+    }
+}
+
+/// Splits a pattern or a topic in segments, so it is only done once for all the patterns.
+pub fn split(bytes: &[u8], delimiter: u8) -> Vec<&[u8]> {
+    bytes.split(|&b| b == delimiter).collect()
+}
+
+/// Tells whether the topic matches the pattern, segment by segment.
+/// A `*` segment matches any single segment, a `**` segment matches any number of segments.
+pub fn matches<P : AsRef<[u8]>>(pattern: &[P], topic: &[&[u8]]) -> bool {
+    // Tracks which topic prefixes are matched by each pattern prefix, so a pattern
+    // with several `**` segments does not try every way of splitting the topic
+    let mut matched = vec![false; topic.len() + 1];
+    let mut previous: Option<&[u8]> = None;
+
+    matched[0] = true;
+
+    for segment in pattern.iter().map(|segment| segment.as_ref()) {
+        // Adjacent `**` segments match the same topics as a single one
+        if segment == ANY_SEGMENTS && previous == Some(ANY_SEGMENTS) {
+            continue;
+        }
+
+        let mut next = vec![false; topic.len() + 1];
+
+        if segment == ANY_SEGMENTS {
+            next[0] = matched[0];
+            for i in 1..topic.len() + 1 {
+                next[i] = matched[i] || next[i - 1];
+            }
+        } else {
+            for i in 1..topic.len() + 1 {
+                next[i] = matched[i - 1] && (segment == ONE_SEGMENT || topic[i - 1] == segment);
+            }
+        }
+
+        matched = next;
+        previous = Some(segment);
+    }
+
+    matched[topic.len()]
+}
+
+/// Returns the part of the pattern before its first wildcard segment,
+/// every topic matching the pattern starts with it.
+/// A `**` segment may match no segment at all, so the delimiter before it is left out.
+pub fn literal_prefix(pattern: &[u8], delimiter: u8) -> &[u8] {
+    let mut end = 0;
+
+    for segment in pattern.split(|&b| b == delimiter) {
+        if segment == ONE_SEGMENT {
+            return &pattern[..end];
+        }
+        if segment == ANY_SEGMENTS {
+            return &pattern[..end.saturating_sub(1)];
+        }
+
+        end += segment.len() + 1;
+    }
+
+    pattern
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn matches_topic(pattern: &[u8], topic: &[u8], delimiter: u8) -> bool {
+        matches(&split(pattern, delimiter), &split(topic, delimiter))
+    }
+
+    #[test]
+    fn topic_ends_at_the_first_nul_byte() {
+        assert_eq!(b"a.b", topic_of(b"a.b\0c\0d"));
+        assert_eq!(b"a.b", topic_of(b"a.b"));
+        assert_eq!(b"", topic_of(b"\0a.b"));
+    }
+
+    #[test]
+    fn pattern_without_wildcard_matches_the_same_topic() {
+        assert!(matches_topic(b"prices.usd.eur", b"prices.usd.eur", b'.'));
+        assert!(!matches_topic(b"prices.usd.eur", b"prices.usd", b'.'));
+        assert!(!matches_topic(b"prices.usd", b"prices.usd.eur", b'.'));
+    }
+
+    #[test]
+    fn star_matches_a_single_segment() {
+        assert!(matches_topic(b"prices.*.eur", b"prices.usd.eur", b'.'));
+        assert!(matches_topic(b"prices.*.eur", b"prices..eur", b'.'));
+        assert!(!matches_topic(b"prices.*.eur", b"prices.usd.gbp.eur", b'.'));
+        assert!(!matches_topic(b"prices.*.eur", b"prices.eur", b'.'));
+        assert!(matches_topic(b"prices/*/eur", b"prices/usd/eur", b'/'));
+        assert!(!matches_topic(b"prices/*/eur", b"prices.usd.eur", b'/'));
+    }
+
+    #[test]
+    fn double_star_matches_any_number_of_segments() {
+        assert!(matches_topic(b"prices.**", b"prices", b'.'));
+        assert!(matches_topic(b"prices.**", b"prices.usd.eur", b'.'));
+        assert!(matches_topic(b"**.eur", b"prices.usd.eur", b'.'));
+        assert!(matches_topic(b"prices.**.eur", b"prices.eur", b'.'));
+        assert!(!matches_topic(b"prices.**.eur", b"prices.usd.gbp", b'.'));
+        assert!(matches_topic(b"prices.**.**.eur", b"prices.eur", b'.'));
+    }
+
+    #[test]
+    fn many_double_stars_do_not_backtrack_exponentially() {
+        let pattern = vec![&b"**.a"[..]; 30].join(&b'.');
+        let topic = vec![&b"a"[..]; 60].join(&b'.');
+        let mut mismatch = topic.clone();
+
+        mismatch.extend_from_slice(b".b");
+
+        assert!(matches_topic(&pattern, &topic, b'.'));
+        assert!(!matches_topic(&pattern, &mismatch, b'.'));
+    }
+
+    #[test]
+    fn literal_prefix_stops_at_the_first_wildcard() {
+        assert_eq!(b"prices.", literal_prefix(b"prices.*.eur", b'.'));
+        assert_eq!(b"prices.usd", literal_prefix(b"prices.usd.**", b'.'));
+        assert_eq!(b"prices.", literal_prefix(b"prices.*.**", b'.'));
+        assert_eq!(b"", literal_prefix(b"*.eur", b'.'));
+        assert_eq!(b"", literal_prefix(b"**.eur", b'.'));
+        assert_eq!(b"prices.usd", literal_prefix(b"prices.usd", b'.'));
+    }
+}
@@ -84,9 +84,15 @@ impl Priolist {
     fn remove_at_index(&mut self, index: usize) {
         let item = self.items.swap_remove(index);
         let priority = item.priority;
+        let moved = self.len();
 
         if self.current == Some((index, priority)) {
             self.compute_next(index, priority);
+        } else if let Some((cur_idx, cur_prio)) = self.current {
+            // the last item took the place of the removed one
+            if cur_idx == moved {
+                self.set_current(index, cur_prio);
+            }
         }
     }
 
@@ -416,4 +422,19 @@ mod tests {
         priolist.activate(&three);
         assert_eq!(Some(three), priolist.pop());
     }
+
+    #[test]
+    fn remove_keeps_track_of_the_current_moved_in_its_place() {
+        let mut priolist = Priolist::new();
+        let one = EndpointId::from(0);
+        let two = EndpointId::from(1);
+
+        priolist.insert(one, 8);
+        priolist.insert(two, 8);
+
+        priolist.activate(&two);
+        priolist.remove(&one);
+        assert_eq!(Some(two), priolist.pop());
+        assert_eq!(None, priolist.pop());
+    }
 }
\ No newline at end of file
@@ -4,21 +4,31 @@
 // or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
 // This file may not be copied, modified, or distributed except according to those terms.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::rc::Rc;
 use std::sync::mpsc::Sender;
+use std::io;
 
 use core::{EndpointId, Message};
 use core::socket::{Protocol, Reply};
+use core::config::{ConfigOption, ConfigOptionName};
 use core::endpoint::Pipe;
 use core::context::{Context, Event};
+use super::subscription::{Forwarded, Filter};
 use super::{Timeout, PUB, SUB};
 use io_error::*;
 
 pub struct Pub {
     reply_tx: Sender<Reply>,
     pipes: HashMap<EndpointId, Pipe>,
-    bc: HashSet<EndpointId>
+    bc: HashSet<EndpointId>,
+    is_raw: bool,
+    forwarding: bool,
+    readable: HashSet<EndpointId>,
+    filters: HashMap<EndpointId, Filter>,
+    topics: HashMap<Vec<u8>, usize>,
+    forwarded: VecDeque<Message>,
+    recv_pending: Option<Timeout>
 }
 
 /*****************************************************************************/
@@ -32,7 +42,125 @@ impl From<Sender<Reply>> for Pub {
         Pub {
             reply_tx: tx,
             pipes: HashMap::new(),
-            bc: HashSet::new()
+            bc: HashSet::new(),
+            is_raw: false,
+            forwarding: false,
+            readable: HashSet::new(),
+            filters: HashMap::new(),
+            topics: HashMap::new(),
+            forwarded: VecDeque::new(),
+            recv_pending: None
+        }
+    }
+}
+
+impl Pub {
+    fn accept(&self, eid: &EndpointId, msg: &Message) -> bool {
+        self.filters.get(eid).map_or(true, |filter| filter.accept(msg))
+    }
+
+    fn recv_from(&mut self, ctx: &mut Context, eid: EndpointId) {
+        self.pipes.get_mut(&eid).map(|pipe| pipe.recv(ctx));
+    }
+
+    fn set_forwarding(&mut self, ctx: &mut Context, forwarding: bool) {
+        if forwarding == self.forwarding {
+            return;
+        }
+
+        if forwarding {
+            let eids: Vec<EndpointId> = self.readable.drain().collect();
+
+            self.forwarding = true;
+            for eid in eids {
+                self.recv_from(ctx, eid);
+            }
+            for _ in 0..self.pipes.len() {
+                self.on_topic_changed(ctx, Forwarded::Subscribe(Vec::new()));
+            }
+        } else {
+            let eids: Vec<EndpointId> = self.pipes.keys().cloned().collect();
+
+            for eid in eids {
+                self.remove_filter(ctx, eid);
+            }
+            self.forwarding = false;
+        }
+    }
+
+    // Until a pipe forwards its subscriptions, it accepts every message,
+    // as if it had subscribed to the empty topic
+    fn remove_filter(&mut self, ctx: &mut Context, eid: EndpointId) {
+        if !self.forwarding {
+            return;
+        }
+
+        match self.filters.remove(&eid) {
+            Some(mut filter) => for topic in filter.drain() {
+                self.on_topic_changed(ctx, Forwarded::Unsubscribe(topic));
+            },
+            None => self.on_topic_changed(ctx, Forwarded::Unsubscribe(Vec::new()))
+        }
+    }
+
+    fn on_forwarded(&mut self, ctx: &mut Context, eid: EndpointId, change: Forwarded) {
+        let is_first = !self.filters.contains_key(&eid);
+        let changed = self.filters.entry(eid).or_insert_with(Filter::default).apply(&change);
+
+        if changed {
+            self.on_topic_changed(ctx, change);
+        }
+        if is_first {
+            self.on_topic_changed(ctx, Forwarded::Unsubscribe(Vec::new()));
+        }
+    }
+
+    // A device passes a topic along only when the first peer subscribes to it
+    // or when the last one unsubscribes from it
+    fn on_topic_changed(&mut self, ctx: &mut Context, change: Forwarded) {
+        if !self.is_raw {
+            return;
+        }
+
+        let count = match change {
+            Forwarded::Subscribe(ref topic) => {
+                let count = self.topics.entry(topic.clone()).or_insert(0);
+
+                *count += 1;
+                *count
+            },
+            Forwarded::Unsubscribe(ref topic) => match self.topics.get_mut(topic) {
+                Some(count) => { *count -= 1; *count },
+                None => return
+            }
+        };
+
+        match change {
+            Forwarded::Subscribe(_) if count == 1 => self.on_forwarded_recv(ctx, change.encode()),
+            Forwarded::Unsubscribe(topic) if count == 0 => {
+                self.topics.remove(&topic);
+                self.on_forwarded_recv(ctx, Forwarded::Unsubscribe(topic).encode())
+            },
+            _ => {}
+        }
+    }
+
+    fn on_forwarded_recv(&mut self, ctx: &mut Context, msg: Message) {
+        match self.recv_pending.take() {
+            Some(timeout) => self.on_recv_ack_msg(ctx, timeout, msg),
+            None => {
+                let was_recv_ready = !self.forwarded.is_empty();
+
+                self.forwarded.push_back(msg);
+                ctx.check_recv_ready_change(was_recv_ready, true);
+            }
+        }
+    }
+
+    fn on_recv_ack_msg(&mut self, ctx: &mut Context, timeout: Timeout, msg: Message) {
+        let _ = self.reply_tx.send(Reply::Recv(msg));
+        if let Some(sched) = timeout {
+            ctx.cancel(sched);
         }
     }
 }
@@ -47,24 +175,35 @@ impl Protocol for Pub {
     fn id(&self)      -> u16 { PUB }
     fn peer_id(&self) -> u16 { SUB }
 
-    fn add_pipe(&mut self, _: &mut Context, eid: EndpointId, pipe: Pipe) {
+    fn add_pipe(&mut self, ctx: &mut Context, eid: EndpointId, pipe: Pipe) {
         self.pipes.insert(eid, pipe);
+        if self.forwarding {
+            self.on_topic_changed(ctx, Forwarded::Subscribe(Vec::new()));
+        }
     }
     fn remove_pipe(&mut self, ctx: &mut Context, eid: EndpointId) -> Option<Pipe> {
         self.bc.remove(&eid);
         if self.bc.is_empty() {
             ctx.raise(Event::CanSend(false));
         }
+        self.readable.remove(&eid);
+        if self.pipes.contains_key(&eid) {
+            self.remove_filter(ctx, eid);
+        }
         self.pipes.remove(&eid)
     }
     fn send(&mut self, ctx: &mut Context, msg: Message, timeout: Timeout) {
+        let ids: Vec<EndpointId> = self.bc.iter().filter(|id| self.accept(id, &msg)).cloned().collect();
         let msg = Rc::new(msg);
 
-        for id in self.bc.drain() {
+        for id in ids {
+            self.bc.remove(&id);
             self.pipes.get_mut(&id).map(|pipe| pipe.send(ctx, msg.clone()));
         }
 
-        ctx.raise(Event::CanSend(false));
+        if self.bc.is_empty() {
+            ctx.raise(Event::CanSend(false));
+        }
 
         let _ = self.reply_tx.send(Reply::Send);
         if let Some(sched) = timeout {
@@ -82,23 +221,69 @@ impl Protocol for Pub {
         self.bc.insert(eid);
     }
     fn recv(&mut self, ctx: &mut Context, timeout: Timeout) {
-        let error = other_io_error("Recv is not supported by pub protocol");
-        let _ = self.reply_tx.send(Reply::Err(error));
-        if let Some(sched) = timeout {
-            ctx.cancel(sched);
+        if !self.is_raw {
+            let error = other_io_error("Recv is not supported by pub protocol");
+            let _ = self.reply_tx.send(Reply::Err(error));
+            if let Some(sched) = timeout {
+                ctx.cancel(sched);
+            }
+            return;
+        }
+
+        match self.forwarded.pop_front() {
+            Some(msg) => {
+                self.on_recv_ack_msg(ctx, timeout, msg);
+                ctx.check_recv_ready_change(true, !self.forwarded.is_empty());
+            },
+            None => self.recv_pending = Some(timeout)
         }
     }
-    fn on_recv_ack(&mut self, _: &mut Context, _: EndpointId, _: Message) {
+    fn on_recv_ack(&mut self, ctx: &mut Context, eid: EndpointId, msg: Message) {
+        if !self.forwarding {
+            return;
+        }
+
+        match Forwarded::decode(&msg) {
+            Some(change) => self.on_forwarded(ctx, eid, change),
+            None => debug!("[{:?}] ignoring a message that is not a subscription from {:?}", ctx, eid)
+        }
     }
     fn on_recv_timeout(&mut self, _: &mut Context) {
+        if self.recv_pending.take().is_some() {
+            let error = timedout_io_error("Recv timed out");
+            let _ = self.reply_tx.send(Reply::Err(error));
+        }
     }
-    fn on_recv_ready(&mut self, _: &mut Context, _: EndpointId) {
+    fn on_recv_ready(&mut self, ctx: &mut Context, eid: EndpointId) {
+        if self.forwarding {
+            self.recv_from(ctx, eid);
+        } else {
+            self.readable.insert(eid);
+        }
     }
     fn is_send_ready(&self) -> bool {
         !self.bc.is_empty()
     }
     fn is_recv_ready(&self) -> bool {
-        false
+        !self.forwarded.is_empty()
+    }
+    fn set_option(&mut self, ctx: &mut Context, opt: ConfigOption) -> io::Result<()> {
+        match opt {
+            ConfigOption::SubscriptionForwarding(x) => Ok(self.set_forwarding(ctx, x)),
+            _ => Err(invalid_input_io_error("option not supported"))
+        }
+    }
+    fn get_option(&self, name: ConfigOptionName) -> io::Result<ConfigOption> {
+        match name {
+            ConfigOptionName::SubscriptionForwarding => Ok(ConfigOption::SubscriptionForwarding(self.forwarding)),
+            _ => Err(invalid_input_io_error("option not supported"))
+        }
+    }
+    fn set_raw(&mut self) {
+        self.is_raw = true;
+    }
+    fn on_device_plugged(&mut self, _: &mut Context) {
+        self.set_raw();
     }
     fn close(&mut self, ctx: &mut Context) {
         for (_, pipe) in self.pipes.drain() {
@@ -106,3 +291,122 @@ impl Protocol for Pub {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::mpsc;
+
+    use core::{EndpointId, Message};
+    use core::socket::{Protocol, Reply};
+    use core::config::ConfigOption;
+    use core::tests::*;
+
+    use super::*;
+
+    #[test]
+    fn when_forwarding_messages_are_only_sent_to_the_pipes_subscribed() {
+        let (tx, _) = mpsc::channel();
+        let mut publ = Pub::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid1 = EndpointId::from(1);
+        let eid2 = EndpointId::from(2);
+        let eid3 = EndpointId::from(3);
+
+        publ.set_option(&mut ctx, ConfigOption::SubscriptionForwarding(true)).unwrap();
+        publ.add_pipe(&mut ctx, eid1, new_test_pipe(eid1));
+        publ.add_pipe(&mut ctx, eid2, new_test_pipe(eid2));
+        publ.add_pipe(&mut ctx, eid3, new_test_pipe(eid3));
+        publ.on_recv_ready(&mut ctx, eid1);
+        publ.on_recv_ack(&mut ctx, eid1, Message::from_body(vec![1, 65]));
+        publ.on_recv_ready(&mut ctx, eid2);
+        publ.on_recv_ack(&mut ctx, eid2, Message::from_body(vec![0]));
+        publ.on_send_ready(&mut ctx, eid1);
+        publ.on_send_ready(&mut ctx, eid2);
+        publ.on_send_ready(&mut ctx, eid3);
+        publ.send(&mut ctx, Message::from_body(vec![66]), None);
+
+        assert!(publ.is_send_ready());
+
+        publ.send(&mut ctx, Message::from_body(vec![65, 66]), None);
+
+        let sensor = ctx_sensor.borrow();
+
+        sensor.assert_send_to(eid1, 1);
+        sensor.assert_send_to(eid2, 0);
+        sensor.assert_send_to(eid3, 1);
+    }
+
+    #[test]
+    fn raw_pub_can_recv_a_topic_when_the_first_pipe_subscribes_and_the_last_unsubscribes() {
+        let (tx, rx) = mpsc::channel();
+        let mut publ = Pub::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid1 = EndpointId::from(1);
+        let eid2 = EndpointId::from(2);
+
+        publ.set_raw();
+        publ.add_pipe(&mut ctx, eid1, new_test_pipe(eid1));
+        publ.add_pipe(&mut ctx, eid2, new_test_pipe(eid2));
+        publ.on_recv_ready(&mut ctx, eid1);
+        publ.on_recv_ready(&mut ctx, eid2);
+        publ.set_option(&mut ctx, ConfigOption::SubscriptionForwarding(true)).unwrap();
+        publ.on_recv_ack(&mut ctx, eid1, Message::from_body(vec![1, 65]));
+        publ.on_recv_ack(&mut ctx, eid2, Message::from_body(vec![1, 65]));
+        publ.recv(&mut ctx, None);
+        publ.recv(&mut ctx, None);
+        publ.recv(&mut ctx, None);
+
+        assert!(!publ.is_recv_ready());
+
+        publ.remove_pipe(&mut ctx, eid1);
+        publ.recv(&mut ctx, None);
+        publ.on_recv_timeout(&mut ctx);
+        publ.remove_pipe(&mut ctx, eid2);
+
+        assert!(publ.is_recv_ready());
+
+        publ.recv(&mut ctx, None);
+
+        let bodies: Vec<Option<Vec<u8>>> = rx.try_iter().map(|reply| match reply {
+            Reply::Recv(msg) => Some(msg.get_body().to_vec()),
+            _ => None
+        }).collect();
+
+        assert_eq!(vec![Some(vec![1]), Some(vec![1, 65]), Some(vec![0]), None, Some(vec![0, 65])], bodies);
+    }
+
+    #[test]
+    fn raw_pub_counts_the_pipes_without_filter_as_subscribed_to_everything() {
+        let (tx, rx) = mpsc::channel();
+        let mut publ = Pub::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid1 = EndpointId::from(1);
+        let eid2 = EndpointId::from(2);
+
+        publ.set_raw();
+        publ.set_option(&mut ctx, ConfigOption::SubscriptionForwarding(true)).unwrap();
+        publ.add_pipe(&mut ctx, eid1, new_test_pipe(eid1));
+        publ.add_pipe(&mut ctx, eid2, new_test_pipe(eid2));
+        publ.on_recv_ready(&mut ctx, eid1);
+        publ.on_recv_ack(&mut ctx, eid1, Message::from_body(vec![1, 65]));
+        publ.remove_pipe(&mut ctx, eid2);
+        publ.recv(&mut ctx, None);
+        publ.recv(&mut ctx, None);
+        publ.recv(&mut ctx, None);
+
+        assert!(!publ.is_recv_ready());
+
+        let bodies: Vec<Vec<u8>> = rx.try_iter().filter_map(|reply| match reply {
+            Reply::Recv(msg) => Some(msg.get_body().to_vec()),
+            _ => None
+        }).collect();
+
+        assert_eq!(vec![vec![1], vec![1, 65], vec![0]], bodies);
+    }
+}
@@ -4,13 +4,14 @@
 // or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
 // This file may not be copied, modified, or distributed except according to those terms.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::rc::Rc;
 use std::sync::mpsc::Sender;
+use std::io;
 
 use byteorder::*;
 
-use core::{EndpointId, Message};
+use core::{EndpointId, ContextId, Message};
 use core::socket::{Protocol, Reply};
 use core::endpoint::Pipe;
 use core::context::Context;
@@ -20,7 +21,9 @@ use io_error::*;
 
 pub struct Rep {
     inner: Inner,
-    state: Option<State>
+    state: Option<State>,
+    contexts: HashMap<ContextId, RepContext>,
+    contexts_on_hold: VecDeque<ContextId>
 }
 
 enum State {
@@ -39,7 +42,24 @@ struct Inner {
     sd: HashSet<EndpointId>,
     ttl: u8,
     backtrace: Vec<u8>,
-    is_device_item: bool
+    is_raw: bool
+}
+
+/// Request being served by a context, the pipes are shared with the socket
+/// and each context saves the backtrace of the request it received.
+struct RepContext {
+    reply_tx: Sender<Reply>,
+    exchange: Exchange,
+    backtrace: Vec<u8>
+}
+
+enum Exchange {
+    Idle,
+    Receiving(EndpointId, Timeout),
+    RecvOnHold(Timeout),
+    Active(EndpointId),
+    Sending(EndpointId, Timeout),
+    SendOnHold(EndpointId, Rc<Message>, Timeout)
 }
 
 /*****************************************************************************/
@@ -75,7 +95,9 @@ impl From<Sender<Reply>> for Rep {
     fn from(tx: Sender<Reply>) -> Rep {
         Rep {
             inner: Inner::new(tx),
-            state: Some(State::Idle)
+            state: Some(State::Idle),
+            contexts: HashMap::new(),
+            contexts_on_hold: VecDeque::new()
         }
     }
 }
@@ -102,6 +124,7 @@ impl Protocol for Rep {
 
         if pipe.is_some() {
             self.apply(ctx, |s, ctx, inner| s.on_pipe_removed(ctx, inner, eid));
+            self.apply_on_contexts(ctx, |rep, ctx| rep.on_context_pipe_removed(ctx, eid));
         }
 
         ctx.check_send_ready_change(was_send_ready, is_send_ready);
@@ -117,20 +140,29 @@ impl Protocol for Rep {
         }
     }
     fn on_send_ack(&mut self, ctx: &mut Context, eid: EndpointId) {
-        self.inner.clear_backtrace();
-        
+        if let Some(cid) = self.find_context_sending_to(eid) {
+            return self.apply_on_contexts(ctx, |rep, ctx| rep.on_context_send_ack(ctx, cid));
+        }
+
         self.apply(ctx, |s, ctx, inner| s.on_send_ack(ctx, inner, eid))
     }
     fn on_send_timeout(&mut self, ctx: &mut Context) {
         self.apply(ctx, |s, ctx, inner| s.on_send_timeout(ctx, inner))
     }
     fn on_send_ready(&mut self, ctx: &mut Context, eid: EndpointId) {
-        self.apply(ctx, |s, ctx, inner| s.on_send_ready(ctx, inner, eid))
+        self.apply(ctx, |s, ctx, inner| s.on_send_ready(ctx, inner, eid));
+        self.apply_on_contexts(ctx, |rep, ctx| rep.resume_context_reply(ctx, eid))
     }
     fn recv(&mut self, ctx: &mut Context, timeout: Timeout) {
         self.apply(ctx, |s, ctx, inner| s.recv(ctx, inner, timeout))
     }
     fn on_recv_ack(&mut self, ctx: &mut Context, eid: EndpointId, raw_msg: Message) {
+        if let Some(cid) = self.find_context_receiving_from(eid) {
+            let msg = self.inner.raw_msg_to_msg(raw_msg, eid);
+
+            return self.apply_on_contexts(ctx, |rep, ctx| rep.on_context_request(ctx, cid, msg));
+        }
+
         if let Some(msg) = self.inner.raw_msg_to_msg(raw_msg, eid) {
             self.apply(ctx, |s, ctx, inner| s.on_recv_ack(ctx, inner, eid, msg))
         } else {
@@ -141,10 +173,14 @@ impl Protocol for Rep {
         self.apply(ctx, |s, ctx, inner| s.on_recv_timeout(ctx, inner))
     }
     fn on_recv_ready(&mut self, ctx: &mut Context, eid: EndpointId) {
-        self.apply(ctx, |s, ctx, inner| s.on_recv_ready(ctx, inner, eid))
+        self.apply(ctx, |s, ctx, inner| s.on_recv_ready(ctx, inner, eid));
+        self.apply_on_contexts(ctx, |rep, ctx| rep.resume_contexts_on_hold(ctx))
+    }
+    fn set_raw(&mut self) {
+        self.inner.is_raw = true;
     }
     fn on_device_plugged(&mut self, _: &mut Context) {
-        self.inner.is_device_item = true;
+        self.set_raw();
     }
     fn is_send_ready(&self) -> bool {
         if let Some(ref state) = self.state {
@@ -160,11 +196,325 @@ impl Protocol for Rep {
             false
         }
     }
+    fn open_context(&mut self, cid: ContextId, reply_tx: Sender<Reply>) -> io::Result<()> {
+        if self.inner.is_raw {
+            return Err(invalid_input_io_error("contexts not supported in raw mode"));
+        }
+
+        self.contexts.insert(cid, RepContext::new(reply_tx));
+
+        Ok(())
+    }
+    fn close_context(&mut self, ctx: &mut Context, cid: ContextId) {
+        if let Some(mut context) = self.contexts.remove(&cid) {
+            context.cancel_timeouts(ctx);
+            self.contexts_on_hold.retain(|id| *id != cid);
+        }
+    }
+    fn send_in_context(&mut self, ctx: &mut Context, cid: ContextId, msg: Message, timeout: Timeout) {
+        self.apply_on_contexts(ctx, |rep, ctx| rep.send_context_reply(ctx, cid, msg, timeout))
+    }
+    fn on_context_send_timeout(&mut self, ctx: &mut Context, cid: ContextId) {
+        self.apply_on_contexts(ctx, |rep, _| rep.on_context_send_timeout_elapsed(cid))
+    }
+    fn recv_in_context(&mut self, ctx: &mut Context, cid: ContextId, timeout: Timeout) {
+        self.apply_on_contexts(ctx, |rep, ctx| rep.recv_context_request(ctx, cid, timeout))
+    }
+    fn on_context_recv_timeout(&mut self, ctx: &mut Context, cid: ContextId) {
+        self.apply_on_contexts(ctx, |rep, _| rep.on_context_recv_timeout_elapsed(cid))
+    }
     fn close(&mut self, ctx: &mut Context) {
+        self.contexts.clear();
+        self.contexts_on_hold.clear();
         self.inner.close(ctx)
     }
 }
 
+/*****************************************************************************/
+/*                                                                           */
+/* Contexts                                                                  */
+/*                                                                           */
+/*****************************************************************************/
+
+impl Rep {
+
+    fn apply_on_contexts<F>(&mut self, ctx: &mut Context, f: F) where F : FnOnce(&mut Rep, &mut Context) {
+        let was_send_ready = self.is_send_ready();
+        let was_recv_ready = self.is_recv_ready();
+
+        f(self, ctx);
+
+        let is_send_ready = self.is_send_ready();
+        let is_recv_ready = self.is_recv_ready();
+
+        ctx.check_send_ready_change(was_send_ready, is_send_ready);
+        ctx.check_recv_ready_change(was_recv_ready, is_recv_ready);
+    }
+
+    fn find_context_sending_to(&self, eid: EndpointId) -> Option<ContextId> {
+        self.contexts.iter()
+            .find(|&(_, context)| context.is_sending_to(eid))
+            .map(|(cid, _)| *cid)
+    }
+
+    fn find_context_receiving_from(&self, eid: EndpointId) -> Option<ContextId> {
+        self.contexts.iter()
+            .find(|&(_, context)| context.is_receiving_from(eid))
+            .map(|(cid, _)| *cid)
+    }
+
+    /// Like the socket, receiving a new request abandons the one not replied yet.
+    fn recv_context_request(&mut self, ctx: &mut Context, cid: ContextId, timeout: Timeout) {
+        self.contexts_on_hold.retain(|id| *id != cid);
+
+        let exchange = match self.inner.recv(ctx) {
+            Some(eid) => Exchange::Receiving(eid, timeout),
+            None => {
+                self.contexts_on_hold.push_back(cid);
+                Exchange::RecvOnHold(timeout)
+            }
+        };
+
+        if let Some(context) = self.contexts.get_mut(&cid) {
+            context.cancel_timeouts(ctx);
+            context.backtrace.clear();
+            context.exchange = exchange;
+        }
+    }
+
+    fn resume_contexts_on_hold(&mut self, ctx: &mut Context) {
+        while self.inner.is_recv_ready() {
+            let cid = match self.contexts_on_hold.pop_front() {
+                Some(cid) => cid,
+                None => break
+            };
+            let exchange = match self.contexts.get_mut(&cid) {
+                Some(context) => context.take_exchange(),
+                None => continue
+            };
+
+            if let Exchange::RecvOnHold(timeout) = exchange {
+                self.recv_context_request(ctx, cid, timeout);
+            } else if let Some(context) = self.contexts.get_mut(&cid) {
+                context.exchange = exchange;
+            }
+        }
+    }
+
+    fn on_context_request(&mut self, ctx: &mut Context, cid: ContextId, msg: Option<Message>) {
+        if let Some(context) = self.contexts.get_mut(&cid) {
+            if let Exchange::Receiving(eid, timeout) = context.take_exchange() {
+                if let Some(sched) = timeout {
+                    ctx.cancel(sched);
+                }
+
+                match msg {
+                    Some(mut msg) => {
+                        context.backtrace = msg.header.split_off(0);
+                        context.exchange = Exchange::Active(eid);
+                        context.send_reply(Reply::Recv(msg));
+                    },
+                    None => context.send_reply(Reply::Err(invalid_data_io_error("Received request without id")))
+                }
+            }
+        }
+    }
+
+    fn on_context_recv_timeout_elapsed(&mut self, cid: ContextId) {
+        if let Some(context) = self.contexts.get_mut(&cid) {
+            match context.take_exchange() {
+                Exchange::Receiving(..) |
+                Exchange::RecvOnHold(_) => context.send_reply(Reply::Err(timedout_io_error("Recv timed out"))),
+                other => context.exchange = other
+            }
+        }
+
+        self.contexts_on_hold.retain(|id| *id != cid);
+    }
+
+    fn send_context_reply(&mut self, ctx: &mut Context, cid: ContextId, msg: Message, timeout: Timeout) {
+        let eid = match self.contexts.get(&cid) {
+            Some(context) => match context.get_active_pipe() {
+                Some(eid) => eid,
+                None => return context.send_error(ctx, other_io_error("Can't send: no active request"), timeout)
+            },
+            None => return
+        };
+        let raw_msg = match self.contexts.get(&cid) {
+            Some(context) => match context.encode(msg) {
+                Some(raw_msg) => Rc::new(raw_msg),
+                None => return context.send_error(ctx, invalid_data_io_error("Sending without eid"), timeout)
+            },
+            None => return
+        };
+
+        let exchange = if self.inner.is_send_ready_to(&eid) && self.inner.send_to(ctx, raw_msg.clone(), eid) {
+            Exchange::Sending(eid, timeout)
+        } else {
+            Exchange::SendOnHold(eid, raw_msg, timeout)
+        };
+
+        if let Some(context) = self.contexts.get_mut(&cid) {
+            context.exchange = exchange;
+        }
+    }
+
+    fn resume_context_reply(&mut self, ctx: &mut Context, eid: EndpointId) {
+        if !self.inner.is_send_ready_to(&eid) {
+            return;
+        }
+
+        let cid = match self.contexts.iter().find(|&(_, context)| context.is_on_hold_for(eid)) {
+            Some((cid, _)) => *cid,
+            None => return
+        };
+
+        if let Some(context) = self.contexts.get_mut(&cid) {
+            if let Exchange::SendOnHold(_, msg, timeout) = context.take_exchange() {
+                if self.inner.send_to(ctx, msg.clone(), eid) {
+                    context.exchange = Exchange::Sending(eid, timeout);
+                } else {
+                    context.exchange = Exchange::SendOnHold(eid, msg, timeout);
+                }
+            }
+        }
+    }
+
+    fn on_context_send_ack(&mut self, ctx: &mut Context, cid: ContextId) {
+        if let Some(context) = self.contexts.get_mut(&cid) {
+            if let Exchange::Sending(_, timeout) = context.take_exchange() {
+                context.backtrace.clear();
+                context.send_reply(Reply::Send);
+                if let Some(sched) = timeout {
+                    ctx.cancel(sched);
+                }
+            }
+        }
+    }
+
+    fn on_context_send_timeout_elapsed(&mut self, cid: ContextId) {
+        if let Some(context) = self.contexts.get_mut(&cid) {
+            match context.take_exchange() {
+                Exchange::Sending(..) |
+                Exchange::SendOnHold(..) => context.send_reply(Reply::Err(timedout_io_error("Send timed out"))),
+                other => context.exchange = other
+            }
+        }
+    }
+
+    /// Requests received from the removed pipe cannot be replied anymore,
+    /// the pending replies fail and the contexts waiting to reply are reset.
+    fn on_context_pipe_removed(&mut self, ctx: &mut Context, eid: EndpointId) {
+        let cids: Vec<ContextId> = self.contexts.iter()
+            .filter(|&(_, context)| context.is_bound_to(eid))
+            .map(|(cid, _)| *cid)
+            .collect();
+
+        for cid in cids {
+            let exchange = match self.contexts.get_mut(&cid) {
+                Some(context) => {
+                    context.backtrace.clear();
+                    context.take_exchange()
+                },
+                None => continue
+            };
+
+            match exchange {
+                Exchange::Receiving(_, timeout) => self.recv_context_request(ctx, cid, timeout),
+                Exchange::Sending(_, timeout) |
+                Exchange::SendOnHold(_, _, timeout) => {
+                    let error = other_io_error("Can't send: requester disconnected");
+
+                    if let Some(context) = self.contexts.get(&cid) {
+                        context.send_error(ctx, error, timeout);
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+impl RepContext {
+    fn new(reply_tx: Sender<Reply>) -> RepContext {
+        RepContext {
+            reply_tx: reply_tx,
+            exchange: Exchange::Idle,
+            backtrace: Vec::new()
+        }
+    }
+
+    fn send_reply(&self, reply: Reply) {
+        let _ = self.reply_tx.send(reply);
+    }
+
+    fn send_error(&self, ctx: &mut Context, error: io::Error, timeout: Timeout) {
+        self.send_reply(Reply::Err(error));
+        if let Some(sched) = timeout {
+            ctx.cancel(sched);
+        }
+    }
+
+    fn take_exchange(&mut self) -> Exchange {
+        ::std::mem::replace(&mut self.exchange, Exchange::Idle)
+    }
+
+    /// Abandons the current operation, without notifying the facade.
+    fn cancel_timeouts(&mut self, ctx: &mut Context) {
+        match self.take_exchange() {
+            Exchange::Receiving(_, Some(sched)) |
+            Exchange::RecvOnHold(Some(sched)) |
+            Exchange::Sending(_, Some(sched)) |
+            Exchange::SendOnHold(_, _, Some(sched)) => ctx.cancel(sched),
+            _ => {}
+        }
+    }
+
+    fn encode(&self, msg: Message) -> Option<Message> {
+        let (_, body) = msg.split();
+
+        split_backtrace(self.backtrace.clone(), body).map(|(raw_msg, _)| raw_msg)
+    }
+
+    fn is_sending_to(&self, eid: EndpointId) -> bool {
+        match self.exchange {
+            Exchange::Sending(id, _) => id == eid,
+            _ => false
+        }
+    }
+
+    fn is_receiving_from(&self, eid: EndpointId) -> bool {
+        match self.exchange {
+            Exchange::Receiving(id, _) => id == eid,
+            _ => false
+        }
+    }
+
+    fn is_on_hold_for(&self, eid: EndpointId) -> bool {
+        match self.exchange {
+            Exchange::SendOnHold(id, _, _) => id == eid,
+            _ => false
+        }
+    }
+
+    fn is_bound_to(&self, eid: EndpointId) -> bool {
+        match self.exchange {
+            Exchange::Receiving(id, _) |
+            Exchange::Active(id) |
+            Exchange::Sending(id, _) |
+            Exchange::SendOnHold(id, _, _) => id == eid,
+            _ => false
+        }
+    }
+
+    fn get_active_pipe(&self) -> Option<EndpointId> {
+        match self.exchange {
+            Exchange::Active(eid) => Some(eid),
+            _ => None
+        }
+    }
+}
+
 /*****************************************************************************/
 /*                                                                           */
 /* State                                                                     */
@@ -205,7 +555,7 @@ impl State {
 /*****************************************************************************/
 
     fn send(self, ctx: &mut Context, inner: &mut Inner, msg: Rc<Message>, timeout: Timeout, eid: EndpointId) -> State {
-        if inner.is_device_item {
+        if inner.is_raw {
             if inner.is_send_ready_to(&eid) {
                 State::Idle.send_reply_to(ctx, inner, msg, timeout, eid)
             } else {
@@ -236,6 +586,7 @@ impl State {
         match self {
             State::Sending(id, msg, timeout) => {
                 if id == eid {
+                    inner.clear_backtrace();
                     inner.on_send_ack(ctx, timeout);
                     State::Idle
                 } else {
@@ -265,7 +616,7 @@ impl State {
         }
     }
     fn is_send_ready(&self, inner: &Inner) -> bool {
-        if inner.is_device_item {
+        if inner.is_raw {
             inner.is_send_ready()
         }
         else if let State::Active(ref eid) = *self {
@@ -291,7 +642,7 @@ impl State {
             State::Receiving(id, timeout) => {
                 if id == eid {
                     inner.on_recv_ack(ctx, timeout, msg);
-                    if inner.is_device_item {
+                    if inner.is_raw {
                         State::Idle
                     } else {
                         State::Active(eid)
@@ -336,7 +687,7 @@ impl Inner {
             sd: HashSet::new(),
             ttl: 8,
             backtrace: Vec::new(),
-            is_device_item: false
+            is_raw: false
         }
     }
     fn add_pipe(&mut self, eid: EndpointId, pipe: Pipe) {
@@ -399,7 +750,7 @@ impl Inner {
         self.fq.activate(&eid)
     }
     fn on_recv_ack(&mut self, ctx: &mut Context, timeout: Timeout, mut msg: Message) {
-        if !self.is_device_item {
+        if !self.is_raw {
             self.set_backtrace(&msg.header);
             msg.header.clear();
         }
@@ -420,7 +771,8 @@ impl Inner {
         self.fq.peek()
     }
  
-    fn raw_msg_to_msg(&self, raw_msg: Message, eid: EndpointId) -> Option<Message> {
+    fn raw_msg_to_msg(&self, mut raw_msg: Message, eid: EndpointId) -> Option<Message> {
+        let peer = raw_msg.peer.take();
         let (mut header, mut body) = raw_msg.split();
         let mut hops = 0;
         let mut eid_bytes: [u8; 4] = [0; 4];
@@ -447,7 +799,7 @@ impl Inner {
 
             let position = header.len() - 4;
             if header[position] & 0x80 != 0 {
-                return Some(Message::from_header_and_body(header, tail));
+                return Some(Message::from_header_and_body(header, tail).with_peer(peer));
             }
             body = tail;
         }
@@ -455,22 +807,14 @@ impl Inner {
     fn msg_to_raw_msg(&self, msg: Message) -> Option<(Message, EndpointId)> {
         let (mut header, body) = msg.split();
 
-        if !self.is_device_item {
+        if !self.is_raw {
             let backtrace = self.get_backtrace();
 
             header.clear();
             header.extend_from_slice(backtrace);
         }
 
-        if header.len() < 4 {
-            return None;
-        }
-
-        let tail = header.split_off(4);
-        let eid_u32 = BigEndian::read_u32(&header);
-        let eid = EndpointId::from(eid_u32 as usize);
-        
-        Some((Message::from_header_and_body(tail, body), eid))
+        split_backtrace(header, body)
     }
     fn set_backtrace(&mut self, bt: &[u8]) {
         self.backtrace.clear();
@@ -489,6 +833,19 @@ impl Inner {
     }
 }
 
+/// Takes the id of the pipe the reply must be sent to from the head of the backtrace.
+fn split_backtrace(mut header: Vec<u8>, body: Vec<u8>) -> Option<(Message, EndpointId)> {
+    if header.len() < 4 {
+        return None;
+    }
+
+    let tail = header.split_off(4);
+    let eid_u32 = BigEndian::read_u32(&header);
+    let eid = EndpointId::from(eid_u32 as usize);
+
+    Some((Message::from_header_and_body(tail, body), eid))
+}
+
 /*****************************************************************************/
 /*                                                                           */
 /* tests                                                                     */
@@ -504,7 +861,8 @@ mod tests {
 
     use byteorder::*;
 
-    use core::{EndpointId, Message, Scheduled};
+    use core::{EndpointId, ContextId, Message, Scheduled};
+    use core::endpoint::PeerInfo;
     use core::socket::{Protocol, Reply};
     use core::context::{Event};
     use core::tests::*;
@@ -753,6 +1111,36 @@ mod tests {
         assert_eq!(0, rep.inner.get_backtrace().len());
     }
 
+    #[test]
+    fn when_recv_the_peer_info_is_kept_in_the_msg() {
+        let (tx, rx) = mpsc::channel();
+        let mut rep = Rep::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid = EndpointId::from(1);
+        let pipe = new_test_pipe(eid);
+        let mut peer = PeerInfo::new(eid, "tcp");
+        let mut body: Vec<u8> = vec![0, 0, 0, 0, 4, 2, 1];
+
+        peer.addr = Some(String::from("127.0.0.1:5454"));
+        BigEndian::write_u32(&mut body[0..4], 666 | 0x80000000);
+
+        let msg = Message::from_body(body).with_peer(Some(peer.clone()));
+
+        rep.add_pipe(&mut ctx, eid, pipe);
+        rep.on_recv_ready(&mut ctx, eid);
+        rep.recv(&mut ctx, None);
+        rep.on_recv_ack(&mut ctx, eid, msg);
+
+        let app_msg = match rx.recv().expect("facade should have been sent a reply !") {
+            Reply::Recv(msg) => msg,
+            _ => panic!("facade should have been sent a msg !")
+        };
+
+        assert_eq!(3, app_msg.get_body().len());
+        assert_eq!(Some(&peer), app_msg.get_peer());
+    }
+
     #[test]
     fn when_in_regular_mode_send_will_restore_backtrace_from_socket_state_in_header_before_removing_endoint_id() {
         let (tx, rx) = mpsc::channel();
@@ -850,4 +1238,210 @@ mod tests {
 
         ctx_sensor.borrow().assert_no_send_call();
     }
-}
+
+    fn request(request_id: u32, body: Vec<u8>) -> Message {
+        let mut raw_body: Vec<u8> = vec![0, 0, 0, 0];
+
+        BigEndian::write_u32(&mut raw_body[0..4], request_id | 0x80000000);
+        raw_body.extend_from_slice(&body);
+
+        Message::from_body(raw_body)
+    }
+
+    #[test]
+    fn contexts_reply_out_of_order_with_their_own_backtrace() {
+        let (tx, _) = mpsc::channel();
+        let mut rep = Rep::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid0 = EndpointId::from(0);
+        let eid1 = EndpointId::from(1);
+        let (tx0, rx0) = mpsc::channel();
+        let (tx1, rx1) = mpsc::channel();
+        let cid0 = ContextId::from(0);
+        let cid1 = ContextId::from(1);
+
+        rep.open_context(cid0, tx0).unwrap();
+        rep.open_context(cid1, tx1).unwrap();
+        rep.add_pipe(&mut ctx, eid0, new_test_pipe(eid0));
+        rep.add_pipe(&mut ctx, eid1, new_test_pipe(eid1));
+        rep.on_recv_ready(&mut ctx, eid0);
+        rep.on_recv_ready(&mut ctx, eid1);
+        rep.on_send_ready(&mut ctx, eid0);
+        rep.on_send_ready(&mut ctx, eid1);
+        rep.recv_in_context(&mut ctx, cid0, None);
+        rep.recv_in_context(&mut ctx, cid1, None);
+        rep.on_recv_ack(&mut ctx, eid1, request(11, vec![1]));
+        rep.on_recv_ack(&mut ctx, eid0, request(10, vec![0]));
+
+        assert!(rx0.try_recv().is_ok());
+        assert!(rx1.try_recv().is_ok());
+
+        rep.send_in_context(&mut ctx, cid1, Message::from_body(vec![11]), None);
+        rep.on_send_ack(&mut ctx, eid1);
+        rep.send_in_context(&mut ctx, cid0, Message::from_body(vec![10]), None);
+        rep.on_send_ack(&mut ctx, eid0);
+
+        match rx0.try_recv().expect("context should have been sent a reply !") {
+            Reply::Send => {},
+            _ => panic!("context 0 should have sent its reply")
+        }
+        match rx1.try_recv().expect("context should have been sent a reply !") {
+            Reply::Send => {},
+            _ => panic!("context 1 should have sent its reply")
+        }
+
+        let sensor = ctx_sensor.borrow();
+        let send_calls = sensor.get_send_calls();
+
+        assert_eq!(2, send_calls.len());
+        assert_eq!(eid1, send_calls[0].0);
+        assert_eq!(&[0x80, 0, 0, 11], send_calls[0].1.get_header());
+        assert_eq!(eid0, send_calls[1].0);
+        assert_eq!(&[0x80, 0, 0, 10], send_calls[1].1.get_header());
+    }
+
+    #[test]
+    fn late_ack_of_a_timed_out_context_reply_keeps_the_backtrace_of_the_socket() {
+        let (tx, rx) = mpsc::channel();
+        let mut rep = Rep::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid0 = EndpointId::from(0);
+        let eid1 = EndpointId::from(1);
+        let (tx0, rx0) = mpsc::channel();
+        let cid0 = ContextId::from(0);
+
+        rep.open_context(cid0, tx0).unwrap();
+        rep.add_pipe(&mut ctx, eid0, new_test_pipe(eid0));
+        rep.on_recv_ready(&mut ctx, eid0);
+        rep.on_send_ready(&mut ctx, eid0);
+        rep.recv_in_context(&mut ctx, cid0, None);
+        rep.on_recv_ack(&mut ctx, eid0, request(10, vec![0]));
+        rep.send_in_context(&mut ctx, cid0, Message::from_body(vec![10]), None);
+        rep.on_context_send_timeout(&mut ctx, cid0);
+
+        assert!(rx0.try_recv().is_ok());
+        match rx0.try_recv().expect("context should have been sent a reply !") {
+            Reply::Err(_) => {},
+            _ => panic!("context send should have timed out")
+        }
+
+        rep.add_pipe(&mut ctx, eid1, new_test_pipe(eid1));
+        rep.on_recv_ready(&mut ctx, eid1);
+        rep.on_send_ready(&mut ctx, eid1);
+        rep.recv(&mut ctx, None);
+        rep.on_recv_ack(&mut ctx, eid1, request(11, vec![1]));
+        rep.on_send_ack(&mut ctx, eid0);
+        rep.send(&mut ctx, Message::from_body(vec![11]), None);
+        rep.on_send_ack(&mut ctx, eid1);
+
+        assert!(rx.try_recv().is_ok());
+        match rx.try_recv().expect("facade should have been sent a reply !") {
+            Reply::Send => {},
+            _ => panic!("socket should have sent its reply")
+        }
+
+        let sensor = ctx_sensor.borrow();
+        let send_calls = sensor.get_send_calls();
+
+        assert_eq!(2, send_calls.len());
+        assert_eq!(eid1, send_calls[1].0);
+        assert_eq!(&[0x80, 0, 0, 11], send_calls[1].1.get_header());
+    }
+
+    #[test]
+    fn contexts_bound_to_a_removed_pipe_are_reset() {
+        let (tx, _) = mpsc::channel();
+        let mut rep = Rep::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid = EndpointId::from(0);
+        let (tx0, rx0) = mpsc::channel();
+        let (tx1, rx1) = mpsc::channel();
+        let cid0 = ContextId::from(0);
+        let cid1 = ContextId::from(1);
+
+        rep.open_context(cid0, tx0).unwrap();
+        rep.open_context(cid1, tx1).unwrap();
+        rep.add_pipe(&mut ctx, eid, new_test_pipe(eid));
+        rep.on_recv_ready(&mut ctx, eid);
+        rep.recv_in_context(&mut ctx, cid0, None);
+        rep.on_recv_ack(&mut ctx, eid, request(10, vec![0]));
+        rep.on_recv_ready(&mut ctx, eid);
+        rep.recv_in_context(&mut ctx, cid1, None);
+        rep.on_recv_ack(&mut ctx, eid, request(11, vec![1]));
+        rep.send_in_context(&mut ctx, cid1, Message::from_body(vec![11]), None);
+
+        assert!(rx0.try_recv().is_ok());
+        assert!(rx1.try_recv().is_ok());
+
+        rep.remove_pipe(&mut ctx, eid);
+
+        match rx1.try_recv().expect("context should have been sent a reply !") {
+            Reply::Err(_) => {},
+            _ => panic!("pending reply should have failed")
+        }
+
+        rep.send_in_context(&mut ctx, cid0, Message::from_body(vec![10]), None);
+
+        match rx0.try_recv().expect("context should have been sent a reply !") {
+            Reply::Err(_) => {},
+            _ => panic!("send should have failed")
+        }
+        ctx_sensor.borrow().assert_no_send_call();
+    }
+
+    #[test]
+    fn context_recv_waits_for_a_pipe_to_be_ready() {
+        let (tx, _) = mpsc::channel();
+        let mut rep = Rep::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid = EndpointId::from(0);
+        let (tx0, _rx0) = mpsc::channel();
+        let cid = ContextId::from(0);
+
+        rep.open_context(cid, tx0).unwrap();
+        rep.add_pipe(&mut ctx, eid, new_test_pipe(eid));
+        rep.recv_in_context(&mut ctx, cid, None);
+
+        ctx_sensor.borrow().assert_no_recv_call();
+
+        rep.on_recv_ready(&mut ctx, eid);
+
+        ctx_sensor.borrow().assert_one_recv_from(eid);
+    }
+
+    #[test]
+    fn context_send_without_request_will_fail() {
+        let (tx, _) = mpsc::channel();
+        let mut rep = Rep::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let (tx0, rx0) = mpsc::channel();
+        let cid = ContextId::from(0);
+
+        rep.open_context(cid, tx0).unwrap();
+        rep.send_in_context(&mut ctx, cid, Message::from_body(vec![0]), None);
+
+        match rx0.try_recv().expect("context should have been sent a reply !") {
+            Reply::Err(_) => {},
+            _ => panic!("send should have failed")
+        }
+        ctx_sensor.borrow().assert_no_send_call();
+    }
+
+    #[test]
+    fn contexts_are_not_supported_in_raw_mode() {
+        let (tx, _) = mpsc::channel();
+        let mut rep = Rep::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let (tx0, _) = mpsc::channel();
+
+        rep.on_device_plugged(&mut ctx);
+
+        assert!(rep.open_context(ContextId::from(0), tx0).is_err());
+    }
+}
\ No newline at end of file
@@ -4,7 +4,7 @@
 // or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
 // This file may not be copied, modified, or distributed except according to those terms.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::rc::Rc;
 use std::sync::mpsc::Sender;
 use std::io;
@@ -14,18 +14,20 @@ use time;
 
 use byteorder::*;
 
-use core::{EndpointId, Message};
+use core::{EndpointId, ContextId, Message};
 use core::socket::{Protocol, Reply};
-use core::config::ConfigOption;
+use core::config::{ConfigOption, ConfigOptionName};
 use core::endpoint::Pipe;
 use core::context::{Context, Schedulable};
 use super::priolist::Priolist;
-use super::{Timeout, REQ, REP};
+use super::{Timeout, REQ, REP, get_backtrace_len};
 use io_error::*;
 
 pub struct Req {
     inner: Inner,
-    state: Option<State>
+    state: Option<State>,
+    contexts: HashMap<ContextId, ReqContext>,
+    contexts_on_hold: VecDeque<ContextId>
 }
 
 enum State {
@@ -43,9 +45,15 @@ struct Inner {
     lb: Priolist,
     fq: Priolist,
     rv: HashSet<EndpointId>,
+    reading: HashSet<EndpointId>,
     req_id_seq: u32,
-    is_device_item: bool,
-    resend_ivl: Duration
+    // Contexts draw their request ids from the same sequence
+    cur_req_id: u32,
+    prefetched: Option<Message>,
+    exchange: Option<Timeout>,
+    is_raw: bool,
+    resend_ivl: Duration,
+    dropped_msg_count: u64
 }
 
 struct PendingRequest {
@@ -53,6 +61,24 @@ struct PendingRequest {
     retry_timeout: Timeout
 }
 
+/// Request in flight of a context, the pipe reads are shared with the socket
+/// and the replies are dispatched to the contexts according to their request id.
+struct ReqContext {
+    reply_tx: Sender<Reply>,
+    req_id: u32,
+    exchange: Exchange,
+    send_timeout: Timeout,
+    recv_pending: Option<Timeout>
+}
+
+enum Exchange {
+    Idle,
+    SendOnHold(Rc<Message>, bool),
+    Sending(EndpointId, Rc<Message>, bool),
+    Active(EndpointId, PendingRequest),
+    Replied(Message)
+}
+
 /*****************************************************************************/
 /*                                                                           */
 /* Req                                                                      */
@@ -80,13 +106,29 @@ impl Req {
         }
     }
 
+    /// Once the request of `Socket::request` is sent, its reply is received without waiting for the user.
+    fn resume_exchange(&mut self, ctx: &mut Context) {
+        let is_sent = match self.state {
+            Some(State::Active(..)) => true,
+            _ => false
+        };
+
+        if is_sent {
+            if let Some(timeout) = self.inner.exchange.take() {
+                self.apply(ctx, |s, ctx, inner| s.recv(ctx, inner, timeout))
+            }
+        }
+    }
+
 }
 
 impl From<Sender<Reply>> for Req {
     fn from(tx: Sender<Reply>) -> Req {
         Req {
             inner: Inner::new(tx),
-            state: Some(State::Idle)
+            state: Some(State::Idle),
+            contexts: HashMap::new(),
+            contexts_on_hold: VecDeque::new()
         }
     }
 }
@@ -113,6 +155,7 @@ impl Protocol for Req {
 
         if pipe.is_some() {
             self.apply(ctx, |s, ctx, inner| s.on_pipe_removed(ctx, inner, eid));
+            self.apply_on_contexts(ctx, |req, ctx| req.on_context_pipe_removed(ctx, eid));
         }
 
         ctx.check_send_ready_change(was_send_ready, is_send_ready);
@@ -126,23 +169,54 @@ impl Protocol for Req {
         self.apply(ctx, |s, ctx, inner| s.send(ctx, inner, Rc::new(raw_msg), timeout, false))
     }
     fn on_send_ack(&mut self, ctx: &mut Context, eid: EndpointId) {
-        self.apply(ctx, |s, ctx, inner| s.on_send_ack(ctx, inner, eid))
+        if self.find_context_sending_to(eid).is_some() {
+            self.apply_on_contexts(ctx, |req, ctx| req.on_context_send_ack(ctx, eid))
+        } else {
+            self.apply(ctx, |s, ctx, inner| s.on_send_ack(ctx, inner, eid));
+            self.resume_exchange(ctx)
+        }
     }
     fn on_send_timeout(&mut self, ctx: &mut Context) {
         self.apply(ctx, |s, ctx, inner| s.on_send_timeout(ctx, inner))
     }
     fn on_send_ready(&mut self, ctx: &mut Context, eid: EndpointId) {
-        self.apply(ctx, |s, ctx, inner| s.on_send_ready(ctx, inner, eid))
+        self.apply(ctx, |s, ctx, inner| s.on_send_ready(ctx, inner, eid));
+        self.apply_on_contexts(ctx, |req, ctx| req.resume_contexts_on_hold(ctx))
     }
     fn recv(&mut self, ctx: &mut Context, timeout: Timeout) {
         self.apply(ctx, |s, ctx, inner| s.recv(ctx, inner, timeout))
     }
+    fn request(&mut self, ctx: &mut Context, msg: Message, timeout: Timeout) -> io::Result<()> {
+        if self.inner.is_raw {
+            return Err(invalid_input_io_error("request not supported in raw mode"));
+        }
+
+        let raw_msg = self.inner.msg_to_raw_msg(msg);
+
+        self.inner.exchange = Some(timeout);
+        self.apply(ctx, |s, ctx, inner| s.send(ctx, inner, Rc::new(raw_msg), None, false));
+
+        Ok(())
+    }
     fn on_recv_ack(&mut self, ctx: &mut Context, eid: EndpointId, raw_msg: Message) {
-        if let Some((msg, req_id)) = self.inner.raw_msg_to_msg(raw_msg) {
-            self.apply(ctx, |s, ctx, inner| s.on_recv_ack(ctx, inner, eid, msg, req_id))
-        } else {
-            self.inner.on_recv_ack_malformed(ctx)
+        self.inner.on_recv_done(eid);
+
+        match self.inner.raw_msg_to_msg(raw_msg) {
+            Some((msg, req_id)) => match self.find_context_waiting_for(req_id) {
+                Some(cid) => {
+                    self.apply_on_contexts(ctx, |req, ctx| req.on_context_reply(ctx, cid, msg));
+                    self.apply(ctx, |s, ctx, inner| s.on_recv_ack_elsewhere(ctx, inner, eid));
+                },
+                None => self.apply(ctx, |s, ctx, inner| s.on_recv_ack(ctx, inner, eid, msg, req_id))
+            },
+            None => if self.is_receiving_from(eid) {
+                self.inner.on_recv_ack_malformed(ctx)
+            } else {
+                self.inner.dropped_msg_count += 1;
+            }
         }
+
+        self.apply_on_contexts(ctx, |req, ctx| req.prefetch_context_replies(ctx))
     }
     fn on_recv_timeout(&mut self, ctx: &mut Context) {
         self.apply(ctx, |s, ctx, inner| s.on_recv_timeout(ctx, inner))
@@ -150,19 +224,34 @@ impl Protocol for Req {
     fn on_recv_ready(&mut self, ctx: &mut Context, eid: EndpointId) {
         self.apply(ctx, |s, ctx, inner| s.on_recv_ready(ctx, inner, eid))
     }
-    fn set_option(&mut self, opt: ConfigOption) -> io::Result<()> {
+    fn set_option(&mut self, _: &mut Context, opt: ConfigOption) -> io::Result<()> {
         match opt {
             ConfigOption::ReqResendIvl(ivl) => Ok(self.inner.set_resend_ivl(ivl)),
             _ => Err(invalid_input_io_error("option not supported"))
         }
     }
+    fn get_option(&self, name: ConfigOptionName) -> io::Result<ConfigOption> {
+        match name {
+            ConfigOptionName::ReqResendIvl => Ok(ConfigOption::ReqResendIvl(self.inner.resend_ivl)),
+            _ => Err(invalid_input_io_error("option not supported"))
+        }
+    }
     fn on_timer_tick(&mut self, ctx: &mut Context, task: Schedulable) {
-        if let Schedulable::ReqResend = task {
-            self.apply(ctx, |s, ctx, inner| s.on_retry_timeout(ctx, inner))
+        match task {
+            Schedulable::ReqResend => self.apply(ctx, |s, ctx, inner| s.on_retry_timeout(ctx, inner)),
+            Schedulable::RequestTimeout => self.apply(ctx, |s, ctx, inner| s.on_request_timeout(ctx, inner)),
+            Schedulable::ContextReqResend(cid) => self.apply_on_contexts(ctx, |req, ctx| req.on_context_retry_timeout(ctx, cid)),
+            _ => {}
         }
     }
+    fn get_dropped_msg_count(&self) -> u64 {
+        self.inner.dropped_msg_count
+    }
+    fn set_raw(&mut self) {
+        self.inner.is_raw = true;
+    }
     fn on_device_plugged(&mut self, _: &mut Context) {
-        self.inner.is_device_item = true;
+        self.set_raw();
     }
     fn is_send_ready(&self) -> bool {
         if let Some(ref state) = self.state {
@@ -178,11 +267,355 @@ impl Protocol for Req {
             false
         }
     }
+    fn open_context(&mut self, cid: ContextId, reply_tx: Sender<Reply>) -> io::Result<()> {
+        if self.inner.is_raw {
+            return Err(invalid_input_io_error("contexts not supported in raw mode"));
+        }
+
+        self.contexts.insert(cid, ReqContext::new(reply_tx));
+
+        Ok(())
+    }
+    fn close_context(&mut self, ctx: &mut Context, cid: ContextId) {
+        if let Some(mut context) = self.contexts.remove(&cid) {
+            context.cancel_timeouts(ctx);
+            self.contexts_on_hold.retain(|id| *id != cid);
+        }
+    }
+    fn send_in_context(&mut self, ctx: &mut Context, cid: ContextId, msg: Message, timeout: Timeout) {
+        let req_id = self.inner.next_req_id();
+        let raw_msg = Rc::new(encode(msg, req_id));
+
+        self.apply_on_contexts(ctx, |req, ctx| req.send_context_request(ctx, cid, raw_msg, req_id, timeout))
+    }
+    fn on_context_send_timeout(&mut self, ctx: &mut Context, cid: ContextId) {
+        self.apply_on_contexts(ctx, |req, _| req.on_context_send_timeout_elapsed(cid))
+    }
+    fn recv_in_context(&mut self, ctx: &mut Context, cid: ContextId, timeout: Timeout) {
+        self.apply_on_contexts(ctx, |req, ctx| req.recv_context_reply(ctx, cid, timeout))
+    }
+    fn on_context_recv_timeout(&mut self, ctx: &mut Context, cid: ContextId) {
+        self.apply_on_contexts(ctx, |req, ctx| req.on_context_recv_timeout_elapsed(ctx, cid))
+    }
     fn close(&mut self, ctx: &mut Context) {
+        self.contexts.clear();
+        self.contexts_on_hold.clear();
         self.inner.close(ctx)
     }
 }
 
+/*****************************************************************************/
+/*                                                                           */
+/* Contexts                                                                  */
+/*                                                                           */
+/*****************************************************************************/
+
+impl Req {
+
+    fn apply_on_contexts<F>(&mut self, ctx: &mut Context, f: F) where F : FnOnce(&mut Req, &mut Context) {
+        let was_send_ready = self.is_send_ready();
+        let was_recv_ready = self.is_recv_ready();
+
+        f(self, ctx);
+
+        let is_send_ready = self.is_send_ready();
+        let is_recv_ready = self.is_recv_ready();
+
+        ctx.check_send_ready_change(was_send_ready, is_send_ready);
+        ctx.check_recv_ready_change(was_recv_ready, is_recv_ready);
+    }
+
+    fn find_context_sending_to(&self, eid: EndpointId) -> Option<ContextId> {
+        self.contexts.iter()
+            .find(|&(_, context)| context.is_sending_to(eid))
+            .map(|(cid, _)| *cid)
+    }
+
+    fn find_context_waiting_for(&self, req_id: u32) -> Option<ContextId> {
+        self.contexts.iter()
+            .find(|&(_, context)| context.is_waiting_for(req_id))
+            .map(|(cid, _)| *cid)
+    }
+
+    fn is_receiving_from(&self, eid: EndpointId) -> bool {
+        match self.state {
+            Some(State::Receiving(id, _, _)) => id == eid,
+            _ => false
+        }
+    }
+
+    fn send_context_request(&mut self, ctx: &mut Context, cid: ContextId, msg: Rc<Message>, req_id: u32, timeout: Timeout) {
+        if let Some(context) = self.contexts.get_mut(&cid) {
+            context.cancel_timeouts(ctx);
+            context.req_id = req_id;
+            context.send_timeout = timeout;
+        } else {
+            return;
+        }
+
+        self.contexts_on_hold.retain(|id| *id != cid);
+        self.send_context_msg(ctx, cid, msg, false);
+    }
+
+    fn send_context_msg(&mut self, ctx: &mut Context, cid: ContextId, msg: Rc<Message>, retry: bool) {
+        let exchange = match self.inner.send(ctx, msg.clone()) {
+            Some(eid) => Exchange::Sending(eid, msg, retry),
+            None => {
+                self.contexts_on_hold.push_back(cid);
+                Exchange::SendOnHold(msg, retry)
+            }
+        };
+
+        if let Some(context) = self.contexts.get_mut(&cid) {
+            context.exchange = exchange;
+        }
+    }
+
+    fn on_context_send_ack(&mut self, ctx: &mut Context, eid: EndpointId) {
+        if let Some(cid) = self.find_context_sending_to(eid) {
+            if let Some(context) = self.contexts.get_mut(&cid) {
+                if let Exchange::Sending(_, msg, retry) = context.take_exchange() {
+                    if !retry {
+                        context.send_reply(Reply::Send);
+                    }
+                    if let Some(sched) = context.send_timeout.take() {
+                        ctx.cancel(sched);
+                    }
+
+                    let retry_timeout = ctx.schedule(Schedulable::ContextReqResend(cid), self.inner.resend_ivl).ok();
+
+                    context.exchange = Exchange::Active(eid, PendingRequest {
+                        req: msg,
+                        retry_timeout: retry_timeout
+                    });
+                }
+            }
+        }
+
+        self.prefetch_context_replies(ctx);
+    }
+
+    fn resume_contexts_on_hold(&mut self, ctx: &mut Context) {
+        while self.inner.is_send_ready() {
+            let cid = match self.contexts_on_hold.pop_front() {
+                Some(cid) => cid,
+                None => break
+            };
+            let exchange = match self.contexts.get_mut(&cid) {
+                Some(context) => context.take_exchange(),
+                None => continue
+            };
+
+            if let Exchange::SendOnHold(msg, retry) = exchange {
+                self.send_context_msg(ctx, cid, msg, retry);
+            } else if let Some(context) = self.contexts.get_mut(&cid) {
+                context.exchange = exchange;
+            }
+        }
+    }
+
+    fn on_context_send_timeout_elapsed(&mut self, cid: ContextId) {
+        if let Some(context) = self.contexts.get_mut(&cid) {
+            context.send_timeout = None;
+
+            match context.take_exchange() {
+                Exchange::SendOnHold(_, false) |
+                Exchange::Sending(_, _, false) => {
+                    context.send_reply(Reply::Err(timedout_io_error("Send timed out")));
+                },
+                other => context.exchange = other
+            }
+        }
+
+        self.forget_contexts_on_hold();
+    }
+
+    fn recv_context_reply(&mut self, ctx: &mut Context, cid: ContextId, timeout: Timeout) {
+        if let Some(context) = self.contexts.get_mut(&cid) {
+            match context.take_exchange() {
+                Exchange::Replied(msg) => {
+                    context.send_reply(Reply::Recv(msg));
+                    if let Some(sched) = timeout {
+                        ctx.cancel(sched);
+                    }
+                },
+                Exchange::Idle => {
+                    context.send_reply(Reply::Err(other_io_error("Can't recv: no active request")));
+                    if let Some(sched) = timeout {
+                        ctx.cancel(sched);
+                    }
+                },
+                other => {
+                    context.exchange = other;
+                    context.recv_pending = Some(timeout);
+                }
+            }
+        }
+
+        self.prefetch_context_replies(ctx);
+    }
+
+    fn on_context_recv_timeout_elapsed(&mut self, ctx: &mut Context, cid: ContextId) {
+        if let Some(context) = self.contexts.get_mut(&cid) {
+            if context.recv_pending.take().is_some() {
+                context.cancel_timeouts(ctx);
+                context.send_reply(Reply::Err(timedout_io_error("Recv timed out")));
+            }
+        }
+
+        self.forget_contexts_on_hold();
+    }
+
+    fn on_context_reply(&mut self, ctx: &mut Context, cid: ContextId, msg: Message) {
+        if let Some(context) = self.contexts.get_mut(&cid) {
+            context.cancel_timeouts(ctx);
+
+            if let Some(timeout) = context.recv_pending.take() {
+                context.send_reply(Reply::Recv(msg));
+                if let Some(sched) = timeout {
+                    ctx.cancel(sched);
+                }
+            } else {
+                context.exchange = Exchange::Replied(msg);
+            }
+        }
+
+        self.forget_contexts_on_hold();
+    }
+
+    fn on_context_retry_timeout(&mut self, ctx: &mut Context, cid: ContextId) {
+        let exchange = match self.contexts.get_mut(&cid) {
+            Some(context) => context.take_exchange(),
+            None => return
+        };
+
+        if let Exchange::Active(_, p) = exchange {
+            self.send_context_msg(ctx, cid, p.req, true);
+        } else if let Some(context) = self.contexts.get_mut(&cid) {
+            context.exchange = exchange;
+        }
+    }
+
+    fn on_context_pipe_removed(&mut self, ctx: &mut Context, eid: EndpointId) {
+        let cids: Vec<ContextId> = self.contexts.iter()
+            .filter(|&(_, context)| context.is_bound_to(eid))
+            .map(|(cid, _)| *cid)
+            .collect();
+
+        for cid in cids {
+            let exchange = match self.contexts.get_mut(&cid) {
+                Some(context) => context.take_exchange(),
+                None => continue
+            };
+
+            match exchange {
+                Exchange::Sending(_, msg, retry) => self.send_context_msg(ctx, cid, msg, retry),
+                Exchange::Active(_, p) => {
+                    if let Some(sched) = p.retry_timeout {
+                        ctx.cancel(sched);
+                    }
+                    self.send_context_msg(ctx, cid, p.req, true);
+                },
+                _ => {}
+            }
+        }
+
+        self.prefetch_context_replies(ctx);
+    }
+
+    /// Replies are read as soon as the request is sent, so they are not
+    /// left behind a reply to another context in the same pipe.
+    fn prefetch_context_replies(&mut self, ctx: &mut Context) {
+        let eids: Vec<EndpointId> = self.contexts.values()
+            .filter_map(|context| context.get_active_pipe())
+            .collect();
+
+        for eid in eids {
+            self.inner.recv_from(ctx, eid);
+        }
+    }
+
+    fn forget_contexts_on_hold(&mut self) {
+        let contexts = &self.contexts;
+
+        self.contexts_on_hold.retain(|cid| contexts.get(cid).map_or(false, |context| context.is_on_hold()));
+    }
+}
+
+impl ReqContext {
+    fn new(reply_tx: Sender<Reply>) -> ReqContext {
+        ReqContext {
+            reply_tx: reply_tx,
+            req_id: 0,
+            exchange: Exchange::Idle,
+            send_timeout: None,
+            recv_pending: None
+        }
+    }
+
+    fn send_reply(&self, reply: Reply) {
+        let _ = self.reply_tx.send(reply);
+    }
+
+    fn take_exchange(&mut self) -> Exchange {
+        ::std::mem::replace(&mut self.exchange, Exchange::Idle)
+    }
+
+    /// Abandons the current request, the pending operation timeouts are left to the caller.
+    fn cancel_timeouts(&mut self, ctx: &mut Context) {
+        if let Exchange::Active(_, ref p) = self.exchange {
+            if let Some(sched) = p.retry_timeout {
+                ctx.cancel(sched);
+            }
+        }
+        if let Some(sched) = self.send_timeout.take() {
+            ctx.cancel(sched);
+        }
+
+        self.exchange = Exchange::Idle;
+    }
+
+    fn is_sending_to(&self, eid: EndpointId) -> bool {
+        match self.exchange {
+            Exchange::Sending(id, _, _) => id == eid,
+            _ => false
+        }
+    }
+
+    fn is_bound_to(&self, eid: EndpointId) -> bool {
+        match self.exchange {
+            Exchange::Sending(id, _, _) | Exchange::Active(id, _) => id == eid,
+            _ => false
+        }
+    }
+
+    fn is_on_hold(&self) -> bool {
+        match self.exchange {
+            Exchange::SendOnHold(..) => true,
+            _ => false
+        }
+    }
+
+    /// A reply can still come while the request is being resent.
+    fn is_waiting_for(&self, req_id: u32) -> bool {
+        if self.req_id != req_id {
+            return false;
+        }
+
+        match self.exchange {
+            Exchange::Active(..) | Exchange::Sending(_, _, true) | Exchange::SendOnHold(_, true) => true,
+            _ => false
+        }
+    }
+
+    fn get_active_pipe(&self) -> Option<EndpointId> {
+        match self.exchange {
+            Exchange::Active(eid, _) => Some(eid),
+            _ => None
+        }
+    }
+}
+
 /*****************************************************************************/
 /*                                                                           */
 /* State                                                                     */
@@ -213,16 +646,46 @@ impl State {
                     State::Sending(id, msg, timeout, retry)
                 }
             },
-            State::Receiving(id, p, timeout) => {
+            State::Receiving(id, None, timeout) => {
                 if eid == id {
                     State::Idle.recv(ctx, inner, timeout)
                 } else {
-                    State::Receiving(id, p, timeout)
+                    State::Receiving(id, None, timeout)
+                }
+            },
+            State::Receiving(id, Some(p), timeout) => {
+                if eid == id {
+                    inner.exchange = Some(timeout);
+                    State::Idle.resend(ctx, inner, p)
+                } else {
+                    State::Receiving(id, Some(p), timeout)
+                }
+            },
+            State::RecvOnHold(Some(id), Some(p), timeout) => {
+                if eid == id {
+                    inner.exchange = Some(timeout);
+                    State::Idle.resend(ctx, inner, p)
+                } else {
+                    State::RecvOnHold(Some(id), Some(p), timeout)
+                }
+            },
+            State::Active(id, p) => {
+                if eid == id && inner.prefetched.is_none() {
+                    State::Idle.resend(ctx, inner, p)
+                } else {
+                    State::Active(id, p)
                 }
             },
             any => any
         }
     }
+    /// The pipe the request was sent to is gone, so there is no point in waiting for the resend timer.
+    fn resend(self, ctx: &mut Context, inner: &mut Inner, p: PendingRequest) -> State {
+        let req = p.req.clone();
+
+        inner.cancel(ctx, p);
+        self.send(ctx, inner, req, None, true)
+    }
 
 /*****************************************************************************/
 /*                                                                           */
@@ -234,6 +697,9 @@ impl State {
         if let State::Active(_, p) = self {
             inner.cancel(ctx, p);
         }
+        if !retry {
+            inner.prefetched = None;
+        }
         if let Some(eid) = inner.send(ctx, msg.clone()) {
             State::Sending(eid, msg, timeout, retry)
         } else {
@@ -249,9 +715,10 @@ impl State {
 
                 let retry_timeout = inner.on_send_ack(ctx, timeout, retry);
 
-                if inner.is_device_item {
+                if inner.is_raw {
                     State::Idle
                 } else {
+                    inner.recv_from(ctx, eid);
                     State::Active(eid, PendingRequest {
                         req: msg,
                         retry_timeout: retry_timeout
@@ -285,12 +752,17 @@ impl State {
 /*****************************************************************************/
 
     fn recv(self, ctx: &mut Context, inner: &mut Inner, timeout: Timeout) -> State {
-        if inner.is_device_item {
+        if inner.is_raw {
             inner.recv(ctx).map_or_else(
                 |   | State::RecvOnHold(None, None, timeout),
                 |eid| State::Receiving(eid, None, timeout))
         } else if let State::Active(eid, p) = self {
-            State::Idle.recv_reply_for(ctx, inner, timeout, eid, p)
+            if let Some(msg) = inner.prefetched.take() {
+                inner.on_recv_ack(ctx, timeout, msg, p.retry_timeout);
+                State::Idle
+            } else {
+                State::Idle.recv_reply_for(ctx, inner, timeout, eid, p)
+            }
         } else {
             inner.recv_when_inactive(ctx, timeout);
 
@@ -320,12 +792,43 @@ impl State {
                         inner.on_recv_ack(ctx, timeout, msg, p.retry_timeout);
                         State::Idle
                     } else {
+                        inner.dropped_msg_count += 1;
                         State::Idle.recv_reply_for(ctx, inner, timeout, id, p)
                     }
                 } else {
                     State::Receiving(id, Some(p), timeout)
                 }
             },
+            State::Active(id, p) => {
+                if eid == id && inner.cur_req_id() == req_id {
+                    inner.prefetched = Some(msg);
+                } else {
+                    inner.dropped_msg_count += 1;
+                    if eid == id {
+                        inner.recv_from(ctx, id);
+                    }
+                }
+                State::Active(id, p)
+            },
+            any => any
+        }
+    }
+    /// The pipe being read has delivered the reply of a context instead.
+    fn on_recv_ack_elsewhere(self, ctx: &mut Context, inner: &mut Inner, eid: EndpointId) -> State {
+        match self {
+            State::Receiving(id, Some(p), timeout) => {
+                if eid == id {
+                    State::Idle.recv_reply_for(ctx, inner, timeout, id, p)
+                } else {
+                    State::Receiving(id, Some(p), timeout)
+                }
+            },
+            State::Active(id, p) => {
+                if eid == id && inner.prefetched.is_none() {
+                    inner.recv_from(ctx, id);
+                }
+                State::Active(id, p)
+            },
             any => any
         }
     }
@@ -335,6 +838,10 @@ impl State {
             State::RecvOnHold(_, None, _) => inner.on_recv_timeout(ctx, None),
             State::Receiving(_, Some(p), _) |
             State::RecvOnHold(_, Some(p), _) => inner.on_recv_timeout(ctx, p.retry_timeout),
+            State::Sending(..) |
+            State::SendOnHold(..) => if inner.exchange.take().is_some() {
+                inner.on_recv_timeout(ctx, None)
+            },
             _ => {}
         }
 
@@ -356,18 +863,31 @@ impl State {
             any => any
         }
     }
+    fn on_request_timeout(self, ctx: &mut Context, inner: &mut Inner) -> State {
+        match self {
+            State::Sending(..) | State::SendOnHold(..) => {
+                inner.exchange = None;
+                self.on_send_timeout(ctx, inner)
+            },
+            any => any.on_recv_timeout(ctx, inner)
+        }
+    }
     fn on_retry_timeout(self, ctx: &mut Context, inner: &mut Inner) -> State {
-        if let State::Active(_, p) = self {
-            State::Idle.send(ctx, inner, p.req, None, true)
-        } else {
-            self
+        match self {
+            State::Active(_, p) => State::Idle.send(ctx, inner, p.req, None, true),
+            State::Receiving(_, Some(p), timeout) |
+            State::RecvOnHold(_, Some(p), timeout) => {
+                inner.exchange = Some(timeout);
+                State::Idle.send(ctx, inner, p.req, None, true)
+            },
+            any => any
         }
     }
     fn is_recv_ready(&self, inner: &Inner) -> bool {
-        if inner.is_device_item {
+        if inner.is_raw {
             inner.is_recv_ready()
         } else if let State::Active(ref eid, _) = *self {
-            inner.is_recv_ready_from(eid)
+            inner.prefetched.is_some() || inner.is_recv_ready_from(eid)
         } else {
             false
         }
@@ -388,9 +908,14 @@ impl Inner {
             lb: Priolist::new(),
             fq: Priolist::new(),
             rv: HashSet::new(),
+            reading: HashSet::new(),
             req_id_seq: time::get_time().nsec as u32,
-            is_device_item: false,
-            resend_ivl: Duration::from_secs(60)
+            cur_req_id: 0,
+            prefetched: None,
+            exchange: None,
+            is_raw: false,
+            resend_ivl: Duration::from_secs(60),
+            dropped_msg_count: 0
         }
     }
     fn add_pipe(&mut self, eid: EndpointId, pipe: Pipe) {
@@ -402,6 +927,7 @@ impl Inner {
         self.lb.remove(&eid);
         self.fq.remove(&eid);
         self.rv.remove(&eid);
+        self.reading.remove(&eid);
         self.pipes.remove(&eid)
     }
     fn send(&mut self, ctx: &mut Context, msg: Rc<Message>) -> Option<EndpointId> {
@@ -417,13 +943,13 @@ impl Inner {
         self.lb.activate(&eid)
     }
     fn on_send_ack(&self, ctx: &mut Context, timeout: Timeout, retry: bool) -> Timeout {
-        if !retry {
+        if !retry && self.exchange.is_none() {
             let _ = self.reply_tx.send(Reply::Send);
         }
         if let Some(sched) = timeout {
             ctx.cancel(sched);
         }
-        if self.is_device_item {
+        if self.is_raw {
             None
         } else {
             ctx.schedule(Schedulable::ReqResend, self.resend_ivl).ok()
@@ -445,15 +971,28 @@ impl Inner {
     fn recv(&mut self, ctx: &mut Context) -> Option<EndpointId> {
         self.fq.pop().map_or(None, |eid| self.recv_from(ctx, eid))
     }
+    /// A pipe is read at most once at a time, whether for the socket or for a context.
     fn recv_from(&mut self, ctx: &mut Context, eid: EndpointId) -> Option<EndpointId> {
+        if self.reading.contains(&eid) {
+            return Some(eid);
+        }
+
+        let reading = &mut self.reading;
+        let rv = &mut self.rv;
+
         self.pipes.get_mut(&eid).map_or(None, |pipe| {
             pipe.recv(ctx); 
+            rv.remove(&eid);
+            reading.insert(eid);
             Some(eid)
         })
     }
     fn recv_reply_from(&mut self, ctx: &mut Context, eid: EndpointId) -> bool {
         self.rv.remove(&eid);
-        self.pipes.get_mut(&eid).map(|pipe| pipe.recv(ctx)).is_some()
+        self.recv_from(ctx, eid).is_some()
+    }
+    fn on_recv_done(&mut self, eid: EndpointId) {
+        self.reading.remove(&eid);
     }
     fn recv_when_inactive(&mut self, ctx: &mut Context, timeout: Timeout) {
         let error = other_io_error("Can't recv: no active request");
@@ -494,15 +1033,16 @@ impl Inner {
     }
 
     fn msg_to_raw_msg(&mut self, msg: Message) -> Message {
-        if self.is_device_item {
+        if self.is_raw {
             msg
         } else {
-            encode(msg, self.next_req_id())
+            self.cur_req_id = self.next_req_id();
+            encode(msg, self.cur_req_id)
         }
     }
 
     fn raw_msg_to_msg(&self, raw_msg: Message) -> Option<(Message, u32)> {
-        if self.is_device_item {
+        if self.is_raw {
             let cur_req_id = self.cur_req_id();
             decode(raw_msg).map(|(msg, _)| (msg, cur_req_id))
         } else {
@@ -511,7 +1051,7 @@ impl Inner {
     }
 
     fn cur_req_id(&self) -> u32 {
-        self.req_id_seq | 0x80000000
+        self.cur_req_id
     }
 
     fn next_req_id(&mut self) -> u32 {
@@ -539,14 +1079,16 @@ fn encode(msg: Message, req_id: u32) -> Message {
     raw_msg
 }
 
-fn decode(raw_msg: Message) -> Option<(Message, u32)> {
+fn decode(mut raw_msg: Message) -> Option<(Message, u32)> {
     if raw_msg.get_body().len() < 4 {
         return None;
     }
 
+    let peer = raw_msg.peer.take();
     let (mut header, mut payload) = raw_msg.split();
-    let body = payload.split_off(4);
-    let req_id = BigEndian::read_u32(&payload);
+    let backtrace_len = get_backtrace_len(&payload).unwrap_or(4);
+    let body = payload.split_off(backtrace_len);
+    let req_id = BigEndian::read_u32(&payload[backtrace_len - 4..]);
 
     if header.is_empty() {
         header = payload;
@@ -554,7 +1096,7 @@ fn decode(raw_msg: Message) -> Option<(Message, u32)> {
         header.extend_from_slice(&payload);
     }
 
-    Some((Message::from_header_and_body(header, body), req_id))
+    Some((Message::from_header_and_body(header, body).with_peer(peer), req_id))
 }
 
 /*****************************************************************************/
@@ -569,12 +1111,13 @@ mod tests {
     use std::cell::RefCell;
     use std::rc::Rc;
     use std::sync::mpsc;
+    use std::io;
 
     use byteorder::*;
 
-    use core::{EndpointId, Message, Scheduled};
+    use core::{EndpointId, ContextId, Message, Scheduled};
     use core::socket::{Protocol, Reply};
-    use core::context::{Event};
+    use core::context::{Event, Schedulable};
     use core::tests::*;
 
     use super::*;
@@ -959,6 +1502,38 @@ mod tests {
         assert_eq!(3, app_msg.get_body().len());
     }
 
+    #[test]
+    fn when_in_raw_mode_recv_moves_the_whole_backtrace_from_the_body_to_the_header() {
+        let (tx, rx) = mpsc::channel();
+        let mut req = Req::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid = EndpointId::from(0);
+        let pipe = new_test_pipe(eid);
+
+        let request_id = (req.inner.req_id_seq + 666) | 0x80000000;
+        let mut body: Vec<u8> = vec![0, 0, 0, 7, 0, 0, 0, 0, 4, 2, 1];
+
+        BigEndian::write_u32(&mut body[4..8], request_id);
+
+        let msg = Message::from_body(body);
+
+        req.set_raw();
+        req.add_pipe(&mut ctx, eid, pipe);
+        req.on_recv_ready(&mut ctx, eid);
+        req.recv(&mut ctx, None);
+        req.on_recv_ack(&mut ctx, eid, msg);
+
+        let reply = rx.try_recv().expect("facade should have been sent a reply !");
+        let reply_msg = match reply {
+            Reply::Recv(msg) => Some(msg),
+            _ => None
+        };
+        let app_msg = reply_msg.unwrap();
+        assert_eq!(8, app_msg.get_header().len());
+        assert_eq!(3, app_msg.get_body().len());
+    }
+
     #[test]
     fn when_in_raw_mode_recv_will_accept_any_msg_with_a_four_bytes_header() {
         let (tx, rx) = mpsc::channel();
@@ -990,4 +1565,271 @@ mod tests {
         assert_eq!(4, app_msg.get_header().len());
         assert_eq!(2, app_msg.get_body().len());
      }
-}
\ No newline at end of file
+
+    fn reply_to(request: &Message, body: Vec<u8>) -> Message {
+        let mut raw_body = request.get_header().to_vec();
+
+        raw_body.extend_from_slice(&body);
+
+        Message::from_body(raw_body)
+    }
+
+    #[test]
+    fn contexts_receive_the_reply_matching_their_request() {
+        let (tx, _) = mpsc::channel();
+        let mut req = Req::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid = EndpointId::from(0);
+        let pipe = new_test_pipe(eid);
+        let (tx0, rx0) = mpsc::channel();
+        let (tx1, rx1) = mpsc::channel();
+        let cid0 = ContextId::from(0);
+        let cid1 = ContextId::from(1);
+
+        req.open_context(cid0, tx0).unwrap();
+        req.open_context(cid1, tx1).unwrap();
+        req.add_pipe(&mut ctx, eid, pipe);
+        req.on_send_ready(&mut ctx, eid);
+        req.send_in_context(&mut ctx, cid0, Message::from_body(vec![0]), None);
+        req.on_send_ack(&mut ctx, eid);
+        req.on_send_ready(&mut ctx, eid);
+        req.send_in_context(&mut ctx, cid1, Message::from_body(vec![1]), None);
+        req.on_send_ack(&mut ctx, eid);
+
+        assert!(rx0.try_recv().is_ok());
+        assert!(rx1.try_recv().is_ok());
+
+        let (reply0, reply1) = {
+            let sensor = ctx_sensor.borrow();
+            let send_calls = sensor.get_send_calls();
+
+            assert_eq!(2, send_calls.len());
+            assert!(send_calls[0].1.get_header() != send_calls[1].1.get_header());
+            (reply_to(&send_calls[0].1, vec![10]), reply_to(&send_calls[1].1, vec![11]))
+        };
+
+        req.on_recv_ack(&mut ctx, eid, reply1);
+        req.recv_in_context(&mut ctx, cid0, None);
+        req.on_recv_ack(&mut ctx, eid, reply0);
+        req.recv_in_context(&mut ctx, cid1, None);
+
+        match rx0.try_recv().expect("context should have been sent a reply !") {
+            Reply::Recv(msg) => assert_eq!(vec![10], msg.get_body()),
+            _ => panic!("context 0 should have received its reply")
+        }
+        match rx1.try_recv().expect("context should have been sent a reply !") {
+            Reply::Recv(msg) => assert_eq!(vec![11], msg.get_body()),
+            _ => panic!("context 1 should have received its reply")
+        }
+    }
+
+    #[test]
+    fn contexts_read_each_pipe_once_at_a_time() {
+        let (tx, _) = mpsc::channel();
+        let mut req = Req::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid = EndpointId::from(0);
+        let pipe = new_test_pipe(eid);
+        let (tx0, _rx0) = mpsc::channel();
+        let (tx1, _rx1) = mpsc::channel();
+
+        req.open_context(ContextId::from(0), tx0).unwrap();
+        req.open_context(ContextId::from(1), tx1).unwrap();
+        req.add_pipe(&mut ctx, eid, pipe);
+        req.on_send_ready(&mut ctx, eid);
+        req.send_in_context(&mut ctx, ContextId::from(0), Message::new(), None);
+        req.on_send_ack(&mut ctx, eid);
+        req.on_send_ready(&mut ctx, eid);
+        req.send_in_context(&mut ctx, ContextId::from(1), Message::new(), None);
+        req.on_send_ack(&mut ctx, eid);
+        req.recv_in_context(&mut ctx, ContextId::from(0), None);
+        req.recv_in_context(&mut ctx, ContextId::from(1), None);
+
+        ctx_sensor.borrow().assert_one_recv_from(eid);
+    }
+
+    #[test]
+    fn context_recv_without_request_will_fail() {
+        let (tx, _) = mpsc::channel();
+        let mut req = Req::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let (tx0, rx0) = mpsc::channel();
+        let cid = ContextId::from(0);
+
+        req.open_context(cid, tx0).unwrap();
+        req.recv_in_context(&mut ctx, cid, None);
+
+        match rx0.try_recv().expect("context should have been sent a reply !") {
+            Reply::Err(_) => {},
+            _ => panic!("recv should have failed")
+        }
+    }
+
+    #[test]
+    fn contexts_are_not_supported_in_raw_mode() {
+        let (tx, _) = mpsc::channel();
+        let mut req = Req::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let (tx0, _) = mpsc::channel();
+
+        req.on_device_plugged(&mut ctx);
+
+        assert!(req.open_context(ContextId::from(0), tx0).is_err());
+    }
+
+    #[test]
+    fn reply_received_before_recv_is_kept_for_recv() {
+        let (tx, rx) = mpsc::channel();
+        let mut req = Req::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid = EndpointId::from(0);
+
+        req.add_pipe(&mut ctx, eid, new_test_pipe(eid));
+        req.on_send_ready(&mut ctx, eid);
+        req.send(&mut ctx, Message::from_body(vec![1]), None);
+        req.on_send_ack(&mut ctx, eid);
+        let _ = rx.try_recv().expect("facade should have been sent a reply !");
+
+        ctx_sensor.borrow().assert_one_recv_from(eid);
+
+        let reply = reply_to(&ctx_sensor.borrow().get_send_calls()[0].1, vec![2]);
+
+        req.on_recv_ack(&mut ctx, eid, reply);
+        req.remove_pipe(&mut ctx, eid);
+
+        assert!(req.is_recv_ready());
+        assert_eq!(1, ctx_sensor.borrow().get_send_calls().len());
+
+        req.recv(&mut ctx, None);
+
+        match rx.try_recv().expect("facade should have been sent a reply !") {
+            Reply::Recv(msg) => assert_eq!(vec![2], msg.get_body()),
+            _ => panic!("recv should have returned the prefetched reply")
+        }
+    }
+
+    #[test]
+    fn request_is_resent_at_once_when_its_pipe_is_removed() {
+        let (tx, rx) = mpsc::channel();
+        let mut req = Req::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid0 = EndpointId::from(0);
+        let eid1 = EndpointId::from(1);
+
+        req.add_pipe(&mut ctx, eid0, new_test_pipe(eid0));
+        req.add_pipe(&mut ctx, eid1, new_test_pipe(eid1));
+        req.on_send_ready(&mut ctx, eid0);
+        req.send(&mut ctx, Message::from_body(vec![1]), None);
+        req.on_send_ack(&mut ctx, eid0);
+        let _ = rx.try_recv().expect("facade should have been sent a reply !");
+
+        req.on_send_ready(&mut ctx, eid1);
+        req.remove_pipe(&mut ctx, eid0);
+
+        let sensor = ctx_sensor.borrow();
+        let send_calls = sensor.get_send_calls();
+
+        assert_eq!(2, send_calls.len());
+        assert_eq!(eid1, send_calls[1].0);
+        assert_eq!(send_calls[0].1.get_header(), send_calls[1].1.get_header());
+    }
+
+    #[test]
+    fn recv_waits_for_the_reply_to_the_request_resent_when_its_pipe_is_removed() {
+        let (tx, rx) = mpsc::channel();
+        let mut req = Req::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid0 = EndpointId::from(0);
+        let eid1 = EndpointId::from(1);
+
+        req.add_pipe(&mut ctx, eid0, new_test_pipe(eid0));
+        req.add_pipe(&mut ctx, eid1, new_test_pipe(eid1));
+        req.on_send_ready(&mut ctx, eid0);
+        req.on_send_ready(&mut ctx, eid1);
+        req.send(&mut ctx, Message::from_body(vec![1]), None);
+
+        let first = ctx_sensor.borrow().get_send_calls()[0].0;
+        let other = if first == eid0 { eid1 } else { eid0 };
+
+        req.on_send_ack(&mut ctx, first);
+        let _ = rx.try_recv().expect("facade should have been sent a reply !");
+
+        req.recv(&mut ctx, None);
+        req.remove_pipe(&mut ctx, first);
+        assert!(rx.try_recv().is_err());
+
+        req.on_send_ack(&mut ctx, other);
+        assert!(rx.try_recv().is_err());
+
+        let reply = reply_to(&ctx_sensor.borrow().get_send_calls()[1].1, vec![2]);
+
+        req.on_recv_ack(&mut ctx, other, reply);
+
+        match rx.try_recv().expect("facade should have been sent a reply !") {
+            Reply::Recv(msg) => assert_eq!(vec![2], msg.get_body()),
+            _ => panic!("recv should have returned the reply to the resent request")
+        }
+    }
+
+    #[test]
+    fn request_receives_its_reply_without_notifying_the_send() {
+        let (tx, rx) = mpsc::channel();
+        let mut req = Req::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid = EndpointId::from(0);
+
+        req.add_pipe(&mut ctx, eid, new_test_pipe(eid));
+        req.on_send_ready(&mut ctx, eid);
+        req.request(&mut ctx, Message::from_body(vec![1]), None).unwrap();
+        req.on_send_ack(&mut ctx, eid);
+
+        assert!(rx.try_recv().is_err());
+
+        let mut body: Vec<u8> = vec![0, 0, 0, 0, 2];
+
+        BigEndian::write_u32(&mut body[0..4], req.inner.cur_req_id());
+        req.on_recv_ready(&mut ctx, eid);
+        req.on_recv_ack(&mut ctx, eid, Message::from_body(body));
+
+        match rx.try_recv().expect("facade should have been sent a reply !") {
+            Reply::Recv(msg) => assert_eq!(vec![2], msg.get_body()),
+            _ => panic!("request should have received its reply")
+        }
+    }
+
+    #[test]
+    fn request_timeout_while_sending_fails_the_send() {
+        let (tx, rx) = mpsc::channel();
+        let mut req = Req::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+
+        req.request(&mut ctx, Message::from_body(vec![1]), None).unwrap();
+        req.on_timer_tick(&mut ctx, Schedulable::RequestTimeout);
+
+        match rx.try_recv().expect("facade should have been sent a reply !") {
+            Reply::Err(e) => assert_eq!(io::ErrorKind::TimedOut, e.kind()),
+            _ => panic!("request should have timed out")
+        }
+    }
+
+    #[test]
+    fn request_is_not_supported_in_raw_mode() {
+        let (tx, _) = mpsc::channel();
+        let mut req = Req::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+
+        req.on_device_plugged(&mut ctx);
+
+        assert!(req.request(&mut ctx, Message::from_body(vec![1]), None).is_err());
+    }
+}
@@ -39,7 +39,7 @@ struct Inner {
     sd: HashSet<EndpointId>,
     ttl: u8,
     backtrace: Vec<u8>,
-    is_device_item: bool
+    is_raw: bool
 }
 
 /*****************************************************************************/
@@ -143,8 +143,11 @@ impl Protocol for Respondent {
     fn on_recv_ready(&mut self, ctx: &mut Context, eid: EndpointId) {
         self.apply(ctx, |s, ctx, inner| s.on_recv_ready(ctx, inner, eid))
     }
+    fn set_raw(&mut self) {
+        self.inner.is_raw = true;
+    }
     fn on_device_plugged(&mut self, _: &mut Context) {
-        self.inner.is_device_item = true;
+        self.set_raw();
     }
     fn is_send_ready(&self) -> bool {
         if let Some(ref state) = self.state {
@@ -205,7 +208,7 @@ impl State {
 /*****************************************************************************/
 
     fn send(self, ctx: &mut Context, inner: &mut Inner, msg: Rc<Message>, timeout: Timeout, eid: EndpointId) -> State {
-        if inner.is_device_item {
+        if inner.is_raw {
             if inner.is_send_ready_to(&eid) {
                 State::Idle.send_reply_to(ctx, inner, msg, timeout, eid)
             } else {
@@ -265,7 +268,7 @@ impl State {
         }
     }
     fn is_send_ready(&self, inner: &Inner) -> bool {
-        if inner.is_device_item {
+        if inner.is_raw {
             inner.is_send_ready()
         }
         else if let State::Active(ref eid) = *self {
@@ -291,7 +294,7 @@ impl State {
             State::Receiving(id, timeout) => {
                 if id == eid {
                     inner.on_recv_ack(ctx, timeout, msg);
-                    if inner.is_device_item {
+                    if inner.is_raw {
                         State::Idle
                     } else {
                         State::Active(eid)
@@ -339,7 +342,7 @@ impl Inner {
             sd: HashSet::new(),
             ttl: 8,
             backtrace: Vec::new(),
-            is_device_item: false
+            is_raw: false
         }
     }
     fn add_pipe(&mut self, eid: EndpointId, pipe: Pipe) {
@@ -403,7 +406,7 @@ impl Inner {
         self.fq.activate(&eid)
     }
     fn on_recv_ack(&mut self, ctx: &mut Context, timeout: Timeout, mut msg: Message) {
-        if !self.is_device_item {
+        if !self.is_raw {
             self.set_backtrace(&msg.header);
             msg.header.clear();
         }
@@ -424,7 +427,8 @@ impl Inner {
         self.fq.peek()
     }
 
-    fn raw_msg_to_msg(&self, raw_msg: Message, eid: EndpointId) -> Option<Message> {
+    fn raw_msg_to_msg(&self, mut raw_msg: Message, eid: EndpointId) -> Option<Message> {
+        let peer = raw_msg.peer.take();
         let (mut header, mut body) = raw_msg.split();
         let mut hops = 0;
         let mut eid_bytes: [u8; 4] = [0; 4];
@@ -451,7 +455,7 @@ impl Inner {
 
             let position = header.len() - 4;
             if header[position] & 0x80 != 0 {
-                return Some(Message::from_header_and_body(header, tail));
+                return Some(Message::from_header_and_body(header, tail).with_peer(peer));
             }
             body = tail;
         }
@@ -459,7 +463,7 @@ impl Inner {
     fn msg_to_raw_msg(&self, msg: Message) -> Option<(Message, EndpointId)> {
         let (mut header, body) = msg.split();
 
-        if !self.is_device_item {
+        if !self.is_raw {
             let backtrace = self.get_backtrace();
 
             header.clear();
@@ -4,17 +4,20 @@
 // or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
 // This file may not be copied, modified, or distributed except according to those terms.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::rc::Rc;
 use std::sync::mpsc::Sender;
 use std::io;
 
 use core::{EndpointId, Message};
-use core::socket::{Protocol, Reply};
-use core::config::ConfigOption;
+use core::socket::{Protocol, Reply, TopicList};
+use core::config::{ConfigOption, ConfigOptionName};
 use core::endpoint::Pipe;
 use core::context::Context;
 use super::priolist::Priolist;
+use super::subscription::Forwarded;
+use super::trie::Trie;
+use super::pattern;
 use super::{Timeout, PUB, SUB};
 use io_error::*;
 
@@ -33,7 +36,18 @@ struct Inner {
     reply_tx: Sender<Reply>,
     pipes: HashMap<EndpointId, Pipe>,
     fq: Priolist,
-    subscriptions: HashSet<Vec<u8>>
+    subscriptions: Trie,
+    exact_subscriptions: Trie,
+    patterns: HashMap<Vec<Vec<u8>>, usize>,
+    exclusions: Trie,
+    delimiter: u8,
+    dropped_msg_count: u64,
+    is_raw: bool,
+    forwarding: bool,
+    forwarded: Trie,
+    upstream: Trie,
+    writable: HashSet<EndpointId>,
+    outbox: HashMap<EndpointId, VecDeque<Forwarded>>
 }
 
 /*****************************************************************************/
@@ -69,7 +83,18 @@ impl From<Sender<Reply>> for Sub {
                 reply_tx: tx,
                 pipes: HashMap::new(),
                 fq: Priolist::new(),
-                subscriptions: HashSet::new()
+                subscriptions: Trie::new(),
+                exact_subscriptions: Trie::new(),
+                patterns: HashMap::new(),
+                exclusions: Trie::new(),
+                delimiter: b'.',
+                dropped_msg_count: 0,
+                is_raw: false,
+                forwarding: false,
+                forwarded: Trie::new(),
+                upstream: Trie::new(),
+                writable: HashSet::new(),
+                outbox: HashMap::new()
             },
             state: Some(State::Idle)
         }
@@ -86,8 +111,8 @@ impl Protocol for Sub {
     fn id(&self)      -> u16 { SUB }
     fn peer_id(&self) -> u16 { PUB }
 
-    fn add_pipe(&mut self, _: &mut Context, eid: EndpointId, pipe: Pipe) {
-        self.inner.add_pipe(eid, pipe)
+    fn add_pipe(&mut self, ctx: &mut Context, eid: EndpointId, pipe: Pipe) {
+        self.inner.add_pipe(ctx, eid, pipe)
     }
     fn remove_pipe(&mut self, ctx: &mut Context, eid: EndpointId) -> Option<Pipe> {
         let was_recv_ready = self.inner.is_recv_ready();
@@ -132,13 +157,45 @@ impl Protocol for Sub {
     fn is_recv_ready(&self) -> bool {
         self.inner.is_recv_ready()
     }
-    fn set_option(&mut self, opt: ConfigOption) -> io::Result<()> {
+    fn get_dropped_msg_count(&self) -> u64 {
+        self.inner.dropped_msg_count
+    }
+    fn set_option(&mut self, ctx: &mut Context, opt: ConfigOption) -> io::Result<()> {
         match opt {
-            ConfigOption::Subscribe(x)   => Ok(self.inner.subscribe(x)),
-            ConfigOption::Unsubscribe(x) => Ok(self.inner.unsubscribe(x)),
+            ConfigOption::Subscribe(x)          => Ok(self.inner.subscribe(ctx, x)),
+            ConfigOption::Unsubscribe(x)        => Ok(self.inner.unsubscribe(ctx, x)),
+            ConfigOption::SubscribeExact(x)     => Ok(self.inner.subscribe_exact(ctx, x)),
+            ConfigOption::UnsubscribeExact(x)   => Ok(self.inner.unsubscribe_exact(ctx, x)),
+            ConfigOption::SubscribePattern(x)   => Ok(self.inner.subscribe_pattern(ctx, x)),
+            ConfigOption::UnsubscribePattern(x) => Ok(self.inner.unsubscribe_pattern(ctx, x)),
+            ConfigOption::Exclude(x)            => Ok(self.inner.exclude(x)),
+            ConfigOption::Unexclude(x)          => Ok(self.inner.unexclude(x)),
+            ConfigOption::TopicDelimiter(x)     => self.inner.set_delimiter(x),
+            ConfigOption::SubscriptionForwarding(x) => Ok(self.inner.set_forwarding(ctx, x)),
+            _ => Err(invalid_input_io_error("option not supported"))
+        }
+    }
+    fn get_option(&self, name: ConfigOptionName) -> io::Result<ConfigOption> {
+        match name {
+            ConfigOptionName::TopicDelimiter => Ok(ConfigOption::TopicDelimiter(self.inner.delimiter)),
+            ConfigOptionName::SubscriptionForwarding => Ok(ConfigOption::SubscriptionForwarding(self.inner.forwarding)),
             _ => Err(invalid_input_io_error("option not supported"))
         }
     }
+    fn get_topics(&self, list: TopicList) -> io::Result<Vec<String>> {
+        match list {
+            TopicList::Subscriptions        => Ok(to_strings(self.inner.subscriptions.topics())),
+            TopicList::ExactSubscriptions   => Ok(to_strings(self.inner.exact_subscriptions.topics())),
+            TopicList::PatternSubscriptions => Ok(self.inner.get_patterns()),
+            TopicList::Exclusions           => Ok(to_strings(self.inner.exclusions.topics()))
+        }
+    }
+    fn set_raw(&mut self) {
+        self.inner.is_raw = true;
+    }
+    fn on_device_plugged(&mut self, _: &mut Context) {
+        self.set_raw();
+    }
     fn close(&mut self, ctx: &mut Context) {
         self.inner.close(ctx)
     }
@@ -180,8 +237,8 @@ impl State {
 /*                                                                           */
 /*****************************************************************************/
 
-    fn send(self, ctx: &mut Context, inner: &mut Inner, _: Rc<Message>, timeout: Timeout) -> State {
-        inner.send(ctx, timeout);
+    fn send(self, ctx: &mut Context, inner: &mut Inner, msg: Rc<Message>, timeout: Timeout) -> State {
+        inner.send(ctx, msg, timeout);
         self
     }
     fn on_send_ack(self, _: &mut Context, _: &mut Inner, _: EndpointId) -> State {
@@ -190,7 +247,8 @@ impl State {
     fn on_send_timeout(self, _: &mut Context, _: &mut Inner) -> State {
         self
     }
-    fn on_send_ready(self, _: &mut Context, _: &mut Inner, _: EndpointId) -> State {
+    fn on_send_ready(self, ctx: &mut Context, inner: &mut Inner, eid: EndpointId) -> State {
+        inner.on_send_ready(ctx, eid);
         self
     }
 
@@ -213,6 +271,7 @@ impl State {
                         inner.on_recv_ack(ctx, timeout, msg);
                         State::Idle
                     } else {
+                        inner.dropped_msg_count += 1;
                         State::Idle.recv(ctx, inner, timeout)
                     }
                 } else {
@@ -244,21 +303,118 @@ impl State {
 /*****************************************************************************/
 
 impl Inner {
-    fn add_pipe(&mut self, eid: EndpointId, pipe: Pipe) {
+    fn add_pipe(&mut self, ctx: &mut Context, eid: EndpointId, pipe: Pipe) {
         self.fq.insert(eid, pipe.get_recv_priority());
         self.pipes.insert(eid, pipe);
+
+        if self.forwarding {
+            self.forward_all_to(ctx, eid);
+        }
     }
     fn remove_pipe(&mut self, eid: EndpointId) -> Option<Pipe> {
         self.fq.remove(&eid);
+        self.writable.remove(&eid);
+        self.outbox.remove(&eid);
         self.pipes.remove(&eid)
     }
-    fn send(&mut self, ctx: &mut Context, timeout: Timeout) {
-        let error = other_io_error("Send is not supported by pull protocol");
-        let _ = self.reply_tx.send(Reply::Err(error));
+    fn send(&mut self, ctx: &mut Context, msg: Rc<Message>, timeout: Timeout) {
+        let reply = if self.is_raw {
+            match Forwarded::decode(&msg) {
+                Some(change) => {
+                    self.on_forwarded(ctx, change);
+                    Reply::Send
+                },
+                None => Reply::Err(invalid_input_io_error("Not a subscription"))
+            }
+        } else {
+            Reply::Err(other_io_error("Send is not supported by sub protocol"))
+        };
+
+        let _ = self.reply_tx.send(reply);
         if let Some(sched) = timeout {
             ctx.cancel(sched);
         }
     }
+    fn on_send_ready(&mut self, ctx: &mut Context, eid: EndpointId) {
+        match self.outbox.get_mut(&eid).and_then(|queue| queue.pop_front()) {
+            Some(change) => { self.pipes.get_mut(&eid).map(|pipe| pipe.send(ctx, Rc::new(change.encode()))); },
+            None      => { self.writable.insert(eid); }
+        }
+    }
+
+    // A peer that does not read the changes would make the queue grow forever,
+    // so only the latest change of each topic is kept until the pipe can send again
+    fn send_to(&mut self, ctx: &mut Context, eid: EndpointId, change: Forwarded) {
+        if self.writable.remove(&eid) {
+            self.pipes.get_mut(&eid).map(|pipe| pipe.send(ctx, Rc::new(change.encode())));
+        } else {
+            let queue = self.outbox.entry(eid).or_insert_with(VecDeque::new);
+
+            queue.retain(|queued| queued.topic() != change.topic());
+            queue.push_back(change);
+        }
+    }
+    fn forward(&mut self, ctx: &mut Context, change: Forwarded) {
+        let eids: Vec<EndpointId> = self.pipes.keys().cloned().collect();
+
+        for eid in eids {
+            self.send_to(ctx, eid, change.clone());
+        }
+    }
+    fn forward_all_to(&mut self, ctx: &mut Context, eid: EndpointId) {
+        // Without the empty topic, the publisher must learn that this peer filters
+        // even when it has not subscribed to anything yet
+        if !self.upstream.contains(b"") {
+            self.send_to(ctx, eid, Forwarded::Unsubscribe(Vec::new()));
+        }
+
+        for topic in self.upstream.topics() {
+            self.send_to(ctx, eid, Forwarded::Subscribe(topic));
+        }
+    }
+    fn set_forwarding(&mut self, ctx: &mut Context, forwarding: bool) {
+        if forwarding == self.forwarding {
+            return;
+        }
+
+        self.forwarding = forwarding;
+
+        if forwarding {
+            let eids: Vec<EndpointId> = self.pipes.keys().cloned().collect();
+
+            for eid in eids {
+                self.forward_all_to(ctx, eid);
+            }
+        } else if !self.upstream.contains(b"") {
+            // Let the publishers send everything again
+            self.forward(ctx, Forwarded::Subscribe(Vec::new()));
+        }
+    }
+    fn on_forwarded(&mut self, ctx: &mut Context, change: Forwarded) {
+        match change {
+            Forwarded::Subscribe(topic) => if !self.forwarded.contains(&topic) {
+                self.forwarded.subscribe(&topic);
+                self.add_upstream(ctx, topic);
+            },
+            Forwarded::Unsubscribe(topic) => if self.forwarded.contains(&topic) {
+                self.forwarded.unsubscribe(&topic);
+                self.remove_upstream(ctx, topic);
+            }
+        }
+    }
+
+    // The publishers are only told about prefixes, so each kind of subscription
+    // adds a prefix covering the messages it accepts, and the exact filtering is done here
+    fn add_upstream(&mut self, ctx: &mut Context, topic: Vec<u8>) {
+        if self.upstream.subscribe(&topic) && self.forwarding {
+            self.forward(ctx, Forwarded::Subscribe(topic));
+        }
+    }
+    fn remove_upstream(&mut self, ctx: &mut Context, topic: Vec<u8>) {
+        if self.upstream.unsubscribe(&topic) && self.forwarding {
+            self.forward(ctx, Forwarded::Unsubscribe(topic));
+        }
+    }
 
     fn recv(&mut self, ctx: &mut Context) -> Option<EndpointId> {
         self.fq.pop().map_or(None, |eid| self.recv_from(ctx, eid))
@@ -286,18 +442,121 @@ impl Inner {
         self.fq.peek()
     }
 
-    fn subscribe(&mut self, subscription :String) {
-        self.subscriptions.insert(subscription.into_bytes());
+    fn subscribe(&mut self, ctx: &mut Context, subscription :String) {
+        let topic = subscription.into_bytes();
+
+        if self.subscriptions.subscribe(&topic) {
+            self.add_upstream(ctx, topic);
+        }
     }
 
-    fn unsubscribe(&mut self, subscription :String) {
-        self.subscriptions.remove(&subscription.into_bytes());
+    fn unsubscribe(&mut self, ctx: &mut Context, subscription :String) {
+        let topic = subscription.into_bytes();
+
+        if self.subscriptions.unsubscribe(&topic) {
+            self.remove_upstream(ctx, topic);
+        }
+    }
+
+    fn subscribe_exact(&mut self, ctx: &mut Context, subscription :String) {
+        let topic = subscription.into_bytes();
+
+        if self.exact_subscriptions.subscribe(&topic) {
+            self.add_upstream(ctx, topic);
+        }
+    }
+
+    fn unsubscribe_exact(&mut self, ctx: &mut Context, subscription :String) {
+        let topic = subscription.into_bytes();
+
+        if self.exact_subscriptions.unsubscribe(&topic) {
+            self.remove_upstream(ctx, topic);
+        }
+    }
+
+    // Patterns are kept split in segments, so matching a message only splits its topic
+    fn split_pattern(&self, pattern: &[u8]) -> Vec<Vec<u8>> {
+        pattern::split(pattern, self.delimiter).into_iter().map(|segment| segment.to_vec()).collect()
+    }
+
+    fn subscribe_pattern(&mut self, ctx: &mut Context, subscription :String) {
+        let pattern = self.split_pattern(subscription.as_bytes());
+        let prefix = pattern::literal_prefix(subscription.as_bytes(), self.delimiter).to_vec();
+        let refs = {
+            let refs = self.patterns.entry(pattern).or_insert(0);
+
+            *refs += 1;
+            *refs
+        };
+
+        if refs == 1 {
+            self.add_upstream(ctx, prefix);
+        }
+    }
+
+    fn unsubscribe_pattern(&mut self, ctx: &mut Context, subscription :String) {
+        let pattern = self.split_pattern(subscription.as_bytes());
+        let refs = match self.patterns.get_mut(&pattern) {
+            Some(refs) => { *refs -= 1; *refs },
+            None => return
+        };
+
+        if refs == 0 {
+            let prefix = pattern::literal_prefix(subscription.as_bytes(), self.delimiter).to_vec();
+
+            self.patterns.remove(&pattern);
+            self.remove_upstream(ctx, prefix);
+        }
+    }
+
+    fn get_patterns(&self) -> Vec<String> {
+        let mut patterns: Vec<String> = self.patterns.keys()
+            .map(|p| String::from_utf8_lossy(&p.join(&self.delimiter)).into_owned())
+            .collect();
+
+        patterns.sort();
+        patterns
+    }
+
+    fn exclude(&mut self, exclusion :String) {
+        self.exclusions.subscribe(exclusion.as_bytes());
+    }
+
+    fn unexclude(&mut self, exclusion :String) {
+        self.exclusions.unsubscribe(exclusion.as_bytes());
+    }
+
+    fn set_delimiter(&mut self, delimiter: u8) -> io::Result<()> {
+        if self.patterns.is_empty() || delimiter == self.delimiter {
+            self.delimiter = delimiter;
+            Ok(())
+        } else {
+            Err(invalid_input_io_error("delimiter cannot change while patterns are subscribed"))
+        }
     }
 
     fn accept(&self, msg: &Message) -> bool {
         let payload = msg.get_body();
-        
-        self.subscriptions.iter().any(|s| payload.starts_with(s))
+
+        if self.exclusions.matches(payload) {
+            return false;
+        }
+        if self.subscriptions.matches(payload) || self.forwarded.matches(payload) {
+            return true;
+        }
+
+        let topic = pattern::topic_of(payload);
+
+        if self.exact_subscriptions.contains(topic) {
+            return true;
+        }
+        if self.patterns.is_empty() {
+            return false;
+        }
+
+        let segments = pattern::split(topic, self.delimiter);
+
+        self.patterns.keys().any(|p| pattern::matches(p, &segments))
     }
     fn close(&mut self, ctx: &mut Context) {
         for (_, pipe) in self.pipes.drain() {
@@ -306,6 +565,10 @@ impl Inner {
     }
 }
 
+fn to_strings(topics: Vec<Vec<u8>>) -> Vec<String> {
+    topics.into_iter().map(|s| String::from_utf8_lossy(&s).into_owned()).collect()
+}
+
 /*****************************************************************************/
 /*                                                                           */
 /* tests                                                                     */
@@ -320,9 +583,9 @@ mod tests {
     use std::sync::mpsc;
 
     use core::{EndpointId, Message, Scheduled};
-    use core::socket::{Protocol, Reply};
+    use core::socket::{Protocol, Reply, TopicList};
     use core::context::{Event};
-    use core::config::ConfigOption;
+    use core::config::{ConfigOption, ConfigOptionName};
     use core::tests::*;
 
     use super::*;
@@ -331,9 +594,9 @@ mod tests {
     fn when_recv_succeed_it_is_notified_and_timeout_is_cancelled() {
         let (tx, rx) = mpsc::channel();
         let mut sub = Sub::from(tx);
-        let _ = sub.set_option(ConfigOption::Subscribe(String::from("")));
         let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
         let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let _ = sub.set_option(&mut ctx, ConfigOption::Subscribe(String::from("")));
         let eid = EndpointId::from(0);
         let pipe = new_test_pipe(eid);
 
@@ -361,9 +624,9 @@ mod tests {
     fn when_recv_starts_event_is_raised() {
         let (tx, _) = mpsc::channel();
         let mut sub = Sub::from(tx);
-        let _ = sub.set_option(ConfigOption::Subscribe(String::from("")));
         let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
         let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let _ = sub.set_option(&mut ctx, ConfigOption::Subscribe(String::from("")));
         let eid = EndpointId::from(1);
         let pipe = new_test_pipe(eid);
 
@@ -382,6 +645,24 @@ mod tests {
         assert_eq!(Event::CanRecv(true), raised_evts[2]);
     }
 
+    #[test]
+    fn when_recv_filters_out_a_msg_it_is_counted_as_dropped() {
+        let (tx, _) = mpsc::channel();
+        let mut sub = Sub::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let _ = sub.set_option(&mut ctx, ConfigOption::Subscribe(String::from("A")));
+        let eid = EndpointId::from(1);
+        let pipe = new_test_pipe(eid);
+
+        sub.add_pipe(&mut ctx, eid, pipe);
+        sub.on_recv_ready(&mut ctx, eid);
+        sub.recv(&mut ctx, None);
+        sub.on_recv_ack(&mut ctx, eid, Message::from_body(vec![66]));
+
+        assert_eq!(1, sub.get_dropped_msg_count());
+    }
+
     /*//#[test]
     fn when_recv_ack_event_is_raised_if_there_is_another_pipe_ready() {
         let (tx, _) = mpsc::channel();
@@ -429,4 +710,288 @@ mod tests {
         assert_eq!(Event::CanRecv(true), raised_evts[0]);
         assert_eq!(Event::CanRecv(false), raised_evts[1]);
     }*/
-}
\ No newline at end of file
+
+    #[test]
+    fn get_subscriptions_should_return_the_current_topics() {
+        let (tx, _) = mpsc::channel();
+        let mut sub = Sub::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor);
+
+        sub.set_option(&mut ctx, ConfigOption::Subscribe(String::from("b"))).unwrap();
+        sub.set_option(&mut ctx, ConfigOption::Subscribe(String::from("a"))).unwrap();
+        sub.set_option(&mut ctx, ConfigOption::Subscribe(String::from("c"))).unwrap();
+        sub.set_option(&mut ctx, ConfigOption::Unsubscribe(String::from("c"))).unwrap();
+
+        let topics = sub.get_topics(TopicList::Subscriptions).unwrap();
+
+        assert_eq!(vec![String::from("a"), String::from("b")], topics);
+    }
+
+    #[test]
+    fn when_forwarding_subscriptions_are_sent_to_a_new_pipe() {
+        let (tx, _) = mpsc::channel();
+        let mut sub = Sub::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid = EndpointId::from(1);
+
+        sub.set_option(&mut ctx, ConfigOption::SubscriptionForwarding(true)).unwrap();
+        sub.set_option(&mut ctx, ConfigOption::Subscribe(String::from("A"))).unwrap();
+        sub.add_pipe(&mut ctx, eid, new_test_pipe(eid));
+        sub.on_send_ready(&mut ctx, eid);
+        sub.on_send_ready(&mut ctx, eid);
+        sub.on_send_ready(&mut ctx, eid);
+
+        let sensor = ctx_sensor.borrow();
+        let sent: Vec<&[u8]> = sensor.get_send_calls().iter().map(|call| call.1.get_body()).collect();
+
+        assert_eq!(vec![&[0][..], &[1, 65][..]], sent);
+    }
+
+    #[test]
+    fn when_forwarding_subscription_changes_are_sent_to_the_pipes() {
+        let (tx, _) = mpsc::channel();
+        let mut sub = Sub::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid = EndpointId::from(1);
+
+        sub.add_pipe(&mut ctx, eid, new_test_pipe(eid));
+        sub.on_send_ready(&mut ctx, eid);
+        sub.set_option(&mut ctx, ConfigOption::Subscribe(String::from("A"))).unwrap();
+        ctx_sensor.borrow().assert_no_send_call();
+
+        sub.set_option(&mut ctx, ConfigOption::SubscriptionForwarding(true)).unwrap();
+        sub.on_send_ready(&mut ctx, eid);
+        sub.set_option(&mut ctx, ConfigOption::Subscribe(String::from("A"))).unwrap();
+        sub.set_option(&mut ctx, ConfigOption::Unsubscribe(String::from("A"))).unwrap();
+        sub.on_send_ready(&mut ctx, eid);
+        sub.set_option(&mut ctx, ConfigOption::Unsubscribe(String::from("A"))).unwrap();
+        sub.on_send_ready(&mut ctx, eid);
+
+        let sensor = ctx_sensor.borrow();
+        let sent: Vec<&[u8]> = sensor.get_send_calls().iter().map(|call| call.1.get_body()).collect();
+
+        assert_eq!(vec![&[0][..], &[1, 65][..], &[0, 65][..]], sent);
+    }
+
+    #[test]
+    fn when_forwarding_only_the_latest_change_of_a_topic_waits_for_the_pipe() {
+        let (tx, _) = mpsc::channel();
+        let mut sub = Sub::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid = EndpointId::from(1);
+
+        sub.set_option(&mut ctx, ConfigOption::SubscriptionForwarding(true)).unwrap();
+        sub.add_pipe(&mut ctx, eid, new_test_pipe(eid));
+
+        for _ in 0..100 {
+            sub.set_option(&mut ctx, ConfigOption::Subscribe(String::from("A"))).unwrap();
+            sub.set_option(&mut ctx, ConfigOption::Subscribe(String::from("B"))).unwrap();
+            sub.set_option(&mut ctx, ConfigOption::Unsubscribe(String::from("A"))).unwrap();
+        }
+
+        for _ in 0..4 {
+            sub.on_send_ready(&mut ctx, eid);
+        }
+
+        let sensor = ctx_sensor.borrow();
+        let sent: Vec<&[u8]> = sensor.get_send_calls().iter().map(|call| call.1.get_body()).collect();
+
+        assert_eq!(vec![&[0][..], &[1, 66][..], &[0, 65][..]], sent);
+    }
+
+    #[test]
+    fn raw_sub_accepts_the_topics_sent_to_it() {
+        let (tx, rx) = mpsc::channel();
+        let mut sub = Sub::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid = EndpointId::from(1);
+
+        sub.set_raw();
+        sub.add_pipe(&mut ctx, eid, new_test_pipe(eid));
+        sub.send(&mut ctx, Message::from_body(vec![1, 65]), None);
+        sub.send(&mut ctx, Message::from_body(vec![2, 65]), None);
+        sub.on_recv_ready(&mut ctx, eid);
+        sub.recv(&mut ctx, None);
+        sub.on_recv_ack(&mut ctx, eid, Message::from_body(vec![66]));
+        sub.on_recv_ready(&mut ctx, eid);
+        sub.on_recv_ack(&mut ctx, eid, Message::from_body(vec![65, 66]));
+
+        assert_eq!(1, sub.get_dropped_msg_count());
+
+        let replies: Vec<Reply> = rx.try_iter().collect();
+        let is_ok = match replies.as_slice() {
+            &[Reply::Send, Reply::Err(_), Reply::Recv(ref msg)] => msg.get_body() == &[65, 66],
+            _ => false
+        };
+        assert!(is_ok);
+        ctx_sensor.borrow().assert_no_send_call();
+    }
+
+    #[test]
+    fn topic_subscribed_twice_is_kept_until_unsubscribed_twice() {
+        let (tx, _) = mpsc::channel();
+        let mut sub = Sub::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid = EndpointId::from(1);
+
+        sub.set_option(&mut ctx, ConfigOption::SubscriptionForwarding(true)).unwrap();
+        sub.add_pipe(&mut ctx, eid, new_test_pipe(eid));
+        sub.on_send_ready(&mut ctx, eid);
+        sub.set_option(&mut ctx, ConfigOption::Subscribe(String::from("A"))).unwrap();
+        sub.set_option(&mut ctx, ConfigOption::Subscribe(String::from("A"))).unwrap();
+        sub.set_option(&mut ctx, ConfigOption::Unsubscribe(String::from("A"))).unwrap();
+
+        assert!(sub.inner.accept(&Message::from_body(vec![65, 66])));
+
+        sub.on_send_ready(&mut ctx, eid);
+        sub.set_option(&mut ctx, ConfigOption::Unsubscribe(String::from("A"))).unwrap();
+        sub.on_send_ready(&mut ctx, eid);
+
+        assert!(!sub.inner.accept(&Message::from_body(vec![65, 66])));
+
+        let sensor = ctx_sensor.borrow();
+        let sent: Vec<&[u8]> = sensor.get_send_calls().iter().map(|call| call.1.get_body()).collect();
+
+        assert_eq!(vec![&[0][..], &[1, 65][..], &[0, 65][..]], sent);
+    }
+
+    #[test]
+    fn exact_subscription_only_accepts_the_same_topic() {
+        let (tx, _) = mpsc::channel();
+        let mut sub = Sub::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor);
+
+        sub.set_option(&mut ctx, ConfigOption::SubscribeExact(String::from("prices.usd"))).unwrap();
+
+        assert!(sub.inner.accept(&Message::from_body(b"prices.usd".to_vec())));
+        assert!(sub.inner.accept(&Message::from_body(b"prices.usd\x001.07".to_vec())));
+        assert!(!sub.inner.accept(&Message::from_body(b"prices.usd.eur".to_vec())));
+        assert!(!sub.inner.accept(&Message::from_body(b"prices.us".to_vec())));
+
+        sub.set_option(&mut ctx, ConfigOption::UnsubscribeExact(String::from("prices.usd"))).unwrap();
+
+        assert!(!sub.inner.accept(&Message::from_body(b"prices.usd".to_vec())));
+    }
+
+    #[test]
+    fn pattern_subscription_accepts_the_matching_topics() {
+        let (tx, _) = mpsc::channel();
+        let mut sub = Sub::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor);
+
+        sub.set_option(&mut ctx, ConfigOption::SubscribePattern(String::from("prices.*.eur"))).unwrap();
+
+        assert!(sub.inner.accept(&Message::from_body(b"prices.usd.eur\x001.07".to_vec())));
+        assert!(!sub.inner.accept(&Message::from_body(b"prices.usd.gbp\x000.79".to_vec())));
+        assert!(sub.set_option(&mut ctx, ConfigOption::TopicDelimiter(b'/')).is_err());
+
+        sub.set_option(&mut ctx, ConfigOption::UnsubscribePattern(String::from("prices.*.eur"))).unwrap();
+        sub.set_option(&mut ctx, ConfigOption::TopicDelimiter(b'/')).unwrap();
+        sub.set_option(&mut ctx, ConfigOption::SubscribePattern(String::from("prices/*/eur"))).unwrap();
+
+        assert!(sub.inner.accept(&Message::from_body(b"prices/usd/eur".to_vec())));
+        assert!(!sub.inner.accept(&Message::from_body(b"prices.usd.eur".to_vec())));
+    }
+
+    #[test]
+    fn exclusion_drops_messages_accepted_by_a_subscription() {
+        let (tx, _) = mpsc::channel();
+        let mut sub = Sub::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor);
+
+        sub.set_option(&mut ctx, ConfigOption::Subscribe(String::from("prices."))).unwrap();
+        sub.set_option(&mut ctx, ConfigOption::Exclude(String::from("prices.test."))).unwrap();
+        sub.set_option(&mut ctx, ConfigOption::Exclude(String::from("prices.test."))).unwrap();
+
+        assert!(sub.inner.accept(&Message::from_body(b"prices.usd.eur".to_vec())));
+        assert!(!sub.inner.accept(&Message::from_body(b"prices.test.eur".to_vec())));
+
+        sub.set_option(&mut ctx, ConfigOption::Unexclude(String::from("prices.test."))).unwrap();
+
+        assert!(!sub.inner.accept(&Message::from_body(b"prices.test.eur".to_vec())));
+
+        sub.set_option(&mut ctx, ConfigOption::Unexclude(String::from("prices.test."))).unwrap();
+
+        assert!(sub.inner.accept(&Message::from_body(b"prices.test.eur".to_vec())));
+    }
+
+    #[test]
+    fn when_forwarding_a_pattern_is_forwarded_as_its_literal_prefix() {
+        let (tx, _) = mpsc::channel();
+        let mut sub = Sub::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid = EndpointId::from(1);
+
+        sub.set_option(&mut ctx, ConfigOption::SubscriptionForwarding(true)).unwrap();
+        sub.add_pipe(&mut ctx, eid, new_test_pipe(eid));
+        sub.on_send_ready(&mut ctx, eid);
+        sub.set_option(&mut ctx, ConfigOption::SubscribePattern(String::from("a.*.c"))).unwrap();
+        sub.set_option(&mut ctx, ConfigOption::SubscribeExact(String::from("a."))).unwrap();
+        sub.set_option(&mut ctx, ConfigOption::Exclude(String::from("a.b"))).unwrap();
+        sub.on_send_ready(&mut ctx, eid);
+        sub.set_option(&mut ctx, ConfigOption::UnsubscribePattern(String::from("a.*.c"))).unwrap();
+        sub.on_send_ready(&mut ctx, eid);
+        sub.set_option(&mut ctx, ConfigOption::UnsubscribeExact(String::from("a."))).unwrap();
+        sub.on_send_ready(&mut ctx, eid);
+
+        let sensor = ctx_sensor.borrow();
+        let sent: Vec<&[u8]> = sensor.get_send_calls().iter().map(|call| call.1.get_body()).collect();
+
+        assert_eq!(vec![&[0][..], &[1, 97, 46][..], &[0, 97, 46][..]], sent);
+    }
+
+    #[test]
+    fn when_forwarding_a_pattern_ending_with_double_star_its_topic_is_forwarded_too() {
+        let (tx, _) = mpsc::channel();
+        let mut sub = Sub::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid = EndpointId::from(1);
+
+        sub.set_option(&mut ctx, ConfigOption::SubscriptionForwarding(true)).unwrap();
+        sub.add_pipe(&mut ctx, eid, new_test_pipe(eid));
+        sub.on_send_ready(&mut ctx, eid);
+        sub.set_option(&mut ctx, ConfigOption::SubscribePattern(String::from("a.**"))).unwrap();
+        sub.on_send_ready(&mut ctx, eid);
+
+        let sensor = ctx_sensor.borrow();
+        let sent: Vec<&[u8]> = sensor.get_send_calls().iter().map(|call| call.1.get_body()).collect();
+
+        assert_eq!(vec![&[0][..], &[1, 97][..]], sent);
+        assert!(sub.inner.accept(&Message::from_body(b"a".to_vec())));
+    }
+
+    #[test]
+    fn get_topics_should_return_the_current_filters() {
+        let (tx, _) = mpsc::channel();
+        let mut sub = Sub::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor);
+
+        sub.set_option(&mut ctx, ConfigOption::SubscribeExact(String::from("b"))).unwrap();
+        sub.set_option(&mut ctx, ConfigOption::SubscribeExact(String::from("a"))).unwrap();
+        sub.set_option(&mut ctx, ConfigOption::SubscribePattern(String::from("*.b"))).unwrap();
+        sub.set_option(&mut ctx, ConfigOption::SubscribePattern(String::from("*.a"))).unwrap();
+        sub.set_option(&mut ctx, ConfigOption::Exclude(String::from("c"))).unwrap();
+
+        let exact = sub.get_topics(TopicList::ExactSubscriptions).unwrap();
+        let patterns = sub.get_topics(TopicList::PatternSubscriptions).unwrap();
+        let exclusions = sub.get_topics(TopicList::Exclusions).unwrap();
+        let delimiter = sub.get_option(ConfigOptionName::TopicDelimiter).unwrap();
+
+        assert_eq!(vec![String::from("a"), String::from("b")], exact);
+        assert_eq!(vec![String::from("*.a"), String::from("*.b")], patterns);
+        assert_eq!(vec![String::from("c")], exclusions);
+        assert_eq!(ConfigOption::TopicDelimiter(b'.'), delimiter);
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use core::Message;
+use super::trie::Trie;
+
+const UNSUBSCRIBE: u8 = 0;
+const SUBSCRIBE:   u8 = 1;
+
+/// Subscription change sent upstream by a `Sub` socket when `SubscriptionForwarding` is on.
+/// The first byte of the body tells whether the topic that follows is subscribed or unsubscribed.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Forwarded {
+    Subscribe(Vec<u8>),
+    Unsubscribe(Vec<u8>)
+}
+
+impl Forwarded {
+    pub fn encode(self) -> Message {
+        let (flag, topic) = match self {
+            Forwarded::Subscribe(topic)   => (SUBSCRIBE, topic),
+            Forwarded::Unsubscribe(topic) => (UNSUBSCRIBE, topic)
+        };
+        let mut body = Vec::with_capacity(1 + topic.len());
+
+        body.push(flag);
+        body.extend_from_slice(&topic);
+
+        Message::from_body(body)
+    }
+
+    pub fn topic(&self) -> &[u8] {
+        match *self {
+            Forwarded::Subscribe(ref topic)   => topic,
+            Forwarded::Unsubscribe(ref topic) => topic
+        }
+    }
+
+    pub fn decode(msg: &Message) -> Option<Forwarded> {
+        let body = msg.get_body();
+
+        match body.first() {
+            Some(&SUBSCRIBE)   => Some(Forwarded::Subscribe(body[1..].to_vec())),
+            Some(&UNSUBSCRIBE) => Some(Forwarded::Unsubscribe(body[1..].to_vec())),
+            _ => None
+        }
+    }
+}
+
+/// Topics a peer forwarded to a `Pub` socket, a message is sent to the peer if its body starts with one of them.
+pub struct Filter {
+    topics: Trie
+}
+
+impl Default for Filter {
+    fn default() -> Filter {
+        Filter { topics: Trie::new() }
+    }
+}
+
+impl Filter {
+    /// Returns whether the change had any effect, so it is only passed on once.
+    pub fn apply(&mut self, change: &Forwarded) -> bool {
+        match *change {
+            Forwarded::Subscribe(ref topic)   => !self.topics.contains(topic) && self.topics.subscribe(topic),
+            Forwarded::Unsubscribe(ref topic) => self.topics.contains(topic) && self.topics.unsubscribe(topic)
+        }
+    }
+
+    pub fn accept(&self, msg: &Message) -> bool {
+        self.topics.matches(msg.get_body())
+    }
+
+    pub fn drain(&mut self) -> Vec<Vec<u8>> {
+        let topics = self.topics.topics();
+
+        self.topics = Trie::new();
+        topics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use core::Message;
+
+    use super::*;
+
+    #[test]
+    fn forwarded_subscriptions_can_be_decoded() {
+        let subscribe = Forwarded::Subscribe(b"abc".to_vec()).encode();
+        let unsubscribe = Forwarded::Unsubscribe(Vec::new()).encode();
+
+        assert_eq!(vec![1, 97, 98, 99], subscribe.get_body());
+        assert_eq!(Some(Forwarded::Subscribe(b"abc".to_vec())), Forwarded::decode(&subscribe));
+        assert_eq!(Some(Forwarded::Unsubscribe(Vec::new())), Forwarded::decode(&unsubscribe));
+        assert_eq!(None, Forwarded::decode(&Message::from_body(vec![2, 97])));
+        assert_eq!(None, Forwarded::decode(&Message::new()));
+    }
+
+    #[test]
+    fn filter_accepts_messages_starting_with_a_topic() {
+        let mut filter = Filter::default();
+
+        assert!(!filter.accept(&Message::from_body(b"abc".to_vec())));
+        assert!(filter.apply(&Forwarded::Subscribe(b"ab".to_vec())));
+        assert!(!filter.apply(&Forwarded::Subscribe(b"ab".to_vec())));
+        assert!(filter.accept(&Message::from_body(b"abc".to_vec())));
+        assert!(!filter.accept(&Message::from_body(b"bc".to_vec())));
+        assert!(filter.apply(&Forwarded::Unsubscribe(b"ab".to_vec())));
+        assert!(!filter.accept(&Message::from_body(b"abc".to_vec())));
+    }
+}
@@ -4,7 +4,8 @@
 // or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
 // This file may not be copied, modified, or distributed except according to those terms.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::cmp;
 use std::rc::Rc;
 use std::sync::mpsc::Sender;
 use std::time::Duration;
@@ -14,35 +15,42 @@ use time;
 
 use byteorder::*;
 
-use core::{EndpointId, Message};
+use core::{EndpointId, ContextId, Message};
+use core::survey::SurveyResponses;
 use core::socket::{Protocol, Reply};
-use core::config::ConfigOption;
+use core::config::{ConfigOption, ConfigOptionName};
 use core::endpoint::Pipe;
 use core::context::{Context, Schedulable};
 use super::priolist::Priolist;
-use super::{Timeout, SURVEYOR, RESPONDENT};
+use super::{Timeout, SURVEYOR, RESPONDENT, get_backtrace_len};
 use io_error::*;
 
 pub struct Surveyor {
     inner: Inner,
-    state: Option<State>
+    state: Option<State>,
+    contexts: HashMap<ContextId, SurveyContext>,
+    contexts_on_hold: VecDeque<ContextId>
 }
 
 enum State {
     Idle,
     Active(PendingSurvey),
     Receiving(EndpointId, Option<PendingSurvey>, Timeout),
-    RecvOnHold(Option<PendingSurvey>, Timeout)
+    RecvOnHold(Option<PendingSurvey>, Timeout),
+    Collecting(Option<EndpointId>, Collection)
 }
 
 struct Inner {
     reply_tx: Sender<Reply>,
     pipes: HashMap<EndpointId, Pipe>,
     bc: HashSet<EndpointId>,
+    backlog: HashMap<EndpointId, VecDeque<(u32, Rc<Message>)>>,
     fq: Priolist,
     survey_id_seq: u32,
-    is_device_item: bool,
-    deadline: Duration
+    is_raw: bool,
+    deadline: Duration,
+    dropped_msg_count: u64,
+    responses: VecDeque<Message>
 }
 
 struct PendingSurvey {
@@ -50,6 +58,36 @@ struct PendingSurvey {
     timeout: Timeout
 }
 
+/// Survey started by `Socket::survey`, the responses are read as they come
+/// until the deadline, the expected count or every respondent has answered.
+struct Collection {
+    survey: PendingSurvey,
+    expected: usize,
+    respondents: usize,
+    responses: Vec<Message>
+}
+
+/// Survey of a context, the responses are dispatched according to their survey id
+/// whoever read them, and kept until the context receives them or the deadline is reached.
+struct SurveyContext {
+    reply_tx: Sender<Reply>,
+    survey: Option<PendingSurvey>,
+    exchange: Exchange,
+    responses: VecDeque<Message>
+}
+
+enum Exchange {
+    Idle,
+    Receiving(EndpointId, Timeout),
+    RecvOnHold(Timeout)
+}
+
+/// Error returned when receiving from a surveyor while no survey is in progress,
+/// it is the only one of kind `NotConnected` so the end of a survey can be told apart.
+pub fn no_active_survey_io_error() -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, "Can't recv: no active survey")
+}
+
 /*****************************************************************************/
 /*                                                                           */
 /* Surveyor                                                                      */
@@ -83,7 +121,9 @@ impl From<Sender<Reply>> for Surveyor {
     fn from(tx: Sender<Reply>) -> Surveyor {
         Surveyor {
             inner: Inner::new(tx),
-            state: Some(State::Idle)
+            state: Some(State::Idle),
+            contexts: HashMap::new(),
+            contexts_on_hold: VecDeque::new()
         }
     }
 }
@@ -110,6 +150,7 @@ impl Protocol for Surveyor {
 
         if pipe.is_some() {
             self.apply(ctx, |s, ctx, inner| s.on_pipe_removed(ctx, inner, eid));
+            self.apply_on_contexts(ctx, |surv, ctx| surv.on_context_pipe_removed(ctx, eid));
         }
 
         ctx.check_send_ready_change(was_send_ready, is_send_ready);
@@ -134,32 +175,77 @@ impl Protocol for Surveyor {
     fn recv(&mut self, ctx: &mut Context, timeout: Timeout) {
         self.apply(ctx, |s, ctx, inner| s.recv(ctx, inner, timeout))
     }
+    fn survey(&mut self, ctx: &mut Context, msg: Message, expected: Option<usize>) -> io::Result<()> {
+        if self.inner.is_raw {
+            return Err(invalid_input_io_error("survey not supported in raw mode"));
+        }
+
+        self.apply(ctx, |s, ctx, inner| s.survey(ctx, inner, msg, expected));
+
+        Ok(())
+    }
     fn on_recv_ack(&mut self, ctx: &mut Context, eid: EndpointId, raw_msg: Message) {
-        if let Some((msg, survey_id)) = self.inner.raw_msg_to_msg(raw_msg) {
-            self.apply(ctx, |s, ctx, inner| s.on_recv_ack(ctx, inner, eid, msg, survey_id))
-        } else {
-            self.inner.on_recv_ack_malformed(ctx)
+        let reader = self.find_context_receiving_from(eid);
+
+        match self.inner.raw_msg_to_msg(raw_msg) {
+            Some((msg, survey_id)) => match self.find_context_surveying(survey_id) {
+                Some(cid) => {
+                    self.apply_on_contexts(ctx, |surv, ctx| surv.on_context_response(ctx, cid, msg));
+                    if reader != Some(cid) {
+                        self.resume_reader(ctx, reader, eid);
+                    }
+                },
+                None => if self.is_receiving_from(eid) {
+                    self.apply(ctx, |s, ctx, inner| s.on_recv_ack(ctx, inner, eid, msg, survey_id))
+                } else {
+                    self.apply(ctx, |s, ctx, inner| s.on_recv_ack_elsewhere(ctx, inner, msg, survey_id));
+                    if reader.is_some() {
+                        self.resume_reader(ctx, reader, eid);
+                    }
+                }
+            },
+            None => if reader.is_some() {
+                self.inner.dropped_msg_count += 1;
+                self.resume_reader(ctx, reader, eid);
+            } else {
+                self.inner.on_recv_ack_malformed(ctx)
+            }
         }
     }
     fn on_recv_timeout(&mut self, ctx: &mut Context) {
         self.apply(ctx, |s, ctx, inner| s.on_recv_timeout(ctx, inner))
     }
     fn on_recv_ready(&mut self, ctx: &mut Context, eid: EndpointId) {
-        self.apply(ctx, |s, ctx, inner| s.on_recv_ready(ctx, inner, eid))
+        self.apply(ctx, |s, ctx, inner| s.on_recv_ready(ctx, inner, eid));
+        self.apply_on_contexts(ctx, |surv, ctx| surv.resume_contexts_on_hold(ctx))
     }
-    fn set_option(&mut self, opt: ConfigOption) -> io::Result<()> {
+    fn set_option(&mut self, _: &mut Context, opt: ConfigOption) -> io::Result<()> {
         match opt {
             ConfigOption::SurveyDeadline(ivl) => Ok(self.inner.set_survey_deadline(ivl)),
             _ => Err(invalid_input_io_error("option not supported"))
         }
     }
+    fn get_option(&self, name: ConfigOptionName) -> io::Result<ConfigOption> {
+        match name {
+            ConfigOptionName::SurveyDeadline => Ok(ConfigOption::SurveyDeadline(self.inner.deadline)),
+            _ => Err(invalid_input_io_error("option not supported"))
+        }
+    }
     fn on_timer_tick(&mut self, ctx: &mut Context, task: Schedulable) {
-        if let Schedulable::SurveyCancel = task {
-            self.apply(ctx, |s, ctx, inner| s.on_survey_timeout(ctx, inner))
+        match task {
+            Schedulable::SurveyCancel => self.apply(ctx, |s, ctx, inner| s.on_survey_timeout(ctx, inner)),
+            Schedulable::ContextSurveyCancel(cid) => self.apply_on_contexts(ctx, |surv, _| surv.on_context_survey_timeout(cid)),
+            _ => {}
         }
     }
+    fn get_dropped_msg_count(&self) -> u64 {
+        self.inner.dropped_msg_count
+    }
+    fn set_raw(&mut self) {
+        self.inner.is_raw = true;
+    }
     fn on_device_plugged(&mut self, _: &mut Context) {
-        self.inner.is_device_item = true;
+        self.set_raw();
     }
     fn is_send_ready(&self) -> bool {
         if let Some(ref state) = self.state {
@@ -175,11 +261,302 @@ impl Protocol for Surveyor {
             false
         }
     }
+    fn open_context(&mut self, cid: ContextId, reply_tx: Sender<Reply>) -> io::Result<()> {
+        if self.inner.is_raw {
+            return Err(invalid_input_io_error("contexts not supported in raw mode"));
+        }
+
+        self.contexts.insert(cid, SurveyContext::new(reply_tx));
+
+        Ok(())
+    }
+    fn close_context(&mut self, ctx: &mut Context, cid: ContextId) {
+        if let Some(mut context) = self.contexts.remove(&cid) {
+            if let Some(ref survey) = context.survey {
+                self.inner.forget_survey(survey.id);
+            }
+            context.cancel(ctx);
+            self.contexts_on_hold.retain(|id| *id != cid);
+        }
+    }
+    fn send_in_context(&mut self, ctx: &mut Context, cid: ContextId, msg: Message, timeout: Timeout) {
+        self.apply_on_contexts(ctx, |surv, ctx| surv.send_context_survey(ctx, cid, msg, timeout))
+    }
+    fn recv_in_context(&mut self, ctx: &mut Context, cid: ContextId, timeout: Timeout) {
+        self.apply_on_contexts(ctx, |surv, ctx| surv.recv_context_response(ctx, cid, timeout))
+    }
+    fn on_context_recv_timeout(&mut self, ctx: &mut Context, cid: ContextId) {
+        self.apply_on_contexts(ctx, |surv, _| surv.on_context_recv_timeout_elapsed(cid))
+    }
     fn close(&mut self, ctx: &mut Context) {
+        self.contexts.clear();
+        self.contexts_on_hold.clear();
         self.inner.close(ctx)
     }
 }
 
+/*****************************************************************************/
+/*                                                                           */
+/* Contexts                                                                  */
+/*                                                                           */
+/*****************************************************************************/
+
+impl Surveyor {
+
+    fn apply_on_contexts<F>(&mut self, ctx: &mut Context, f: F) where F : FnOnce(&mut Surveyor, &mut Context) {
+        let was_send_ready = self.is_send_ready();
+        let was_recv_ready = self.is_recv_ready();
+
+        f(self, ctx);
+
+        let is_send_ready = self.is_send_ready();
+        let is_recv_ready = self.is_recv_ready();
+
+        ctx.check_send_ready_change(was_send_ready, is_send_ready);
+        ctx.check_recv_ready_change(was_recv_ready, is_recv_ready);
+    }
+
+    fn find_context_surveying(&self, survey_id: u32) -> Option<ContextId> {
+        self.contexts.iter()
+            .find(|&(_, context)| context.is_surveying(survey_id))
+            .map(|(cid, _)| *cid)
+    }
+
+    fn is_receiving_from(&self, eid: EndpointId) -> bool {
+        match self.state {
+            Some(State::Receiving(id, _, _)) => id == eid,
+            Some(State::Collecting(Some(id), _)) => id == eid,
+            _ => false
+        }
+    }
+
+    fn find_context_receiving_from(&self, eid: EndpointId) -> Option<ContextId> {
+        self.contexts.iter()
+            .find(|&(_, context)| context.is_receiving_from(eid))
+            .map(|(cid, _)| *cid)
+    }
+
+    /// Sending a new survey in a context abandons the previous one of the same context only.
+    fn send_context_survey(&mut self, ctx: &mut Context, cid: ContextId, msg: Message, timeout: Timeout) {
+        if !self.contexts.contains_key(&cid) {
+            return;
+        }
+
+        let survey_id = self.inner.next_survey_id();
+        let raw_msg = Rc::new(encode(msg, survey_id));
+        let deadline = ctx.schedule(Schedulable::ContextSurveyCancel(cid), self.inner.deadline).ok();
+
+        self.inner.broadcast_to_all(ctx, survey_id, raw_msg);
+        self.contexts_on_hold.retain(|id| *id != cid);
+
+        if let Some(context) = self.contexts.get_mut(&cid) {
+            if let Some(ref survey) = context.survey {
+                self.inner.forget_survey(survey.id);
+            }
+            context.cancel(ctx);
+            context.survey = Some(PendingSurvey {
+                id: survey_id,
+                timeout: deadline
+            });
+            context.send_reply(Reply::Send);
+        }
+
+        if let Some(sched) = timeout {
+            ctx.cancel(sched);
+        }
+    }
+
+    fn recv_context_response(&mut self, ctx: &mut Context, cid: ContextId, timeout: Timeout) {
+        let exchange = match self.contexts.get_mut(&cid) {
+            Some(context) => {
+                if context.survey.is_none() {
+                    return context.send_error(ctx, no_active_survey_io_error(), timeout);
+                }
+                if let Some(msg) = context.responses.pop_front() {
+                    context.send_reply(Reply::Recv(msg));
+                    if let Some(sched) = timeout {
+                        ctx.cancel(sched);
+                    }
+                    return;
+                }
+
+                match self.inner.recv(ctx) {
+                    Some(eid) => Exchange::Receiving(eid, timeout),
+                    None => Exchange::RecvOnHold(timeout)
+                }
+            },
+            None => return
+        };
+
+        if let Exchange::RecvOnHold(_) = exchange {
+            self.contexts_on_hold.push_back(cid);
+        }
+        if let Some(context) = self.contexts.get_mut(&cid) {
+            context.exchange = exchange;
+        }
+    }
+
+    fn resume_contexts_on_hold(&mut self, ctx: &mut Context) {
+        while self.inner.is_recv_ready() {
+            let cid = match self.contexts_on_hold.pop_front() {
+                Some(cid) => cid,
+                None => break
+            };
+            let exchange = match self.contexts.get_mut(&cid) {
+                Some(context) => context.take_exchange(),
+                None => continue
+            };
+
+            if let Exchange::RecvOnHold(timeout) = exchange {
+                self.recv_context_response(ctx, cid, timeout);
+            } else if let Some(context) = self.contexts.get_mut(&cid) {
+                context.exchange = exchange;
+            }
+        }
+    }
+
+    /// The pipe read on behalf of a context or of the socket returned the response
+    /// to another survey, so the reader must try again.
+    fn resume_reader(&mut self, ctx: &mut Context, reader: Option<ContextId>, eid: EndpointId) {
+        match reader {
+            Some(cid) => {
+                let exchange = match self.contexts.get_mut(&cid) {
+                    Some(context) => context.take_exchange(),
+                    None => return
+                };
+
+                if let Exchange::Receiving(_, timeout) = exchange {
+                    self.apply_on_contexts(ctx, |surv, ctx| surv.recv_context_response(ctx, cid, timeout));
+                }
+            },
+            None => self.apply(ctx, |s, ctx, inner| s.on_recv_ack_consumed(ctx, inner, eid))
+        }
+    }
+
+    fn on_context_response(&mut self, ctx: &mut Context, cid: ContextId, msg: Message) {
+        if let Some(context) = self.contexts.get_mut(&cid) {
+            match context.take_exchange() {
+                Exchange::Receiving(_, timeout) |
+                Exchange::RecvOnHold(timeout) => {
+                    context.send_reply(Reply::Recv(msg));
+                    if let Some(sched) = timeout {
+                        ctx.cancel(sched);
+                    }
+                },
+                Exchange::Idle => context.responses.push_back(msg)
+            }
+        }
+
+        self.contexts_on_hold.retain(|id| *id != cid);
+    }
+
+    fn on_context_recv_timeout_elapsed(&mut self, cid: ContextId) {
+        if let Some(context) = self.contexts.get_mut(&cid) {
+            match context.take_exchange() {
+                Exchange::Receiving(..) |
+                Exchange::RecvOnHold(_) => context.send_reply(Reply::Err(timedout_io_error("Recv timed out"))),
+                Exchange::Idle => {}
+            }
+        }
+
+        self.contexts_on_hold.retain(|id| *id != cid);
+    }
+
+    /// Unlike the socket, a context waiting for a response is told the survey is over.
+    fn on_context_survey_timeout(&mut self, cid: ContextId) {
+        if let Some(context) = self.contexts.get_mut(&cid) {
+            if let Some(survey) = context.survey.take() {
+                self.inner.forget_survey(survey.id);
+            }
+            context.responses.clear();
+
+            match context.take_exchange() {
+                Exchange::Receiving(..) |
+                Exchange::RecvOnHold(_) => context.send_reply(Reply::Err(timedout_io_error("Survey expired"))),
+                Exchange::Idle => {}
+            }
+        }
+
+        self.contexts_on_hold.retain(|id| *id != cid);
+    }
+
+    fn on_context_pipe_removed(&mut self, ctx: &mut Context, eid: EndpointId) {
+        let cids: Vec<ContextId> = self.contexts.iter()
+            .filter(|&(_, context)| context.is_receiving_from(eid))
+            .map(|(cid, _)| *cid)
+            .collect();
+
+        for cid in cids {
+            self.resume_reader(ctx, Some(cid), eid);
+        }
+    }
+}
+
+impl Collection {
+    fn on_response(&mut self, inner: &mut Inner, msg: Message, survey_id: u32) {
+        if self.survey.id == survey_id {
+            self.responses.push(msg);
+        } else {
+            inner.dropped_msg_count += 1;
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.responses.len() >= self.expected
+    }
+}
+
+impl SurveyContext {
+    fn new(reply_tx: Sender<Reply>) -> SurveyContext {
+        SurveyContext {
+            reply_tx: reply_tx,
+            survey: None,
+            exchange: Exchange::Idle,
+            responses: VecDeque::new()
+        }
+    }
+
+    fn send_reply(&self, reply: Reply) {
+        let _ = self.reply_tx.send(reply);
+    }
+
+    fn send_error(&self, ctx: &mut Context, error: io::Error, timeout: Timeout) {
+        self.send_reply(Reply::Err(error));
+        if let Some(sched) = timeout {
+            ctx.cancel(sched);
+        }
+    }
+
+    fn take_exchange(&mut self) -> Exchange {
+        ::std::mem::replace(&mut self.exchange, Exchange::Idle)
+    }
+
+    /// Abandons the survey and the pending recv, without notifying the facade.
+    fn cancel(&mut self, ctx: &mut Context) {
+        if let Some(sched) = self.survey.take().and_then(|p| p.timeout) {
+            ctx.cancel(sched);
+        }
+        match self.take_exchange() {
+            Exchange::Receiving(_, Some(sched)) |
+            Exchange::RecvOnHold(Some(sched)) => ctx.cancel(sched),
+            _ => {}
+        }
+
+        self.responses.clear();
+    }
+
+    fn is_surveying(&self, survey_id: u32) -> bool {
+        self.survey.as_ref().map_or(false, |p| p.id == survey_id)
+    }
+
+    fn is_receiving_from(&self, eid: EndpointId) -> bool {
+        match self.exchange {
+            Exchange::Receiving(id, _) => id == eid,
+            _ => false
+        }
+    }
+}
+
 /*****************************************************************************/
 /*                                                                           */
 /* State                                                                     */
@@ -194,7 +571,8 @@ impl State {
             State::Idle           => "Idle",
             State::Active(..)     => "Active",
             State::Receiving(..)  => "Receiving",
-            State::RecvOnHold(..) => "RecvOnHold"
+            State::RecvOnHold(..) => "RecvOnHold",
+            State::Collecting(..) => "Collecting"
         }
     }
 
@@ -207,6 +585,13 @@ impl State {
                     State::Receiving(id, p, timeout)
                 }
             },
+            State::Collecting(Some(id), c) => {
+                if id == eid {
+                    State::Collecting(None, c).collect(ctx, inner)
+                } else {
+                    State::Collecting(Some(id), c)
+                }
+            },
             any => any
         }
     }
@@ -222,9 +607,12 @@ impl State {
             inner.cancel(ctx, p);
         }
 
-        let pending_survey = inner.send(ctx, msg, timeout);
+        inner.responses.clear();
 
-        State::Active(pending_survey)
+        match inner.send(ctx, msg, timeout) {
+            Some(pending_survey) => State::Active(pending_survey),
+            None => State::Idle
+        }
     }
     fn on_send_ack(self, _: &mut Context, _: &mut Inner, _: EndpointId) -> State {
         self
@@ -232,14 +620,61 @@ impl State {
     fn on_send_timeout(self, _: &mut Context, _: &mut Inner) -> State {
         self
     }
-    fn on_send_ready(self, _: &mut Context, inner: &mut Inner, eid: EndpointId) -> State {
-        inner.on_send_ready(eid);
+    fn on_send_ready(self, ctx: &mut Context, inner: &mut Inner, eid: EndpointId) -> State {
+        inner.on_send_ready(ctx, eid);
         self
     }
     fn is_send_ready(&self, inner: &Inner) -> bool {
         inner.is_send_ready()
     }
 
+/*****************************************************************************/
+/*                                                                           */
+/* survey                                                                    */
+/*                                                                           */
+/*****************************************************************************/
+
+    /// Busy pipes get the survey once they are ready, so every pipe counts as a respondent.
+    fn survey(self, ctx: &mut Context, inner: &mut Inner, msg: Message, expected: Option<usize>) -> State {
+        if let State::Active(p) = self {
+            inner.cancel(ctx, p);
+        }
+
+        inner.responses.clear();
+
+        let survey_id = inner.next_survey_id();
+        let respondents = inner.pipes.len();
+        let survey = PendingSurvey {
+            id: survey_id,
+            timeout: ctx.schedule(Schedulable::SurveyCancel, inner.deadline).ok()
+        };
+        let collection = Collection {
+            survey: survey,
+            expected: expected.map_or(respondents, |count| cmp::min(count, respondents)),
+            respondents: respondents,
+            responses: Vec::new()
+        };
+
+        inner.broadcast_to_all(ctx, survey_id, Rc::new(encode(msg, survey_id)));
+
+        State::Collecting(None, collection).collect(ctx, inner)
+    }
+    fn collect(self, ctx: &mut Context, inner: &mut Inner) -> State {
+        match self {
+            State::Collecting(reader, c) => {
+                if c.is_complete() {
+                    inner.on_survey_complete(ctx, c);
+                    State::Idle
+                } else if reader.is_some() {
+                    State::Collecting(reader, c)
+                } else {
+                    State::Collecting(inner.recv(ctx), c)
+                }
+            },
+            any => any
+        }
+    }
+
 /*****************************************************************************/
 /*                                                                           */
 /* recv                                                                      */
@@ -247,12 +682,17 @@ impl State {
 /*****************************************************************************/
 
     fn recv(self, ctx: &mut Context, inner: &mut Inner, timeout: Timeout) -> State {
-        if inner.is_device_item {
+        if inner.is_raw {
             inner.recv(ctx).map_or_else(
                 |   | State::RecvOnHold(None, timeout),
                 |eid| State::Receiving(eid, None, timeout))
         } else if let State::Active(p) = self {
-            State::Idle.recv_reply_for(ctx, inner, timeout, p)
+            if let Some(msg) = inner.responses.pop_front() {
+                inner.on_recv_ack(ctx, timeout, msg);
+                State::Active(p)
+            } else {
+                State::Idle.recv_reply_for(ctx, inner, timeout, p)
+            }
         } else {
             inner.recv_when_inactive(ctx, timeout);
 
@@ -282,12 +722,79 @@ impl State {
                         inner.on_recv_ack(ctx, timeout, msg);
                         State::Active(p)
                     } else {
+                        inner.dropped_msg_count += 1;
                         State::Idle.recv_reply_for(ctx, inner, timeout, p)
                     }
                 } else {
                     State::Receiving(id, Some(p), timeout)
                 }
             },
+            State::Collecting(Some(id), mut c) => {
+                if id == eid {
+                    c.on_response(inner, msg, survey_id);
+                    State::Collecting(None, c).collect(ctx, inner)
+                } else {
+                    State::Collecting(Some(id), c)
+                }
+            },
+            any => any
+        }
+    }
+    /// The response to the survey of the socket was read on behalf of a context.
+    fn on_recv_ack_elsewhere(self, ctx: &mut Context, inner: &mut Inner, msg: Message, survey_id: u32) -> State {
+        match self {
+            State::RecvOnHold(Some(p), timeout) => {
+                if p.id == survey_id {
+                    inner.on_recv_ack(ctx, timeout, msg);
+                    State::Active(p)
+                } else {
+                    inner.dropped_msg_count += 1;
+                    State::RecvOnHold(Some(p), timeout)
+                }
+            },
+            State::Active(p) => {
+                if p.id == survey_id {
+                    inner.responses.push_back(msg);
+                } else {
+                    inner.dropped_msg_count += 1;
+                }
+                State::Active(p)
+            },
+            State::Receiving(id, Some(p), timeout) => {
+                if p.id == survey_id {
+                    inner.responses.push_back(msg);
+                } else {
+                    inner.dropped_msg_count += 1;
+                }
+                State::Receiving(id, Some(p), timeout)
+            },
+            State::Collecting(reader, mut c) => {
+                c.on_response(inner, msg, survey_id);
+                State::Collecting(reader, c).collect(ctx, inner)
+            },
+            any => {
+                inner.dropped_msg_count += 1;
+                any
+            }
+        }
+    }
+    /// The response read by the socket belonged to the survey of a context.
+    fn on_recv_ack_consumed(self, ctx: &mut Context, inner: &mut Inner, eid: EndpointId) -> State {
+        match self {
+            State::Receiving(id, Some(p), timeout) => {
+                if id == eid {
+                    State::Idle.recv_reply_for(ctx, inner, timeout, p)
+                } else {
+                    State::Receiving(id, Some(p), timeout)
+                }
+            },
+            State::Collecting(Some(id), c) => {
+                if id == eid {
+                    State::Collecting(None, c).collect(ctx, inner)
+                } else {
+                    State::Collecting(Some(id), c)
+                }
+            },
             any => any
         }
     }
@@ -308,21 +815,28 @@ impl State {
                 State::Idle.recv(ctx, inner, timeout)
             },
             State::RecvOnHold(Some(p), timeout) => State::Active(p).recv(ctx, inner, timeout),
+            State::Collecting(None, c) => State::Collecting(None, c).collect(ctx, inner),
             any => any
         }
     }
-    fn on_survey_timeout(self, _: &mut Context, _: &mut Inner) -> State {
-        if let State::Active(_) = self {
-            State::Idle
-        } else {
-            self
+    fn on_survey_timeout(self, ctx: &mut Context, inner: &mut Inner) -> State {
+        match self {
+            State::Active(_) => {
+                inner.responses.clear();
+                State::Idle
+            },
+            State::Collecting(_, c) => {
+                inner.on_survey_complete(ctx, c);
+                State::Idle
+            },
+            any => any
         }
     }
     fn is_recv_ready(&self, inner: &Inner) -> bool {
-        if inner.is_device_item {
+        if inner.is_raw {
             inner.is_recv_ready()
         } else if let State::Active(..) = *self {
-            inner.is_recv_ready()
+            inner.is_recv_ready() || !inner.responses.is_empty()
         } else {
             false
         }
@@ -342,10 +856,13 @@ impl Inner {
             reply_tx: tx,
             pipes: HashMap::new(),
             bc: HashSet::new(),
+            backlog: HashMap::new(),
             fq: Priolist::new(),
             survey_id_seq: time::get_time().nsec as u32,
-            is_device_item: false,
-            deadline: Duration::from_secs(1)
+            is_raw: false,
+            deadline: Duration::from_secs(1),
+            dropped_msg_count: 0,
+            responses: VecDeque::new()
         }
     }
     fn add_pipe(&mut self, eid: EndpointId, pipe: Pipe) {
@@ -354,26 +871,55 @@ impl Inner {
     }
     fn remove_pipe(&mut self, eid: EndpointId) -> Option<Pipe> {
         self.bc.remove(&eid);
+        self.backlog.remove(&eid);
         self.fq.remove(&eid);
         self.pipes.remove(&eid)
     }
-    fn send(&mut self, ctx: &mut Context, msg: Rc<Message>, timeout: Timeout) -> PendingSurvey {
-        for id in self.bc.drain() {
-            self.pipes.get_mut(&id).map(|pipe| pipe.send(ctx, msg.clone()));
-        }
+    fn send(&mut self, ctx: &mut Context, msg: Rc<Message>, timeout: Timeout) -> Option<PendingSurvey> {
+        self.broadcast(ctx, msg);
 
         let _ = self.reply_tx.send(Reply::Send);
         if let Some(sched) = timeout {
             ctx.cancel(sched);
         }
 
-        PendingSurvey {
-            id: self.cur_survey_id(),
-            timeout: ctx.schedule(Schedulable::SurveyCancel, self.deadline).ok()
+        if self.is_raw {
+            None
+        } else {
+            Some(PendingSurvey {
+                id: self.cur_survey_id(),
+                timeout: ctx.schedule(Schedulable::SurveyCancel, self.deadline).ok()
+            })
+        }
+    }
+    fn broadcast(&mut self, ctx: &mut Context, msg: Rc<Message>) {
+        for id in self.bc.drain() {
+            self.pipes.get_mut(&id).map(|pipe| pipe.send(ctx, msg.clone()));
+        }
+    }
+    /// Context surveys overlap, so a pipe still busy writing a survey
+    /// gets the next ones once it is ready again instead of missing them.
+    fn broadcast_to_all(&mut self, ctx: &mut Context, survey_id: u32, msg: Rc<Message>) {
+        for (id, pipe) in self.pipes.iter_mut() {
+            if self.bc.remove(id) {
+                pipe.send(ctx, msg.clone());
+            } else {
+                self.backlog.entry(*id).or_insert_with(VecDeque::new).push_back((survey_id, msg.clone()));
+            }
+        }
+    }
+    fn forget_survey(&mut self, survey_id: u32) {
+        for queue in self.backlog.values_mut() {
+            queue.retain(|&(id, _)| id != survey_id);
         }
     }
-    fn on_send_ready(&mut self, eid: EndpointId) {
-        self.bc.insert(eid);
+    fn on_send_ready(&mut self, ctx: &mut Context, eid: EndpointId) {
+        let queued = self.backlog.get_mut(&eid).and_then(|queue| queue.pop_front());
+
+        match queued {
+            Some((_, msg)) => { self.pipes.get_mut(&eid).map(|pipe| pipe.send(ctx, msg)); },
+            None => { self.bc.insert(eid); }
+        }
     }
     fn is_send_ready(&self) -> bool {
         !self.bc.is_empty()
@@ -389,7 +935,7 @@ impl Inner {
         })
     }
     fn recv_when_inactive(&mut self, ctx: &mut Context, timeout: Timeout) {
-        let error = other_io_error("Can't recv: no active survey");
+        let error = no_active_survey_io_error();
         let _ = self.reply_tx.send(Reply::Err(error));
         if let Some(sched) = timeout {
             ctx.cancel(sched);
@@ -416,6 +962,19 @@ impl Inner {
         let _ = self.reply_tx.send(Reply::Err(error));
     }
 
+    fn on_survey_complete(&mut self, ctx: &mut Context, collection: Collection) {
+        let survey_id = collection.survey.id;
+        let result = SurveyResponses {
+            responses: collection.responses,
+            respondents: collection.respondents
+        };
+
+        self.cancel(ctx, collection.survey);
+        self.forget_survey(survey_id);
+
+        let _ = self.reply_tx.send(Reply::Survey(result));
+    }
+
     fn cancel(&self, ctx: &mut Context, mut pending_survey: PendingSurvey) {
         if let Some(timeout) = pending_survey.timeout.take() {
             ctx.cancel(timeout);
@@ -423,7 +982,7 @@ impl Inner {
     }
 
     fn msg_to_raw_msg(&mut self, msg: Message) -> Message {
-        if self.is_device_item {
+        if self.is_raw {
             msg
         } else {
             encode(msg, self.next_survey_id())
@@ -431,7 +990,7 @@ impl Inner {
     }
 
     fn raw_msg_to_msg(&self, raw_msg: Message) -> Option<(Message, u32)> {
-        if self.is_device_item {
+        if self.is_raw {
             let cur_survey_id = self.cur_survey_id();
             decode(raw_msg).map(|(msg, _)| (msg, cur_survey_id))
         } else {
@@ -452,6 +1011,8 @@ impl Inner {
         self.deadline = ivl;
     }
     fn close(&mut self, ctx: &mut Context) {
+        self.responses.clear();
+        self.backlog.clear();
         for (_, pipe) in self.pipes.drain() {
             pipe.close(ctx);
         }
@@ -469,14 +1030,16 @@ fn encode(msg: Message, survey_id: u32) -> Message {
     raw_msg
 }
 
-fn decode(raw_msg: Message) -> Option<(Message, u32)> {
+fn decode(mut raw_msg: Message) -> Option<(Message, u32)> {
     if raw_msg.get_body().len() < 4 {
         return None;
     }
 
+    let peer = raw_msg.peer.take();
     let (mut header, mut payload) = raw_msg.split();
-    let body = payload.split_off(4);
-    let survey_id = BigEndian::read_u32(&payload);
+    let backtrace_len = get_backtrace_len(&payload).unwrap_or(4);
+    let body = payload.split_off(backtrace_len);
+    let survey_id = BigEndian::read_u32(&payload[backtrace_len - 4..]);
 
     if header.is_empty() {
         header = payload;
@@ -484,7 +1047,7 @@ fn decode(raw_msg: Message) -> Option<(Message, u32)> {
         header.extend_from_slice(&payload);
     }
 
-    Some((Message::from_header_and_body(header, body), survey_id))
+    Some((Message::from_header_and_body(header, body).with_peer(peer), survey_id))
 }
 
 /*****************************************************************************/
@@ -502,9 +1065,9 @@ mod tests {
 
     use byteorder::*;
 
-    use core::{EndpointId, Message, Scheduled};
+    use core::{EndpointId, ContextId, Message, Scheduled};
     use core::socket::{Protocol, Reply};
-    use core::context::{Event};
+    use core::context::{Event, Schedulable};
     use core::tests::*;
 
     use super::*;
@@ -639,6 +1202,23 @@ mod tests {
         assert_eq!(3, body.len());
     }
 
+    #[test]
+    fn when_in_raw_mode_send_will_not_schedule_the_survey_deadline() {
+        let (tx, _) = mpsc::channel();
+        let mut surv = Surveyor::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid = EndpointId::from(5);
+        let pipe = new_test_pipe(eid);
+
+        surv.set_raw();
+        surv.add_pipe(&mut ctx, eid, pipe);
+        surv.on_send_ready(&mut ctx, eid);
+        surv.send(&mut ctx, Message::from_body(vec![1u8, 2, 3]), None);
+
+        assert!(ctx_sensor.borrow().get_schedule_calls().is_empty());
+    }
+
     #[test]
     fn when_in_regular_mode_recv_while_idle_will_fail() {
         let (tx, rx) = mpsc::channel();
@@ -786,5 +1366,303 @@ mod tests {
         assert_eq!(3, app_msg.get_body().len());
     }
 
+    #[test]
+    fn when_in_raw_mode_recv_moves_the_whole_backtrace_from_the_body_to_the_header() {
+        let (tx, rx) = mpsc::channel();
+        let mut surv = Surveyor::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid = EndpointId::from(0);
+        let pipe = new_test_pipe(eid);
+
+        let any_survey_id = 666 | 0x80000000;
+        let mut body: Vec<u8> = vec![0, 0, 0, 7, 0, 0, 0, 0, 4, 2, 1];
+
+        BigEndian::write_u32(&mut body[4..8], any_survey_id);
+
+        let msg = Message::from_body(body);
+        surv.on_device_plugged(&mut ctx);
+        surv.add_pipe(&mut ctx, eid, pipe);
+        surv.on_recv_ready(&mut ctx, eid);
+        surv.recv(&mut ctx, None);
+        surv.on_recv_ack(&mut ctx, eid, msg);
+
+        let reply = rx.try_recv().expect("facade should have been sent a reply !");
+        let reply_msg = match reply {
+            Reply::Recv(msg) => Some(msg),
+            _ => None
+        };
+        let app_msg = reply_msg.expect("facade should have been sent a Recv reply !");
+        assert_eq!(vec![0, 0, 0, 7, 0x80, 0, 2, 154], app_msg.get_header());
+        assert_eq!(vec![4, 2, 1], app_msg.get_body());
+    }
+
     // test CanRecv events ...
+
+    fn respond_to(survey: &Message, body: Vec<u8>) -> Message {
+        let mut raw_body = survey.get_header().to_vec();
+
+        raw_body.extend_from_slice(&body);
+
+        Message::from_body(raw_body)
+    }
+
+    #[test]
+    fn contexts_receive_the_responses_to_their_own_survey() {
+        let (tx, _) = mpsc::channel();
+        let mut surv = Surveyor::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid = EndpointId::from(0);
+        let (tx0, rx0) = mpsc::channel();
+        let (tx1, rx1) = mpsc::channel();
+        let cid0 = ContextId::from(0);
+        let cid1 = ContextId::from(1);
+
+        surv.open_context(cid0, tx0).unwrap();
+        surv.open_context(cid1, tx1).unwrap();
+        surv.add_pipe(&mut ctx, eid, new_test_pipe(eid));
+        surv.on_send_ready(&mut ctx, eid);
+        surv.send_in_context(&mut ctx, cid0, Message::from_body(vec![0]), None);
+        surv.on_send_ready(&mut ctx, eid);
+        surv.send_in_context(&mut ctx, cid1, Message::from_body(vec![1]), None);
+
+        assert!(rx0.try_recv().is_ok());
+        assert!(rx1.try_recv().is_ok());
+
+        let (response0, response1) = {
+            let sensor = ctx_sensor.borrow();
+            let send_calls = sensor.get_send_calls();
+
+            assert_eq!(2, send_calls.len());
+            assert!(send_calls[0].1.get_header() != send_calls[1].1.get_header());
+            (respond_to(&send_calls[0].1, vec![10]), respond_to(&send_calls[1].1, vec![11]))
+        };
+
+        surv.on_recv_ready(&mut ctx, eid);
+        surv.recv_in_context(&mut ctx, cid1, None);
+        surv.on_recv_ack(&mut ctx, eid, response0);
+        surv.on_recv_ready(&mut ctx, eid);
+        surv.on_recv_ack(&mut ctx, eid, response1);
+        surv.recv_in_context(&mut ctx, cid0, None);
+
+        match rx0.try_recv().expect("context should have been sent a reply !") {
+            Reply::Recv(msg) => assert_eq!(vec![10], msg.get_body()),
+            _ => panic!("context 0 should have received its response")
+        }
+        match rx1.try_recv().expect("context should have been sent a reply !") {
+            Reply::Recv(msg) => assert_eq!(vec![11], msg.get_body()),
+            _ => panic!("context 1 should have received its response")
+        }
+    }
+
+    #[test]
+    fn context_survey_waits_for_a_busy_pipe_to_be_ready() {
+        let (tx, _) = mpsc::channel();
+        let mut surv = Surveyor::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid = EndpointId::from(0);
+        let (tx0, _rx0) = mpsc::channel();
+        let (tx1, _rx1) = mpsc::channel();
+        let cid0 = ContextId::from(0);
+        let cid1 = ContextId::from(1);
+
+        surv.open_context(cid0, tx0).unwrap();
+        surv.open_context(cid1, tx1).unwrap();
+        surv.add_pipe(&mut ctx, eid, new_test_pipe(eid));
+        surv.on_send_ready(&mut ctx, eid);
+        surv.send_in_context(&mut ctx, cid0, Message::from_body(vec![0]), None);
+        surv.send_in_context(&mut ctx, cid1, Message::from_body(vec![1]), None);
+
+        assert_eq!(1, ctx_sensor.borrow().get_send_calls().len());
+
+        surv.on_send_ready(&mut ctx, eid);
+
+        let sensor = ctx_sensor.borrow();
+        let send_calls = sensor.get_send_calls();
+
+        assert_eq!(2, send_calls.len());
+        assert_eq!(vec![1], send_calls[1].1.get_body());
+        assert!(!surv.is_send_ready());
+    }
+
+    #[test]
+    fn socket_survey_response_read_for_a_context_is_kept_for_the_socket() {
+        let (tx, rx) = mpsc::channel();
+        let mut surv = Surveyor::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid = EndpointId::from(0);
+        let (tx0, _rx0) = mpsc::channel();
+        let cid = ContextId::from(0);
+
+        surv.open_context(cid, tx0).unwrap();
+        surv.add_pipe(&mut ctx, eid, new_test_pipe(eid));
+        surv.on_send_ready(&mut ctx, eid);
+        surv.send(&mut ctx, Message::from_body(vec![0]), None);
+        surv.on_send_ready(&mut ctx, eid);
+        surv.send_in_context(&mut ctx, cid, Message::from_body(vec![1]), None);
+
+        assert!(rx.try_recv().is_ok());
+
+        let response = respond_to(&ctx_sensor.borrow().get_send_calls()[0].1, vec![10]);
+
+        surv.on_recv_ready(&mut ctx, eid);
+        surv.recv_in_context(&mut ctx, cid, None);
+        surv.on_recv_ack(&mut ctx, eid, response);
+        surv.recv(&mut ctx, None);
+
+        match rx.try_recv().expect("facade should have been sent a reply !") {
+            Reply::Recv(msg) => assert_eq!(vec![10], msg.get_body()),
+            _ => panic!("socket should have received its response")
+        }
+    }
+
+    #[test]
+    fn context_waiting_for_a_response_is_told_when_the_deadline_is_reached() {
+        let (tx, _) = mpsc::channel();
+        let mut surv = Surveyor::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let (tx0, rx0) = mpsc::channel();
+        let cid = ContextId::from(0);
+
+        surv.open_context(cid, tx0).unwrap();
+        surv.send_in_context(&mut ctx, cid, Message::from_body(vec![0]), None);
+        surv.recv_in_context(&mut ctx, cid, None);
+        surv.on_timer_tick(&mut ctx, Schedulable::ContextSurveyCancel(cid));
+
+        assert!(rx0.try_recv().is_ok());
+        match rx0.try_recv().expect("context should have been sent a reply !") {
+            Reply::Err(e) => assert_eq!(::std::io::ErrorKind::TimedOut, e.kind()),
+            _ => panic!("recv should have failed")
+        }
+    }
+
+    #[test]
+    fn survey_collects_the_responses_until_every_respondent_answered() {
+        let (tx, rx) = mpsc::channel();
+        let mut surv = Surveyor::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid0 = EndpointId::from(0);
+        let eid1 = EndpointId::from(1);
+
+        surv.add_pipe(&mut ctx, eid0, new_test_pipe(eid0));
+        surv.add_pipe(&mut ctx, eid1, new_test_pipe(eid1));
+        surv.on_send_ready(&mut ctx, eid0);
+        surv.survey(&mut ctx, Message::from_body(vec![0]), None).unwrap();
+
+        let survey = {
+            let sensor = ctx_sensor.borrow();
+            let send_calls = sensor.get_send_calls();
+
+            assert_eq!(1, send_calls.len());
+            Message::from_header_and_body(send_calls[0].1.get_header().to_vec(), Vec::new())
+        };
+
+        surv.on_send_ready(&mut ctx, eid1);
+        assert_eq!(2, ctx_sensor.borrow().get_send_calls().len());
+
+        surv.on_recv_ready(&mut ctx, eid1);
+        surv.on_recv_ack(&mut ctx, eid1, respond_to(&survey, vec![11]));
+        assert!(rx.try_recv().is_err());
+
+        surv.on_recv_ready(&mut ctx, eid0);
+        surv.on_recv_ack(&mut ctx, eid0, respond_to(&survey, vec![10]));
+
+        match rx.try_recv().expect("surveyor should have sent a reply !") {
+            Reply::Survey(result) => {
+                assert_eq!(2, result.respondents);
+                assert_eq!(vec![11], result.responses[0].get_body());
+                assert_eq!(vec![10], result.responses[1].get_body());
+            },
+            _ => panic!("surveyor should have sent the responses")
+        }
+    }
+
+    #[test]
+    fn survey_replies_with_the_responses_received_before_the_deadline() {
+        let (tx, rx) = mpsc::channel();
+        let mut surv = Surveyor::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid0 = EndpointId::from(0);
+        let eid1 = EndpointId::from(1);
+
+        surv.add_pipe(&mut ctx, eid0, new_test_pipe(eid0));
+        surv.add_pipe(&mut ctx, eid1, new_test_pipe(eid1));
+        surv.on_send_ready(&mut ctx, eid0);
+        surv.on_send_ready(&mut ctx, eid1);
+        surv.survey(&mut ctx, Message::from_body(vec![0]), None).unwrap();
+
+        let response = respond_to(&ctx_sensor.borrow().get_send_calls()[0].1, vec![10]);
+
+        surv.on_recv_ready(&mut ctx, eid0);
+        surv.on_recv_ack(&mut ctx, eid0, response);
+        surv.on_timer_tick(&mut ctx, Schedulable::SurveyCancel);
+
+        match rx.try_recv().expect("surveyor should have sent a reply !") {
+            Reply::Survey(result) => {
+                assert_eq!(2, result.respondents);
+                assert_eq!(1, result.responses.len());
+            },
+            _ => panic!("surveyor should have sent the responses")
+        }
+    }
+
+    #[test]
+    fn survey_stops_once_the_expected_responses_are_received() {
+        let (tx, rx) = mpsc::channel();
+        let mut surv = Surveyor::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let eid0 = EndpointId::from(0);
+        let eid1 = EndpointId::from(1);
+
+        surv.add_pipe(&mut ctx, eid0, new_test_pipe(eid0));
+        surv.add_pipe(&mut ctx, eid1, new_test_pipe(eid1));
+        surv.on_send_ready(&mut ctx, eid0);
+        surv.on_send_ready(&mut ctx, eid1);
+        surv.survey(&mut ctx, Message::from_body(vec![0]), Some(1)).unwrap();
+
+        let response = respond_to(&ctx_sensor.borrow().get_send_calls()[1].1, vec![11]);
+
+        surv.on_recv_ready(&mut ctx, eid1);
+        surv.on_recv_ack(&mut ctx, eid1, response);
+
+        match rx.try_recv().expect("surveyor should have sent a reply !") {
+            Reply::Survey(result) => {
+                assert_eq!(2, result.respondents);
+                assert_eq!(vec![11], result.responses[0].get_body());
+            },
+            _ => panic!("surveyor should have sent the responses")
+        }
+    }
+
+    #[test]
+    fn survey_is_not_supported_in_raw_mode() {
+        let (tx, _) = mpsc::channel();
+        let mut surv = Surveyor::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+
+        surv.on_device_plugged(&mut ctx);
+
+        assert!(surv.survey(&mut ctx, Message::from_body(vec![0]), None).is_err());
+    }
+
+    #[test]
+    fn contexts_are_not_supported_in_raw_mode() {
+        let (tx, _) = mpsc::channel();
+        let mut surv = Surveyor::from(tx);
+        let ctx_sensor = Rc::new(RefCell::new(TestContextSensor::default()));
+        let mut ctx = TestContext::with_sensor(ctx_sensor.clone());
+        let (tx0, _) = mpsc::channel();
+
+        surv.on_device_plugged(&mut ctx);
+
+        assert!(surv.open_context(ContextId::from(0), tx0).is_err());
+    }
 }
\ No newline at end of file
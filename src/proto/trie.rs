@@ -0,0 +1,367 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+/*
+Trie needs to support:
+ - subscribe (topic): called each time a topic is subscribed, duplicates are counted
+ - unsubscribe (topic): called each time a topic is unsubscribed
+ - matches (payload): called for each message received
+
+Like nanomsg's nn_trie, nodes hold a compressed prefix so a chain of single-child
+nodes is stored as one node, and the children are sorted by the first byte of
+their prefix. Matching a message walks down at most one node per prefix byte,
+whatever the number of subscriptions.
+*/
+
+/* functions spec
+### SUBSCRIBE
+ - walk down the nodes whose prefix is a prefix of the remaining topic
+ - split the node whose prefix only partially matches the remaining topic
+ - add a node for what remains of the topic, if anything
+ - increment the reference count of the node where the topic ends
+
+### UNSUBSCRIBE
+ - decrement the reference count of the node where the topic ends
+ - remove the node if it has no references and no children left
+ - merge the node with its child if it has no references and a single child
+
+### MATCHES
+ - walk down the nodes whose prefix is a prefix of the remaining payload
+ - the payload matches as soon as one of the nodes is referenced
+
+*/
+
+pub struct Trie {
+    root: Node
+}
+
+struct Node {
+    prefix: Vec<u8>,
+    refs: usize,
+    children: Vec<Node>
+}
+
+impl Trie {
+
+    pub fn new() -> Trie {
+        Trie { root: Node::new(Vec::new(), 0) }
+    }
+
+    /// Returns `true` when the topic was not subscribed yet.
+    pub fn subscribe(&mut self, topic: &[u8]) -> bool {
+        self.root.subscribe(topic)
+    }
+
+    /// Returns `true` when the last subscription to the topic is removed.
+    pub fn unsubscribe(&mut self, topic: &[u8]) -> bool {
+        self.root.unsubscribe(topic)
+    }
+
+    pub fn contains(&self, topic: &[u8]) -> bool {
+        let mut node = &self.root;
+        let mut rest = topic;
+
+        loop {
+            if rest.is_empty() {
+                return node.refs > 0;
+            }
+
+            match node.find_child(rest) {
+                Some(child) => {
+                    rest = &rest[child.prefix.len()..];
+                    node = child;
+                },
+                None => return false
+            }
+        }
+    }
+
+    /// Tells whether one of the topics is a prefix of the payload.
+    pub fn matches(&self, payload: &[u8]) -> bool {
+        let mut node = &self.root;
+        let mut rest = payload;
+
+        loop {
+            if node.refs > 0 {
+                return true;
+            }
+
+            match node.find_child(rest) {
+                Some(child) => {
+                    rest = &rest[child.prefix.len()..];
+                    node = child;
+                },
+                None => return false
+            }
+        }
+    }
+
+    /// Returns each subscribed topic once, sorted.
+    pub fn topics(&self) -> Vec<Vec<u8>> {
+        let mut topics = Vec::new();
+        let mut prefix = Vec::new();
+
+        self.root.collect(&mut prefix, &mut topics);
+        topics
+    }
+}
+
+impl Node {
+
+    fn new(prefix: Vec<u8>, refs: usize) -> Node {
+        Node {
+            prefix: prefix,
+            refs: refs,
+            children: Vec::new()
+        }
+    }
+
+    fn child_index(&self, first: u8) -> Result<usize, usize> {
+        self.children.binary_search_by_key(&first, |child| child.prefix[0])
+    }
+
+    /// Finds the child whose prefix is a prefix of the key.
+    fn find_child(&self, key: &[u8]) -> Option<&Node> {
+        key.first().and_then(|&first| self.child_index(first).ok()).and_then(|index| {
+            let child = &self.children[index];
+
+            if key.starts_with(&child.prefix) { Some(child) } else { None }
+        })
+    }
+
+    fn subscribe(&mut self, topic: &[u8]) -> bool {
+        if topic.is_empty() {
+            self.refs += 1;
+            return self.refs == 1;
+        }
+
+        match self.child_index(topic[0]) {
+            Ok(index) => {
+                let common = common_prefix_len(&self.children[index].prefix, topic);
+
+                if common < self.children[index].prefix.len() {
+                    self.children[index].split(common);
+                }
+
+                self.children[index].subscribe(&topic[common..])
+            },
+            Err(index) => {
+                self.children.insert(index, Node::new(topic.to_vec(), 1));
+                true
+            }
+        }
+    }
+
+    fn unsubscribe(&mut self, topic: &[u8]) -> bool {
+        if topic.is_empty() {
+            if self.refs == 0 {
+                return false;
+            }
+
+            self.refs -= 1;
+            return self.refs == 0;
+        }
+
+        let index = match self.child_index(topic[0]) {
+            Ok(index) if topic.starts_with(&self.children[index].prefix) => index,
+            _ => return false
+        };
+        let len = self.children[index].prefix.len();
+        let removed = self.children[index].unsubscribe(&topic[len..]);
+
+        if self.children[index].refs == 0 {
+            match self.children[index].children.len() {
+                0 => { self.children.remove(index); },
+                1 => self.children[index].merge(),
+                _ => {}
+            }
+        }
+
+        removed
+    }
+
+    /// Moves the end of the prefix to a new child, holding the references and children of the node.
+    fn split(&mut self, at: usize) {
+        let suffix = self.prefix.split_off(at);
+        let mut child = Node::new(suffix, self.refs);
+
+        child.children = self.children.split_off(0);
+        self.refs = 0;
+        self.children.push(child);
+    }
+
+    /// Absorbs the single child of the node.
+    fn merge(&mut self) {
+        let child = self.children.remove(0);
+
+        self.prefix.extend_from_slice(&child.prefix);
+        self.refs = child.refs;
+        self.children = child.children;
+    }
+
+    fn collect(&self, prefix: &mut Vec<u8>, topics: &mut Vec<Vec<u8>>) {
+        let len = prefix.len();
+
+        prefix.extend_from_slice(&self.prefix);
+
+        if self.refs > 0 {
+            topics.push(prefix.clone());
+        }
+
+        for child in &self.children {
+            child.collect(prefix, topics);
+        }
+
+        prefix.truncate(len);
+    }
+}
+
+fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b.iter()).take_while(|&(x, y)| x == y).count()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::Trie;
+
+    #[test]
+    fn empty_trie_matches_nothing() {
+        let trie = Trie::new();
+
+        assert!(!trie.matches(b""));
+        assert!(!trie.matches(b"abc"));
+    }
+
+    #[test]
+    fn empty_topic_matches_everything() {
+        let mut trie = Trie::new();
+
+        assert!(trie.subscribe(b""));
+        assert!(trie.matches(b""));
+        assert!(trie.matches(b"abc"));
+    }
+
+    #[test]
+    fn topic_matches_the_payloads_it_prefixes() {
+        let mut trie = Trie::new();
+
+        trie.subscribe(b"abc");
+        trie.subscribe(b"abd");
+        trie.subscribe(b"b");
+
+        assert!(trie.matches(b"abc"));
+        assert!(trie.matches(b"abcd"));
+        assert!(trie.matches(b"abd"));
+        assert!(trie.matches(b"bcd"));
+        assert!(!trie.matches(b"ab"));
+        assert!(!trie.matches(b"abe"));
+        assert!(!trie.matches(b"c"));
+    }
+
+    #[test]
+    fn subscriptions_are_counted() {
+        let mut trie = Trie::new();
+
+        assert!(trie.subscribe(b"abc"));
+        assert!(!trie.subscribe(b"abc"));
+        assert!(!trie.unsubscribe(b"abc"));
+        assert!(trie.matches(b"abc"));
+        assert!(trie.unsubscribe(b"abc"));
+        assert!(!trie.matches(b"abc"));
+        assert!(!trie.unsubscribe(b"abc"));
+    }
+
+    #[test]
+    fn unsubscribe_keeps_the_other_topics() {
+        let mut trie = Trie::new();
+
+        trie.subscribe(b"ab");
+        trie.subscribe(b"abcd");
+        trie.subscribe(b"abce");
+
+        assert!(trie.unsubscribe(b"ab"));
+        assert!(!trie.matches(b"abc"));
+        assert!(trie.matches(b"abcd"));
+        assert!(trie.unsubscribe(b"abcd"));
+        assert!(!trie.matches(b"abcd"));
+        assert!(trie.matches(b"abce"));
+        assert!(!trie.unsubscribe(b"abc"));
+        assert!(trie.contains(b"abce"));
+        assert!(!trie.contains(b"abc"));
+        assert_eq!(vec![b"abce".to_vec()], trie.topics());
+    }
+
+    #[test]
+    fn topics_are_sorted() {
+        let mut trie = Trie::new();
+
+        trie.subscribe(b"b");
+        trie.subscribe(b"ab");
+        trie.subscribe(b"a");
+        trie.subscribe(b"a");
+        trie.subscribe(b"");
+
+        assert_eq!(vec![b"".to_vec(), b"a".to_vec(), b"ab".to_vec(), b"b".to_vec()], trie.topics());
+    }
+}
+
+#[cfg(test)]
+mod benches {
+
+    use test::{Bencher, black_box};
+
+    use super::Trie;
+
+    fn trie_with_topics(count: usize) -> Trie {
+        let mut trie = Trie::new();
+
+        for i in 0..count {
+            trie.subscribe(format!("topic.{:06}.", i).as_bytes());
+        }
+
+        trie
+    }
+
+    fn bench_matches(b: &mut Bencher, count: usize) {
+        let trie = trie_with_topics(count);
+        let matching = format!("topic.{:06}.payload", count / 2).into_bytes();
+        let not_matching = b"topic.none.payload".to_vec();
+
+        b.iter(|| {
+            black_box(trie.matches(&matching));
+            black_box(trie.matches(&not_matching));
+        });
+    }
+
+    #[bench]
+    fn matches_among_10_topics(b: &mut Bencher) {
+        bench_matches(b, 10)
+    }
+
+    #[bench]
+    fn matches_among_1_000_topics(b: &mut Bencher) {
+        bench_matches(b, 1_000)
+    }
+
+    #[bench]
+    fn matches_among_100_000_topics(b: &mut Bencher) {
+        bench_matches(b, 100_000)
+    }
+
+    // The matching of the former HashSet based subscriptions, for comparison
+    #[bench]
+    fn scans_100_000_topics(b: &mut Bencher) {
+        let topics: Vec<Vec<u8>> = (0..100_000).map(|i| format!("topic.{:06}.", i).into_bytes()).collect();
+        let matching = format!("topic.{:06}.payload", 50_000).into_bytes();
+        let not_matching = b"topic.none.payload".to_vec();
+
+        b.iter(|| {
+            black_box(topics.iter().any(|topic| matching.starts_with(topic)));
+            black_box(topics.iter().any(|topic| not_matching.starts_with(topic)));
+        });
+    }
+}
@@ -11,12 +11,12 @@ use std::io;
 use std::time::Duration;
 
 use mio::{Evented, Token, Ready, PollOpt};
-use mio::timer::{Timer, Timeout};
 
 use core::context;
 use core::device;
 use core::probe;
 use core::network::Network;
+use core::endpoint::PeerCredentials;
 use core::{SocketId, EndpointId, DeviceId, ProbeId, Message, EndpointTmpl, Scheduled};
 use transport::{Transport, Destination};
 use transport::endpoint::*;
@@ -24,6 +24,7 @@ use transport::pipe;
 use transport::acceptor;
 use super::bus::EventLoopBus;
 use super::{Signal, Task};
+use super::timer::{Clock, Timeout};
 use sequence::Sequence;
 use io_error::*;
 use super::event_loop::EventLoop;
@@ -39,7 +40,7 @@ pub struct SocketEventLoopContext<'a> {
     signal_tx: &'a mut EventLoopBus<Signal>,
     endpoints: &'a mut EndpointCollection,
     schedule: &'a mut Schedule,
-    timer: &'a mut Timer<Task>
+    timer: &'a mut Clock<Task>
 }
 
 pub struct EndpointEventLoopContext<'a, 'b> {
@@ -58,13 +59,14 @@ pub struct ProbeEventLoopContext<'a> {
     probe_id: ProbeId,
     signal_tx: &'a mut EventLoopBus<Signal>,
     schedule: &'a mut Schedule,
-    timer: &'a mut Timer<Task>
+    timer: &'a mut Clock<Task>
 }
 
 pub struct PipeController {
     socket_id: SocketId,
     endpoint_id: EndpointId,
-    pipe: Box<pipe::Pipe>
+    pipe: Box<pipe::Pipe>,
+    sending_len: usize
 }
 
 pub struct AcceptorController {
@@ -116,11 +118,24 @@ impl PipeController {
         match cmd {
             pipe::Command::Open      => self.pipe.open(&mut ctx),
             pipe::Command::Close     => self.pipe.close(&mut ctx),
-            pipe::Command::Send(msg) => self.pipe.send(&mut ctx, msg),
+            pipe::Command::Linger    => self.pipe.linger(&mut ctx),
+            pipe::Command::Send(msg) => {
+                self.sending_len = msg.len();
+                self.pipe.send(&mut ctx, msg)
+            },
             pipe::Command::Recv      => self.pipe.recv(&mut ctx)
         }
     }
 
+    pub fn get_peer(&self) -> (Option<String>, Option<PeerCredentials>) {
+        (self.pipe.peer_addr(), self.pipe.peer_credentials())
+    }
+
+    /// Length of the last message given to the pipe, a pipe has at most one send in progress.
+    pub fn get_sending_len(&self) -> usize {
+        self.sending_len
+    }
+
     fn create_context<'a, 'b>(&self, registrar: &'b mut Registrar, signal_bus: &'a mut EventLoopBus<Signal>) -> EndpointEventLoopContext<'a, 'b> {
         EndpointEventLoopContext {
             socket_id: self.socket_id,
@@ -173,6 +188,10 @@ impl EndpointCollection {
             ok_or_else(|| invalid_input_io_error("invalid scheme"))
     }
 
+    pub fn get_pipe(&self, eid: EndpointId) -> Option<&PipeController> {
+        self.pipes.get(&eid)
+    }
+
     pub fn get_pipe_mut(&mut self, eid: EndpointId) -> Option<&mut PipeController> {
         self.pipes.get_mut(&eid)
     }
@@ -189,7 +208,8 @@ impl EndpointCollection {
         let controller = PipeController {
             socket_id: sid,
             endpoint_id: eid,
-            pipe: pipe
+            pipe: pipe,
+            sending_len: 0
         };
 
         self.pipes.insert(eid, controller);
@@ -251,7 +271,7 @@ impl<'a> SocketEventLoopContext<'a> {
         tx: &'a mut EventLoopBus<Signal>,
         eps: &'a mut EndpointCollection,
         sched: &'a mut Schedule,
-        timer: &'a mut Timer<Task>) -> SocketEventLoopContext<'a> {
+        timer: &'a mut Clock<Task>) -> SocketEventLoopContext<'a> {
         SocketEventLoopContext {
             socket_id: sid,
             signal_tx: tx,
@@ -330,17 +350,19 @@ impl<'a> SocketEventLoopContext<'a> {
 
 impl<'a> Network for SocketEventLoopContext<'a> {
 
-    fn connect(&mut self, sid: SocketId, tmpl: &EndpointTmpl) -> io::Result<EndpointId> {
+    fn connect(&mut self, sid: SocketId, tmpl: &EndpointTmpl) -> io::Result<(EndpointId, Option<String>)> {
         let pipe = try!(self.connect(tmpl));
+        let addr = pipe.peer_addr();
         let eid = self.endpoints.insert_pipe(sid, pipe);
 
-        Ok(eid)
+        Ok((eid, addr))
     }
-    fn bind(&mut self, sid: SocketId, tmpl: &EndpointTmpl) -> io::Result<EndpointId> {
+    fn bind(&mut self, sid: SocketId, tmpl: &EndpointTmpl) -> io::Result<(EndpointId, Option<String>)> {
         let acceptor = try!(self.bind(tmpl));
+        let addr = acceptor.local_addr();
         let eid = self.endpoints.insert_acceptor(sid, acceptor);
 
-        Ok(eid)
+        Ok((eid, addr))
     }
     fn reconnect(&mut self, sid: SocketId, eid: EndpointId, tmpl: &EndpointTmpl) -> io::Result<()> {
         let pipe = try!(self.connect(tmpl));
@@ -366,19 +388,25 @@ impl<'a> Network for SocketEventLoopContext<'a> {
             self.send_acceptor_cmd(endpoint_id, acceptor::Command::Close)
         }
     }
+    fn linger(&mut self, endpoint_id: EndpointId) {
+        self.send_pipe_cmd(endpoint_id, pipe::Command::Linger);
+    }
     fn send(&mut self, endpoint_id: EndpointId, msg: Rc<Message>) {
         self.send_pipe_cmd(endpoint_id, pipe::Command::Send(msg));
     }
     fn recv(&mut self, endpoint_id: EndpointId) {
         self.send_pipe_cmd(endpoint_id, pipe::Command::Recv);
     }
+    fn get_peer(&self, endpoint_id: EndpointId) -> (Option<String>, Option<PeerCredentials>) {
+        self.endpoints.get_pipe(endpoint_id).map_or((None, None), |pipe| pipe.get_peer())
+    }
 
 }
 
 impl<'a> context::Scheduler for SocketEventLoopContext<'a> {
     fn schedule(&mut self, schedulable: context::Schedulable, delay: Duration) -> io::Result<Scheduled> {
         let task = Task::Socket(self.socket_id, schedulable);
-        let handle = try!(self.timer.set_timeout(delay, task).map_err(from_timer_error));
+        let handle = try!(self.timer.set_timeout(delay, task));
         let scheduled = self.schedule.insert(handle);
         
         Ok(scheduled)
@@ -497,7 +525,7 @@ impl<'a> ProbeEventLoopContext<'a> {
         id: ProbeId,
         tx: &'a mut EventLoopBus<Signal>,
         sched: &'a mut Schedule,
-        timer: &'a mut Timer<Task>) -> ProbeEventLoopContext<'a> {
+        timer: &'a mut Clock<Task>) -> ProbeEventLoopContext<'a> {
         ProbeEventLoopContext {
             probe_id: id,
             signal_tx: tx,
@@ -524,7 +552,7 @@ impl<'a> probe::Context for ProbeEventLoopContext<'a> {
 impl<'a> probe::Scheduler for ProbeEventLoopContext<'a> {
     fn schedule(&mut self, schedulable: probe::Schedulable, delay: Duration) -> io::Result<Scheduled> {
         let task = Task::Probe(self.probe_id, schedulable);
-        let handle = try!(self.timer.set_timeout(delay, task).map_err(from_timer_error));
+        let handle = try!(self.timer.set_timeout(delay, task));
         let scheduled = self.schedule.insert(handle);
         
         Ok(scheduled)
@@ -10,7 +10,6 @@ use std::io;
 use std::time::Duration;
 
 use mio::{Token, Ready, PollOpt};
-use mio::timer::{Timer, Builder};
 use mio::channel::{Receiver};
 
 use core::{SocketId, EndpointId, DeviceId, ProbeId, session, socket, context, endpoint, device, probe};
@@ -18,6 +17,7 @@ use transport::{Transport, pipe, acceptor};
 use super::{Signal, Request, Task};
 use super::event_loop::{EventLoop, EventHandler};
 use super::bus::EventLoopBus;
+use super::timer::{Clock, TimerConfig};
 use super::adapter::{
     EndpointCollection, 
     Schedule, 
@@ -34,7 +34,7 @@ pub struct Dispatcher {
     // request inputs
     channel: Receiver<Request>,
     bus: EventLoopBus<Signal>,
-    timer: Timer<Task>,
+    timer: Clock<Task>,
 
     // request handlers
     sockets: session::Session,
@@ -45,25 +45,23 @@ pub struct Dispatcher {
 impl Dispatcher {
     pub fn dispatch(
         transports: HashMap<String, Box<Transport + Send>>,
+        timer_config: TimerConfig,
         rx: Receiver<Request>,
         tx: Sender<session::Reply>) -> io::Result<()> {
 
-        let mut dispatcher = Dispatcher::new(transports, rx, tx);
+        let mut dispatcher = Dispatcher::new(transports, timer_config, rx, tx);
 
         dispatcher.run()
     }
     pub fn new(
         transports: HashMap<String, Box<Transport + Send>>,
+        timer_config: TimerConfig,
         rx: Receiver<Request>, 
         tx: Sender<session::Reply>) -> Dispatcher {
 
         let id_seq = Sequence::new();
         let timeout_eq = Sequence::new();
-        let clock = Builder::default().
-            tick_duration(Duration::from_millis(25)).
-            num_slots(1_024).
-            capacity(8_192).
-            build();
+        let clock = Clock::new(&timer_config);
 
         Dispatcher {
             channel: rx,
@@ -89,7 +87,7 @@ impl Dispatcher {
 
         try!(event_loop.register(&self.channel, CHANNEL_TOKEN, interest, opt));
         try!(event_loop.register(&self.bus, BUS_TOKEN, interest, opt));
-        try!(event_loop.register(&self.timer, TIMER_TOKEN, interest, opt));
+        try!(self.timer.register(&mut event_loop, TIMER_TOKEN));
 
         event_loop.run(self)
     }
@@ -159,6 +157,9 @@ impl Dispatcher {
             context::Schedulable::Rebind(eid, spec)    => self.apply_on_socket(sid, |socket, ctx| socket.rebind(ctx, eid, spec)),
             context::Schedulable::SendTimeout          => self.apply_on_socket(sid, |socket, ctx| socket.on_send_timeout(ctx)),
             context::Schedulable::RecvTimeout          => self.apply_on_socket(sid, |socket, ctx| socket.on_recv_timeout(ctx)),
+            context::Schedulable::ContextSendTimeout(cid) => self.apply_on_socket(sid, |socket, ctx| socket.on_context_send_timeout(ctx, cid)),
+            context::Schedulable::ContextRecvTimeout(cid) => self.apply_on_socket(sid, |socket, ctx| socket.on_context_recv_timeout(ctx, cid)),
+            context::Schedulable::Linger               => self.apply_on_socket(sid, |socket, ctx| socket.on_linger_timeout(ctx)),
             other                                      => self.apply_on_socket(sid, |socket, ctx| socket.on_timer_tick(ctx, other))
         }
     }
@@ -218,6 +219,19 @@ impl Dispatcher {
             socket::Request::Recv(false)      => self.apply_on_socket(id, |socket, ctx| socket.recv(ctx)),
             socket::Request::Recv(true)       => self.apply_on_socket(id, |socket, ctx| socket.try_recv(ctx)),
             socket::Request::SetOption(x)     => self.apply_on_socket(id, |socket, ctx| socket.set_option(ctx, x)),
+            socket::Request::GetOption(x)     => self.apply_on_socket(id, |socket, ctx| socket.get_option(ctx, x)),
+            socket::Request::GetTopics(x)     => self.apply_on_socket(id, |socket, ctx| socket.get_topics(ctx, x)),
+            socket::Request::GetEndpointState(eid) => self.apply_on_socket(id, |socket, ctx| socket.query_endpoint_state(ctx, eid)),
+            socket::Request::GetStats         => self.apply_on_socket(id, |socket, ctx| socket.query_stats(ctx)),
+            socket::Request::AddNotifier(n)   => self.apply_on_socket(id, |socket, ctx| socket.add_notifier(ctx, n)),
+            socket::Request::AddMonitor(tx)   => self.apply_on_socket(id, |socket, ctx| socket.add_monitor(ctx, tx)),
+            socket::Request::OpenContext(tx)  => self.apply_on_socket(id, |socket, ctx| socket.open_context(ctx, tx)),
+            socket::Request::CloseContext(cid) => self.apply_on_socket(id, |socket, ctx| socket.close_context(ctx, cid)),
+            socket::Request::ContextSend(cid, msg) => self.apply_on_socket(id, |socket, ctx| socket.send_in_context(ctx, cid, msg)),
+            socket::Request::ContextRecv(cid) => self.apply_on_socket(id, |socket, ctx| socket.recv_in_context(ctx, cid)),
+            socket::Request::ContextSetOption(cid, x) => self.apply_on_socket(id, |socket, ctx| socket.set_context_option(ctx, cid, x)),
+            socket::Request::Survey(msg, x)   => self.apply_on_socket(id, |socket, ctx| socket.survey(ctx, msg, x)),
+            socket::Request::Exchange(msg, x) => self.apply_on_socket(id, |socket, ctx| socket.request(ctx, msg, x)),
             socket::Request::Close            => self.apply_on_socket(id, |socket, ctx| socket.close(ctx)),
         }
     }
@@ -230,8 +244,11 @@ impl Dispatcher {
             socket.close_acceptor(ctx, eid)
         });
     }
-    fn process_device_request(&mut self, _: &mut EventLoop, id: DeviceId, request: device::Request) {
+    fn process_device_request(&mut self, el: &mut EventLoop, id: DeviceId, request: device::Request) {
         if let device::Request::Check = request { 
+            // The readiness changes caused by the previous forwarding may still be on the bus,
+            // answering before them would make the device block on a socket that has nothing left
+            self.process_bus(el);
             self.apply_on_device(id, |device, ctx| device.check(ctx)) 
         }
     }
@@ -272,11 +289,17 @@ impl Dispatcher {
         match evt {
             pipe::Event::Opened        => self.apply_on_socket(sid, |socket, ctx| socket.on_pipe_opened(ctx, eid)),
             pipe::Event::CanSend       => self.apply_on_socket(sid, |socket, ctx| socket.on_send_ready(ctx, eid)),
-            pipe::Event::Sent          => self.apply_on_socket(sid, |socket, ctx| socket.on_send_ack(ctx, eid)),
+            pipe::Event::Sent          => {
+                let len = self.endpoints.get_pipe(eid).map_or(0, |pipe| pipe.get_sending_len());
+                self.apply_on_socket(sid, |socket, ctx| socket.on_send_ack(ctx, eid, len));
+            },
             pipe::Event::CanRecv       => self.apply_on_socket(sid, |socket, ctx| socket.on_recv_ready(ctx, eid)),
             pipe::Event::Received(msg) => self.apply_on_socket(sid, |socket, ctx| socket.on_recv_ack(ctx, eid, msg)),
             pipe::Event::Error(err)    => self.apply_on_socket(sid, |socket, ctx| socket.on_pipe_error(ctx, eid, err)),
-            pipe::Event::Closed        => self.endpoints.remove_pipe(eid)
+            pipe::Event::Closed        => {
+                self.endpoints.remove_pipe(eid);
+                self.apply_on_socket(sid, |socket, ctx| socket.on_pipe_closed(ctx, eid));
+            }
         }
     }
     fn process_acceptor_evt(&mut self, _: &mut EventLoop, sid: SocketId, aid: EndpointId, evt: acceptor::Event) {
@@ -285,9 +308,10 @@ impl Dispatcher {
             acceptor::Event::Error(e) => self.apply_on_socket(sid, |socket, ctx| socket.on_acceptor_error(ctx, aid, e)),
             acceptor::Event::Accepted(pipes) => {
                 for pipe in pipes {
+                    let addr = pipe.peer_addr();
                     let pipe_id = self.endpoints.insert_pipe(sid, pipe);
 
-                    self.apply_on_socket(sid, |socket, ctx| socket.on_pipe_accepted(ctx, aid, pipe_id));
+                    self.apply_on_socket(sid, |socket, ctx| socket.on_pipe_accepted(ctx, aid, pipe_id, addr));
                 }
             },
             _ => {}
@@ -298,10 +322,11 @@ impl Dispatcher {
             context::Event::CanRecv(x) => {
                 self.apply_on_device_link(sid, |device| device.on_socket_can_recv(sid, x));
                 self.apply_on_probe_link(sid, |probe, ctx| probe.on_socket_can_recv(ctx, sid, x));
+                self.apply_on_notifiers(sid, |notifier| notifier.on_can_recv(x));
             },
             context::Event::CanSend(x) => {
                 self.apply_on_probe_link(sid, |probe, ctx| probe.on_socket_can_send(ctx, sid, x));
-
+                self.apply_on_notifiers(sid, |notifier| notifier.on_can_send(x));
             },
             context::Event::Closed => self.sockets.remove_socket(sid)
         }
@@ -318,6 +343,14 @@ impl Dispatcher {
                 &mut self.timer);
 
             f(socket, &mut ctx);
+            socket.notify(|notifier| notifier.on_activity());
+        }
+    }
+
+    fn apply_on_notifiers<F>(&mut self, id: SocketId, f: F) 
+    where F : Fn(&socket::Notify) {
+        if let Some(socket) = self.sockets.get_socket_mut(id) {
+            socket.notify(f);
         }
     }
 
@@ -375,4 +408,14 @@ impl EventHandler for Dispatcher {
 
         self.process_io(el, token, events)
     }
+
+    fn next_timeout(&mut self) -> Option<Duration> {
+        self.timer.next_timeout()
+    }
+
+    fn tick(&mut self, el: &mut EventLoop) {
+        if self.timer.is_precise() {
+            self.process_timer(el)
+        }
+    }
 }
@@ -5,11 +5,18 @@
 // This file may not be copied, modified, or distributed except according to those terms.
 
 use std::io;
+use std::time::Duration;
 
 use mio::{Poll, Token, Ready, Events, Evented, PollOpt};
 
 pub trait EventHandler {
     fn handle(&mut self, el: &mut EventLoop, token: Token, events: Ready);
+
+    /// Bounds the wait for the next events, `None` waits until something happens.
+    fn next_timeout(&mut self) -> Option<Duration>;
+
+    /// Called after each poll, whether events were received or not.
+    fn tick(&mut self, el: &mut EventLoop);
 }
 
 pub struct EventLoop {
@@ -46,7 +53,8 @@ impl EventLoop {
     }
 
     pub fn run_once<H: EventHandler>(&mut self, event_handler: &mut H) -> io::Result<()> {
-        let event_count = match self.poll_events() {
+        let timeout = event_handler.next_timeout();
+        let event_count = match self.poll_events(timeout) {
             Ok(count) => count,
             Err(err) => {
                 if err.kind() == io::ErrorKind::Interrupted {
@@ -58,12 +66,13 @@ impl EventLoop {
         };
 
         self.process_events(event_handler, event_count);
+        event_handler.tick(self);
 
         Ok(())
     }
 
-    fn poll_events(&mut self) -> io::Result<usize> {
-        self.events_poller.poll(&mut self.events, None)
+    fn poll_events(&mut self, timeout: Option<Duration>) -> io::Result<usize> {
+        self.events_poller.poll(&mut self.events, timeout)
     }
 
     fn process_events<H: EventHandler>(&mut self, event_handler: &mut H, count: usize) {
@@ -5,6 +5,7 @@
 // This file may not be copied, modified, or distributed except according to those terms.
 
 pub mod dispatcher;
+pub mod timer;
 mod event_loop;
 mod bus;
 mod adapter;
@@ -0,0 +1,279 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::io;
+use std::time::{Duration, Instant};
+
+use mio::{Token, Ready, PollOpt};
+use mio::timer;
+
+use super::event_loop::EventLoop;
+use io_error::*;
+
+/// Settings of the timer driving the socket timeouts, see `SessionBuilder`.
+#[derive(Debug, Copy, Clone)]
+pub struct TimerConfig {
+    pub tick: Duration,
+    pub slots: usize,
+    pub capacity: usize,
+    pub high_resolution: bool
+}
+
+impl Default for TimerConfig {
+    fn default() -> TimerConfig {
+        TimerConfig {
+            tick: Duration::from_millis(25),
+            slots: 1_024,
+            capacity: 8_192,
+            high_resolution: false
+        }
+    }
+}
+
+/// Either the mio hashed wheel, which rounds deadlines up to the next tick,
+/// or a heap of exact deadlines checked by the event loop after each poll.
+pub enum Clock<T> {
+    Wheel(timer::Timer<T>),
+    Precise(PreciseTimer<T>)
+}
+
+pub enum Timeout {
+    Wheel(timer::Timeout),
+    Precise(usize)
+}
+
+impl<T> Clock<T> {
+    pub fn new(config: &TimerConfig) -> Clock<T> {
+        if config.high_resolution {
+            Clock::Precise(PreciseTimer::with_capacity(config.capacity))
+        } else {
+            let wheel = timer::Builder::default().
+                tick_duration(config.tick).
+                num_slots(config.slots).
+                capacity(config.capacity).
+                build();
+
+            Clock::Wheel(wheel)
+        }
+    }
+
+    pub fn register(&self, el: &mut EventLoop, token: Token) -> io::Result<()> {
+        match *self {
+            Clock::Wheel(ref wheel) => el.register(wheel, token, Ready::readable(), PollOpt::edge()),
+            Clock::Precise(_)       => Ok(())
+        }
+    }
+
+    pub fn set_timeout(&mut self, delay: Duration, state: T) -> io::Result<Timeout> {
+        match *self {
+            Clock::Wheel(ref mut wheel) => wheel.set_timeout(delay, state).map(Timeout::Wheel).map_err(from_timer_error),
+            Clock::Precise(ref mut precise) => Ok(Timeout::Precise(precise.set_timeout(delay, state)))
+        }
+    }
+
+    pub fn cancel_timeout(&mut self, timeout: &Timeout) {
+        match (self, timeout) {
+            (&mut Clock::Wheel(ref mut wheel), &Timeout::Wheel(ref handle)) => { wheel.cancel_timeout(handle); },
+            (&mut Clock::Precise(ref mut precise), &Timeout::Precise(id)) => precise.cancel_timeout(id),
+            _ => {}
+        }
+    }
+
+    pub fn poll(&mut self) -> Option<T> {
+        match *self {
+            Clock::Wheel(ref mut wheel) => wheel.poll(),
+            Clock::Precise(ref mut precise) => precise.poll(Instant::now())
+        }
+    }
+
+    /// Each poll of the wheel moves it forward by at least one tick,
+    /// so it must only be polled once it is readable, not after each wait.
+    pub fn is_precise(&self) -> bool {
+        match *self {
+            Clock::Wheel(_) => false,
+            Clock::Precise(_) => true
+        }
+    }
+
+    /// How long the event loop can wait for I/O before the next deadline.
+    /// The wheel wakes the event loop up by itself, so there is no need to bound the wait.
+    pub fn next_timeout(&mut self) -> Option<Duration> {
+        match *self {
+            Clock::Wheel(_) => None,
+            Clock::Precise(ref mut precise) => precise.next_timeout(Instant::now())
+        }
+    }
+}
+
+/*****************************************************************************/
+/*                                                                           */
+/* high resolution timer                                                     */
+/*                                                                           */
+/*****************************************************************************/
+
+#[derive(PartialEq, Eq)]
+struct Deadline {
+    at: Instant,
+    id: usize
+}
+
+// Reversed so the binary heap pops the earliest deadline first.
+impl Ord for Deadline {
+    fn cmp(&self, other: &Deadline) -> Ordering {
+        other.at.cmp(&self.at).then_with(|| other.id.cmp(&self.id))
+    }
+}
+
+impl PartialOrd for Deadline {
+    fn partial_cmp(&self, other: &Deadline) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+pub struct PreciseTimer<T> {
+    deadlines: BinaryHeap<Deadline>,
+    states: HashMap<usize, T>,
+    next_id: usize
+}
+
+impl<T> PreciseTimer<T> {
+    pub fn with_capacity(capacity: usize) -> PreciseTimer<T> {
+        PreciseTimer {
+            deadlines: BinaryHeap::with_capacity(capacity),
+            states: HashMap::with_capacity(capacity),
+            next_id: 0
+        }
+    }
+
+    pub fn set_timeout(&mut self, delay: Duration, state: T) -> usize {
+        let id = self.next_id;
+
+        self.next_id = self.next_id.wrapping_add(1);
+        self.deadlines.push(Deadline { at: Instant::now() + delay, id: id });
+        self.states.insert(id, state);
+
+        id
+    }
+
+    // The deadline stays in the heap until it is reached or seen by next_timeout.
+    pub fn cancel_timeout(&mut self, id: usize) {
+        self.states.remove(&id);
+    }
+
+    pub fn poll(&mut self, now: Instant) -> Option<T> {
+        while let Some(id) = self.pop_elapsed(now) {
+            if let Some(state) = self.states.remove(&id) {
+                return Some(state);
+            }
+        }
+
+        None
+    }
+
+    /// Epoll only accepts milliseconds and rounds the wait up,
+    /// so the wait is rounded down instead and the last millisecond is spent polling without blocking.
+    pub fn next_timeout(&mut self, now: Instant) -> Option<Duration> {
+        self.discard_cancelled();
+        self.deadlines.peek().map(|deadline| {
+            if deadline.at > now {
+                let remaining = deadline.at - now;
+                let millis = remaining.subsec_nanos() / 1_000_000;
+
+                Duration::new(remaining.as_secs(), millis * 1_000_000)
+            } else {
+                Duration::from_millis(0)
+            }
+        })
+    }
+
+    fn pop_elapsed(&mut self, now: Instant) -> Option<usize> {
+        let elapsed = match self.deadlines.peek() {
+            Some(deadline) => deadline.at <= now,
+            None => false
+        };
+
+        if elapsed {
+            self.deadlines.pop().map(|deadline| deadline.id)
+        } else {
+            None
+        }
+    }
+
+    fn discard_cancelled(&mut self) {
+        loop {
+            let cancelled = match self.deadlines.peek() {
+                Some(deadline) => !self.states.contains_key(&deadline.id),
+                None => false
+            };
+
+            if cancelled {
+                self.deadlines.pop();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::{Duration, Instant};
+
+    use super::PreciseTimer;
+
+    #[test]
+    fn poll_returns_elapsed_timeouts_in_deadline_order() {
+        let mut timer = PreciseTimer::with_capacity(4);
+
+        timer.set_timeout(Duration::from_millis(2), "second");
+        timer.set_timeout(Duration::from_millis(1), "first");
+        timer.set_timeout(Duration::from_secs(60), "later");
+
+        let now = Instant::now() + Duration::from_millis(5);
+
+        assert_eq!(Some("first"), timer.poll(now));
+        assert_eq!(Some("second"), timer.poll(now));
+        assert_eq!(None, timer.poll(now));
+    }
+
+    #[test]
+    fn poll_honours_sub_millisecond_delays() {
+        let mut timer = PreciseTimer::with_capacity(4);
+        let start = Instant::now();
+
+        timer.set_timeout(Duration::new(0, 300_000), ());
+
+        assert_eq!(None, timer.poll(start));
+        assert_eq!(Some(()), timer.poll(Instant::now() + Duration::new(0, 400_000)));
+    }
+
+    #[test]
+    fn cancelled_timeout_is_not_returned() {
+        let mut timer = PreciseTimer::with_capacity(4);
+        let id = timer.set_timeout(Duration::from_millis(1), ());
+
+        timer.cancel_timeout(id);
+
+        assert_eq!(None, timer.poll(Instant::now() + Duration::from_millis(5)));
+        assert_eq!(None, timer.next_timeout(Instant::now()));
+    }
+
+    #[test]
+    fn next_timeout_is_rounded_down_to_the_millisecond() {
+        let mut timer = PreciseTimer::with_capacity(4);
+
+        timer.set_timeout(Duration::new(0, 2_700_000), ());
+
+        let now = Instant::now();
+        let wait = timer.next_timeout(now).unwrap();
+
+        assert!(wait <= Duration::from_millis(2));
+        assert!(wait >= Duration::from_millis(1));
+    }
+}
@@ -27,6 +27,12 @@ pub trait Acceptor {
     fn ready(&mut self, ctx: &mut Context, events: Ready);
     fn open(&mut self, ctx: &mut Context);
     fn close(&mut self, ctx: &mut Context);
+
+    /// Address the acceptor is actually bound to, if the transport knows it.
+    /// When binding to an ephemeral port, this is where the chosen port can be found.
+    fn local_addr(&self) -> Option<String> {
+        None
+    }
 }
 
 pub trait Context : EndpointRegistrar {
@@ -12,6 +12,7 @@ use mio::{Ready, PollOpt};
 use core::Message;
 use transport::async::stub::*;
 use transport::async::state::*;
+use transport::async::lingering::Lingering;
 use transport::async::dead::Dead; 
 use transport::pipe::{Event, Context};
 use io_error::*;
@@ -87,6 +88,12 @@ impl<S : AsyncPipeStub> Active<S> {
     }
 }
 
+impl<S : AsyncPipeStub> Into<Lingering<S>> for Active<S> {
+    fn into(self) -> Lingering<S> {
+        Lingering::new(self.stub)
+    }
+}
+
 impl<S : AsyncPipeStub + 'static> PipeState<S> for Active<S> {
     fn name(&self) -> &'static str {"Active"}
 
@@ -99,6 +106,16 @@ impl<S : AsyncPipeStub + 'static> PipeState<S> for Active<S> {
 
         box Dead
     }
+    fn linger(self: Box<Self>, ctx: &mut Context) -> Box<PipeState<S>> {
+        if self.stub.has_pending_send() {
+            transition::<Active<S>, Lingering<S>, S>(self, ctx)
+        } else {
+            ctx.deregister(self.stub.deref());
+            ctx.raise(Event::Closed);
+
+            box Dead
+        }
+    }
     fn send(mut self: Box<Self>, ctx: &mut Context, msg: Rc<Message>) -> Box<PipeState<S>> {
         self.should_raise_can_send = true;
 
@@ -4,7 +4,7 @@
 // or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
 // This file may not be copied, modified, or distributed except according to those terms.
 
-use std::io::Result;
+use std::io::{Result, ErrorKind};
 
 use mio::{Ready, PollOpt};
 
@@ -55,7 +55,11 @@ impl<S : AsyncPipeStub> PipeState<S> for HandshakeTx<S> {
         if events.is_writable() {
             let res = self.send_handshake();
 
-            transition_if_ok::<HandshakeTx<S>, HandshakeRx<S>, S>(self, ctx, res)
+            if is_incomplete(&res) {
+                self
+            } else {
+                transition_if_ok::<HandshakeTx<S>, HandshakeRx<S>, S>(self, ctx, res)
+            }
         } else {
             self
         }
@@ -80,6 +84,15 @@ impl<S: AsyncPipeStub> HandshakeRx<S> {
 
         self.stub.recv_handshake(pids)
     }
+
+    /// While a part of the handshake is waiting to be written, only writable events are of interest.
+    fn interest(&self) -> Ready {
+        if self.stub.has_pending_handshake_send() {
+            Ready::writable()
+        } else {
+            Ready::readable()
+        }
+    }
 }
 
 impl<S : AsyncPipeStub> Into<Active<S>> for HandshakeRx<S> {
@@ -93,7 +106,7 @@ impl<S : AsyncPipeStub + 'static> PipeState<S> for HandshakeRx<S> {
     fn name(&self) -> &'static str {"HandshakeRx"}
 
     fn enter(&mut self, ctx: &mut Context) {
-        ctx.reregister(self.stub.deref(), Ready::readable(), PollOpt::level());
+        ctx.reregister(self.stub.deref(), self.interest(), PollOpt::level());
     }
     fn close(self: Box<Self>, ctx: &mut Context) -> Box<PipeState<S>> {
         ctx.deregister(self.stub.deref());
@@ -101,16 +114,34 @@ impl<S : AsyncPipeStub + 'static> PipeState<S> for HandshakeRx<S> {
         box Dead
     }
     fn ready(mut self: Box<Self>, ctx: &mut Context, events: Ready) -> Box<PipeState<S>> {
-        if events.is_readable() {
+        let interest = self.interest();
+
+        if events.contains(interest) {
             let res = self.recv_handshake();
-            
-            transition_if_ok::<HandshakeRx<S>, Active<S>, S>(self, ctx, res)
+
+            if is_incomplete(&res) {
+                if self.interest() != interest {
+                    ctx.reregister(self.stub.deref(), self.interest(), PollOpt::level());
+                }
+                self
+            } else {
+                transition_if_ok::<HandshakeRx<S>, Active<S>, S>(self, ctx, res)
+            }
         } else {
             self
         }
     }
 }
 
+/// A stub that could not complete the handshake yet reports it with a `WouldBlock` error,
+/// the handshake is then resumed on the next readiness notification.
+fn is_incomplete(res: &Result<()>) -> bool {
+    match *res {
+        Err(ref e) => e.kind() == ErrorKind::WouldBlock,
+        Ok(_) => false
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::rc::Rc;
@@ -222,4 +253,48 @@ mod tests {
         assert_eq!(1, sensor.borrow().get_received_handshakes());
         assert_eq!("Active", new_state.name());
     }
+
+    #[test]
+    fn readable_an_incomplete_handshake_should_be_resumed_later() {
+        let sensor_srv = TestStepStreamSensor::new();
+        let sensor = Rc::new(RefCell::new(sensor_srv));
+        let mut stub = TestStepStream::with_sensor(sensor.clone());
+        stub.set_recv_handshake_incomplete(true);
+        let state = box HandshakeRx::new(stub, (6, 6));
+        let mut ctx = TestPipeContext::new();
+        let events = mio::Ready::readable();
+        let new_state = state.ready(&mut ctx, events);
+
+        assert_eq!(1, sensor.borrow().get_received_handshakes());
+        assert_eq!(0, ctx.get_raised_events().len());
+        assert_eq!("HandshakeRx", new_state.name());
+    }
+
+    #[test]
+    fn a_handshake_waiting_to_be_written_should_be_resumed_when_writable() {
+        let sensor_srv = TestStepStreamSensor::new();
+        let sensor = Rc::new(RefCell::new(sensor_srv));
+        let mut stub = TestStepStream::with_sensor(sensor.clone());
+        stub.set_recv_handshake_wants_write(true);
+        let state = box HandshakeRx::new(stub, (6, 6));
+        let mut ctx = TestPipeContext::new();
+        let state = state.ready(&mut ctx, mio::Ready::readable());
+
+        assert_eq!(1, sensor.borrow().get_received_handshakes());
+        assert_eq!("HandshakeRx", state.name());
+        assert_eq!(1, ctx.get_reregistrations().len());
+
+        let (ref interest, _) = ctx.get_reregistrations()[0];
+        assert_eq!(&mio::Ready::writable(), interest);
+
+        let state = state.ready(&mut ctx, mio::Ready::readable());
+
+        assert_eq!(1, sensor.borrow().get_received_handshakes());
+        assert_eq!("HandshakeRx", state.name());
+
+        let state = state.ready(&mut ctx, mio::Ready::writable());
+
+        assert_eq!(2, sensor.borrow().get_received_handshakes());
+        assert_eq!("Active", state.name());
+    }
 }
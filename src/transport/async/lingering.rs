@@ -0,0 +1,171 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use std::rc::Rc;
+use std::io::Error;
+
+use mio::{Ready, PollOpt};
+
+use core::Message;
+use transport::async::stub::*;
+use transport::async::state::*;
+use transport::async::dead::Dead;
+use transport::pipe::{Event, Context};
+
+/// The pipe has been closed while a message was partially written.
+/// It keeps writing until the message is completely sent, then dies.
+/// Nothing is received anymore and the Sent event is not raised.
+pub struct Lingering<S> {
+    stub: S
+}
+
+impl<S : AsyncPipeStub> Lingering<S> {
+    pub fn new(s: S) -> Lingering<S> {
+        Lingering { stub: s }
+    }
+}
+
+impl<S : AsyncPipeStub + 'static> Lingering<S> {
+    fn die(self: Box<Self>, ctx: &mut Context) -> Box<PipeState<S>> {
+        ctx.deregister(self.stub.deref());
+        ctx.raise(Event::Closed);
+
+        box Dead
+    }
+}
+
+#[cfg(unix)]
+fn poll_opt() -> PollOpt {
+    PollOpt::level()
+}
+
+#[cfg(windows)]
+fn poll_opt() -> PollOpt {
+    PollOpt::edge()
+}
+
+impl<S : AsyncPipeStub + 'static> PipeState<S> for Lingering<S> {
+    fn name(&self) -> &'static str {"Lingering"}
+
+    fn enter(&mut self, ctx: &mut Context) {
+        ctx.reregister(self.stub.deref(), Ready::writable(), poll_opt());
+    }
+    fn close(self: Box<Self>, ctx: &mut Context) -> Box<PipeState<S>> {
+        self.die(ctx)
+    }
+    fn linger(self: Box<Self>, _: &mut Context) -> Box<PipeState<S>> {
+        self
+    }
+    fn send(self: Box<Self>, _: &mut Context, _: Rc<Message>) -> Box<PipeState<S>> {
+        self
+    }
+    fn recv(self: Box<Self>, _: &mut Context) -> Box<PipeState<S>> {
+        self
+    }
+    fn error(self: Box<Self>, ctx: &mut Context, _: Error) -> Box<PipeState<S>> {
+        self.die(ctx)
+    }
+    fn ready(mut self: Box<Self>, ctx: &mut Context, events: Ready) -> Box<PipeState<S>> {
+        if events.is_hup() {
+            return self.die(ctx);
+        }
+        if events.is_writable() == false {
+            return self;
+        }
+
+        match self.stub.resume_send() {
+            Ok(true)  => self.die(ctx),
+            Ok(false) => self,
+            Err(e)    => self.error(ctx, e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    use mio;
+
+    use core::Message;
+    use transport::*;
+    use transport::tests::*;
+    use transport::async::state::*;
+    use transport::async::tests::*;
+    use transport::async::active::*;
+
+    fn is_closed(evt: &pipe::Event) -> bool {
+        match *evt {
+            pipe::Event::Closed => true,
+            _ => false
+        }
+    }
+
+    #[test]
+    fn linger_without_pending_send_should_close_right_away() {
+        let stub = TestStepStream::new();
+        let state = box Active::new(stub);
+        let mut ctx = TestPipeContext::new();
+        let new_state = state.linger(&mut ctx);
+
+        assert_eq!("Dead", new_state.name());
+        assert_eq!(1, ctx.get_deregistrations());
+        assert_eq!(1, ctx.get_raised_events().len());
+        assert!(is_closed(&ctx.get_raised_events()[0]));
+    }
+
+    #[test]
+    fn linger_with_pending_send_should_wait_for_completion() {
+        let sensor_srv = TestStepStreamSensor::new();
+        let sensor = Rc::new(RefCell::new(sensor_srv));
+        let stub = TestStepStream::with_sensor(sensor.clone());
+        let state = box Active::new(stub);
+        let mut ctx = TestPipeContext::new();
+
+        sensor.borrow_mut().set_start_send_result(Some(false));
+        let msg = Rc::new(Message::from_body(vec!(66, 65, 67)));
+        let state = state.send(&mut ctx, msg);
+        let state = state.linger(&mut ctx);
+
+        assert_eq!("Lingering", state.name());
+        assert_eq!(0, ctx.get_raised_events().len());
+        assert_eq!(1, ctx.get_reregistrations().len());
+
+        sensor.borrow_mut().set_resume_send_result(Some(false));
+        let state = state.ready(&mut ctx, mio::Ready::writable());
+
+        assert_eq!("Lingering", state.name());
+        assert_eq!(0, ctx.get_raised_events().len());
+
+        sensor.borrow_mut().set_resume_send_result(Some(true));
+        let state = state.ready(&mut ctx, mio::Ready::writable());
+
+        assert_eq!("Dead", state.name());
+        assert_eq!(1, ctx.get_deregistrations());
+        assert_eq!(1, ctx.get_raised_events().len());
+        assert!(is_closed(&ctx.get_raised_events()[0]));
+    }
+
+    #[test]
+    fn lingering_should_stop_on_send_error() {
+        let sensor_srv = TestStepStreamSensor::new();
+        let sensor = Rc::new(RefCell::new(sensor_srv));
+        let stub = TestStepStream::with_sensor(sensor.clone());
+        let state = box Active::new(stub);
+        let mut ctx = TestPipeContext::new();
+
+        sensor.borrow_mut().set_start_send_result(Some(false));
+        let msg = Rc::new(Message::from_body(vec!(66, 65, 67)));
+        let state = state.send(&mut ctx, msg);
+        let state = state.linger(&mut ctx);
+        let state = state.ready(&mut ctx, mio::Ready::writable());
+
+        assert_eq!("Dead", state.name());
+        assert_eq!(1, ctx.get_raised_events().len());
+        assert!(is_closed(&ctx.get_raised_events()[0]));
+    }
+}
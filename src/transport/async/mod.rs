@@ -11,6 +11,7 @@ mod state;
 mod initial;
 mod handshake;
 mod active;
+mod lingering;
 mod dead;
 
 #[cfg(test)]
@@ -21,6 +22,7 @@ use std::rc::Rc;
 use mio;
 
 use core::Message;
+use core::endpoint::PeerCredentials;
 use transport::*;
 use transport::pipe::*;
 
@@ -29,7 +31,9 @@ use self::state::PipeState;
 
 pub struct AsyncPipe<S : AsyncPipeStub + 'static> {
 
-    state: Option<Box<PipeState<S>>>
+    state: Option<Box<PipeState<S>>>,
+    peer_addr: Option<String>,
+    peer_credentials: Option<PeerCredentials>
 
 }
 
@@ -37,7 +41,21 @@ impl<S : AsyncPipeStub + 'static> AsyncPipe<S> {
     pub fn new(stub: S, pids: (u16, u16)) -> AsyncPipe<S> {
         let initial_state = box initial::Initial::new(stub, pids);
 
-        AsyncPipe { state: Some(initial_state) }
+        AsyncPipe {
+            state: Some(initial_state),
+            peer_addr: None,
+            peer_credentials: None
+        }
+    }
+
+    pub fn with_peer_addr(mut self, addr: String) -> AsyncPipe<S> {
+        self.peer_addr = Some(addr);
+        self
+    }
+
+    pub fn with_peer_credentials(mut self, creds: Option<PeerCredentials>) -> AsyncPipe<S> {
+        self.peer_credentials = creds;
+        self
     }
 
     fn apply<F>(&mut self, ctx: &mut Context, transition: F) where F : FnOnce(Box<PipeState<S>>, &mut Context) -> Box<PipeState<S>> {
@@ -67,6 +85,10 @@ impl<S : AsyncPipeStub> pipe::Pipe for AsyncPipe<S> {
         self.apply(ctx, |s, ctx| s.close(ctx))
     }
 
+    fn linger(&mut self, ctx: &mut Context) {
+        self.apply(ctx, |s, ctx| s.linger(ctx))
+    }
+
     fn send(&mut self, ctx: &mut Context, msg: Rc<Message>) {
         self.apply(ctx, |s, ctx| s.send(ctx, msg))
     }
@@ -74,4 +96,12 @@ impl<S : AsyncPipeStub> pipe::Pipe for AsyncPipe<S> {
     fn recv(&mut self, ctx: &mut Context) {
         self.apply(ctx, |s, ctx| s.recv(ctx))
     }
+
+    fn peer_addr(&self) -> Option<String> {
+        self.peer_addr.clone()
+    }
+
+    fn peer_credentials(&self) -> Option<PeerCredentials> {
+        self.peer_credentials
+    }
 }
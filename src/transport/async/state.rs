@@ -23,6 +23,13 @@ pub trait PipeState<S : AsyncPipeStub + 'static> {
     fn close(self: Box<Self>, _: &mut Context) -> Box<PipeState<S>> {
         box Dead
     }
+    fn linger(self: Box<Self>, ctx: &mut Context) -> Box<PipeState<S>> {
+        let new_state = self.close(ctx);
+
+        ctx.raise(Event::Closed);
+
+        new_state
+    }
     fn send(self: Box<Self>, _: &mut Context, _: Rc<Message>) -> Box<PipeState<S>> {
         box Dead
     }
@@ -6,7 +6,7 @@
 
 use std::ops::Deref;
 use std::rc::Rc;
-use std::io::{Result, Read, Write, ErrorKind};
+use std::io::{Result, Read, Write, Error, ErrorKind};
 
 use byteorder::{ BigEndian, ByteOrder };
 
@@ -35,15 +35,19 @@ pub trait Receiver {
 pub trait Handshake {
     fn send_handshake(&mut self, pids: (u16, u16)) -> Result<()>;
     fn recv_handshake(&mut self, pids: (u16, u16)) -> Result<()>;
+    /// Tells whether receiving the handshake is waiting for the stream to become writable.
+    fn has_pending_handshake_send(&self) -> bool;
 }
 
+/// A partially written handshake can't be resumed, so it is reported as a failure
+/// and not as a `WouldBlock` error that would make the handshake state retry it.
 pub fn send_and_check_handshake<T:Write>(stream: &mut T, pids: (u16, u16)) -> Result<()> {
     let (proto_id, _) = pids;
     let handshake = create_handshake(proto_id);
 
     match try!(stream.write(&handshake)) {
         8 => Ok(()),
-        _ => Err(would_block_io_error("failed to send handshake"))
+        _ => Err(other_io_error("failed to send handshake"))
     }
 }
 
@@ -66,8 +70,10 @@ fn check_handshake(pids: (u16, u16), handshake: &[u8; 8]) -> Result<()> {
 
     if handshake == &expected_handshake {
         Ok(())
+    } else if handshake[0..4] == expected_handshake[0..4] {
+        Err(bad_handshake_io_error(Some(BigEndian::read_u16(&handshake[4..6]))))
     } else {
-        Err(invalid_data_io_error("received bad handshake"))
+        Err(bad_handshake_io_error(None))
     }
 }
 
@@ -102,6 +108,9 @@ pub trait ReadBuffer {
 impl<T:Read> ReadBuffer for T {
     fn read_buffer(&mut self, buf: &mut [u8]) -> Result<usize> {
         match self.read(buf) {
+            Ok(0) if !buf.is_empty() => {
+                Err(Error::new(ErrorKind::UnexpectedEof, "connection closed by peer"))
+            },
             Ok(x) => {
                 Ok(x)
             },
@@ -115,3 +124,45 @@ impl<T:Read> ReadBuffer for T {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+
+    use super::*;
+
+    struct ShortWriter {
+        capacity: usize,
+        written: Vec<u8>
+    }
+
+    impl Write for ShortWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let len = ::std::cmp::min(self.capacity, buf.len());
+
+            self.written.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn send_handshake_in_one_write() {
+        let mut stream = Vec::new();
+
+        send_and_check_handshake(&mut stream, (16, 17)).expect("handshake should have been sent");
+
+        assert_eq!(vec![0, 83, 80, 0, 0, 16, 0, 0], stream);
+    }
+
+    #[test]
+    fn partially_sent_handshake_is_a_failure() {
+        let mut stream = ShortWriter { capacity: 3, written: Vec::new() };
+        let err = send_and_check_handshake(&mut stream, (16, 17)).err().expect("handshake should have failed");
+
+        assert_eq!(io::ErrorKind::Other, err.kind());
+        assert_eq!(3, stream.written.len());
+    }
+}
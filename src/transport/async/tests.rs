@@ -89,6 +89,9 @@ pub struct TestStepStream {
     sensor: Rc<RefCell<TestStepStreamSensor>>,
     send_handshake_ok: bool,
     recv_handshake_ok: bool,
+    recv_handshake_incomplete: bool,
+    recv_handshake_wants_write: bool,
+    pending_handshake_send: bool,
     pending_send: bool,
     pending_recv: bool
 }
@@ -103,6 +106,9 @@ impl TestStepStream {
             sensor: sensor,
             send_handshake_ok: true,
             recv_handshake_ok: true,
+            recv_handshake_incomplete: false,
+            recv_handshake_wants_write: false,
+            pending_handshake_send: false,
             pending_send: false,
             pending_recv: false
         }
@@ -110,6 +116,13 @@ impl TestStepStream {
     pub fn set_send_handshake_ok(&mut self, send_handshake_ok: bool) {
         self.send_handshake_ok = send_handshake_ok;
     }
+    pub fn set_recv_handshake_incomplete(&mut self, recv_handshake_incomplete: bool) {
+        self.recv_handshake_incomplete = recv_handshake_incomplete;
+    }
+    /// The next handshake reception stops until the stream is writable, the one after completes.
+    pub fn set_recv_handshake_wants_write(&mut self, recv_handshake_wants_write: bool) {
+        self.recv_handshake_wants_write = recv_handshake_wants_write;
+    }
 }
 
 impl stub::AsyncPipeStub for TestStepStream {
@@ -143,8 +156,18 @@ impl stub::Handshake for TestStepStream {
     }
     fn recv_handshake(&mut self, _: (u16, u16)) -> io::Result<()> {
         self.sensor.borrow_mut().push_received_handshake();
+        if self.recv_handshake_incomplete { return Err(would_block_io_error("test")); }
+        if self.recv_handshake_wants_write {
+            self.recv_handshake_wants_write = false;
+            self.pending_handshake_send = true;
+            return Err(would_block_io_error("test"));
+        }
+        self.pending_handshake_send = false;
         if self.recv_handshake_ok { Ok(()) } else { Err(other_io_error("test")) }
     }
+    fn has_pending_handshake_send(&self) -> bool {
+        self.pending_handshake_send
+    }
 }
 
 impl stub::Sender for TestStepStream {
@@ -0,0 +1,86 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use mio;
+
+use transport::*;
+use transport::acceptor::*;
+use super::notifier::Notifier;
+use super::pipe::{InprocPipe, Mailbox};
+use super::registry;
+
+pub struct InprocAcceptor {
+    addr: String,
+    proto_ids: (u16, u16),
+    notifier: Notifier,
+    bound: bool
+}
+
+impl InprocAcceptor {
+
+    pub fn new(notifier: Notifier, dest: &Destination) -> InprocAcceptor {
+        InprocAcceptor {
+            addr: dest.addr.to_owned(),
+            proto_ids: dest.pids,
+            notifier: notifier,
+            bound: true
+        }
+    }
+
+    fn accept(&mut self, ctx: &mut Context) {
+        let pipes: Vec<Box<pipe::Pipe>> = registry::accept(&self.addr).
+            iter().
+            map(|remote| self.create_pipe(remote)).
+            collect();
+
+        if pipes.is_empty() == false {
+            ctx.raise(Event::Accepted(pipes));
+        }
+    }
+
+    fn create_pipe(&self, remote: &Rc<RefCell<Mailbox>>) -> Box<pipe::Pipe> {
+        box InprocPipe::accept(self.proto_ids, remote)
+    }
+
+    fn unbind(&mut self) {
+        if self.bound {
+            self.bound = false;
+            registry::unbind(&self.addr);
+        }
+    }
+}
+
+impl acceptor::Acceptor for InprocAcceptor {
+    fn ready(&mut self, ctx: &mut Context, _: mio::Ready) {
+        if self.bound {
+            self.accept(ctx);
+        }
+    }
+
+    fn open(&mut self, ctx: &mut Context) {
+        ctx.register(&self.notifier, mio::Ready::readable(), mio::PollOpt::edge());
+        ctx.raise(Event::Opened);
+    }
+
+    fn close(&mut self, ctx: &mut Context) {
+        ctx.deregister(&self.notifier);
+        self.unbind();
+        ctx.raise(Event::Closed);
+    }
+
+    fn local_addr(&self) -> Option<String> {
+        Some(self.addr.clone())
+    }
+}
+
+impl Drop for InprocAcceptor {
+    fn drop(&mut self) {
+        self.unbind();
+    }
+}
@@ -0,0 +1,43 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+mod notifier;
+mod registry;
+mod pipe;
+mod acceptor;
+
+use std::io;
+
+use self::notifier::Notifier;
+use self::pipe::InprocPipe;
+use self::acceptor::InprocAcceptor;
+use transport::{Transport, Destination};
+use transport::pipe::Pipe;
+use transport::acceptor::Acceptor;
+
+/// In-process transport, messages are handed over from one pipe to the other without any OS socket.
+/// Addresses are only visible from the sockets of the session the transport was registered in.
+/// Like nanomsg's inproc, a socket can connect before the address is bound,
+/// the connection is then established as soon as another socket binds it.
+pub struct Inproc;
+
+impl Transport for Inproc {
+    fn connect(&self, dest: &Destination) -> io::Result<Box<Pipe>> {
+        let pipe = box InprocPipe::connect(dest.addr, dest.pids);
+
+        Ok(pipe)
+    }
+
+    fn bind(&self, dest: &Destination) -> io::Result<Box<Acceptor>> {
+        let notifier = Notifier::new();
+
+        try!(registry::bind(dest.addr, notifier.handle()));
+
+        let acceptor = box InprocAcceptor::new(notifier, dest);
+
+        Ok(acceptor)
+    }
+}
@@ -0,0 +1,87 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::io::Result;
+
+use mio::{Registration, SetReadiness, Evented, Poll, Token, Ready, PollOpt};
+
+use io_error::*;
+
+/// Evented handle of an inproc endpoint.
+/// Since there is no OS socket to watch, the peers of the endpoint use a
+/// [NotifyHandle](struct.NotifyHandle.html) to wake it up when something changed.
+pub struct Notifier {
+    registration: RefCell<Option<Registration>>,
+    readiness: Rc<RefCell<Option<SetReadiness>>>
+}
+
+#[derive(Clone)]
+pub struct NotifyHandle {
+    readiness: Rc<RefCell<Option<SetReadiness>>>
+}
+
+impl Notifier {
+    pub fn new() -> Notifier {
+        Notifier {
+            registration: RefCell::new(None),
+            readiness: Rc::new(RefCell::new(None))
+        }
+    }
+
+    pub fn handle(&self) -> NotifyHandle {
+        NotifyHandle { readiness: self.readiness.clone() }
+    }
+
+    pub fn notify(&self) {
+        notify(&self.readiness)
+    }
+}
+
+impl NotifyHandle {
+    pub fn notify(&self) {
+        notify(&self.readiness)
+    }
+}
+
+fn notify(readiness: &RefCell<Option<SetReadiness>>) {
+    if let Some(ref readiness) = *readiness.borrow() {
+        let _ = readiness.set_readiness(Ready::readable());
+    }
+}
+
+impl Evented for Notifier {
+    fn register(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> Result<()> {
+        if self.registration.borrow().is_some() {
+            return Err(other_io_error("notifier already registered"));
+        }
+
+        let (registration, readiness) = Registration::new(poll, token, interest, opts);
+
+        // Whatever happened before registration must not be missed
+        let _ = readiness.set_readiness(Ready::readable());
+
+        *self.registration.borrow_mut() = Some(registration);
+        *self.readiness.borrow_mut() = Some(readiness);
+
+        Ok(())
+    }
+
+    fn reregister(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> Result<()> {
+        match *self.registration.borrow_mut() {
+            Some(ref registration) => registration.update(poll, token, interest, opts),
+            None => Err(other_io_error("notifier not registered")),
+        }
+    }
+
+    fn deregister(&self, poll: &Poll) -> Result<()> {
+        match *self.registration.borrow_mut() {
+            Some(ref registration) => registration.deregister(poll),
+            None => Err(other_io_error("notifier not registered")),
+        }
+    }
+}
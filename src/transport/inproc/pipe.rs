@@ -0,0 +1,455 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use std::rc::{Rc, Weak};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+
+use mio::{Ready, PollOpt};
+
+use core::Message;
+use transport::pipe::{self, Event, Context};
+use super::notifier::{Notifier, NotifyHandle};
+use super::registry;
+use io_error::*;
+
+/// Number of bytes a mailbox can hold before its peer stops being told it can send.
+const MAILBOX_CAPACITY: usize = 128 * 1024;
+
+/// Shared state of one side of an inproc connection.
+/// Each pipe owns its mailbox and only keeps a weak reference to the peer's one,
+/// so dropping a pipe is enough for the peer to notice the connection is gone.
+pub struct Mailbox {
+    pids: (u16, u16),
+    msgs: VecDeque<Rc<Message>>,
+    size: usize,
+    notify_handle: NotifyHandle,
+    peer: Option<Weak<RefCell<Mailbox>>>,
+    closed: bool
+}
+
+enum State {
+    Initial,
+    Linking,
+    Active,
+    Dead
+}
+
+pub struct InprocPipe {
+    state: State,
+    addr: Option<String>,
+    notifier: Notifier,
+    mailbox: Rc<RefCell<Mailbox>>,
+    pending_recv: bool,
+    should_raise_can_send: bool,
+    should_raise_can_recv: bool
+}
+
+/*****************************************************************************/
+/*                                                                           */
+/* Mailbox                                                                   */
+/*                                                                           */
+/*****************************************************************************/
+
+impl Mailbox {
+    fn new(pids: (u16, u16), notify_handle: NotifyHandle) -> Mailbox {
+        Mailbox {
+            pids: pids,
+            msgs: VecDeque::new(),
+            size: 0,
+            notify_handle: notify_handle,
+            peer: None,
+            closed: false
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    fn push(&mut self, msg: Rc<Message>) {
+        self.size += msg.len();
+        self.msgs.push_back(msg);
+        self.notify_handle.notify();
+    }
+
+    fn pop(&mut self) -> Option<Rc<Message>> {
+        self.msgs.pop_front().map(|msg| {
+            self.size -= msg.len();
+            msg
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.msgs.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.size >= MAILBOX_CAPACITY
+    }
+
+    fn get_peer(&self) -> Option<Rc<RefCell<Mailbox>>> {
+        self.peer.as_ref().and_then(|peer| peer.upgrade()).and_then(|peer| {
+            if peer.borrow().closed {
+                None
+            } else {
+                Some(peer)
+            }
+        })
+    }
+
+    fn notify_peer(&self) {
+        if let Some(peer) = self.peer.as_ref().and_then(|peer| peer.upgrade()) {
+            peer.borrow().notify_handle.notify();
+        }
+    }
+}
+
+/// Connects two mailboxes and wakes both sides up so they can perform the handshake.
+pub fn link(left: &Rc<RefCell<Mailbox>>, right: &Rc<RefCell<Mailbox>>) {
+    left.borrow_mut().peer = Some(Rc::downgrade(right));
+    right.borrow_mut().peer = Some(Rc::downgrade(left));
+
+    left.borrow().notify_handle.notify();
+    right.borrow().notify_handle.notify();
+}
+
+/// Merges header and body back together, as if the message had been read from a stream.
+fn flatten(msg: Rc<Message>) -> Message {
+    match Rc::try_unwrap(msg) {
+        Ok(msg) => {
+            let (mut raw, body) = msg.split();
+
+            raw.extend(body);
+            Message::from_body(raw)
+        },
+        Err(msg) => {
+            let mut raw = Vec::with_capacity(msg.len());
+
+            raw.extend_from_slice(msg.get_header());
+            raw.extend_from_slice(msg.get_body());
+            Message::from_body(raw)
+        }
+    }
+}
+
+/*****************************************************************************/
+/*                                                                           */
+/* InprocPipe                                                                */
+/*                                                                           */
+/*****************************************************************************/
+
+impl InprocPipe {
+    /// Creates the pipe of a connecting endpoint, it will be linked once an acceptor picks it up.
+    pub fn connect(addr: &str, pids: (u16, u16)) -> InprocPipe {
+        InprocPipe::new(Some(addr.to_owned()), pids)
+    }
+
+    /// Creates the pipe of an accepting endpoint, linked to the mailbox of the connecting pipe.
+    pub fn accept(pids: (u16, u16), remote: &Rc<RefCell<Mailbox>>) -> InprocPipe {
+        let pipe = InprocPipe::new(None, pids);
+
+        link(&pipe.mailbox, remote);
+
+        pipe
+    }
+
+    fn new(addr: Option<String>, pids: (u16, u16)) -> InprocPipe {
+        let notifier = Notifier::new();
+        let mailbox = Mailbox::new(pids, notifier.handle());
+
+        InprocPipe {
+            state: State::Initial,
+            addr: addr,
+            notifier: notifier,
+            mailbox: Rc::new(RefCell::new(mailbox)),
+            pending_recv: false,
+            should_raise_can_send: true,
+            should_raise_can_recv: true
+        }
+    }
+
+    fn handshake(&mut self, ctx: &mut Context) {
+        if self.mailbox.borrow().peer.is_none() {
+            return;
+        }
+
+        let peer = self.mailbox.borrow().get_peer();
+
+        match peer {
+            Some(peer) => {
+                let (proto_id, _) = peer.borrow().pids;
+                let (_, peer_proto_id) = self.mailbox.borrow().pids;
+
+                if proto_id == peer_proto_id {
+                    self.state = State::Active;
+                    ctx.raise(Event::Opened);
+                    self.resume(ctx);
+                } else {
+                    self.error(ctx, bad_handshake_io_error(Some(proto_id)));
+                }
+            },
+            None => self.error(ctx, other_io_error("peer closed"))
+        }
+    }
+
+    fn resume(&mut self, ctx: &mut Context) {
+        if self.pending_recv {
+            self.try_recv(ctx);
+        }
+
+        if self.should_raise_can_recv && !self.mailbox.borrow().is_empty() {
+            self.should_raise_can_recv = false;
+            ctx.raise(Event::CanRecv);
+        }
+
+        let peer = self.mailbox.borrow().get_peer();
+
+        match peer {
+            Some(peer) => {
+                if self.should_raise_can_send && !peer.borrow().is_full() {
+                    self.should_raise_can_send = false;
+                    ctx.raise(Event::CanSend);
+                }
+            },
+            None => {
+                // Messages already delivered by the peer can still be received
+                if self.mailbox.borrow().is_empty() {
+                    self.error(ctx, other_io_error("peer closed"));
+                }
+            }
+        }
+    }
+
+    fn try_recv(&mut self, ctx: &mut Context) {
+        let (msg, was_full, is_full) = {
+            let mut mailbox = self.mailbox.borrow_mut();
+            let was_full = mailbox.is_full();
+            let msg = mailbox.pop();
+
+            (msg, was_full, mailbox.is_full())
+        };
+
+        if let Some(msg) = msg {
+            self.pending_recv = false;
+
+            if was_full && !is_full {
+                self.mailbox.borrow().notify_peer();
+            }
+
+            ctx.raise(Event::Received(flatten(msg)));
+        }
+    }
+
+    fn error(&mut self, ctx: &mut Context, err: io::Error) {
+        ctx.raise(Event::Error(err));
+
+        self.die(ctx);
+    }
+
+    fn die(&mut self, ctx: &mut Context) {
+        match self.state {
+            State::Initial | State::Dead => {},
+            _ => ctx.deregister(&self.notifier)
+        }
+
+        self.state = State::Dead;
+        self.mailbox.borrow_mut().closed = true;
+        self.mailbox.borrow().notify_peer();
+
+        ctx.raise(Event::Closed);
+    }
+}
+
+impl pipe::Pipe for InprocPipe {
+    fn ready(&mut self, ctx: &mut Context, _: Ready) {
+        match self.state {
+            State::Linking => self.handshake(ctx),
+            State::Active  => self.resume(ctx),
+            _              => {}
+        }
+    }
+
+    fn open(&mut self, ctx: &mut Context) {
+        if let State::Initial = self.state {
+            ctx.register(&self.notifier, Ready::readable(), PollOpt::edge());
+
+            self.state = State::Linking;
+
+            if let Some(ref addr) = self.addr {
+                registry::connect(addr, Rc::downgrade(&self.mailbox));
+            }
+        }
+    }
+
+    fn close(&mut self, ctx: &mut Context) {
+        if let State::Dead = self.state {
+            return;
+        }
+
+        self.die(ctx);
+    }
+
+    fn send(&mut self, ctx: &mut Context, msg: Rc<Message>) {
+        if let State::Active = self.state {
+            let peer = self.mailbox.borrow().get_peer();
+
+            match peer {
+                Some(peer) => {
+                    peer.borrow_mut().push(msg);
+
+                    self.should_raise_can_send = true;
+                    self.notifier.notify();
+
+                    ctx.raise(Event::Sent);
+                },
+                None => self.error(ctx, other_io_error("peer closed"))
+            }
+        }
+    }
+
+    fn recv(&mut self, ctx: &mut Context) {
+        if let State::Active = self.state {
+            self.should_raise_can_recv = true;
+            self.pending_recv = true;
+
+            self.try_recv(ctx);
+            self.notifier.notify();
+        }
+    }
+
+    /// Sent messages are already in the peer mailbox, there is nothing to wait for.
+    fn linger(&mut self, ctx: &mut Context) {
+        if let State::Dead = self.state {
+            ctx.raise(Event::Closed);
+        } else {
+            self.die(ctx);
+        }
+    }
+
+    fn peer_addr(&self) -> Option<String> {
+        self.addr.clone()
+    }
+}
+
+impl Drop for InprocPipe {
+    fn drop(&mut self) {
+        self.mailbox.borrow().notify_peer();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use core::Message;
+    use transport::tests::*;
+    use transport::pipe::{self, Pipe};
+    use super::*;
+
+    fn is_opened(evt: &pipe::Event) -> bool {
+        match *evt {
+            pipe::Event::Opened => true,
+            _ => false
+        }
+    }
+
+    #[test]
+    fn accepted_pipe_should_open_when_pids_match() {
+        let mut connected = InprocPipe::connect("test", (1, 2));
+        let mut accepted = InprocPipe::accept((2, 1), &connected.mailbox);
+        let mut connected_ctx = TestPipeContext::new();
+        let mut accepted_ctx = TestPipeContext::new();
+
+        connected.open(&mut connected_ctx);
+        accepted.open(&mut accepted_ctx);
+        connected.ready(&mut connected_ctx, Ready::readable());
+        accepted.ready(&mut accepted_ctx, Ready::readable());
+
+        assert_eq!(1, connected_ctx.get_registrations().len());
+        assert_eq!(1, accepted_ctx.get_registrations().len());
+        assert!(is_opened(&connected_ctx.get_raised_events()[0]));
+        assert!(is_opened(&accepted_ctx.get_raised_events()[0]));
+    }
+
+    #[test]
+    fn pipe_should_fail_when_pids_do_not_match() {
+        let mut connected = InprocPipe::connect("test", (1, 2));
+        let mut accepted = InprocPipe::accept((3, 1), &connected.mailbox);
+        let mut connected_ctx = TestPipeContext::new();
+        let mut accepted_ctx = TestPipeContext::new();
+
+        connected.open(&mut connected_ctx);
+        accepted.open(&mut accepted_ctx);
+        connected.ready(&mut connected_ctx, Ready::readable());
+        accepted.ready(&mut accepted_ctx, Ready::readable());
+
+        let connected_evts = connected_ctx.get_raised_events();
+        let accepted_evts = accepted_ctx.get_raised_events();
+
+        assert_eq!(2, connected_evts.len());
+        assert_eq!("Error", connected_evts[0].name());
+        assert_eq!("Closed", connected_evts[1].name());
+        assert_eq!(1, connected_ctx.get_deregistrations());
+        assert_eq!(2, accepted_evts.len());
+        assert_eq!("Error", accepted_evts[0].name());
+        assert_eq!("Closed", accepted_evts[1].name());
+    }
+
+    #[test]
+    fn sent_message_should_be_received_by_peer() {
+        let mut connected = InprocPipe::connect("test", (1, 2));
+        let mut accepted = InprocPipe::accept((2, 1), &connected.mailbox);
+        let mut connected_ctx = TestPipeContext::new();
+        let mut accepted_ctx = TestPipeContext::new();
+
+        connected.open(&mut connected_ctx);
+        accepted.open(&mut accepted_ctx);
+        connected.ready(&mut connected_ctx, Ready::readable());
+        accepted.ready(&mut accepted_ctx, Ready::readable());
+
+        let msg = Message::from_header_and_body(vec![1, 2], vec![3, 4]);
+        connected.send(&mut connected_ctx, Rc::new(msg));
+        accepted.ready(&mut accepted_ctx, Ready::readable());
+        accepted.recv(&mut accepted_ctx);
+
+        assert_eq!("Sent", connected_ctx.get_raised_events()[2].name());
+
+        let accepted_evts = accepted_ctx.get_raised_events();
+        let received = accepted_evts.iter().filter_map(|evt| match *evt {
+            pipe::Event::Received(ref msg) => Some(msg),
+            _ => None
+        }).next().expect("message should have been received");
+
+        assert_eq!("CanRecv", accepted_evts[2].name());
+        assert!(received.get_header().is_empty());
+        assert_eq!(&[1, 2, 3, 4], received.get_body());
+    }
+
+    #[test]
+    fn pipe_should_fail_when_peer_is_dropped() {
+        let mut connected = InprocPipe::connect("test", (1, 2));
+        let mut connected_ctx = TestPipeContext::new();
+
+        {
+            let mut accepted = InprocPipe::accept((2, 1), &connected.mailbox);
+            let mut accepted_ctx = TestPipeContext::new();
+
+            accepted.open(&mut accepted_ctx);
+            accepted.ready(&mut accepted_ctx, Ready::readable());
+        }
+
+        connected.open(&mut connected_ctx);
+        connected.ready(&mut connected_ctx, Ready::readable());
+
+        let evts = connected_ctx.get_raised_events();
+
+        assert_eq!(2, evts.len());
+        assert_eq!("Error", evts[0].name());
+        assert_eq!("Closed", evts[1].name());
+    }
+}
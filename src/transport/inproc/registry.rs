@@ -0,0 +1,94 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+//! Keeps track of the bound inproc addresses and of the pipes waiting to be accepted.
+//! All the endpoints of a session live in the event loop thread, so the registry is thread local.
+
+use std::rc::{Rc, Weak};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+
+use super::notifier::NotifyHandle;
+use super::pipe::Mailbox;
+use io_error::*;
+
+#[derive(Default)]
+struct Binding {
+    acceptor: Option<NotifyHandle>,
+    backlog: Vec<Weak<RefCell<Mailbox>>>
+}
+
+thread_local!(static BINDINGS: RefCell<HashMap<String, Binding>> = RefCell::new(HashMap::new()));
+
+/// Registers an acceptor for the specified address, failing if another one is already bound to it.
+pub fn bind(addr: &str, acceptor: NotifyHandle) -> io::Result<()> {
+    BINDINGS.with(|bindings| {
+        let mut bindings = bindings.borrow_mut();
+        let binding = bindings.entry(addr.to_owned()).or_insert_with(Binding::default);
+
+        if binding.acceptor.is_some() {
+            return Err(addr_in_use_io_error(addr));
+        }
+
+        // Pipes that connected before the bind are waiting in the backlog
+        if !binding.backlog.is_empty() {
+            acceptor.notify();
+        }
+
+        binding.acceptor = Some(acceptor);
+
+        Ok(())
+    })
+}
+
+pub fn unbind(addr: &str) {
+    BINDINGS.with(|bindings| {
+        let mut bindings = bindings.borrow_mut();
+        let is_unused = match bindings.get_mut(addr) {
+            Some(binding) => {
+                binding.acceptor = None;
+                binding.backlog.is_empty()
+            },
+            None => false
+        };
+
+        if is_unused {
+            bindings.remove(addr);
+        }
+    })
+}
+
+/// Puts a connecting pipe in the backlog of the specified address.
+/// The pipe stays there until an acceptor is bound to the address.
+pub fn connect(addr: &str, mailbox: Weak<RefCell<Mailbox>>) {
+    BINDINGS.with(|bindings| {
+        let mut bindings = bindings.borrow_mut();
+        let binding = bindings.entry(addr.to_owned()).or_insert_with(Binding::default);
+
+        binding.backlog.push(mailbox);
+
+        if let Some(ref acceptor) = binding.acceptor {
+            acceptor.notify();
+        }
+    })
+}
+
+/// Takes all the connecting pipes that are still alive from the backlog of the specified address.
+pub fn accept(addr: &str) -> Vec<Rc<RefCell<Mailbox>>> {
+    BINDINGS.with(|bindings| {
+        let mut bindings = bindings.borrow_mut();
+
+        match bindings.get_mut(addr) {
+            Some(binding) => binding.backlog.
+                drain(..).
+                filter_map(|mailbox| mailbox.upgrade()).
+                filter(|mailbox| !mailbox.borrow().is_closed()).
+                collect(),
+            None => Vec::new()
+        }
+    })
+}
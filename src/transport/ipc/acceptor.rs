@@ -14,6 +14,7 @@ use transport::*;
 use transport::acceptor::*;
 use transport::async::AsyncPipe;
 use super::stub::IpcPipeStub;
+use super::cred;
 
 pub struct IpcAcceptor {
     listener: UnixListener,
@@ -60,9 +61,10 @@ impl IpcAcceptor {
     }
 
     fn create_pipe(&self, stream: UnixStream) -> Box<pipe::Pipe> {
+        let creds = cred::peer_credentials(&stream);
         let stub = IpcPipeStub::new(stream, self.recv_max_size);
 
-        box AsyncPipe::new(stub, self.proto_ids)
+        box AsyncPipe::new(stub, self.proto_ids).with_peer_credentials(creds)
     }
 }
 
@@ -82,4 +84,9 @@ impl acceptor::Acceptor for IpcAcceptor {
         ctx.deregister(&self.listener);
         ctx.raise(Event::Closed);
     }
+
+    fn local_addr(&self) -> Option<String> {
+        self.listener.local_addr().ok()
+            .and_then(|addr| addr.as_pathname().map(|path| path.to_string_lossy().into_owned()))
+    }
 }
\ No newline at end of file
@@ -0,0 +1,43 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use mio_uds::UnixStream;
+
+use core::endpoint::PeerCredentials;
+
+/// Reads the identity of the process at the other end of the stream.
+#[cfg(target_os = "linux")]
+pub fn peer_credentials(stream: &UnixStream) -> Option<PeerCredentials> {
+    use std::mem;
+    use std::os::unix::io::AsRawFd;
+    use libc;
+
+    let mut ucred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let res = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut ucred as *mut libc::ucred as *mut libc::c_void,
+            &mut len)
+    };
+
+    if res == 0 {
+        Some(PeerCredentials {
+            pid: ucred.pid as u32,
+            uid: ucred.uid,
+            gid: ucred.gid
+        })
+    } else {
+        None
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn peer_credentials(_: &UnixStream) -> Option<PeerCredentials> {
+    None
+}
@@ -8,6 +8,7 @@ mod stub;
 mod send;
 mod recv;
 mod acceptor;
+mod cred;
 
 use std::io;
 use std::path;
@@ -27,10 +28,13 @@ impl Transport for Ipc {
     fn connect(&self, dest: &Destination) -> io::Result<Box<Pipe>> {
         let filename = path::Path::new(dest.addr);
         let stream = try!(UnixStream::connect(filename));
+        let creds = cred::peer_credentials(&stream);
         let stub = IpcPipeStub::new(stream, dest.recv_max_size);
-        let pipe = box AsyncPipe::new(stub, dest.pids);
+        let pipe = AsyncPipe::new(stub, dest.pids)
+            .with_peer_addr(dest.addr.to_owned())
+            .with_peer_credentials(creds);
 
-        Ok(pipe)
+        Ok(box pipe)
     }
 
     fn bind(&self, dest: &Destination) -> io::Result<Box<Acceptor>> {
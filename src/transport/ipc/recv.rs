@@ -111,4 +111,17 @@ mod tests {
 
         assert_eq!(&expected_bytes, msg.get_body());
     }
+
+    #[test]
+    fn recv_fails_when_the_peer_closed_the_connection() {
+        let buffer = vec![1, 0, 0, 0, 0, 0, 0, 0, 8, 1, 4];
+        let mut stream = io::Cursor::new(buffer);
+        let mut operation = RecvOperation::new(1024);
+
+        assert!(operation.run(&mut stream).expect("recv should have succeeded").is_none());
+
+        let err = operation.run(&mut stream).err().expect("recv should have failed");
+
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+    }
 }
\ No newline at end of file
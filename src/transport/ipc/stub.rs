@@ -140,6 +140,9 @@ impl Handshake for IpcPipeStub {
     fn recv_handshake(&mut self, pids: (u16, u16)) -> io::Result<()> {
         recv_and_check_handshake(&mut self.stream, pids)
     }
+    fn has_pending_handshake_send(&self) -> bool {
+        false
+    }
 }
 
 impl AsyncPipeStub for IpcPipeStub {
@@ -8,6 +8,10 @@ pub mod async;
 pub mod tcp;
 #[cfg(not(windows))]
 pub mod ipc;
+pub mod inproc;
+pub mod ws;
+#[cfg(feature = "tls")]
+pub mod tls;
 pub mod endpoint;
 pub mod pipe;
 pub mod acceptor;
@@ -11,11 +11,13 @@ use std::fmt;
 use mio::Ready;
 
 use core::Message;
+use core::endpoint::PeerCredentials;
 use transport::endpoint::*;
 
 pub enum Command {
     Open,
     Close,
+    Linger,
     Send(Rc<Message>),
     Recv
 }
@@ -36,6 +38,22 @@ pub trait Pipe {
     fn close(&mut self, ctx: &mut Context);
     fn send(&mut self, ctx: &mut Context, msg: Rc<Message>);
     fn recv(&mut self, ctx: &mut Context);
+
+    /// Closes the pipe once the message being sent, if any, is completely written.
+    /// The `Closed` event must be raised when done, the default is to close right away.
+    fn linger(&mut self, ctx: &mut Context) {
+        self.close(ctx)
+    }
+
+    /// Address of the remote end of the pipe, if the transport knows it.
+    fn peer_addr(&self) -> Option<String> {
+        None
+    }
+
+    /// Identity of the process at the remote end of the pipe, if the transport knows it.
+    fn peer_credentials(&self) -> Option<PeerCredentials> {
+        None
+    }
 }
 
 pub trait Context : EndpointRegistrar + fmt::Debug {
@@ -53,6 +71,7 @@ impl Command {
         match *self {
             Command::Open    => "Open",
             Command::Close   => "Close",
+            Command::Linger  => "Linger",
             Command::Send(_) => "Send",
             Command::Recv    => "Recv"
         }
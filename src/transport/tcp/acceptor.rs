@@ -5,6 +5,7 @@
 // This file may not be copied, modified, or distributed except according to those terms.
 
 use std::io;
+use std::net::SocketAddr;
 
 use mio;
 use mio::tcp::{TcpListener, TcpStream};
@@ -37,9 +38,9 @@ impl TcpAcceptor {
 
         loop {
             match self.listener.accept() {
-                Ok((stream, _)) => {
+                Ok((stream, addr)) => {
                     let _ = stream.set_nodelay(self.no_delay);
-                    let pipe = self.create_pipe(stream);
+                    let pipe = self.create_pipe(stream, addr);
 
                     pipes.push(pipe);
                 },
@@ -58,10 +59,10 @@ impl TcpAcceptor {
         }
     }
 
-    fn create_pipe(&self, stream: TcpStream) -> Box<pipe::Pipe> {
+    fn create_pipe(&self, stream: TcpStream, addr: SocketAddr) -> Box<pipe::Pipe> {
         let stub = TcpPipeStub::new(stream, self.recv_max_size);
 
-        box AsyncPipe::new(stub, self.proto_ids)
+        box AsyncPipe::new(stub, self.proto_ids).with_peer_addr(addr.to_string())
     }
 }
 
@@ -81,4 +82,8 @@ impl acceptor::Acceptor for TcpAcceptor {
         ctx.deregister(&self.listener);
         ctx.raise(Event::Closed);
     }
+
+    fn local_addr(&self) -> Option<String> {
+        self.listener.local_addr().ok().map(|addr| addr.to_string())
+    }
 }
\ No newline at end of file
@@ -5,8 +5,8 @@
 // This file may not be copied, modified, or distributed except according to those terms.
 
 mod stub;
-mod send;
-mod recv;
+pub mod send;
+pub mod recv;
 mod acceptor;
 
 use std::str::FromStr;
@@ -30,7 +30,7 @@ impl Tcp {
         let stream = try!(TcpStream::connect(addr));
         try!(stream.set_nodelay(dest.tcp_no_delay));
         let stub = TcpPipeStub::new(stream, dest.recv_max_size);
-        let pipe = box AsyncPipe::new(stub, dest.pids);
+        let pipe = box AsyncPipe::new(stub, dest.pids).with_peer_addr(addr.to_string());
 
         Ok(pipe)
     }
@@ -92,3 +92,23 @@ fn read_payload<T:io::Read>(stream: &mut T, mut buffer: Vec<u8>, mut read: usize
         Ok((false, RecvOperationStep::Payload(buffer, read)))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+
+    #[test]
+    fn recv_fails_when_the_peer_closed_the_connection() {
+        let buffer = vec![0, 0, 0, 0, 0, 0, 0, 8, 1, 4];
+        let mut stream = io::Cursor::new(buffer);
+        let mut operation = RecvOperation::new(1024);
+
+        assert!(operation.run(&mut stream).expect("recv should have succeeded").is_none());
+
+        let err = operation.run(&mut stream).err().expect("recv should have failed");
+
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+    }
+}
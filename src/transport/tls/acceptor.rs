@@ -0,0 +1,98 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use std::io;
+use std::net::SocketAddr;
+
+use mio;
+use mio::tcp::{TcpListener, TcpStream};
+
+use openssl::ssl::{Ssl, SslAcceptor};
+
+use transport::*;
+use transport::acceptor::*;
+use transport::async::AsyncPipe;
+use super::stub::TlsPipeStub;
+use super::stream::TlsStream;
+use io_error::*;
+
+pub struct TlsAcceptor {
+    listener: TcpListener,
+    ssl_acceptor: SslAcceptor,
+    proto_ids: (u16, u16),
+    no_delay: bool,
+    recv_max_size: u64
+}
+
+impl TlsAcceptor {
+
+    pub fn new(l: TcpListener, ssl_acceptor: SslAcceptor, dest: &Destination) -> TlsAcceptor {
+        TlsAcceptor {
+            listener: l,
+            ssl_acceptor: ssl_acceptor,
+            proto_ids: dest.pids,
+            no_delay: dest.tcp_no_delay,
+            recv_max_size: dest.recv_max_size
+        }
+    }
+
+    fn accept(&mut self, ctx: &mut Context) {
+        let mut pipes = Vec::new();
+
+        loop {
+            match self.listener.accept() {
+                Ok((stream, addr)) => {
+                    let _ = stream.set_nodelay(self.no_delay);
+
+                    match self.create_pipe(stream, addr) {
+                        Ok(pipe) => pipes.push(pipe),
+                        Err(e) => ctx.raise(Event::Error(e))
+                    }
+                },
+                Err(e) => {
+                    if e.kind() == io::ErrorKind::WouldBlock {
+                        break;
+                    } else {
+                        ctx.raise(Event::Error(e));
+                    }
+                }
+            }
+        }
+
+        if pipes.is_empty() == false {
+            ctx.raise(Event::Accepted(pipes));
+        }
+    }
+
+    fn create_pipe(&self, stream: TcpStream, addr: SocketAddr) -> io::Result<Box<pipe::Pipe>> {
+        let ssl = try!(Ssl::new(self.ssl_acceptor.context()).map_err(other_io_error));
+        let stub = TlsPipeStub::new(TlsStream::new(ssl, stream, false), self.recv_max_size);
+
+        Ok(box AsyncPipe::new(stub, self.proto_ids).with_peer_addr(addr.to_string()))
+    }
+}
+
+impl acceptor::Acceptor for TlsAcceptor {
+    fn ready(&mut self, ctx: &mut Context, events: mio::Ready) {
+        if events.is_readable() {
+            self.accept(ctx);
+        }
+    }
+
+    fn open(&mut self, ctx: &mut Context) {
+        ctx.register(&self.listener, mio::Ready::readable(), mio::PollOpt::edge());
+        ctx.raise(Event::Opened);
+    }
+
+    fn close(&mut self, ctx: &mut Context) {
+        ctx.deregister(&self.listener);
+        ctx.raise(Event::Closed);
+    }
+
+    fn local_addr(&self) -> Option<String> {
+        self.listener.local_addr().ok().map(|addr| addr.to_string())
+    }
+}
\ No newline at end of file
@@ -0,0 +1,197 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+mod stream;
+mod stub;
+mod acceptor;
+
+use std::str::FromStr;
+use std::io;
+use std::net;
+
+use mio::tcp::{TcpListener, TcpStream};
+
+use openssl::ssl::{SslMethod, SslConnector, SslAcceptor, SslContextBuilder, SslVerifyMode};
+use openssl::x509::X509;
+use openssl::pkey::PKey;
+use openssl::error::ErrorStack;
+
+use self::stream::TlsStream;
+use self::stub::TlsPipeStub;
+use self::acceptor::TlsAcceptor;
+use transport::{Transport, Destination};
+use transport::pipe::Pipe;
+use transport::acceptor::Acceptor;
+use transport::async::AsyncPipe;
+use io_error::*;
+
+/// Certificates and keys used by the [Tls](struct.Tls.html) transport, all in PEM format.
+#[derive(Clone, Default)]
+pub struct TlsConfig {
+    certificate: Option<Vec<u8>>,
+    private_key: Option<Vec<u8>>,
+    ca_roots: Vec<Vec<u8>>,
+    client_auth: bool,
+    server_name: Option<String>
+}
+
+impl TlsConfig {
+    pub fn new() -> TlsConfig {
+        TlsConfig::default()
+    }
+
+    /// Certificate chain presented to the peers, starting with the certificate of this end.
+    /// Required to bind, optional to connect unless the server asks for client authentication.
+    pub fn certificate(mut self, pem: &[u8]) -> TlsConfig {
+        self.certificate = Some(pem.to_vec());
+        self
+    }
+
+    /// Private key matching the certificate.
+    pub fn private_key(mut self, pem: &[u8]) -> TlsConfig {
+        self.private_key = Some(pem.to_vec());
+        self
+    }
+
+    /// Adds certificate authorities trusted to verify the peers, in addition to the system ones.
+    pub fn ca_root(mut self, pem: &[u8]) -> TlsConfig {
+        self.ca_roots.push(pem.to_vec());
+        self
+    }
+
+    /// When required, binding sockets reject the peers that do not present a trusted certificate.
+    pub fn require_client_auth(mut self, required: bool) -> TlsConfig {
+        self.client_auth = required;
+        self
+    }
+
+    /// Name the server certificate is checked against, defaults to the host of the connect address.
+    pub fn server_name(mut self, name: &str) -> TlsConfig {
+        self.server_name = Some(name.to_owned());
+        self
+    }
+
+    fn has_identity(&self) -> bool {
+        self.certificate.is_some() && self.private_key.is_some()
+    }
+
+    fn load_identity(&self, builder: &mut SslContextBuilder) -> Result<(), ErrorStack> {
+        if let Some(ref pem) = self.certificate {
+            let mut chain = try!(X509::stack_from_pem(pem)).into_iter();
+
+            if let Some(cert) = chain.next() {
+                try!(builder.set_certificate(&cert));
+            }
+            for cert in chain {
+                try!(builder.add_extra_chain_cert(cert));
+            }
+        }
+        if let Some(ref pem) = self.private_key {
+            let key = try!(PKey::private_key_from_pem(pem));
+
+            try!(builder.set_private_key(&key));
+            try!(builder.check_private_key());
+        }
+
+        Ok(())
+    }
+
+    fn load_ca_roots(&self, builder: &mut SslContextBuilder) -> Result<(), ErrorStack> {
+        for pem in &self.ca_roots {
+            for cert in try!(X509::stack_from_pem(pem)) {
+                try!(builder.cert_store_mut().add_cert(cert));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn create_connector(&self) -> Result<SslConnector, ErrorStack> {
+        let mut builder = try!(SslConnector::builder(SslMethod::tls()));
+
+        try!(self.load_identity(&mut builder));
+        try!(self.load_ca_roots(&mut builder));
+
+        Ok(builder.build())
+    }
+
+    fn create_acceptor(&self) -> Result<SslAcceptor, ErrorStack> {
+        let mut builder = try!(SslAcceptor::mozilla_intermediate(SslMethod::tls()));
+
+        try!(self.load_identity(&mut builder));
+        try!(self.load_ca_roots(&mut builder));
+
+        if self.client_auth {
+            builder.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+        }
+
+        Ok(builder.build())
+    }
+}
+
+/// TLS over TCP transport, using the same framing as the [Tcp](struct.Tcp.html) transport once the session is established.
+/// Addresses have the same format as the tcp ones, and are usually registered with the `tls+tcp` scheme.
+/// Requires the `tls` feature, which links to the system OpenSSL library.
+pub struct Tls {
+    connector: SslConnector,
+    acceptor: Option<SslAcceptor>,
+    server_name: Option<String>
+}
+
+impl Tls {
+    /// Creates the transport, failing with `InvalidInput` if the certificates or the key cannot be loaded.
+    pub fn new(config: TlsConfig) -> io::Result<Tls> {
+        let connector = try!(config.create_connector().map_err(invalid_input_io_error));
+        let acceptor = if config.has_identity() {
+            Some(try!(config.create_acceptor().map_err(invalid_input_io_error)))
+        } else {
+            None
+        };
+
+        Ok(Tls {
+            connector: connector,
+            acceptor: acceptor,
+            server_name: config.server_name
+        })
+    }
+
+    fn connect(&self, addr: &net::SocketAddr, dest: &Destination) -> io::Result<Box<Pipe>> {
+        let domain = self.server_name.clone().unwrap_or_else(|| addr.ip().to_string());
+        let ssl = try!(self.connector.configure().and_then(|c| c.into_ssl(&domain)).map_err(other_io_error));
+        let stream = try!(TcpStream::connect(addr));
+        try!(stream.set_nodelay(dest.tcp_no_delay));
+        let stub = TlsPipeStub::new(TlsStream::new(ssl, stream, true), dest.recv_max_size);
+        let pipe = box AsyncPipe::new(stub, dest.pids).with_peer_addr(addr.to_string());
+
+        Ok(pipe)
+    }
+    fn bind(&self, addr: &net::SocketAddr, dest: &Destination) -> io::Result<Box<Acceptor>> {
+        let ssl_acceptor = match self.acceptor {
+            Some(ref ssl_acceptor) => ssl_acceptor.clone(),
+            None => return Err(invalid_input_io_error("a certificate and a private key are required to bind"))
+        };
+        let listener = try!(TcpListener::bind(addr));
+        let acceptor = box TlsAcceptor::new(listener, ssl_acceptor, dest);
+
+        Ok(acceptor)
+    }
+}
+
+impl Transport for Tls {
+    fn connect(&self, dest: &Destination) -> io::Result<Box<Pipe>> {
+        match net::SocketAddr::from_str(dest.addr) {
+            Ok(addr) => self.connect(&addr, dest),
+            Err(_) => Err(invalid_input_io_error(dest.addr))
+        }
+    }
+
+    fn bind(&self, dest: &Destination) -> io::Result<Box<Acceptor>> {
+        match net::SocketAddr::from_str(dest.addr) {
+            Ok(addr) => self.bind(&addr, dest),
+            Err(_) => Err(invalid_input_io_error(dest.addr))
+        }
+    }
+}
@@ -0,0 +1,205 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use std::cell::RefCell;
+use std::io::{self, Read, Write};
+
+use mio::{Registration, SetReadiness, Evented, Poll, Token, Ready, PollOpt};
+use mio::tcp::{TcpStream, Shutdown};
+
+use openssl::ssl::{self, Ssl, SslStream, MidHandshakeSslStream, HandshakeError};
+
+use io_error::*;
+
+enum Session {
+    Idle(Ssl, TcpStream),
+    Handshaking(MidHandshakeSslStream<TcpStream>),
+    Established(SslStream<TcpStream>),
+    Broken
+}
+
+/// TLS session running over a non-blocking tcp stream.
+/// Reading a record may leave decrypted bytes buffered in the session, out of sight of the poll.
+/// To keep the level-triggered semantics the pipe states rely on, the stream also registers
+/// a readiness that is set as long as buffered bytes are waiting to be read.
+pub struct TlsStream {
+    is_client: bool,
+    session: Option<Session>,
+    registration: RefCell<Option<Registration>>,
+    readiness: RefCell<Option<SetReadiness>>
+}
+
+impl TlsStream {
+    pub fn new(ssl: Ssl, stream: TcpStream, is_client: bool) -> TlsStream {
+        TlsStream {
+            is_client: is_client,
+            session: Some(Session::Idle(ssl, stream)),
+            registration: RefCell::new(None),
+            readiness: RefCell::new(None)
+        }
+    }
+
+    /// Starts or resumes the TLS handshake.
+    /// Returns a `WouldBlock` error until the handshake is completed.
+    pub fn handshake(&mut self) -> io::Result<()> {
+        let (session, res) = match self.session.take() {
+            Some(Session::Idle(ssl, stream)) => {
+                if self.is_client {
+                    on_handshake_progress(ssl.connect(stream))
+                } else {
+                    on_handshake_progress(ssl.accept(stream))
+                }
+            },
+            Some(Session::Handshaking(mid)) => on_handshake_progress(mid.handshake()),
+            Some(Session::Established(stream)) => (Session::Established(stream), Ok(())),
+            Some(Session::Broken) | None => (Session::Broken, Err(other_io_error("tls session is broken")))
+        };
+
+        self.session = Some(session);
+        self.update_readiness();
+
+        res
+    }
+
+    /// Tells whether the handshake is waiting for the tcp stream to become writable.
+    pub fn is_handshake_writing(&self) -> bool {
+        match self.session {
+            Some(Session::Handshaking(ref mid)) => mid.error().code() == ssl::ErrorCode::WANT_WRITE,
+            _ => false
+        }
+    }
+
+    pub fn shutdown(&mut self) {
+        match self.session {
+            Some(Session::Established(ref mut stream)) => {
+                let _ = stream.shutdown();
+                let _ = stream.get_ref().shutdown(Shutdown::Both);
+            },
+            Some(Session::Handshaking(ref mid)) => {
+                let _ = mid.get_ref().shutdown(Shutdown::Both);
+            },
+            Some(Session::Idle(_, ref stream)) => {
+                let _ = stream.shutdown(Shutdown::Both);
+            },
+            _ => {}
+        }
+    }
+
+    #[cfg(windows)]
+    pub fn read_and_write_void(&mut self) {
+        if let Ok(mut stream) = self.get_tcp_stream() {
+            let mut buffer: [u8; 0] = [0; 0];
+
+            let _ = stream.read(&mut buffer);
+            let _ = stream.write(&buffer);
+        }
+    }
+
+    fn get_tcp_stream(&self) -> io::Result<&TcpStream> {
+        match self.session {
+            Some(Session::Idle(_, ref stream))       => Ok(stream),
+            Some(Session::Handshaking(ref mid))      => Ok(mid.get_ref()),
+            Some(Session::Established(ref stream))   => Ok(stream.get_ref()),
+            _ => Err(other_io_error("tls session is broken"))
+        }
+    }
+
+    fn get_ssl_stream(&mut self) -> io::Result<&mut SslStream<TcpStream>> {
+        match self.session {
+            Some(Session::Established(ref mut stream)) => Ok(stream),
+            _ => Err(would_block_io_error("tls handshake in progress"))
+        }
+    }
+
+    fn update_readiness(&self) {
+        let pending = match self.session {
+            Some(Session::Established(ref stream)) => stream.ssl().pending(),
+            _ => 0
+        };
+        let ready = if pending > 0 { Ready::readable() } else { Ready::empty() };
+
+        if let Some(ref readiness) = *self.readiness.borrow() {
+            let _ = readiness.set_readiness(ready);
+        }
+    }
+}
+
+fn on_handshake_progress(res: Result<SslStream<TcpStream>, HandshakeError<TcpStream>>) -> (Session, io::Result<()>) {
+    match res {
+        Ok(stream) => (Session::Established(stream), Ok(())),
+        Err(HandshakeError::WouldBlock(mid)) => (Session::Handshaking(mid), Err(would_block_io_error("tls handshake in progress"))),
+        Err(HandshakeError::Failure(mid)) => {
+            let err = from_ssl_error(mid.error());
+
+            (Session::Handshaking(mid), Err(err))
+        },
+        Err(HandshakeError::SetupFailure(e)) => (Session::Broken, Err(other_io_error(e)))
+    }
+}
+
+fn from_ssl_error(err: &ssl::Error) -> io::Error {
+    match err.io_error() {
+        Some(e) => io::Error::new(e.kind(), err.to_string()),
+        None => other_io_error(err.to_string())
+    }
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let res = try!(self.get_ssl_stream()).read(buf);
+
+        self.update_readiness();
+
+        res
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        try!(self.get_ssl_stream()).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        try!(self.get_ssl_stream()).flush()
+    }
+}
+
+impl Evented for TlsStream {
+    fn register(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> io::Result<()> {
+        if self.registration.borrow().is_some() {
+            return Err(other_io_error("tls stream already registered"));
+        }
+
+        try!(try!(self.get_tcp_stream()).register(poll, token, interest, opts));
+
+        let (registration, readiness) = Registration::new(poll, token, interest, opts);
+
+        *self.registration.borrow_mut() = Some(registration);
+        *self.readiness.borrow_mut() = Some(readiness);
+
+        self.update_readiness();
+
+        Ok(())
+    }
+
+    fn reregister(&self, poll: &Poll, token: Token, interest: Ready, opts: PollOpt) -> io::Result<()> {
+        try!(try!(self.get_tcp_stream()).reregister(poll, token, interest, opts));
+
+        match *self.registration.borrow() {
+            Some(ref registration) => registration.update(poll, token, interest, opts),
+            None => Err(other_io_error("tls stream not registered")),
+        }
+    }
+
+    fn deregister(&self, poll: &Poll) -> io::Result<()> {
+        try!(try!(self.get_tcp_stream()).deregister(poll));
+
+        match *self.registration.borrow() {
+            Some(ref registration) => registration.deregister(poll),
+            None => Err(other_io_error("tls stream not registered")),
+        }
+    }
+}
@@ -0,0 +1,358 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use std::ops::Deref;
+use std::rc::Rc;
+use std::io;
+
+use mio;
+
+use core::Message;
+use transport::tcp::send::SendOperation;
+use transport::tcp::recv::RecvOperation;
+use transport::async::stub::*;
+use super::stream::TlsStream;
+use io_error::*;
+
+/*****************************************************************************/
+/*                                                                           */
+/* TlsPipeStub                                                               */
+/*                                                                           */
+/*****************************************************************************/
+
+/// Same framing as the tcp stub, but over a TLS session.
+/// The TLS handshake is performed before the SP handshake, while the pipe waits for the latter.
+pub struct TlsPipeStub {
+    stream: TlsStream,
+    recv_max_size: u64,
+    handshake_sent: bool,
+    send_operation: Option<SendOperation>,
+    recv_operation: Option<RecvOperation>
+}
+
+impl Deref for TlsPipeStub {
+    type Target = mio::Evented;
+    fn deref(&self) -> &Self::Target {
+        &self.stream
+    }
+}
+
+impl TlsPipeStub {
+    pub fn new(stream: TlsStream, recv_max_size: u64) -> TlsPipeStub {
+        TlsPipeStub {
+            stream: stream,
+            recv_max_size: recv_max_size,
+            handshake_sent: false,
+            send_operation: None,
+            recv_operation: None
+        }
+    }
+
+    fn run_send_operation(&mut self, mut send_operation: SendOperation) -> io::Result<bool> {
+        if try!(send_operation.run(&mut self.stream)) {
+            Ok(true)
+        } else {
+            self.send_operation = Some(send_operation);
+            Ok(false)
+        }
+    }
+
+    fn run_recv_operation(&mut self, mut recv_operation: RecvOperation) -> io::Result<Option<Message>> {
+        match try!(recv_operation.run(&mut self.stream)) {
+            Some(msg) => Ok(Some(msg)),
+            None => {
+                self.recv_operation = Some(recv_operation);
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl Drop for TlsPipeStub {
+    fn drop(&mut self) {
+        self.stream.shutdown();
+    }
+}
+
+/*****************************************************************************/
+/*                                                                           */
+/* Sender for TlsPipeStub                                                    */
+/*                                                                           */
+/*****************************************************************************/
+
+impl Sender for TlsPipeStub {
+    fn start_send(&mut self, msg: Rc<Message>) -> io::Result<bool> {
+        let send_operation = SendOperation::new(msg);
+
+        self.run_send_operation(send_operation)
+    }
+
+    fn resume_send(&mut self) -> io::Result<bool> {
+        if let Some(send_operation) = self.send_operation.take() {
+            self.run_send_operation(send_operation)
+        } else {
+            Err(other_io_error("Cannot resume send: no pending operation"))
+        }
+    }
+
+    fn has_pending_send(&self) -> bool {
+        self.send_operation.is_some()
+    }
+}
+
+/*****************************************************************************/
+/*                                                                           */
+/* Receiver for TlsPipeStub                                                  */
+/*                                                                           */
+/*****************************************************************************/
+
+impl Receiver for TlsPipeStub {
+    fn start_recv(&mut self) -> io::Result<Option<Message>> {
+        let recv_operation = RecvOperation::new(self.recv_max_size);
+
+        self.run_recv_operation(recv_operation)
+    }
+
+    fn resume_recv(&mut self) -> io::Result<Option<Message>> {
+        if let Some(recv_operation) = self.recv_operation.take() {
+            self.run_recv_operation(recv_operation)
+        } else {
+            Err(other_io_error("Cannot resume recv: no pending operation"))
+        }
+    }
+
+    fn has_pending_recv(&self) -> bool {
+        self.recv_operation.is_some()
+    }
+}
+
+/*****************************************************************************/
+/*                                                                           */
+/* Handshake for TlsPipeStub                                                 */
+/*                                                                           */
+/*****************************************************************************/
+
+impl Handshake for TlsPipeStub {
+    /// Starts the TLS handshake and keeps it going on writable events until the first
+    /// handshake message is fully written, the rest of it is then driven by `recv_handshake`.
+    fn send_handshake(&mut self, _: (u16, u16)) -> io::Result<()> {
+        match self.stream.handshake() {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                if e.kind() == io::ErrorKind::WouldBlock && !self.stream.is_handshake_writing() {
+                    Ok(())
+                } else {
+                    Err(e)
+                }
+            }
+        }
+    }
+
+    fn recv_handshake(&mut self, pids: (u16, u16)) -> io::Result<()> {
+        try!(self.stream.handshake());
+
+        if !self.handshake_sent {
+            try!(send_and_check_handshake(&mut self.stream, pids));
+            self.handshake_sent = true;
+        }
+
+        recv_and_check_handshake(&mut self.stream, pids)
+    }
+
+    /// The TLS handshake may have to write at any step, when the send buffer of the socket is full.
+    fn has_pending_handshake_send(&self) -> bool {
+        self.stream.is_handshake_writing()
+    }
+}
+
+impl AsyncPipeStub for TlsPipeStub {
+    #[cfg(windows)]
+    fn read_and_write_void(&mut self) {
+        self.stream.read_and_write_void();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read, Write};
+    use std::net;
+    use std::rc::Rc;
+    use std::thread;
+    use std::time::Duration;
+
+    use mio::tcp::TcpStream;
+
+    use openssl::asn1::Asn1Time;
+    use openssl::bn::BigNum;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::PKey;
+    use openssl::ssl::Ssl;
+    use openssl::x509::{X509, X509NameBuilder};
+    use openssl::x509::extension::SubjectAlternativeName;
+
+    use core::Message;
+    use transport::async::stub::*;
+    use super::TlsPipeStub;
+    use super::super::TlsConfig;
+    use super::super::stream::TlsStream;
+
+    const PIDS: (u16, u16) = (16, 16);
+
+    fn make_self_signed_certificate() -> (Vec<u8>, Vec<u8>) {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "localhost").unwrap();
+        let name = name.build();
+        let mut builder = X509::builder().unwrap();
+
+        builder.set_version(2).unwrap();
+        builder.set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap()).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+        builder.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+
+        let alt_name = SubjectAlternativeName::new().ip("127.0.0.1").build(&builder.x509v3_context(None, None)).unwrap();
+        builder.append_extension(alt_name).unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+
+        (builder.build().to_pem().unwrap(), key.private_key_to_pem_pkcs8().unwrap())
+    }
+
+    fn connect_tcp_streams() -> (TcpStream, TcpStream) {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        (TcpStream::from_stream(client).unwrap(), TcpStream::from_stream(server).unwrap())
+    }
+
+    fn make_client_stub(stream: TcpStream, cert: &[u8]) -> TlsPipeStub {
+        let connector = TlsConfig::new().ca_root(cert).create_connector().unwrap();
+        let ssl = connector.configure().and_then(|c| c.into_ssl("127.0.0.1")).unwrap();
+
+        TlsPipeStub::new(TlsStream::new(ssl, stream, true), 1024)
+    }
+
+    fn make_server_stub(stream: TcpStream, cert: &[u8], key: &[u8]) -> TlsPipeStub {
+        let acceptor = TlsConfig::new().certificate(cert).private_key(key).create_acceptor().unwrap();
+        let ssl = Ssl::new(acceptor.context()).unwrap();
+
+        TlsPipeStub::new(TlsStream::new(ssl, stream, false), 1024)
+    }
+
+    /// Plays the handshake states of a pipe, returns true once the handshake is completed.
+    fn step_handshake(stub: &mut TlsPipeStub, sent: &mut bool) -> bool {
+        let res = if *sent { stub.recv_handshake(PIDS) } else { stub.send_handshake(PIDS) };
+
+        match res {
+            Ok(()) if *sent => true,
+            Ok(()) => { *sent = true; false },
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => false,
+            Err(e) => panic!("handshake failed: {}", e)
+        }
+    }
+
+    /// Fixed buffer sizes disable their automatic tuning, which could make room again.
+    fn fill_send_buffer(stream: &mut TcpStream, peer: &TcpStream) {
+        let chunk = [0u8; 4 * 1024];
+
+        stream.set_send_buffer_size(4 * 1024).unwrap();
+        peer.set_recv_buffer_size(4 * 1024).unwrap();
+
+        for _ in 0..10 {
+            loop {
+                match stream.write(&chunk) {
+                    Ok(_) => continue,
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) => panic!("failed to fill the send buffer: {}", e)
+                }
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn drain_recv_buffer(stream: &mut TcpStream) {
+        let mut chunk = [0u8; 64 * 1024];
+
+        loop {
+            match stream.read(&mut chunk) {
+                Ok(0) => return,
+                Ok(_) => continue,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) => panic!("failed to drain the recv buffer: {}", e)
+            }
+        }
+    }
+
+    #[test]
+    fn handshake_should_be_completed_before_messages_are_exchanged() {
+        let (cert, key) = make_self_signed_certificate();
+        let (client, server) = connect_tcp_streams();
+        let mut client = make_client_stub(client, &cert);
+        let mut server = make_server_stub(server, &cert, &key);
+        let (mut client_sent, mut server_sent) = (false, false);
+        let (mut client_done, mut server_done) = (false, false);
+
+        for _ in 0..1000 {
+            client_done = client_done || step_handshake(&mut client, &mut client_sent);
+            server_done = server_done || step_handshake(&mut server, &mut server_sent);
+
+            if client_done && server_done {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert!(client_done && server_done);
+
+        assert!(client.start_send(Rc::new(Message::from_body(vec![65, 66, 67]))).unwrap());
+
+        let mut received = server.start_recv().unwrap();
+
+        for _ in 0..1000 {
+            if received.is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+            received = server.resume_recv().unwrap();
+        }
+
+        assert_eq!(vec![65, 66, 67], received.expect("message should have been received").get_body());
+    }
+
+    #[test]
+    fn handshake_should_wait_for_writable_when_the_send_buffer_is_full() {
+        let (cert, _) = make_self_signed_certificate();
+        let (mut client, mut server) = connect_tcp_streams();
+
+        fill_send_buffer(&mut client, &server);
+
+        let mut client = make_client_stub(client, &cert);
+        let err = client.send_handshake(PIDS).err().expect("handshake should not have been written");
+
+        assert_eq!(io::ErrorKind::WouldBlock, err.kind());
+        assert!(client.has_pending_handshake_send());
+
+        let mut res = Err(err);
+
+        for _ in 0..1000 {
+            if res.is_ok() {
+                break;
+            }
+            drain_recv_buffer(&mut server);
+            thread::sleep(Duration::from_millis(1));
+            res = client.send_handshake(PIDS);
+        }
+
+        assert!(res.is_ok());
+        assert!(!client.has_pending_handshake_send());
+    }
+}
@@ -0,0 +1,399 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use std::io;
+
+use byteorder::{ BigEndian, ByteOrder };
+
+use transport::async::stub::*;
+use io_error::*;
+
+pub const OPCODE_CONTINUATION: u8 = 0x0;
+pub const OPCODE_TEXT: u8         = 0x1;
+pub const OPCODE_BINARY: u8       = 0x2;
+pub const OPCODE_CLOSE: u8        = 0x8;
+pub const OPCODE_PING: u8         = 0x9;
+pub const OPCODE_PONG: u8         = 0xA;
+
+const FIN_BIT: u8 = 0x80;
+const MASK_BIT: u8 = 0x80;
+
+/// What a recv operation can produce once a frame has been fully read.
+pub enum Frame {
+    Message(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong,
+    Close
+}
+
+/*****************************************************************************/
+/*                                                                           */
+/* SendOperation                                                             */
+/*                                                                           */
+/*****************************************************************************/
+
+/// Writes a single, unfragmented frame.
+pub struct SendOperation {
+    buffer: Vec<u8>,
+    written: usize
+}
+
+impl SendOperation {
+    pub fn new(opcode: u8, payload: &[&[u8]], mask: Option<[u8; 4]>) -> SendOperation {
+        let len = payload.iter().fold(0, |acc, part| acc + part.len());
+        let mut buffer = Vec::with_capacity(len + 14);
+        let mask_bit = if mask.is_some() { MASK_BIT } else { 0 };
+
+        buffer.push(FIN_BIT | opcode);
+
+        if len < 126 {
+            buffer.push(mask_bit | len as u8);
+        } else if len <= 0xFFFF {
+            let mut ext_len = [0u8; 2];
+            BigEndian::write_u16(&mut ext_len, len as u16);
+            buffer.push(mask_bit | 126);
+            buffer.extend_from_slice(&ext_len);
+        } else {
+            let mut ext_len = [0u8; 8];
+            BigEndian::write_u64(&mut ext_len, len as u64);
+            buffer.push(mask_bit | 127);
+            buffer.extend_from_slice(&ext_len);
+        }
+
+        let payload_start = match mask {
+            Some(key) => {
+                buffer.extend_from_slice(&key);
+                buffer.len()
+            },
+            None => buffer.len()
+        };
+
+        for part in payload {
+            buffer.extend_from_slice(part);
+        }
+
+        if let Some(key) = mask {
+            apply_mask(&mut buffer[payload_start..], key);
+        }
+
+        SendOperation {
+            buffer: buffer,
+            written: 0
+        }
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.written > 0
+    }
+
+    pub fn run<T:io::Write>(&mut self, stream: &mut T) -> io::Result<bool> {
+        stream.write_buffer(&self.buffer, &mut self.written)
+    }
+}
+
+/*****************************************************************************/
+/*                                                                           */
+/* RecvOperation                                                             */
+/*                                                                           */
+/*****************************************************************************/
+
+struct FrameHeader {
+    fin: bool,
+    opcode: u8,
+    mask: Option<[u8; 4]>,
+    len: u64
+}
+
+enum RecvStep {
+    Header([u8; 14], usize),
+    Payload(FrameHeader, Vec<u8>, usize)
+}
+
+/// Reads frames until a complete message or a control frame is available.
+/// Fragmented messages are reassembled, their total size is checked against `recv_max_size`.
+pub struct RecvOperation {
+    recv_max_size: u64,
+    step: Option<RecvStep>,
+    fragments: Option<Vec<u8>>
+}
+
+impl RecvOperation {
+    pub fn new(recv_max_size: u64) -> RecvOperation {
+        RecvOperation {
+            recv_max_size: recv_max_size,
+            step: Some(RecvStep::Header([0; 14], 0)),
+            fragments: None
+        }
+    }
+
+    pub fn run<T:io::Read>(&mut self, stream: &mut T) -> io::Result<Option<Frame>> {
+        loop {
+            let step = match self.step.take() {
+                Some(step) => step,
+                None => return Err(other_io_error("Cannot resume already failed recv operation"))
+            };
+
+            match step {
+                RecvStep::Header(buffer, read) => {
+                    match try!(self.read_header(stream, buffer, read)) {
+                        Some(next_step) => self.step = Some(next_step),
+                        None => return Ok(None)
+                    }
+                },
+                RecvStep::Payload(header, buffer, read) => {
+                    match try!(self.read_payload(stream, header, buffer, read)) {
+                        Some(frame) => return Ok(Some(frame)),
+                        None => continue
+                    }
+                }
+            }
+        }
+    }
+
+    fn read_header<T:io::Read>(&mut self, stream: &mut T, mut buffer: [u8; 14], mut read: usize) -> io::Result<Option<RecvStep>> {
+        loop {
+            let needed = header_len(&buffer, read);
+
+            if read == needed {
+                let header = parse_header(&buffer);
+                let payload = try!(self.check_header(&header));
+
+                return Ok(Some(RecvStep::Payload(header, payload, 0)));
+            }
+
+            let progress = try!(stream.read_buffer(&mut buffer[read..needed]));
+
+            if progress == 0 {
+                self.step = Some(RecvStep::Header(buffer, read));
+                return Ok(None);
+            }
+
+            read += progress;
+        }
+    }
+
+    fn check_header(&self, header: &FrameHeader) -> io::Result<Vec<u8>> {
+        let is_control = header.opcode & 0x8 != 0;
+
+        if is_control && (header.len > 125 || !header.fin) {
+            return Err(invalid_data_io_error("invalid control frame"));
+        }
+
+        // The most significant bit of a 64-bit length must be 0
+        if header.len & (1 << 63) != 0 {
+            return Err(invalid_data_io_error("invalid frame length"));
+        }
+
+        let buffered = self.fragments.as_ref().map_or(0, |f| f.len() as u64);
+
+        if !is_control && header.len > self.recv_max_size.saturating_sub(buffered) {
+            return Err(invalid_data_io_error("message is too long"));
+        }
+
+        Ok(vec![0u8; header.len as usize])
+    }
+
+    fn read_payload<T:io::Read>(&mut self, stream: &mut T, header: FrameHeader, mut buffer: Vec<u8>, mut read: usize) -> io::Result<Option<Frame>> {
+        while read < buffer.len() {
+            let progress = try!(stream.read_buffer(&mut buffer[read..]));
+
+            if progress == 0 {
+                self.step = Some(RecvStep::Payload(header, buffer, read));
+                return Ok(None);
+            }
+
+            read += progress;
+        }
+
+        if let Some(key) = header.mask {
+            apply_mask(&mut buffer, key);
+        }
+
+        self.step = Some(RecvStep::Header([0; 14], 0));
+        self.on_frame_received(header, buffer)
+    }
+
+    fn on_frame_received(&mut self, header: FrameHeader, payload: Vec<u8>) -> io::Result<Option<Frame>> {
+        match header.opcode {
+            OPCODE_CONTINUATION => {
+                match self.fragments.take() {
+                    Some(mut fragments) => {
+                        fragments.extend(payload);
+                        Ok(self.on_fragment_received(header.fin, fragments))
+                    },
+                    None => Err(invalid_data_io_error("unexpected continuation frame"))
+                }
+            },
+            OPCODE_TEXT | OPCODE_BINARY => {
+                if self.fragments.is_some() {
+                    Err(invalid_data_io_error("expected continuation frame"))
+                } else {
+                    Ok(self.on_fragment_received(header.fin, payload))
+                }
+            },
+            OPCODE_CLOSE => Ok(Some(Frame::Close)),
+            OPCODE_PING  => Ok(Some(Frame::Ping(payload))),
+            OPCODE_PONG  => Ok(Some(Frame::Pong)),
+            _            => Err(invalid_data_io_error("unknown frame opcode"))
+        }
+    }
+
+    fn on_fragment_received(&mut self, fin: bool, fragments: Vec<u8>) -> Option<Frame> {
+        if fin {
+            Some(Frame::Message(fragments))
+        } else {
+            self.fragments = Some(fragments);
+            None
+        }
+    }
+}
+
+/// Given what has been read so far, tells how long the frame header is.
+fn header_len(buffer: &[u8; 14], read: usize) -> usize {
+    if read < 2 {
+        return 2;
+    }
+
+    let mask_len = if buffer[1] & MASK_BIT != 0 { 4 } else { 0 };
+    let ext_len = match buffer[1] & 0x7F {
+        126 => 2,
+        127 => 8,
+        _   => 0
+    };
+
+    2 + ext_len + mask_len
+}
+
+fn parse_header(buffer: &[u8; 14]) -> FrameHeader {
+    let (len, mask_offset) = match buffer[1] & 0x7F {
+        126 => (BigEndian::read_u16(&buffer[2..4]) as u64, 4),
+        127 => (BigEndian::read_u64(&buffer[2..10]), 10),
+        x   => (x as u64, 2)
+    };
+    let mask = if buffer[1] & MASK_BIT != 0 {
+        let mut key = [0u8; 4];
+        key.copy_from_slice(&buffer[mask_offset..mask_offset + 4]);
+        Some(key)
+    } else {
+        None
+    };
+
+    FrameHeader {
+        fin: buffer[0] & FIN_BIT != 0,
+        opcode: buffer[0] & 0x0F,
+        mask: mask,
+        len: len
+    }
+}
+
+fn apply_mask(buffer: &mut [u8], key: [u8; 4]) {
+    for (i, byte) in buffer.iter_mut().enumerate() {
+        *byte ^= key[i % 4];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Read};
+
+    use super::*;
+
+    /// Behaves like a non-blocking socket whose peer has nothing more to send.
+    struct Pending(Cursor<Vec<u8>>);
+
+    impl Read for Pending {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.0.read(buf) {
+                Ok(0) => Err(io::Error::new(io::ErrorKind::WouldBlock, "no more data")),
+                any => any
+            }
+        }
+    }
+
+    fn recv_all(bytes: Vec<u8>) -> Vec<Frame> {
+        let mut stream = Pending(Cursor::new(bytes));
+        let mut operation = RecvOperation::new(1024);
+        let mut frames = Vec::new();
+
+        while let Some(frame) = operation.run(&mut stream).unwrap() {
+            frames.push(frame);
+        }
+
+        frames
+    }
+
+    #[test]
+    fn masked_frame_can_be_read_back() {
+        let mut stream = Cursor::new(Vec::new());
+        let mut operation = SendOperation::new(OPCODE_BINARY, &[&[1, 2], &[3, 4]], Some([7, 8, 9, 10]));
+
+        assert!(operation.run(&mut stream).unwrap());
+
+        let bytes = stream.into_inner();
+        assert_eq!(&[0x82, 0x84, 7, 8, 9, 10], &bytes[..6]);
+
+        let frames = recv_all(bytes);
+        assert_eq!(1, frames.len());
+        match frames[0] {
+            Frame::Message(ref payload) => assert_eq!(&[1, 2, 3, 4], &payload[..]),
+            _ => panic!("should have received a message")
+        }
+    }
+
+    #[test]
+    fn fragmented_message_is_reassembled() {
+        let bytes = vec![
+            0x02, 0x02, 1, 2,
+            0x89, 0x01, 9,
+            0x80, 0x01, 3];
+        let frames = recv_all(bytes);
+
+        assert_eq!(2, frames.len());
+        match frames[0] {
+            Frame::Ping(ref payload) => assert_eq!(&[9], &payload[..]),
+            _ => panic!("should have received a ping")
+        }
+        match frames[1] {
+            Frame::Message(ref payload) => assert_eq!(&[1, 2, 3], &payload[..]),
+            _ => panic!("should have received a message")
+        }
+    }
+
+    #[test]
+    fn extended_length_is_supported() {
+        let payload = vec![42u8; 300];
+        let mut stream = Cursor::new(Vec::new());
+        let mut operation = SendOperation::new(OPCODE_BINARY, &[&payload], None);
+
+        assert!(operation.run(&mut stream).unwrap());
+
+        let bytes = stream.into_inner();
+        assert_eq!(&[0x82, 126, 1, 44], &bytes[..4]);
+
+        match recv_all(bytes).pop() {
+            Some(Frame::Message(received)) => assert_eq!(payload, received),
+            _ => panic!("should have received a message")
+        }
+    }
+
+    #[test]
+    fn too_long_message_is_rejected() {
+        let mut stream = Cursor::new(vec![0x82, 126, 8, 0]);
+        let mut operation = RecvOperation::new(1024);
+        let err = operation.run(&mut stream).err().expect("should have failed");
+
+        assert_eq!(::std::io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn huge_length_is_rejected() {
+        let mut stream = Cursor::new(vec![0x82, 127, 255, 255, 255, 255, 255, 255, 255, 255]);
+        let mut operation = RecvOperation::new(1024);
+        let err = operation.run(&mut stream).err().expect("should have failed");
+
+        assert_eq!(::std::io::ErrorKind::InvalidData, err.kind());
+    }
+}
@@ -0,0 +1,263 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+//! HTTP upgrade request and reply, as described in RFC 6455 section 4.
+//! The SP protocol is negotiated with the `Sec-WebSocket-Protocol` header:
+//! the client asks for the protocol of the socket it wants to talk to, and the server echoes it back.
+
+use std::io::{self, Write};
+use std::str;
+
+use transport::async::stub::WriteBuffer;
+use super::sha1::{sha1, base64};
+use io_error::*;
+
+const WEBSOCKET_GUID: &'static str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Upper bound of the request or reply size, anything bigger is considered malicious.
+pub const MAX_HEAD_LEN: usize = 8 * 1024;
+
+pub struct Head {
+    start_line: String,
+    headers: Vec<(String, String)>
+}
+
+impl Head {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.headers.
+            iter().
+            find(|&&(ref n, _)| n.to_lowercase() == name.to_lowercase()).
+            map(|&(_, ref v)| v.as_str())
+    }
+
+    fn has_token(&self, name: &str, token: &str) -> bool {
+        self.get(name).map_or(false, |value| {
+            value.split(',').any(|x| x.trim().to_lowercase() == token.to_lowercase())
+        })
+    }
+}
+
+/// Returns the length of the request or reply head, including the terminating empty line, if complete.
+pub fn find_head_end(buffer: &[u8]) -> Option<usize> {
+    buffer.windows(4).position(|w| w == b"\r\n\r\n").map(|pos| pos + 4)
+}
+
+/// Tells how many bytes can be read without going past the end of the head.
+pub fn head_end_missing_len(buffer: &[u8]) -> usize {
+    let terminator = b"\r\n\r\n";
+
+    for matched in (1..4).rev() {
+        if buffer.ends_with(&terminator[..matched]) {
+            return 4 - matched;
+        }
+    }
+
+    4
+}
+
+pub fn parse_head(buffer: &[u8]) -> io::Result<Head> {
+    let text = try!(str::from_utf8(buffer).map_err(|_| invalid_data_io_error("handshake is not valid utf8")));
+    let mut lines = text.split("\r\n");
+    let start_line = match lines.next() {
+        Some(line) => line.to_owned(),
+        None => return Err(invalid_data_io_error("empty handshake"))
+    };
+    let mut headers = Vec::new();
+
+    for line in lines.take_while(|line| !line.is_empty()) {
+        match line.find(':') {
+            Some(index) => {
+                let (name, value) = line.split_at(index);
+                headers.push((name.trim().to_owned(), value[1..].trim().to_owned()));
+            },
+            None => return Err(invalid_data_io_error("malformed handshake header"))
+        }
+    }
+
+    Ok(Head {
+        start_line: start_line,
+        headers: headers
+    })
+}
+
+pub fn create_accept_key(key: &str) -> String {
+    let mut input = String::with_capacity(key.len() + WEBSOCKET_GUID.len());
+
+    input.push_str(key);
+    input.push_str(WEBSOCKET_GUID);
+
+    base64(&sha1(input.as_bytes()))
+}
+
+pub fn create_request(host: &str, path: &str, key: &str, protocol: &str) -> Vec<u8> {
+    format!(
+        "GET {} HTTP/1.1\r\n\
+        Host: {}\r\n\
+        Upgrade: websocket\r\n\
+        Connection: Upgrade\r\n\
+        Sec-WebSocket-Key: {}\r\n\
+        Sec-WebSocket-Version: 13\r\n\
+        Sec-WebSocket-Protocol: {}\r\n\
+        \r\n", path, host, key, protocol).into_bytes()
+}
+
+pub fn create_reply(key: &str, protocol: &str) -> Vec<u8> {
+    format!(
+        "HTTP/1.1 101 Switching Protocols\r\n\
+        Upgrade: websocket\r\n\
+        Connection: Upgrade\r\n\
+        Sec-WebSocket-Accept: {}\r\n\
+        Sec-WebSocket-Protocol: {}\r\n\
+        \r\n", create_accept_key(key), protocol).into_bytes()
+}
+
+pub fn create_error_reply(status: &str) -> Vec<u8> {
+    format!("HTTP/1.1 {}\r\nConnection: close\r\n\r\n", status).into_bytes()
+}
+
+/// Checks the request of a client and returns its key.
+/// On failure, the error comes with the HTTP status that should be sent back.
+pub fn check_request<'a>(head: &'a Head, path: &str, protocol: &str) -> Result<&'a str, (&'static str, io::Error)> {
+    let mut start_line = head.start_line.split(' ');
+
+    if start_line.next() != Some("GET") {
+        return Err(("405 Method Not Allowed", invalid_data_io_error("handshake is not a GET request")));
+    }
+    if start_line.next() != Some(path) {
+        return Err(("404 Not Found", invalid_data_io_error("handshake targets an unknown path")));
+    }
+    if !head.has_token("Upgrade", "websocket") || !head.has_token("Connection", "Upgrade") {
+        return Err(("400 Bad Request", invalid_data_io_error("handshake is not an upgrade request")));
+    }
+    if head.get("Sec-WebSocket-Version") != Some("13") {
+        return Err(("426 Upgrade Required", invalid_data_io_error("unsupported websocket version")));
+    }
+    if !head.has_token("Sec-WebSocket-Protocol", protocol) {
+        return Err(("400 Bad Request", invalid_data_io_error("received bad handshake")));
+    }
+
+    head.get("Sec-WebSocket-Key").
+        ok_or_else(|| ("400 Bad Request", invalid_data_io_error("handshake has no key")))
+}
+
+pub fn check_reply(head: &Head, key: &str, protocol: &str) -> io::Result<()> {
+    let mut start_line = head.start_line.split(' ');
+    let _ = start_line.next();
+
+    if start_line.next() != Some("101") {
+        return Err(invalid_data_io_error("handshake was refused"));
+    }
+    if head.get("Sec-WebSocket-Accept") != Some(create_accept_key(key).as_str()) {
+        return Err(invalid_data_io_error("handshake has a bad accept key"));
+    }
+    if head.get("Sec-WebSocket-Protocol") != Some(protocol) {
+        return Err(invalid_data_io_error("received bad handshake"));
+    }
+
+    Ok(())
+}
+
+/// Request or reply being written, resumed where it stopped when the stream becomes writable again.
+pub struct HandshakeOperation {
+    buffer: Vec<u8>,
+    written: usize
+}
+
+impl HandshakeOperation {
+    pub fn new(buffer: Vec<u8>) -> HandshakeOperation {
+        HandshakeOperation {
+            buffer: buffer,
+            written: 0
+        }
+    }
+
+    /// Returns `true` once the whole head has been written.
+    pub fn run<T:Write>(&mut self, stream: &mut T) -> io::Result<bool> {
+        stream.write_buffer(&self.buffer, &mut self.written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+
+    use super::*;
+
+    /// Behaves like a non-blocking socket whose send buffer fills up after a few bytes.
+    struct ShortWriter {
+        capacity: usize,
+        written: Vec<u8>
+    }
+
+    impl Write for ShortWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.capacity == 0 {
+                return Err(io::Error::new(io::ErrorKind::WouldBlock, "send buffer is full"));
+            }
+
+            let len = ::std::cmp::min(self.capacity, buf.len());
+
+            self.capacity -= len;
+            self.written.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn partially_written_reply_is_resumed() {
+        let reply = create_reply("dGhlIHNhbXBsZSBub25jZQ==", "pair.sp.nanomsg.org");
+        let mut stream = ShortWriter { capacity: 10, written: Vec::new() };
+        let mut operation = HandshakeOperation::new(reply.clone());
+
+        assert!(!operation.run(&mut stream).unwrap());
+        assert!(!operation.run(&mut stream).unwrap());
+        assert_eq!(10, stream.written.len());
+
+        stream.capacity = 1024;
+
+        assert!(operation.run(&mut stream).unwrap());
+        assert_eq!(reply, stream.written);
+    }
+
+    #[test]
+    fn accept_key_matches_rfc_sample() {
+        assert_eq!("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=", create_accept_key("dGhlIHNhbXBsZSBub25jZQ=="));
+    }
+
+    #[test]
+    fn head_end_is_never_read_past() {
+        assert_eq!(4, head_end_missing_len(b"GET / HTTP/1.1"));
+        assert_eq!(3, head_end_missing_len(b"GET / HTTP/1.1\r"));
+        assert_eq!(2, head_end_missing_len(b"GET / HTTP/1.1\r\n"));
+        assert_eq!(1, head_end_missing_len(b"GET / HTTP/1.1\r\n\r"));
+    }
+
+    #[test]
+    fn request_can_be_checked_by_server() {
+        let request = create_request("127.0.0.1:5454", "/", "dGhlIHNhbXBsZSBub25jZQ==", "rep.sp.nanomsg.org");
+        let len = find_head_end(&request).unwrap();
+        let head = parse_head(&request[..len]).unwrap();
+
+        assert_eq!(request.len(), len);
+        assert_eq!("dGhlIHNhbXBsZSBub25jZQ==", check_request(&head, "/", "rep.sp.nanomsg.org").unwrap());
+        assert!(check_request(&head, "/", "pull.sp.nanomsg.org").is_err());
+        assert!(check_request(&head, "/other", "rep.sp.nanomsg.org").is_err());
+    }
+
+    #[test]
+    fn reply_can_be_checked_by_client() {
+        let reply = create_reply("dGhlIHNhbXBsZSBub25jZQ==", "rep.sp.nanomsg.org");
+        let len = find_head_end(&reply).unwrap();
+        let head = parse_head(&reply[..len]).unwrap();
+
+        assert!(check_reply(&head, "dGhlIHNhbXBsZSBub25jZQ==", "rep.sp.nanomsg.org").is_ok());
+        assert!(check_reply(&head, "dGhlIHNhbXBsZSBub25jZQ==", "pull.sp.nanomsg.org").is_err());
+        assert!(check_reply(&head, "AAAAAAAAAAAAAAAAAAAAAA==", "rep.sp.nanomsg.org").is_err());
+    }
+}
@@ -0,0 +1,114 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+mod sha1;
+mod frame;
+mod handshake;
+mod stub;
+mod acceptor;
+
+use std::str::FromStr;
+use std::io;
+use std::net;
+
+use mio::tcp::{TcpListener, TcpStream};
+
+use self::stub::{WsPipeStub, Role};
+use self::acceptor::WsAcceptor;
+use transport::{Transport, Destination};
+use transport::pipe::Pipe;
+use transport::acceptor::Acceptor;
+use transport::async::AsyncPipe;
+use proto;
+use io_error::*;
+
+/// WebSocket transport, as specified by the SP over WebSocket mapping.
+/// Addresses look like `127.0.0.1:5454/path`, the path defaults to `/`.
+/// Messages are sent as binary frames and the protocol is negotiated
+/// with the `Sec-WebSocket-Protocol` header, using names like `rep.sp.nanomsg.org`.
+pub struct Ws;
+
+impl Ws {
+    fn connect(&self, addr: &net::SocketAddr, host: &str, path: &str, dest: &Destination) -> io::Result<Box<Pipe>> {
+        let stream = try!(TcpStream::connect(addr));
+        try!(stream.set_nodelay(dest.tcp_no_delay));
+        let role = Role::Client(host.to_owned(), path.to_owned());
+        let stub = WsPipeStub::new(stream, role, dest.recv_max_size);
+        let pipe = box AsyncPipe::new(stub, dest.pids).with_peer_addr(format!("{}{}", addr, path));
+
+        Ok(pipe)
+    }
+    fn bind(&self, addr: &net::SocketAddr, path: &str, dest: &Destination) -> io::Result<Box<Acceptor>> {
+        let listener = try!(TcpListener::bind(addr));
+        let acceptor = box WsAcceptor::new(listener, path, dest);
+
+        Ok(acceptor)
+    }
+}
+
+impl Transport for Ws {
+    fn connect(&self, dest: &Destination) -> io::Result<Box<Pipe>> {
+        let (host, path) = split_addr(dest.addr);
+
+        match net::SocketAddr::from_str(host) {
+            Ok(addr) => self.connect(&addr, host, path, dest),
+            Err(_) => Err(invalid_input_io_error(dest.addr))
+        }
+    }
+
+    fn bind(&self, dest: &Destination) -> io::Result<Box<Acceptor>> {
+        let (host, path) = split_addr(dest.addr);
+
+        match net::SocketAddr::from_str(host) {
+            Ok(addr) => self.bind(&addr, path, dest),
+            Err(_) => Err(invalid_input_io_error(dest.addr))
+        }
+    }
+}
+
+fn split_addr(addr: &str) -> (&str, &str) {
+    match addr.find('/') {
+        Some(index) => addr.split_at(index),
+        None => (addr, "/")
+    }
+}
+
+fn protocol_name(proto_id: u16) -> String {
+    let name = match proto_id {
+        proto::PAIR       => "pair",
+        proto::PUB        => "pub",
+        proto::SUB        => "sub",
+        proto::REQ        => "req",
+        proto::REP        => "rep",
+        proto::PUSH       => "push",
+        proto::PULL       => "pull",
+        proto::SURVEYOR   => "surveyor",
+        proto::RESPONDENT => "respondent",
+        proto::BUS        => "bus",
+        _                 => return format!("{}.sp.nanomsg.org", proto_id)
+    };
+
+    format!("{}.sp.nanomsg.org", name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_addr, protocol_name};
+
+    #[test]
+    fn addr_can_be_split_into_host_and_path() {
+        assert_eq!(("127.0.0.1:5454", "/"), split_addr("127.0.0.1:5454"));
+        assert_eq!(("127.0.0.1:5454", "/"), split_addr("127.0.0.1:5454/"));
+        assert_eq!(("127.0.0.1:5454", "/a/b"), split_addr("127.0.0.1:5454/a/b"));
+    }
+
+    #[test]
+    fn protocol_name_follows_the_sp_mapping() {
+        assert_eq!("req.sp.nanomsg.org", protocol_name(48));
+        assert_eq!("respondent.sp.nanomsg.org", protocol_name(99));
+        assert_eq!("7.sp.nanomsg.org", protocol_name(7));
+    }
+}
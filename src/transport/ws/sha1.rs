@@ -0,0 +1,124 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+//! Minimal SHA-1 and base64 implementations, only used to compute the `Sec-WebSocket-Accept` header.
+
+use byteorder::{ BigEndian, ByteOrder };
+
+pub fn sha1(input: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let bit_len = (input.len() as u64).wrapping_mul(8);
+    let mut padded = input.to_vec();
+
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+
+    let mut len_bytes = [0u8; 8];
+    BigEndian::write_u64(&mut len_bytes, bit_len);
+    padded.extend_from_slice(&len_bytes);
+
+    for block in padded.chunks(64) {
+        process_block(&mut state, block);
+    }
+
+    let mut digest = [0u8; 20];
+    for (i, word) in state.iter().enumerate() {
+        BigEndian::write_u32(&mut digest[i * 4..i * 4 + 4], *word);
+    }
+    digest
+}
+
+fn process_block(state: &mut [u32; 5], block: &[u8]) {
+    let mut w = [0u32; 80];
+
+    for i in 0..16 {
+        w[i] = BigEndian::read_u32(&block[i * 4..i * 4 + 4]);
+    }
+    for i in 16..80 {
+        w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+    }
+
+    let (mut a, mut b, mut c, mut d, mut e) = (state[0], state[1], state[2], state[3], state[4]);
+
+    for (i, wi) in w.iter().enumerate() {
+        let (f, k) = match i {
+            0...19  => ((b & c) | (!b & d), 0x5A827999),
+            20...39 => (b ^ c ^ d, 0x6ED9EBA1),
+            40...59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+            _       => (b ^ c ^ d, 0xCA62C1D6)
+        };
+        let temp = a.rotate_left(5).
+            wrapping_add(f).
+            wrapping_add(e).
+            wrapping_add(k).
+            wrapping_add(*wi);
+
+        e = d;
+        d = c;
+        c = b.rotate_left(30);
+        b = a;
+        a = temp;
+    }
+
+    state[0] = state[0].wrapping_add(a);
+    state[1] = state[1].wrapping_add(b);
+    state[2] = state[2].wrapping_add(c);
+    state[3] = state[3].wrapping_add(d);
+    state[4] = state[4].wrapping_add(e);
+}
+
+const BASE64_CHARS: &'static [u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn base64(input: &[u8]) -> String {
+    let mut output = String::with_capacity((input.len() + 2) / 3 * 4);
+
+    for chunk in input.chunks(3) {
+        let b0 = chunk[0] as usize;
+        let b1 = if chunk.len() > 1 { chunk[1] as usize } else { 0 };
+        let b2 = if chunk.len() > 2 { chunk[2] as usize } else { 0 };
+
+        output.push(BASE64_CHARS[b0 >> 2] as char);
+        output.push(BASE64_CHARS[((b0 & 0x03) << 4) | (b1 >> 4)] as char);
+
+        if chunk.len() > 1 {
+            output.push(BASE64_CHARS[((b1 & 0x0F) << 2) | (b2 >> 6)] as char);
+        } else {
+            output.push('=');
+        }
+        if chunk.len() > 2 {
+            output.push(BASE64_CHARS[b2 & 0x3F] as char);
+        } else {
+            output.push('=');
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha1_of_known_inputs() {
+        let empty = sha1(b"");
+        let abc = sha1(b"abc");
+
+        assert_eq!("2jmj7l5rSw0yVb/vlWAYkK/YBwk=", base64(&empty));
+        assert_eq!("qZk+NkcGgWq6PiVxeFDCbJzQ2J0=", base64(&abc));
+    }
+
+    #[test]
+    fn base64_handles_padding() {
+        assert_eq!("", base64(b""));
+        assert_eq!("Zg==", base64(b"f"));
+        assert_eq!("Zm8=", base64(b"fo"));
+        assert_eq!("Zm9v", base64(b"foo"));
+        assert_eq!("Zm9vYmFy", base64(b"foobar"));
+    }
+}
@@ -0,0 +1,348 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use std::ops::Deref;
+use std::rc::Rc;
+use std::io::{self, Read};
+#[cfg(windows)]
+use std::io::Write;
+
+use mio;
+use mio::tcp::{TcpStream, Shutdown};
+
+use core::Message;
+use transport::async::stub::*;
+use super::frame::*;
+use super::handshake::*;
+use super::protocol_name;
+use super::sha1::base64;
+use io_error::*;
+use xorshift::XorShift;
+
+pub enum Role {
+    Client(String, String),
+    Server(String)
+}
+
+/*****************************************************************************/
+/*                                                                           */
+/* WsPipeStub                                                                */
+/*                                                                           */
+/*****************************************************************************/
+
+pub struct WsPipeStub {
+    stream: TcpStream,
+    role: Role,
+    recv_max_size: u64,
+    rng: XorShift,
+    key: Option<String>,
+    input: Vec<u8>,
+    handshake_operation: Option<HandshakeOperation>,
+    control_operation: Option<SendOperation>,
+    send_operation: Option<SendOperation>,
+    recv_operation: Option<RecvOperation>
+}
+
+impl Deref for WsPipeStub {
+    type Target = mio::Evented;
+    fn deref(&self) -> &Self::Target {
+        &self.stream
+    }
+}
+
+impl WsPipeStub {
+    pub fn new(stream: TcpStream, role: Role, recv_max_size: u64) -> WsPipeStub {
+        WsPipeStub {
+            stream: stream,
+            role: role,
+            recv_max_size: recv_max_size,
+            rng: XorShift::new(),
+            key: None,
+            input: Vec::new(),
+            handshake_operation: None,
+            control_operation: None,
+            send_operation: None,
+            recv_operation: None
+        }
+    }
+
+    /// Frames sent by a client must be masked, frames sent by a server must not.
+    fn create_mask(&mut self) -> Option<[u8; 4]> {
+        match self.role {
+            Role::Client(..) => Some(next_mask(&mut self.rng)),
+            Role::Server(..) => None
+        }
+    }
+
+    fn create_send_operation(&mut self, opcode: u8, payload: &[&[u8]]) -> SendOperation {
+        let mask = self.create_mask();
+
+        SendOperation::new(opcode, payload, mask)
+    }
+
+    /// Pending control frames are written before any other frame.
+    fn flush_control_operation(&mut self) -> io::Result<bool> {
+        if let Some(mut control_operation) = self.control_operation.take() {
+            if !try!(control_operation.run(&mut self.stream)) {
+                self.control_operation = Some(control_operation);
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// A control frame can't be written while a frame is partially sent,
+    /// it is flushed either before the send starts or right after it completes.
+    fn run_send_operation(&mut self, mut send_operation: SendOperation) -> io::Result<bool> {
+        let can_send = send_operation.is_started() || try!(self.flush_control_operation());
+
+        if can_send && try!(send_operation.run(&mut self.stream)) {
+            try!(self.flush_control_operation());
+            Ok(true)
+        } else {
+            self.send_operation = Some(send_operation);
+            Ok(false)
+        }
+    }
+
+    fn run_recv_operation(&mut self, mut recv_operation: RecvOperation) -> io::Result<Option<Message>> {
+        loop {
+            let frame = try!(recv_operation.run(&mut self.stream));
+
+            match frame {
+                Some(Frame::Message(payload)) => return Ok(Some(Message::from_body(payload))),
+                Some(Frame::Ping(payload))    => try!(self.on_ping(payload)),
+                Some(Frame::Pong)             => continue,
+                Some(Frame::Close)            => return Err(other_io_error("connection closed by peer")),
+                None => {
+                    self.recv_operation = Some(recv_operation);
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    fn on_ping(&mut self, payload: Vec<u8>) -> io::Result<()> {
+        let pong = self.create_send_operation(OPCODE_PONG, &[&payload]);
+
+        self.control_operation = Some(pong);
+
+        if self.send_operation.is_none() {
+            try!(self.flush_control_operation());
+        }
+
+        Ok(())
+    }
+
+    /// A request or reply that can't be written at once is resumed on the next writable event.
+    fn run_handshake_operation(&mut self, mut handshake_operation: HandshakeOperation) -> io::Result<()> {
+        if try!(handshake_operation.run(&mut self.stream)) {
+            Ok(())
+        } else {
+            self.handshake_operation = Some(handshake_operation);
+            Err(would_block_io_error("handshake not fully sent"))
+        }
+    }
+
+    /// Accumulates received bytes until the whole request or reply head is available.
+    /// Never reads past the end of the head, so the first frames are left in the stream.
+    fn read_handshake(&mut self) -> io::Result<Head> {
+        let mut buffer = [0u8; 4];
+
+        loop {
+            if let Some(len) = find_head_end(&self.input) {
+                let head = try!(parse_head(&self.input[..len]));
+
+                self.input.clear();
+
+                return Ok(head);
+            }
+            if self.input.len() > MAX_HEAD_LEN {
+                return Err(invalid_data_io_error("handshake is too long"));
+            }
+
+            let needed = head_end_missing_len(&self.input);
+
+            match self.stream.read(&mut buffer[..needed]) {
+                Ok(0) => return Err(other_io_error("connection closed by peer")),
+                Ok(x) => self.input.extend_from_slice(&buffer[..x]),
+                Err(e) => return Err(e)
+            }
+        }
+    }
+
+    fn send_request(&mut self, host: String, path: String, pids: (u16, u16)) -> io::Result<()> {
+        let (_, peer_proto_id) = pids;
+        let key = base64(&next_key(&mut self.rng));
+        let request = create_request(&host, &path, &key, &protocol_name(peer_proto_id));
+
+        self.key = Some(key);
+        self.run_handshake_operation(HandshakeOperation::new(request))
+    }
+
+    fn recv_reply(&mut self, pids: (u16, u16)) -> io::Result<()> {
+        let (_, peer_proto_id) = pids;
+        let head = try!(self.read_handshake());
+        let key = self.key.take().unwrap_or_default();
+
+        check_reply(&head, &key, &protocol_name(peer_proto_id))
+    }
+
+    fn recv_request(&mut self, path: String, pids: (u16, u16)) -> io::Result<()> {
+        let (proto_id, _) = pids;
+        let protocol = protocol_name(proto_id);
+        let head = try!(self.read_handshake());
+        let reply = match check_request(&head, &path, &protocol) {
+            Ok(key) => Ok(create_reply(key, &protocol)),
+            Err((status, err)) => Err((create_error_reply(status), err))
+        };
+
+        match reply {
+            Ok(reply) => self.run_handshake_operation(HandshakeOperation::new(reply)),
+            Err((reply, err)) => {
+                let _ = HandshakeOperation::new(reply).run(&mut self.stream);
+                Err(err)
+            }
+        }
+    }
+}
+
+impl Drop for WsPipeStub {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+/*****************************************************************************/
+/*                                                                           */
+/* Sender for WsPipeStub                                                     */
+/*                                                                           */
+/*****************************************************************************/
+
+impl Sender for WsPipeStub {
+    fn start_send(&mut self, msg: Rc<Message>) -> io::Result<bool> {
+        let send_operation = self.create_send_operation(OPCODE_BINARY, &[msg.get_header(), msg.get_body()]);
+
+        self.run_send_operation(send_operation)
+    }
+
+    fn resume_send(&mut self) -> io::Result<bool> {
+        if let Some(send_operation) = self.send_operation.take() {
+            self.run_send_operation(send_operation)
+        } else {
+            Err(other_io_error("Cannot resume send: no pending operation"))
+        }
+    }
+
+    fn has_pending_send(&self) -> bool {
+        self.send_operation.is_some()
+    }
+}
+
+/*****************************************************************************/
+/*                                                                           */
+/* Receiver for WsPipeStub                                                   */
+/*                                                                           */
+/*****************************************************************************/
+
+impl Receiver for WsPipeStub {
+    fn start_recv(&mut self) -> io::Result<Option<Message>> {
+        let recv_operation = RecvOperation::new(self.recv_max_size);
+
+        self.run_recv_operation(recv_operation)
+    }
+
+    fn resume_recv(&mut self) -> io::Result<Option<Message>> {
+        if let Some(recv_operation) = self.recv_operation.take() {
+            self.run_recv_operation(recv_operation)
+        } else {
+            Err(other_io_error("Cannot resume recv: no pending operation"))
+        }
+    }
+
+    fn has_pending_recv(&self) -> bool {
+        self.recv_operation.is_some()
+    }
+}
+
+/*****************************************************************************/
+/*                                                                           */
+/* Handshake for WsPipeStub                                                  */
+/*                                                                           */
+/*****************************************************************************/
+
+impl Handshake for WsPipeStub {
+    /// The client sends the upgrade request, the server has nothing to say until it receives it.
+    fn send_handshake(&mut self, pids: (u16, u16)) -> io::Result<()> {
+        if let Some(handshake_operation) = self.handshake_operation.take() {
+            return self.run_handshake_operation(handshake_operation);
+        }
+
+        match self.role {
+            Role::Client(ref host, ref path) => {
+                let (host, path) = (host.clone(), path.clone());
+
+                self.send_request(host, path, pids)
+            },
+            Role::Server(..) => Ok(())
+        }
+    }
+
+    /// The client checks the upgrade reply, the server checks the request and replies to it.
+    /// A reply that could not be fully written is resumed first.
+    fn recv_handshake(&mut self, pids: (u16, u16)) -> io::Result<()> {
+        if let Some(handshake_operation) = self.handshake_operation.take() {
+            return self.run_handshake_operation(handshake_operation);
+        }
+
+        match self.role {
+            Role::Client(..) => self.recv_reply(pids),
+            Role::Server(ref path) => {
+                let path = path.clone();
+
+                self.recv_request(path, pids)
+            }
+        }
+    }
+
+    fn has_pending_handshake_send(&self) -> bool {
+        self.handshake_operation.is_some()
+    }
+}
+
+impl AsyncPipeStub for WsPipeStub {
+    #[cfg(windows)]
+    fn read_and_write_void(&mut self) {
+        let mut buffer: [u8; 0] = [0; 0];
+
+        let _ = self.stream.read(&mut buffer);
+        let _ = self.stream.write(&buffer);
+    }
+}
+
+/// Source of masking keys, RFC 6455 asks for unpredictable masks
+/// but it is about defeating broken proxies, not cryptography.
+fn next_mask(rng: &mut XorShift) -> [u8; 4] {
+    let x = rng.next();
+
+    [x as u8, (x >> 8) as u8, (x >> 16) as u8, (x >> 24) as u8]
+}
+
+/// Source of handshake nonces.
+fn next_key(rng: &mut XorShift) -> [u8; 16] {
+    let mut key = [0u8; 16];
+    let mut x = 0;
+
+    for (i, byte) in key.iter_mut().enumerate() {
+        if i % 8 == 0 {
+            x = rng.next();
+        }
+        *byte = (x >> ((i % 8) * 8)) as u8;
+    }
+
+    key
+}
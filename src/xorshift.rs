@@ -0,0 +1,32 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use time;
+
+/// xorshift64 generator seeded from the clock.
+/// Fast and good enough to spread values around, but not suitable for cryptography.
+pub struct XorShift {
+    state: u64
+}
+
+impl XorShift {
+    pub fn new() -> XorShift {
+        XorShift { state: time::precise_time_ns() | 1 }
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+}
+
+impl Default for XorShift {
+    fn default() -> Self {
+        XorShift::new()
+    }
+}
@@ -0,0 +1,75 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+pub use std::time::Duration;
+pub use std::thread;
+pub use std::io;
+
+pub use futures::{Future, Stream, Sink};
+
+pub use scaproust::*;
+
+pub use super::{urls, make_session, make_timeout, sleep_some};
+
+describe! can {
+
+    before_each {
+        let _ = ::env_logger::init();
+        let mut session = make_session();
+        let url = urls::tcp::get();
+    }
+
+    it "send and receive messages without blocking a thread per socket" {
+        let mut push = session.create_socket::<Push>().expect("Failed to create socket !");
+        let mut pull = session.create_socket::<Pull>().expect("Failed to create socket !");
+
+        pull.bind(&url).unwrap();
+        push.connect(&url).unwrap();
+
+        let push = push.into_async().unwrap();
+        let pull = pull.into_async().unwrap();
+        let pull_thread = thread::spawn(move || {
+            pull.take(3).map(|msg| msg.body).collect().wait().unwrap()
+        });
+
+        sleep_some();
+        let push = push.send(vec![65]).wait().unwrap();
+        let push = push.send(vec![66]).wait().unwrap();
+        let _ = push.send(vec![67]).wait().unwrap();
+
+        let received = pull_thread.join().unwrap();
+        assert_eq!(vec![vec![65], vec![66], vec![67]], received);
+    }
+
+    it "reply to requests from a split socket" {
+        let mut req = session.create_socket::<Req>().expect("Failed to create socket !");
+        let mut rep = session.create_socket::<Rep>().expect("Failed to create socket !");
+
+        rep.bind(&url).unwrap();
+        req.connect(&url).unwrap();
+        req.set_send_timeout(make_timeout()).unwrap();
+        req.set_recv_timeout(make_timeout()).unwrap();
+
+        let rep = rep.into_async().unwrap();
+        let rep_thread = thread::spawn(move || {
+            let (sink, stream) = rep.split();
+            let replies = stream.take(2).map(|mut msg| {
+                msg.body.reverse();
+                msg
+            });
+
+            replies.forward(sink).wait().map(|_| ()).unwrap();
+        });
+
+        sleep_some();
+        req.send(vec![65, 66, 67]).unwrap();
+        assert_eq!(vec![67, 66, 65], req.recv().unwrap());
+        req.send(vec![68, 69]).unwrap();
+        assert_eq!(vec![69, 68], req.recv().unwrap());
+
+        rep_thread.join().unwrap();
+    }
+}
@@ -145,4 +145,111 @@ describe! can {
         drop(session);
         device_thread.join().unwrap().unwrap_err();
     }
-}
\ No newline at end of file
+
+    it "forward subscriptions, then the messages they select" {
+
+        let mut d_sub = session.create_socket::<Sub>().expect("Failed to create socket !");
+        let mut d_pub = session.create_socket::<Pub>().expect("Failed to create socket !");
+        let mut publ = session.create_socket::<Pub>().expect("Failed to create socket !");
+        let mut sub = session.create_socket::<Sub>().expect("Failed to create socket !");
+
+        let pub_url = urls::tcp::get();
+        let d_pub_url = urls::tcp::get();
+
+        publ.set_option(ConfigOption::SubscriptionForwarding(true)).unwrap();
+        d_sub.set_option(ConfigOption::SubscriptionForwarding(true)).unwrap();
+        d_pub.set_option(ConfigOption::SubscriptionForwarding(true)).unwrap();
+        sub.set_option(ConfigOption::SubscriptionForwarding(true)).unwrap();
+        sub.set_recv_timeout(timeout).unwrap();
+
+        publ.bind(&pub_url).unwrap();
+        d_pub.bind(&d_pub_url).unwrap();
+        d_sub.connect(&pub_url).unwrap();
+
+        let barrier = Arc::new(Barrier::new(2));
+        let d_barrier = barrier.clone();
+        let device = session.create_bridge_device(d_sub, d_pub).unwrap();
+        let device_thread = thread::spawn(move || {
+            d_barrier.wait();
+            let res = device.run();
+            res
+        });
+
+        barrier.wait();
+        sleep_some();
+
+        // The device Pub receives the forwarded subscription from its queue,
+        // so the device checks the sockets again while that readiness change is pending
+        sub.connect(&d_pub_url).unwrap();
+        sub.set_option(ConfigOption::Subscribe(String::from("A"))).unwrap();
+        sleep_some();
+
+        publ.send(vec![66, 65]).expect("Pub should have sent a message");
+        publ.send(vec![65, 66]).expect("Pub should have sent a message");
+        let received = sub.recv().expect("Sub should have received a message");
+        assert_eq!(vec![65, 66], received);
+
+        let err = sub.recv().unwrap_err();
+        assert_eq!(io::ErrorKind::TimedOut, err.kind());
+        assert_eq!(0, sub.stats().unwrap().dropped_messages);
+
+        drop(session);
+        device_thread.join().unwrap().unwrap_err();
+    }
+
+    it "forward messages to a plain subscriber next to a forwarding one" {
+
+        let mut d_sub = session.create_socket::<Sub>().expect("Failed to create socket !");
+        let mut d_pub = session.create_socket::<Pub>().expect("Failed to create socket !");
+        let mut publ = session.create_socket::<Pub>().expect("Failed to create socket !");
+        let mut sub = session.create_socket::<Sub>().expect("Failed to create socket !");
+        let mut plain_sub = session.create_socket::<Sub>().expect("Failed to create socket !");
+
+        let pub_url = urls::tcp::get();
+        let d_pub_url = urls::tcp::get();
+
+        publ.set_option(ConfigOption::SubscriptionForwarding(true)).unwrap();
+        d_sub.set_option(ConfigOption::SubscriptionForwarding(true)).unwrap();
+        d_pub.set_option(ConfigOption::SubscriptionForwarding(true)).unwrap();
+        sub.set_option(ConfigOption::SubscriptionForwarding(true)).unwrap();
+        sub.set_recv_timeout(timeout).unwrap();
+        plain_sub.set_recv_timeout(timeout).unwrap();
+
+        publ.bind(&pub_url).unwrap();
+        d_pub.bind(&d_pub_url).unwrap();
+        d_sub.connect(&pub_url).unwrap();
+
+        let barrier = Arc::new(Barrier::new(2));
+        let d_barrier = barrier.clone();
+        let device = session.create_bridge_device(d_sub, d_pub).unwrap();
+        let device_thread = thread::spawn(move || {
+            d_barrier.wait();
+            let res = device.run();
+            res
+        });
+
+        barrier.wait();
+        sleep_some();
+
+        sub.connect(&d_pub_url).unwrap();
+        sub.set_option(ConfigOption::Subscribe(String::from("A"))).unwrap();
+        plain_sub.connect(&d_pub_url).unwrap();
+        plain_sub.set_option(ConfigOption::Subscribe(String::from(""))).unwrap();
+        sleep_some();
+
+        publ.send(vec![66, 65]).expect("Pub should have sent a message");
+        sleep_some();
+        publ.send(vec![65, 66]).expect("Pub should have sent a message");
+
+        assert_eq!(vec![65, 66], sub.recv().expect("Sub should have received a message"));
+        assert_eq!(vec![66, 65], plain_sub.recv().expect("Plain sub should have received a message"));
+        assert_eq!(vec![65, 66], plain_sub.recv().expect("Plain sub should have received a message"));
+
+        let err = sub.recv().unwrap_err();
+        assert_eq!(io::ErrorKind::TimedOut, err.kind());
+
+        drop(session);
+        device_thread.join().unwrap().unwrap_err();
+    }
+}
+
@@ -0,0 +1,77 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+pub use std::time::Duration;
+pub use std::thread;
+pub use std::io;
+pub use std::os::unix::io::RawFd;
+
+pub use mio::{Poll, Events, Token, Ready, PollOpt};
+pub use mio::unix::EventedFd;
+
+pub use scaproust::*;
+
+pub use super::{urls, make_session, make_timeout, sleep_some};
+
+pub fn is_readable(fd: RawFd) -> bool {
+    let poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(1);
+
+    poll.register(&EventedFd(&fd), Token(0), Ready::readable(), PollOpt::level()).unwrap();
+    poll.poll(&mut events, Some(Duration::from_millis(100))).unwrap();
+
+    events.iter().any(|event| event.readiness().is_readable())
+}
+
+describe! fd {
+
+    before_each {
+        let _ = ::env_logger::init();
+        let mut session = make_session();
+        let url = urls::tcp::get();
+        let mut push = session.create_socket::<Push>().expect("Failed to create socket !");
+        let mut pull = session.create_socket::<Pull>().expect("Failed to create socket !");
+        let send_fd = push.send_fd().unwrap();
+        let recv_fd = pull.recv_fd().unwrap();
+    }
+
+    it "is always the same for a given socket" {
+        assert_eq!(send_fd, push.send_fd().unwrap());
+        assert_eq!(recv_fd, pull.recv_fd().unwrap());
+    }
+
+    it "is readable when the socket can send" {
+        assert!(!is_readable(send_fd));
+
+        pull.bind(&url).unwrap();
+        push.connect(&url).unwrap();
+        sleep_some();
+
+        assert!(is_readable(send_fd));
+    }
+
+    it "is readable while the socket can receive" {
+        pull.set_recv_timeout(make_timeout()).unwrap();
+        pull.bind(&url).unwrap();
+        push.connect(&url).unwrap();
+        sleep_some();
+
+        assert!(!is_readable(recv_fd));
+
+        push.send(vec![65]).unwrap();
+        push.send(vec![66]).unwrap();
+        sleep_some();
+        assert!(is_readable(recv_fd));
+
+        assert_eq!(vec![65], pull.recv().unwrap());
+        sleep_some();
+        assert!(is_readable(recv_fd));
+
+        assert_eq!(vec![66], pull.recv().unwrap());
+        sleep_some();
+        assert!(!is_readable(recv_fd));
+    }
+}
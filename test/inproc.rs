@@ -0,0 +1,114 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+pub use std::time::Duration;
+pub use std::thread;
+pub use std::io;
+
+pub use scaproust::*;
+
+pub use super::{urls, make_session, make_timeout, sleep_some};
+
+describe! can {
+
+    before_each {
+        let _ = ::env_logger::init();
+        let mut session = make_session();
+        let mut push = session.create_socket::<Push>().expect("Failed to create socket !");
+        let mut pull = session.create_socket::<Pull>().expect("Failed to create socket !");
+        let url = urls::inproc::get();
+        let timeout = make_timeout();
+
+        push.set_send_timeout(timeout).expect("Failed to set send timeout !");
+        pull.set_recv_timeout(timeout).expect("Failed to set recv timeout !");
+    }
+
+    it "send a message through local endpoint" {
+        push.bind(&url).unwrap();
+        pull.connect(&url).unwrap();
+
+        push.send(vec![65, 66, 67]).unwrap();
+        let received = pull.recv().unwrap();
+
+        assert_eq!(vec![65, 66, 67], received)
+    }
+
+    it "connect before the address is bound" {
+        pull.connect(&url).unwrap();
+        push.bind(&url).unwrap();
+
+        push.send(vec![65, 66, 67]).unwrap();
+        let received = pull.recv().unwrap();
+
+        assert_eq!(vec![65, 66, 67], received)
+    }
+
+    it "reconnect when the address is bound again" {
+        push.connect(&url).unwrap();
+
+        let mut first = session.create_socket::<Pull>().expect("Failed to create socket !");
+        first.set_recv_timeout(timeout).unwrap();
+        first.bind(&url).unwrap();
+        push.send(vec![65, 66, 67]).unwrap();
+        assert_eq!(vec![65, 66, 67], first.recv().unwrap());
+        drop(first);
+        sleep_some();
+
+        pull.bind(&url).unwrap();
+        sleep_some();
+        push.send(vec![67, 66, 65]).unwrap();
+        assert_eq!(vec![67, 66, 65], pull.recv().unwrap());
+    }
+
+    it "exchange requests and replies" {
+        let mut req = session.create_socket::<Req>().expect("Failed to create socket !");
+        let mut rep = session.create_socket::<Rep>().expect("Failed to create socket !");
+
+        req.set_recv_timeout(timeout).unwrap();
+        rep.set_recv_timeout(timeout).unwrap();
+        rep.bind(&url).unwrap();
+        req.connect(&url).unwrap();
+
+        req.send(vec![65, 66, 67]).unwrap();
+        let request = rep.recv().unwrap();
+        rep.send(request).unwrap();
+        let reply = req.recv().unwrap();
+
+        assert_eq!(vec![65, 66, 67], reply)
+    }
+}
+
+describe! cannot {
+
+    before_each {
+        let _ = ::env_logger::init();
+        let mut session = make_session();
+        let url = urls::inproc::get();
+    }
+
+    it "bind an address twice" {
+        let mut first = session.create_socket::<Pull>().expect("Failed to create socket !");
+        let mut second = session.create_socket::<Pull>().expect("Failed to create socket !");
+
+        first.bind(&url).unwrap();
+        let err = second.bind(&url).unwrap_err();
+
+        assert_eq!(io::ErrorKind::AddrInUse, err.kind());
+    }
+
+    it "talk to a peer of the wrong protocol" {
+        let mut push = session.create_socket::<Push>().expect("Failed to create socket !");
+        let mut sub = session.create_socket::<Sub>().expect("Failed to create socket !");
+
+        push.bind(&url).unwrap();
+        sub.connect(&url).unwrap();
+        sleep_some();
+
+        let err = push.try_send(vec![65, 66, 67]).unwrap_err();
+
+        assert_eq!(io::ErrorKind::WouldBlock, err.kind());
+    }
+}
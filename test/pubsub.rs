@@ -106,4 +106,129 @@ describe! can {
         assert_eq!(io::ErrorKind::TimedOut, not_received2.kind());
         assert_eq!(vec![65, 66, 67], received3);
     }
-}
\ No newline at end of file
+
+    it "keep a topic subscribed twice until it is unsubscribed twice" {
+        let url = urls::tcp::get();
+
+        publ.bind(&url).unwrap();
+        sub1.connect(&url).unwrap();
+
+        sub1.set_option(ConfigOption::Subscribe(String::from("A"))).unwrap();
+        sub1.set_option(ConfigOption::Subscribe(String::from("A"))).unwrap();
+        sub1.set_option(ConfigOption::Unsubscribe(String::from("A"))).unwrap();
+
+        sleep_some();
+
+        publ.send(vec![65, 66, 67]).unwrap();
+        let received = sub1.recv().unwrap();
+
+        assert_eq!(vec![65, 66, 67], received);
+
+        sub1.set_option(ConfigOption::Unsubscribe(String::from("A"))).unwrap();
+
+        publ.send(vec![65, 66, 67]).unwrap();
+        let not_received = sub1.recv().unwrap_err();
+
+        assert_eq!(io::ErrorKind::TimedOut, not_received.kind());
+    }
+
+    it "filter messages with exact, pattern and exclusion subscriptions" {
+        let url = urls::tcp::get();
+
+        publ.bind(&url).unwrap();
+        sub1.connect(&url).unwrap();
+        sub2.connect(&url).unwrap();
+
+        sub1.set_option(ConfigOption::SubscribeExact(String::from("prices.usd"))).unwrap();
+        sub2.set_option(ConfigOption::SubscribePattern(String::from("prices.*.eur"))).unwrap();
+        sub2.set_option(ConfigOption::Exclude(String::from("prices.test."))).unwrap();
+
+        assert_eq!(vec![String::from("prices.usd")], sub1.get_exact_subscriptions().unwrap());
+        assert_eq!(vec![String::from("prices.*.eur")], sub2.get_pattern_subscriptions().unwrap());
+        assert_eq!(vec![String::from("prices.test.")], sub2.get_exclusions().unwrap());
+
+        sleep_some();
+
+        publ.send(b"prices.usd.eur\x001.07".to_vec()).unwrap();
+        sleep_some();
+        publ.send(b"prices.test.eur\x001.00".to_vec()).unwrap();
+        sleep_some();
+        publ.send(b"prices.usd\x001.00".to_vec()).unwrap();
+        let received1 = sub1.recv().unwrap();
+        let received2 = sub2.recv().unwrap();
+
+        assert_eq!(b"prices.usd\x001.00".to_vec(), received1);
+        assert_eq!(b"prices.usd.eur\x001.07".to_vec(), received2);
+
+        let not_received1 = sub1.recv().unwrap_err();
+        let not_received2 = sub2.recv().unwrap_err();
+
+        assert_eq!(io::ErrorKind::TimedOut, not_received1.kind());
+        assert_eq!(io::ErrorKind::TimedOut, not_received2.kind());
+    }
+
+    it "filter messages on the publisher side when subscriptions are forwarded" {
+        let url = urls::tcp::get();
+
+        publ.set_option(ConfigOption::SubscriptionForwarding(true)).unwrap();
+        sub1.set_option(ConfigOption::SubscriptionForwarding(true)).unwrap();
+        sub2.set_option(ConfigOption::SubscriptionForwarding(true)).unwrap();
+
+        publ.bind(&url).unwrap();
+        sub1.connect(&url).unwrap();
+        sub2.connect(&url).unwrap();
+        sub3.connect(&url).unwrap();
+
+        sub1.set_option(ConfigOption::Subscribe(String::from("A"))).unwrap();
+        sub2.set_option(ConfigOption::Subscribe(String::from("B"))).unwrap();
+        sub3.set_option(ConfigOption::Subscribe(String::from("A"))).unwrap();
+
+        sleep_some();
+
+        publ.send(vec![66, 65]).unwrap();
+        sleep_some();
+        publ.send(vec![65, 66, 67]).unwrap();
+        let received1 = sub1.recv().unwrap();
+        let received2 = sub2.recv().unwrap();
+        let received3 = sub3.recv().unwrap();
+
+        assert_eq!(vec![65, 66, 67], received1);
+        assert_eq!(vec![66, 65], received2);
+        assert_eq!(vec![65, 66, 67], received3);
+
+        let not_received1 = sub1.recv().unwrap_err();
+        let not_received2 = sub2.recv().unwrap_err();
+
+        assert_eq!(io::ErrorKind::TimedOut, not_received1.kind());
+        assert_eq!(io::ErrorKind::TimedOut, not_received2.kind());
+        assert_eq!(0, sub1.stats().unwrap().dropped_messages);
+        assert_eq!(0, sub2.stats().unwrap().dropped_messages);
+        assert_eq!(1, sub3.stats().unwrap().dropped_messages);
+    }
+
+    it "stop filtering messages on the publisher side when forwarding is disabled" {
+        let url = urls::tcp::get();
+
+        publ.set_option(ConfigOption::SubscriptionForwarding(true)).unwrap();
+        sub1.set_option(ConfigOption::SubscriptionForwarding(true)).unwrap();
+        sub1.set_option(ConfigOption::Subscribe(String::from("A"))).unwrap();
+
+        publ.bind(&url).unwrap();
+        sub1.connect(&url).unwrap();
+
+        sleep_some();
+
+        sub1.set_option(ConfigOption::SubscriptionForwarding(false)).unwrap();
+
+        sleep_some();
+
+        publ.send(vec![66, 65]).unwrap();
+        sleep_some();
+        publ.send(vec![65, 66, 67]).unwrap();
+        let received = sub1.recv().unwrap();
+
+        assert_eq!(vec![65, 66, 67], received);
+        assert_eq!(1, sub1.stats().unwrap().dropped_messages);
+    }
+}
+
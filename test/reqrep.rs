@@ -83,4 +83,283 @@ describe! can {
         let received_reply = req.recv().unwrap();
         assert_eq!(vec![66, 65, 67], received_reply);
     }
-}
\ No newline at end of file
+
+    it "forward requests and replies through a raw broker" {
+        let mut front = session.create_raw_socket::<Rep>().expect("Failed to create socket !");
+        let mut back = session.create_raw_socket::<Req>().expect("Failed to create socket !");
+        let back_url = urls::tcp::get();
+
+        front.set_recv_timeout(timeout).unwrap();
+        back.set_recv_timeout(timeout).unwrap();
+
+        front.bind(&url).unwrap();
+        back.bind(&back_url).unwrap();
+        req.connect(&url).unwrap();
+        rep.connect(&back_url).unwrap();
+
+        sleep_some();
+
+        req.send(vec![65, 66, 67]).unwrap();
+        let raw_request = front.recv_msg().unwrap();
+        assert!(raw_request.get_header().len() > 0);
+        assert_eq!(&[65, 66, 67], raw_request.get_body());
+
+        back.send_msg(raw_request).unwrap();
+        let received_request = rep.recv().unwrap();
+        assert_eq!(vec![65, 66, 67], received_request);
+
+        rep.send(vec![66, 65, 67]).unwrap();
+        let raw_reply = back.recv_msg().unwrap();
+        assert!(raw_reply.get_header().len() > 0);
+
+        front.send_msg(raw_reply).unwrap();
+        let received_reply = req.recv().unwrap();
+        assert_eq!(vec![66, 65, 67], received_reply);
+    }
+
+    it "resend a request to another worker when the first one crashes mid-request" {
+        let mut worker = session.create_socket::<Rep>().expect("Failed to create socket !");
+
+        worker.set_send_timeout(timeout).expect("Failed to set send timeout !");
+        worker.set_recv_timeout(timeout).expect("Failed to set recv timeout !");
+        req.bind(&url).unwrap();
+        rep.connect(&url).unwrap();
+        worker.connect(&url).unwrap();
+        sleep_some();
+
+        req.send(vec![65, 66, 67]).unwrap();
+        let (crashed, mut survivor) = match rep.recv() {
+            Ok(_) => (rep, worker),
+            Err(_) => (worker, rep)
+        };
+
+        drop(crashed);
+
+        let resent_request = survivor.recv().unwrap();
+        assert_eq!(vec![65, 66, 67], resent_request);
+
+        survivor.send(resent_request).unwrap();
+        assert_eq!(vec![65, 66, 67], req.recv().unwrap());
+    }
+
+    it "receive a reply that arrived before the worker crashed" {
+        rep.bind(&url).unwrap();
+        req.connect(&url).unwrap();
+        sleep_some();
+
+        req.send(vec![65, 66, 67]).unwrap();
+        let request = rep.recv().unwrap();
+        rep.send(request).unwrap();
+        sleep_some();
+        drop(rep);
+        sleep_some();
+
+        assert_eq!(vec![65, 66, 67], req.recv().unwrap());
+    }
+}
+
+
+describe! context {
+
+    before_each {
+        let _ = ::env_logger::init();
+        let mut session = make_session();
+        let mut req = session.create_socket::<Req>().expect("Failed to create socket !");
+        let mut rep = session.create_socket::<Rep>().expect("Failed to create socket !");
+        let url = urls::tcp::get();
+        let timeout = make_timeout();
+
+        req.set_send_timeout(timeout).expect("Failed to set send timeout !");
+        req.set_recv_timeout(timeout).expect("Failed to set recv timeout !");
+        rep.set_send_timeout(timeout).expect("Failed to set send timeout !");
+        rep.set_recv_timeout(timeout).expect("Failed to set recv timeout !");
+        rep.bind(&url).unwrap();
+        req.connect(&url).unwrap();
+        sleep_some();
+    }
+
+    it "receive the reply to its own request when replies come out of order" {
+        let mut raw_rep = session.create_raw_socket::<Rep>().expect("Failed to create socket !");
+        let raw_url = urls::tcp::get();
+        let mut ctx1 = req.open_context().unwrap();
+        let mut ctx2 = req.open_context().unwrap();
+
+        drop(rep);
+        raw_rep.set_recv_timeout(timeout).unwrap();
+        raw_rep.bind(&raw_url).unwrap();
+        req.connect(&raw_url).unwrap();
+        sleep_some();
+
+        ctx1.set_recv_timeout(timeout).unwrap();
+        ctx2.set_recv_timeout(timeout).unwrap();
+        ctx1.send(vec![65, 66, 67]).unwrap();
+        ctx2.send(vec![67, 66, 65]).unwrap();
+        let raw_request1 = raw_rep.recv_msg().unwrap();
+        let raw_request2 = raw_rep.recv_msg().unwrap();
+
+        raw_rep.send_msg(raw_request2).unwrap();
+        sleep_some();
+        raw_rep.send_msg(raw_request1).unwrap();
+
+        assert_eq!(vec![67, 66, 65], ctx2.recv().unwrap());
+        assert_eq!(vec![65, 66, 67], ctx1.recv().unwrap());
+    }
+
+    it "run concurrent exchanges from several threads" {
+        let threads: Vec<_> = (0..4u8).map(|i| {
+            let mut ctx = req.open_context().unwrap();
+
+            ctx.set_recv_timeout(timeout).unwrap();
+            thread::spawn(move || {
+                ctx.send(vec![i]).unwrap();
+                ctx.recv().unwrap()
+            })
+        }).collect();
+
+        for _ in 0..4 {
+            let request = rep.recv().unwrap();
+            rep.send(request).unwrap();
+        }
+
+        for (i, t) in threads.into_iter().enumerate() {
+            assert_eq!(vec![i as u8], t.join().unwrap());
+        }
+    }
+
+    it "serve several requests and reply to them out of order" {
+        let mut other_req = session.create_socket::<Req>().expect("Failed to create socket !");
+        let mut ctx1 = rep.open_context().unwrap();
+        let mut ctx2 = rep.open_context().unwrap();
+
+        other_req.set_recv_timeout(timeout).unwrap();
+        other_req.connect(&url).unwrap();
+        sleep_some();
+
+        ctx1.set_recv_timeout(timeout).unwrap();
+        ctx2.set_recv_timeout(timeout).unwrap();
+        req.send(vec![65, 66, 67]).unwrap();
+        let received_request1 = ctx1.recv().unwrap();
+        other_req.send(vec![67, 66, 65]).unwrap();
+        let received_request2 = ctx2.recv().unwrap();
+
+        let mut replies = vec![(ctx1, received_request1), (ctx2, received_request2)];
+        while let Some((mut ctx, mut request)) = replies.pop() {
+            request.reverse();
+            ctx.send(request).unwrap();
+        }
+
+        let mut received_replies = vec![req.recv().unwrap(), other_req.recv().unwrap()];
+        received_replies.sort();
+        assert_eq!(vec![vec![65, 66, 67], vec![67, 66, 65]], received_replies);
+    }
+
+    it "serve requests from a pool of threads" {
+        let workers: Vec<_> = (0..2).map(|_| {
+            let mut ctx = rep.open_context().unwrap();
+
+            ctx.set_recv_timeout(timeout).unwrap();
+            thread::spawn(move || {
+                let request = ctx.recv().unwrap();
+                ctx.send(request).unwrap();
+            })
+        }).collect();
+        let mut ctx1 = req.open_context().unwrap();
+        let mut ctx2 = req.open_context().unwrap();
+
+        ctx1.set_recv_timeout(timeout).unwrap();
+        ctx2.set_recv_timeout(timeout).unwrap();
+        ctx1.send(vec![1]).unwrap();
+        ctx2.send(vec![2]).unwrap();
+        assert_eq!(vec![1], ctx1.recv().unwrap());
+        assert_eq!(vec![2], ctx2.recv().unwrap());
+
+        for worker in workers {
+            worker.join().unwrap();
+        }
+    }
+
+    it "refuse to receive a reply before sending a request" {
+        let mut ctx = req.open_context().unwrap();
+        let not_received = ctx.recv().unwrap_err();
+
+        assert_eq!(io::ErrorKind::Other, not_received.kind());
+    }
+
+    it "not be opened by a protocol without contexts" {
+        let mut push = session.create_socket::<Push>().expect("Failed to create socket !");
+        let not_opened = push.open_context().err().expect("Should have failed to open context !");
+
+        assert_eq!(io::ErrorKind::InvalidInput, not_opened.kind());
+    }
+}
+describe! exchange {
+
+    before_each {
+        let _ = ::env_logger::init();
+        let mut session = make_session();
+        let mut req = session.create_socket::<Req>().expect("Failed to create socket !");
+        let mut rep = session.create_socket::<Rep>().expect("Failed to create socket !");
+        let url = urls::tcp::get();
+        let timeout = make_timeout();
+
+        rep.set_send_timeout(timeout).expect("Failed to set send timeout !");
+        rep.set_recv_timeout(timeout).expect("Failed to set recv timeout !");
+        rep.bind(&url).unwrap();
+        req.connect(&url).unwrap();
+        sleep_some();
+    }
+
+    it "send a request and receive its reply in a single call" {
+        let server = thread::spawn(move || {
+            let mut request = rep.recv().unwrap();
+
+            request.reverse();
+            rep.send(request).unwrap();
+        });
+
+        let reply = req.request(vec![65, 66, 67], timeout).unwrap();
+
+        assert_eq!(vec![67, 66, 65], reply);
+        server.join().unwrap();
+    }
+
+    it "serve requests with a handler until no more request is received" {
+        let server = thread::spawn(move || {
+            rep.serve(|mut request| {
+                request.body.reverse();
+                Ok(request)
+            })
+        });
+
+        assert_eq!(vec![67, 66, 65], req.request(vec![65, 66, 67], timeout).unwrap());
+        assert_eq!(vec![2, 1], req.request(vec![1, 2], timeout).unwrap());
+
+        let stopped = server.join().unwrap().unwrap_err();
+        assert_eq!(io::ErrorKind::TimedOut, stopped.kind());
+    }
+
+    it "stop serving when the handler fails" {
+        let server = thread::spawn(move || {
+            rep.serve(|_| Err(io::Error::new(io::ErrorKind::Other, "handler failed")))
+        });
+
+        req.send(vec![1]).unwrap();
+
+        let stopped = server.join().unwrap().unwrap_err();
+        assert_eq!(io::ErrorKind::Other, stopped.kind());
+    }
+
+    it "time out when no reply is received" {
+        let not_replied = req.request(vec![65, 66, 67], timeout).unwrap_err();
+
+        assert_eq!(io::ErrorKind::TimedOut, not_replied.kind());
+        assert_eq!(vec![65, 66, 67], rep.recv().unwrap());
+    }
+
+    it "not be run by another protocol" {
+        let mut pair = session.create_socket::<Pair>().expect("Failed to create socket !");
+        let not_run = pair.request(vec![1], timeout).unwrap_err();
+
+        assert_eq!(io::ErrorKind::InvalidInput, not_run.kind());
+    }
+}
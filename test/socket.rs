@@ -172,3 +172,544 @@ describe! try_recv {
     }
 
 }
+
+describe! endpoint {
+
+    before_each {
+        let _ = ::env_logger::init();
+        let mut session = make_session();
+    }
+
+    it "can report the port chosen when binding to port zero" {
+        let mut push = session.create_socket::<Push>().expect("Failed to create socket !");
+        let mut pull = session.create_socket::<Pull>().expect("Failed to create socket !");
+        let ep = push.bind("tcp://127.0.0.1:0").unwrap();
+        let addr = ep.local_addr().expect("Bound endpoint should know its address").to_owned();
+
+        assert!(addr.starts_with("127.0.0.1:"));
+        assert!(!addr.ends_with(":0"));
+        assert_eq!(None, ep.peer_addr());
+
+        let url = format!("tcp://{}", addr);
+        let ep = pull.connect(&url).unwrap();
+
+        assert_eq!(Some(addr.as_str()), ep.peer_addr());
+        assert_eq!(None, ep.local_addr());
+
+        push.set_send_timeout(make_timeout()).unwrap();
+        pull.set_recv_timeout(make_timeout()).unwrap();
+        push.send(vec![65, 66, 67]).unwrap();
+        let received = pull.recv().unwrap();
+        assert_eq!(vec![65, 66, 67], received);
+    }
+
+    it "can report the address of an inproc endpoint" {
+        let mut push = session.create_socket::<Push>().expect("Failed to create socket !");
+        let mut pull = session.create_socket::<Pull>().expect("Failed to create socket !");
+        let url = urls::inproc::get();
+        let addr = url[url.find("://").unwrap() + 3..].to_owned();
+
+        assert_eq!(Some(addr.as_str()), push.bind(&url).unwrap().local_addr());
+        assert_eq!(Some(addr.as_str()), pull.connect(&url).unwrap().peer_addr());
+    }
+
+    it "can report whether it is waiting to reconnect" {
+        let mut push = session.create_socket::<Push>().expect("Failed to create socket !");
+        let mut pull = session.create_socket::<Pull>().expect("Failed to create socket !");
+        let url = urls::tcp::get();
+        let ep = push.connect(&url).unwrap();
+
+        sleep_some();
+        assert_eq!(EndpointState::WaitingToReconnect, push.get_endpoint_state(&ep).unwrap());
+
+        pull.bind(&url).unwrap();
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(EndpointState::Active, push.get_endpoint_state(&ep).unwrap());
+    }
+
+}
+
+describe! options {
+
+    before_each {
+        let _ = ::env_logger::init();
+        let mut session = make_session();
+    }
+
+    it "can be read back once set" {
+        let mut socket = session.create_socket::<Push>().expect("Failed to create socket !");
+
+        assert_eq!(ConfigOption::SendTimeout(None), socket.get_option(ConfigOptionName::SendTimeout).unwrap());
+
+        socket.set_send_timeout(make_timeout()).unwrap();
+        socket.set_option(ConfigOption::RecvMaxSize(512)).unwrap();
+
+        assert_eq!(ConfigOption::SendTimeout(make_timeout()), socket.get_option(ConfigOptionName::SendTimeout).unwrap());
+        assert_eq!(ConfigOption::RecvMaxSize(512), socket.get_option(ConfigOptionName::RecvMaxSize).unwrap());
+    }
+
+    it "include the protocol specific ones" {
+        let mut req = session.create_socket::<Req>().expect("Failed to create socket !");
+        let mut surv = session.create_socket::<Surveyor>().expect("Failed to create socket !");
+        let mut sub = session.create_socket::<Sub>().expect("Failed to create socket !");
+
+        req.set_option(ConfigOption::ReqResendIvl(Duration::from_secs(5))).unwrap();
+        surv.set_option(ConfigOption::SurveyDeadline(Duration::from_millis(150))).unwrap();
+        sub.set_option(ConfigOption::Subscribe(String::from("B"))).unwrap();
+        sub.set_option(ConfigOption::Subscribe(String::from("A"))).unwrap();
+
+        let resend_ivl = req.get_option(ConfigOptionName::ReqResendIvl).unwrap();
+        let deadline = surv.get_option(ConfigOptionName::SurveyDeadline).unwrap();
+        let subscriptions = sub.get_subscriptions().unwrap();
+
+        assert_eq!(ConfigOption::ReqResendIvl(Duration::from_secs(5)), resend_ivl);
+        assert_eq!(ConfigOption::SurveyDeadline(Duration::from_millis(150)), deadline);
+        assert_eq!(vec![String::from("A"), String::from("B")], subscriptions);
+    }
+
+    it "cannot be read on a socket whose protocol does not define them" {
+        let mut socket = session.create_socket::<Push>().expect("Failed to create socket !");
+        let err = socket.get_option(ConfigOptionName::TopicDelimiter).unwrap_err();
+
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+
+        let err = socket.get_subscriptions().unwrap_err();
+
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+    }
+
+}
+
+describe! stats {
+
+    before_each {
+        let _ = ::env_logger::init();
+        let mut session = make_session();
+    }
+
+    it "count the messages and connections" {
+        let mut push = session.create_socket::<Push>().expect("Failed to create socket !");
+        let mut pull = session.create_socket::<Pull>().expect("Failed to create socket !");
+        let url = urls::tcp::get();
+
+        pull.bind(&url).unwrap();
+        push.connect(&url).unwrap();
+        push.set_send_timeout(make_timeout()).unwrap();
+        pull.set_recv_timeout(make_timeout()).unwrap();
+
+        push.send(vec![65, 66, 67]).unwrap();
+        pull.recv().unwrap();
+
+        let push_stats = push.stats().unwrap();
+        assert_eq!(1, push_stats.messages_sent);
+        assert_eq!(3, push_stats.bytes_sent);
+        assert_eq!(1, push_stats.established_connections);
+        assert_eq!(1, push_stats.current_connections);
+
+        let pull_stats = pull.stats().unwrap();
+        assert_eq!(1, pull_stats.messages_received);
+        assert_eq!(3, pull_stats.bytes_received);
+        assert_eq!(1, pull_stats.accepted_connections);
+    }
+
+    it "count the bytes of each message sent concurrently by contexts" {
+        let mut req = session.create_socket::<Req>().expect("Failed to create socket !");
+        let mut rep = session.create_socket::<Rep>().expect("Failed to create socket !");
+        let url = urls::tcp::get();
+        let timeout = make_timeout();
+
+        rep.bind(&url).unwrap();
+        req.connect(&url).unwrap();
+        rep.set_recv_timeout(timeout).unwrap();
+        sleep_some();
+
+        let mut ctx1 = req.open_context().unwrap();
+        let mut ctx2 = req.open_context().unwrap();
+
+        ctx1.send(vec![65, 66, 67]).unwrap();
+        ctx2.send(vec![65, 66, 67, 68, 69]).unwrap();
+        rep.recv().unwrap();
+        rep.recv().unwrap();
+
+        let req_stats = req.stats().unwrap();
+        assert_eq!(2, req_stats.messages_sent);
+        assert_eq!(16, req_stats.bytes_sent);
+    }
+
+}
+
+describe! monitor {
+
+    before_each {
+        let _ = ::env_logger::init();
+        let mut session = make_session();
+    }
+
+    it "report the connections established and accepted" {
+        let mut server = session.create_socket::<Pair>().expect("Failed to create socket !");
+        let mut client = session.create_socket::<Pair>().expect("Failed to create socket !");
+        let server_events = server.monitor().unwrap();
+        let client_events = client.monitor().unwrap();
+        let url = urls::tcp::get();
+        let server_ep = server.bind(&url).unwrap();
+        let client_ep = client.connect(&url).unwrap();
+        let timeout = Duration::from_secs(1);
+
+        match client_events.recv_timeout(timeout).unwrap() {
+            EndpointEvent::Connected(eid, _) => assert_eq!(client_ep.id(), eid),
+            other => panic!("unexpected event {:?}", other)
+        }
+        match server_events.recv_timeout(timeout).unwrap() {
+            EndpointEvent::Accepted(eid, _, addr) => {
+                assert_eq!(server_ep.id(), eid);
+                assert!(addr.is_some());
+            },
+            other => panic!("unexpected event {:?}", other)
+        }
+    }
+
+    it "report the handshake failures" {
+        let mut server = session.create_socket::<Pull>().expect("Failed to create socket !");
+        let mut client = session.create_socket::<Pub>().expect("Failed to create socket !");
+        let server_events = server.monitor().unwrap();
+        let url = urls::tcp::get();
+
+        server.bind(&url).unwrap();
+        client.connect(&url).unwrap();
+
+        let timeout = Duration::from_secs(1);
+        let _ = server_events.recv_timeout(timeout).unwrap();
+
+        match server_events.recv_timeout(timeout).unwrap() {
+            EndpointEvent::HandshakeFailed(_, peer_id) => assert_eq!(Some(32), peer_id),
+            other => panic!("unexpected event {:?}", other)
+        }
+    }
+
+    it "report the tcp connections closed by the peer" {
+        let mut server = session.create_socket::<Pair>().expect("Failed to create socket !");
+        let mut client = session.create_socket::<Pair>().expect("Failed to create socket !");
+        let server_events = server.monitor().unwrap();
+        let url = urls::tcp::get();
+        let timeout = Duration::from_secs(1);
+
+        server.bind(&url).unwrap();
+        client.connect(&url).unwrap();
+
+        let conn_id = match server_events.recv_timeout(timeout).unwrap() {
+            EndpointEvent::Accepted(_, conn_id, _) => conn_id,
+            other => panic!("unexpected event {:?}", other)
+        };
+
+        drop(client);
+        server.set_recv_timeout(Some(Duration::from_millis(250))).unwrap();
+        server.recv().unwrap_err();
+
+        match server_events.recv_timeout(timeout).unwrap() {
+            EndpointEvent::Disconnected(eid) => assert_eq!(conn_id, eid),
+            other => panic!("unexpected event {:?}", other)
+        }
+    }
+
+    it "report the ipc connections closed by the peer" {
+        let mut server = session.create_socket::<Pair>().expect("Failed to create socket !");
+        let mut client = session.create_socket::<Pair>().expect("Failed to create socket !");
+        let server_events = server.monitor().unwrap();
+        let url = urls::ipc::get();
+        let timeout = Duration::from_secs(1);
+
+        server.bind(&url).unwrap();
+        client.connect(&url).unwrap();
+
+        let conn_id = match server_events.recv_timeout(timeout).unwrap() {
+            EndpointEvent::Accepted(_, conn_id, _) => conn_id,
+            other => panic!("unexpected event {:?}", other)
+        };
+
+        drop(client);
+        server.set_recv_timeout(Some(Duration::from_millis(250))).unwrap();
+        server.recv().unwrap_err();
+
+        match server_events.recv_timeout(timeout).unwrap() {
+            EndpointEvent::Disconnected(eid) => assert_eq!(conn_id, eid),
+            other => panic!("unexpected event {:?}", other)
+        }
+    }
+
+}
+
+describe! peer_info {
+
+    before_each {
+        let _ = ::env_logger::init();
+        let mut session = make_session();
+    }
+
+    it "is not attached to the received messages by default" {
+        let mut push = session.create_socket::<Push>().expect("Failed to create socket !");
+        let mut pull = session.create_socket::<Pull>().expect("Failed to create socket !");
+        let url = urls::tcp::get();
+
+        pull.bind(&url).unwrap();
+        push.connect(&url).unwrap();
+        push.set_send_timeout(make_timeout()).unwrap();
+        pull.set_recv_timeout(make_timeout()).unwrap();
+
+        push.send(vec![65, 66, 67]).unwrap();
+
+        let msg = pull.recv_msg().unwrap();
+
+        assert!(msg.get_peer().is_none());
+    }
+
+    it "describe the tcp peer of the received messages" {
+        let mut push = session.create_socket::<Push>().expect("Failed to create socket !");
+        let mut pull = session.create_socket::<Pull>().expect("Failed to create socket !");
+        let pull_events = pull.monitor().unwrap();
+        let url = urls::tcp::get();
+
+        pull.set_recv_peer_info(true).unwrap();
+        pull.bind(&url).unwrap();
+        push.connect(&url).unwrap();
+        push.set_send_timeout(make_timeout()).unwrap();
+        pull.set_recv_timeout(make_timeout()).unwrap();
+
+        push.send(vec![65, 66, 67]).unwrap();
+
+        let msg = pull.recv_msg().unwrap();
+        let peer = msg.get_peer().unwrap();
+
+        match pull_events.recv_timeout(Duration::from_secs(1)).unwrap() {
+            EndpointEvent::Accepted(_, eid, _) => assert_eq!(eid, peer.endpoint_id),
+            other => panic!("unexpected event {:?}", other)
+        }
+        assert_eq!("tcp", peer.scheme);
+        assert!(peer.addr.as_ref().unwrap().starts_with("127.0.0.1:"));
+        assert!(peer.credentials.is_none());
+    }
+
+    it "describe the ipc peer of the received requests" {
+        let mut server = session.create_socket::<Rep>().expect("Failed to create socket !");
+        let mut client = session.create_socket::<Req>().expect("Failed to create socket !");
+        let url = urls::ipc::get();
+
+        server.set_recv_peer_info(true).unwrap();
+        server.bind(&url).unwrap();
+        client.connect(&url).unwrap();
+        client.set_send_timeout(make_timeout()).unwrap();
+        server.set_recv_timeout(make_timeout()).unwrap();
+
+        client.send(vec![65, 66, 67]).unwrap();
+
+        let msg = server.recv_msg().unwrap();
+        let peer = msg.get_peer().unwrap();
+
+        assert_eq!("ipc", peer.scheme);
+        if cfg!(target_os = "linux") {
+            assert!(peer.credentials.is_some());
+        }
+    }
+
+}
+
+describe! linger {
+
+    before_each {
+        let _ = ::env_logger::init();
+        let mut session = make_session();
+    }
+
+    it "lets a dropped push socket deliver the messages it was still sending" {
+        let mut push = session.create_socket::<Push>().expect("Failed to create socket !");
+        let mut pulls: Vec<Socket> = (0..3).map(|_| session.create_socket::<Pull>().expect("Failed to create socket !")).collect();
+        let url = urls::ipc::get();
+
+        push.set_send_timeout(Some(Duration::from_millis(50))).unwrap();
+        push.bind(&url).unwrap();
+        for pull in pulls.iter_mut() {
+            pull.set_recv_timeout(make_timeout()).unwrap();
+            pull.connect(&url).unwrap();
+        }
+        sleep_some();
+
+        // bigger than the socket buffers, so each send times out with its pipe still writing
+        for i in 0..3 {
+            let err = push.send(vec![65 + i; 1000 * 1000]).unwrap_err();
+            assert_eq!(io::ErrorKind::TimedOut, err.kind());
+        }
+
+        let pull_threads: Vec<_> = pulls.into_iter().map(|mut pull| thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            pull.recv().unwrap()
+        })).collect();
+
+        drop(push);
+
+        let mut received: Vec<u8> = pull_threads.into_iter().map(|pull_thread| {
+            let msg = pull_thread.join().unwrap();
+            assert_eq!(1000 * 1000, msg.len());
+            msg[0]
+        }).collect();
+
+        received.sort();
+        assert_eq!(vec![65, 66, 67], received);
+    }
+
+    it "discards the messages a dropped push socket was still sending when zero" {
+        let mut push = session.create_socket::<Push>().expect("Failed to create socket !");
+        let mut pulls: Vec<Socket> = (0..3).map(|_| session.create_socket::<Pull>().expect("Failed to create socket !")).collect();
+        let url = urls::ipc::get();
+
+        push.set_linger(Duration::from_millis(0)).unwrap();
+        push.set_send_timeout(Some(Duration::from_millis(50))).unwrap();
+        push.bind(&url).unwrap();
+        for pull in pulls.iter_mut() {
+            pull.set_recv_timeout(make_timeout()).unwrap();
+            pull.connect(&url).unwrap();
+        }
+        sleep_some();
+
+        for i in 0..3 {
+            let err = push.send(vec![65 + i; 1000 * 1000]).unwrap_err();
+            assert_eq!(io::ErrorKind::TimedOut, err.kind());
+        }
+
+        let pull_threads: Vec<_> = pulls.into_iter().map(|mut pull| thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            pull.recv()
+        })).collect();
+
+        drop(push);
+
+        for pull_thread in pull_threads {
+            let err = pull_thread.join().unwrap().unwrap_err();
+            assert_eq!(io::ErrorKind::TimedOut, err.kind());
+        }
+    }
+
+    it "lets a dropped pub socket finish sending its last message" {
+        let mut publ = session.create_socket::<Pub>().expect("Failed to create socket !");
+        let mut sub = session.create_socket::<Sub>().expect("Failed to create socket !");
+        let url = urls::ipc::get();
+
+        sub.set_option(ConfigOption::Subscribe(String::new())).unwrap();
+        sub.set_recv_timeout(make_timeout()).unwrap();
+        publ.bind(&url).unwrap();
+        sub.connect(&url).unwrap();
+        sleep_some();
+
+        // bigger than the socket buffers, so the send is still in progress when dropping
+        publ.send(vec![65; 1000 * 1000]).unwrap();
+
+        let sub_thread = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            sub.recv().unwrap()
+        });
+
+        drop(publ);
+
+        let received = sub_thread.join().unwrap();
+        assert_eq!(1000 * 1000, received.len());
+    }
+
+    it "should discard the pending messages when zero" {
+        let mut publ = session.create_socket::<Pub>().expect("Failed to create socket !");
+        let mut sub = session.create_socket::<Sub>().expect("Failed to create socket !");
+        let url = urls::ipc::get();
+
+        sub.set_option(ConfigOption::Subscribe(String::new())).unwrap();
+        sub.set_recv_timeout(make_timeout()).unwrap();
+        publ.set_linger(Duration::from_millis(0)).unwrap();
+        publ.bind(&url).unwrap();
+        sub.connect(&url).unwrap();
+        sleep_some();
+
+        publ.send(vec![65; 1000 * 1000]).unwrap();
+
+        let sub_thread = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            sub.recv()
+        });
+
+        drop(publ);
+
+        let err = sub_thread.join().unwrap().unwrap_err();
+        assert_eq!(io::ErrorKind::TimedOut, err.kind());
+    }
+
+}
+
+describe! timer {
+
+    before_each {
+        let _ = ::env_logger::init();
+    }
+
+    it "can honour sub tick timeouts with the high resolution timer" {
+        let mut session = SessionBuilder::new().
+            with("tcp", Tcp).
+            with_high_resolution_timer().
+            build().
+            expect("Failed to create session !");
+        let mut pull = session.create_socket::<Pull>().expect("Failed to create socket !");
+        let timeout = Duration::new(0, 2_500_000);
+
+        pull.set_recv_timeout(Some(timeout)).unwrap();
+
+        let start = ::std::time::Instant::now();
+        let err = pull.recv().unwrap_err();
+        let elapsed = start.elapsed();
+
+        assert_eq!(io::ErrorKind::TimedOut, err.kind());
+        assert!(elapsed >= timeout);
+        assert!(elapsed < Duration::from_millis(20));
+    }
+
+    it "can use a finer timer wheel" {
+        let mut session = SessionBuilder::new().
+            with("tcp", Tcp).
+            with_timer_tick(Duration::from_millis(1)).
+            with_timer_capacity(65_536).
+            build().
+            expect("Failed to create session !");
+        let mut pull = session.create_socket::<Pull>().expect("Failed to create socket !");
+
+        pull.set_recv_timeout(Some(Duration::from_millis(5))).unwrap();
+
+        let start = ::std::time::Instant::now();
+        let err = pull.recv().unwrap_err();
+
+        assert_eq!(io::ErrorKind::TimedOut, err.kind());
+        assert!(start.elapsed() < Duration::from_millis(20));
+    }
+
+    it "does not fire the wheel timeouts early when the session is busy" {
+        let mut session = SessionBuilder::new().
+            with("tcp", Tcp).
+            build().
+            expect("Failed to create session !");
+        let mut pull = session.create_socket::<Pull>().expect("Failed to create socket !");
+        let timeout = Duration::from_millis(100);
+
+        for _ in 0..100 {
+            pull.set_recv_timeout(Some(timeout)).unwrap();
+        }
+
+        let start = ::std::time::Instant::now();
+        let err = pull.recv().unwrap_err();
+
+        assert_eq!(io::ErrorKind::TimedOut, err.kind());
+        assert!(start.elapsed() >= Duration::from_millis(75));
+    }
+
+    it "rejects a tick below one millisecond" {
+        let err = SessionBuilder::new().
+            with_timer_tick(Duration::new(0, 100_000)).
+            build().
+            err().
+            expect("Should have failed to create session !");
+
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+    }
+
+}
@@ -61,7 +61,7 @@ describe! can {
         resp2.connect(&url).unwrap();
 
         let not_received = surv.recv().unwrap_err();
-        assert_eq!(io::ErrorKind::Other, not_received.kind());
+        assert_eq!(io::ErrorKind::NotConnected, not_received.kind());
     }
 
     it "refuse to send a response before receiving a survey" {
@@ -72,4 +72,183 @@ describe! can {
         let not_sent = resp1.send(vec![66, 65, 67]).unwrap_err();
         assert_eq!(io::ErrorKind::InvalidData, not_sent.kind());
     }
-}
\ No newline at end of file
+
+    it "forward surveys and responses through a raw broker" {
+        let mut front = session.create_raw_socket::<Respondent>().expect("Failed to create socket !");
+        let mut back = session.create_raw_socket::<Surveyor>().expect("Failed to create socket !");
+        let back_url = urls::tcp::get();
+
+        front.set_recv_timeout(timeout).unwrap();
+        back.set_recv_timeout(timeout).unwrap();
+
+        front.bind(&url).unwrap();
+        back.bind(&back_url).unwrap();
+        surv.connect(&url).unwrap();
+        resp1.connect(&back_url).unwrap();
+
+        sleep_some();
+
+        surv.send(vec![65, 66, 67]).unwrap();
+        let raw_survey = front.recv_msg().unwrap();
+        assert_eq!(8, raw_survey.get_header().len());
+        assert_eq!(&[65, 66, 67], raw_survey.get_body());
+
+        back.send_msg(raw_survey).unwrap();
+        let received_survey = resp1.recv().unwrap();
+        assert_eq!(vec![65, 66, 67], received_survey);
+
+        resp1.send(vec![66, 65, 67]).unwrap();
+        let raw_response = back.recv_msg().unwrap();
+        assert_eq!(8, raw_response.get_header().len());
+        assert_eq!(&[66, 65, 67], raw_response.get_body());
+
+        front.send_msg(raw_response).unwrap();
+        let received_response = surv.recv().unwrap();
+        assert_eq!(vec![66, 65, 67], received_response);
+    }
+}
+
+describe! context {
+
+    before_each {
+        let _ = ::env_logger::init();
+        let mut session = make_session();
+        let mut surv = session.create_socket::<Surveyor>().expect("Failed to create socket !");
+        let mut resp1 = session.create_socket::<Respondent>().expect("Failed to create socket !");
+        let mut resp2 = session.create_socket::<Respondent>().expect("Failed to create socket !");
+        let url = urls::tcp::get();
+        let timeout = make_timeout();
+
+        resp1.set_send_timeout(timeout).expect("Failed to set send timeout !");
+        resp1.set_recv_timeout(timeout).expect("Failed to set recv timeout !");
+        resp2.set_send_timeout(timeout).expect("Failed to set send timeout !");
+        resp2.set_recv_timeout(timeout).expect("Failed to set recv timeout !");
+        surv.bind(&url).unwrap();
+        resp1.connect(&url).unwrap();
+        resp2.connect(&url).unwrap();
+        sleep_some();
+    }
+
+    it "run overlapping surveys and receive the responses to each of them" {
+        let mut ctx1 = surv.open_context().unwrap();
+        let mut ctx2 = surv.open_context().unwrap();
+
+        ctx1.set_recv_timeout(timeout).unwrap();
+        ctx2.set_recv_timeout(timeout).unwrap();
+
+        ctx1.send(vec![1]).unwrap();
+        for resp in [&mut resp1, &mut resp2].iter_mut() {
+            let survey = resp.recv().unwrap();
+            resp.send(vec![survey[0], 10]).unwrap();
+        }
+
+        ctx2.send(vec![2]).unwrap();
+        for resp in [&mut resp1, &mut resp2].iter_mut() {
+            let survey = resp.recv().unwrap();
+            resp.send(vec![survey[0], 20]).unwrap();
+        }
+
+        assert_eq!(vec![2, 20], ctx2.recv().unwrap());
+        assert_eq!(vec![2, 20], ctx2.recv().unwrap());
+        assert_eq!(vec![1, 10], ctx1.recv().unwrap());
+        assert_eq!(vec![1, 10], ctx1.recv().unwrap());
+    }
+
+    it "tell a context waiting for responses that its survey deadline is reached" {
+        surv.set_option(ConfigOption::SurveyDeadline(Duration::from_millis(50))).unwrap();
+
+        let mut ctx = surv.open_context().unwrap();
+
+        ctx.set_recv_timeout(timeout).unwrap();
+        ctx.send(vec![1]).unwrap();
+
+        let not_received = ctx.recv().unwrap_err();
+        assert_eq!(io::ErrorKind::TimedOut, not_received.kind());
+
+        let not_received = ctx.recv().unwrap_err();
+        assert_eq!(io::ErrorKind::NotConnected, not_received.kind());
+    }
+}
+
+describe! collect {
+
+    before_each {
+        let _ = ::env_logger::init();
+        let mut session = make_session();
+        let mut surv = session.create_socket::<Surveyor>().expect("Failed to create socket !");
+        let mut resp1 = session.create_socket::<Respondent>().expect("Failed to create socket !");
+        let mut resp2 = session.create_socket::<Respondent>().expect("Failed to create socket !");
+        let url = urls::tcp::get();
+        let timeout = make_timeout();
+
+        resp1.set_send_timeout(timeout).expect("Failed to set send timeout !");
+        resp1.set_recv_timeout(timeout).expect("Failed to set recv timeout !");
+        resp2.set_send_timeout(timeout).expect("Failed to set send timeout !");
+        resp2.set_recv_timeout(timeout).expect("Failed to set recv timeout !");
+        surv.bind(&url).unwrap();
+        resp1.connect(&url).unwrap();
+        resp2.connect(&url).unwrap();
+        sleep_some();
+    }
+
+    it "receive the responses of every respondent" {
+        let respondents: Vec<_> = vec![resp1, resp2].into_iter().enumerate().map(|(i, mut resp)| {
+            thread::spawn(move || {
+                let survey = resp.recv().unwrap();
+                resp.send(vec![survey[0], i as u8]).unwrap();
+            })
+        }).collect();
+
+        let result = surv.survey(vec![1], None).unwrap();
+        let mut responses: Vec<Vec<u8>> = result.responses.into_iter().map(|msg| msg.into()).collect();
+
+        responses.sort();
+        assert_eq!(2, result.respondents);
+        assert_eq!(vec![vec![1, 0], vec![1, 1]], responses);
+
+        for respondent in respondents {
+            respondent.join().unwrap();
+        }
+    }
+
+    it "return the responses received before the deadline" {
+        surv.set_option(ConfigOption::SurveyDeadline(Duration::from_millis(200))).unwrap();
+
+        let respondent = thread::spawn(move || {
+            let survey = resp1.recv().unwrap();
+            resp1.send(survey).unwrap();
+        });
+
+        let result = surv.survey(vec![1], None).unwrap();
+
+        assert_eq!(2, result.respondents);
+        assert_eq!(1, result.responses.len());
+
+        respondent.join().unwrap();
+        drop(resp2);
+    }
+
+    it "stop once the expected number of responses is received" {
+        surv.set_option(ConfigOption::SurveyDeadline(Duration::from_secs(10))).unwrap();
+
+        let respondent = thread::spawn(move || {
+            let survey = resp2.recv().unwrap();
+            resp2.send(survey).unwrap();
+        });
+
+        let result = surv.survey(vec![1], Some(1)).unwrap();
+
+        assert_eq!(2, result.respondents);
+        assert_eq!(vec![1], result.responses[0].get_body());
+
+        respondent.join().unwrap();
+        drop(resp1);
+    }
+
+    it "not be run by another protocol" {
+        let mut pair = session.create_socket::<Pair>().expect("Failed to create socket !");
+        let not_run = pair.survey(vec![1], None).unwrap_err();
+
+        assert_eq!(io::ErrorKind::InvalidInput, not_run.kind());
+    }
+}
@@ -11,6 +11,12 @@
 extern crate log;
 extern crate env_logger;
 extern crate scaproust;
+#[cfg(unix)]
+extern crate mio;
+#[cfg(feature = "tls")]
+extern crate openssl;
+#[cfg(feature = "async")]
+extern crate futures;
 
 mod socket;
 mod fair_queue;
@@ -23,6 +29,14 @@ mod bus;
 mod device;
 mod reqrep_device;
 mod probe;
+mod inproc;
+mod ws;
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(feature = "async")]
+mod async;
+#[cfg(unix)]
+mod fd;
 
 pub use std::time::Duration;
 pub use std::thread;
@@ -53,6 +67,9 @@ pub fn make_timeout() -> Option<Duration> {
 pub fn make_session() -> Session {
     SessionBuilder::new().
         with("tcp", Tcp).
+        with("ipc", Ipc).
+        with("inproc", Inproc).
+        with("ws", Ws).
         build().
         expect("Failed to create session !")
 }
@@ -68,7 +85,7 @@ pub mod urls {
         static mut NEXT_PORT: AtomicUsize = ATOMIC_USIZE_INIT;
         const FIRST_PORT: usize = 18080;
 
-        fn next_port() -> usize {
+        pub fn next_port() -> usize {
             unsafe {
                 // If the atomic was never used, set it to the initial port
                 NEXT_PORT.compare_and_swap(0, FIRST_PORT, SeqCst);
@@ -83,8 +100,48 @@ pub mod urls {
         }
     }
 
-    mod ipc {
+    pub mod ipc {
+        use std::fs;
+        use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT};
+        use std::sync::atomic::Ordering::SeqCst;
+
+        static NEXT_ID: AtomicUsize = ATOMIC_USIZE_INIT;
+
+        pub fn get() -> String {
+            let path = format!("/tmp/scaproust_test_{}.ipc", NEXT_ID.fetch_add(1, SeqCst));
+
+            // left over by a previous run
+            let _ = fs::remove_file(&path);
+
+            format!("ipc://{}", path)
+        }
+    }
+
+    pub mod inproc {
+        use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT};
+        use std::sync::atomic::Ordering::SeqCst;
+
+        static NEXT_ID: AtomicUsize = ATOMIC_USIZE_INIT;
 
+        pub fn get() -> String {
+            format!("inproc://test{}", NEXT_ID.fetch_add(1, SeqCst))
+        }
+    }
+
+    pub mod ws {
+        use super::tcp::next_port;
+
+        pub fn get() -> String {
+            format!("ws://127.0.0.1:{}", next_port())
+        }
+    }
+
+    pub mod tls {
+        use super::tcp::next_port;
+
+        pub fn get() -> String {
+            format!("tls+tcp://127.0.0.1:{}", next_port())
+        }
     }
 }
 
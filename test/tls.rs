@@ -0,0 +1,173 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+pub use std::time::Duration;
+pub use std::thread;
+pub use std::io;
+
+pub use openssl::asn1::Asn1Time;
+pub use openssl::bn::BigNum;
+pub use openssl::ec::{EcGroup, EcKey};
+pub use openssl::hash::MessageDigest;
+pub use openssl::nid::Nid;
+pub use openssl::pkey::PKey;
+pub use openssl::x509::{X509, X509NameBuilder};
+pub use openssl::x509::extension::SubjectAlternativeName;
+
+pub use scaproust::*;
+
+pub use super::{urls, make_timeout, sleep_some};
+
+/// Generates a certificate valid for the loopback address, and its private key, both in PEM format.
+pub fn make_self_signed_certificate() -> (Vec<u8>, Vec<u8>) {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "localhost").unwrap();
+    let name = name.build();
+    let mut builder = X509::builder().unwrap();
+
+    builder.set_version(2).unwrap();
+    builder.set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap()).unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+    builder.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+
+    let alt_name = SubjectAlternativeName::new().ip("127.0.0.1").build(&builder.x509v3_context(None, None)).unwrap();
+    builder.append_extension(alt_name).unwrap();
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+
+    (builder.build().to_pem().unwrap(), key.private_key_to_pem_pkcs8().unwrap())
+}
+
+pub fn make_tls_session(config: TlsConfig) -> Session {
+    SessionBuilder::new().
+        with("tls+tcp", Tls::new(config).expect("Failed to create transport !")).
+        build().
+        expect("Failed to create session !")
+}
+
+describe! can {
+
+    before_each {
+        let _ = ::env_logger::init();
+        let (cert, key) = make_self_signed_certificate();
+        let server_config = TlsConfig::new().certificate(&cert).private_key(&key);
+        let client_config = TlsConfig::new().ca_root(&cert);
+        let url = urls::tls::get();
+        let timeout = make_timeout();
+    }
+
+    it "send a message through an encrypted connection" {
+        let mut server_session = make_tls_session(server_config);
+        let mut client_session = make_tls_session(client_config);
+        let mut pull = server_session.create_socket::<Pull>().expect("Failed to create socket !");
+        let mut push = client_session.create_socket::<Push>().expect("Failed to create socket !");
+
+        push.set_send_timeout(timeout).unwrap();
+        pull.set_recv_timeout(timeout).unwrap();
+        pull.bind(&url).unwrap();
+        push.connect(&url).unwrap();
+
+        push.send(vec![65, 66, 67]).unwrap();
+        let received = pull.recv().unwrap();
+
+        assert_eq!(vec![65, 66, 67], received)
+    }
+
+    it "exchange requests and replies" {
+        let mut server_session = make_tls_session(server_config);
+        let mut client_session = make_tls_session(client_config);
+        let mut rep = server_session.create_socket::<Rep>().expect("Failed to create socket !");
+        let mut req = client_session.create_socket::<Req>().expect("Failed to create socket !");
+
+        req.set_recv_timeout(timeout).unwrap();
+        rep.set_recv_timeout(timeout).unwrap();
+        rep.bind(&url).unwrap();
+        req.connect(&url).unwrap();
+
+        req.send(vec![65, 66, 67]).unwrap();
+        let request = rep.recv().unwrap();
+        rep.send(request).unwrap();
+        let reply = req.recv().unwrap();
+
+        assert_eq!(vec![65, 66, 67], reply)
+    }
+
+    it "authenticate the client" {
+        let (client_cert, client_key) = make_self_signed_certificate();
+        let server_config = server_config.ca_root(&client_cert).require_client_auth(true);
+        let client_config = client_config.certificate(&client_cert).private_key(&client_key);
+        let mut server_session = make_tls_session(server_config);
+        let mut client_session = make_tls_session(client_config);
+        let mut pull = server_session.create_socket::<Pull>().expect("Failed to create socket !");
+        let mut push = client_session.create_socket::<Push>().expect("Failed to create socket !");
+
+        push.set_send_timeout(timeout).unwrap();
+        pull.set_recv_timeout(timeout).unwrap();
+        pull.bind(&url).unwrap();
+        push.connect(&url).unwrap();
+
+        push.send(vec![65, 66, 67]).unwrap();
+        let received = pull.recv().unwrap();
+
+        assert_eq!(vec![65, 66, 67], received)
+    }
+}
+
+describe! cannot {
+
+    before_each {
+        let _ = ::env_logger::init();
+        let (cert, key) = make_self_signed_certificate();
+        let server_config = TlsConfig::new().certificate(&cert).private_key(&key);
+        let url = urls::tls::get();
+    }
+
+    it "bind without a certificate" {
+        let mut session = make_tls_session(TlsConfig::new());
+        let mut pull = session.create_socket::<Pull>().expect("Failed to create socket !");
+        let err = pull.bind(&url).unwrap_err();
+
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+    }
+
+    it "connect to an untrusted server" {
+        let mut server_session = make_tls_session(server_config);
+        let mut client_session = make_tls_session(TlsConfig::new());
+        let mut pull = server_session.create_socket::<Pull>().expect("Failed to create socket !");
+        let mut push = client_session.create_socket::<Push>().expect("Failed to create socket !");
+
+        pull.bind(&url).unwrap();
+        push.connect(&url).unwrap();
+        sleep_some();
+
+        let err = push.try_send(vec![65, 66, 67]).unwrap_err();
+
+        assert_eq!(io::ErrorKind::WouldBlock, err.kind());
+    }
+
+    it "connect anonymously when client authentication is required" {
+        let server_config = server_config.require_client_auth(true);
+        let client_config = TlsConfig::new().ca_root(&cert);
+        let mut server_session = make_tls_session(server_config);
+        let mut client_session = make_tls_session(client_config);
+        let mut pull = server_session.create_socket::<Pull>().expect("Failed to create socket !");
+        let mut push = client_session.create_socket::<Push>().expect("Failed to create socket !");
+
+        pull.bind(&url).unwrap();
+        push.connect(&url).unwrap();
+        sleep_some();
+
+        let _ = push.try_send(vec![65, 66, 67]);
+        sleep_some();
+        let err = pull.try_recv().unwrap_err();
+
+        assert_eq!(io::ErrorKind::WouldBlock, err.kind());
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright 2016 Benoît Labaere (benoit.labaere@gmail.com)
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0>
+// or the MIT license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your option.
+// This file may not be copied, modified, or distributed except according to those terms.
+
+pub use std::time::Duration;
+pub use std::thread;
+pub use std::io;
+
+pub use scaproust::*;
+
+pub use super::{urls, make_session, make_timeout, sleep_some};
+
+describe! can {
+
+    before_each {
+        let _ = ::env_logger::init();
+        let mut session = make_session();
+        let mut push = session.create_socket::<Push>().expect("Failed to create socket !");
+        let mut pull = session.create_socket::<Pull>().expect("Failed to create socket !");
+        let url = urls::ws::get();
+        let timeout = make_timeout();
+
+        push.set_send_timeout(timeout).expect("Failed to set send timeout !");
+        pull.set_recv_timeout(timeout).expect("Failed to set recv timeout !");
+    }
+
+    it "send a message through local endpoint" {
+        push.bind(&url).unwrap();
+        pull.connect(&url).unwrap();
+
+        push.send(vec![65, 66, 67]).unwrap();
+        let received = pull.recv().unwrap();
+
+        assert_eq!(vec![65, 66, 67], received)
+    }
+
+    it "send a message through a path" {
+        let url = format!("{}/some/path", url);
+
+        pull.bind(&url).unwrap();
+        push.connect(&url).unwrap();
+
+        push.send(vec![65, 66, 67]).unwrap();
+        let received = pull.recv().unwrap();
+
+        assert_eq!(vec![65, 66, 67], received)
+    }
+
+    it "send a large message" {
+        let msg = vec![42u8; 200 * 1024];
+
+        pull.bind(&url).unwrap();
+        push.connect(&url).unwrap();
+
+        push.send(msg.clone()).unwrap();
+        let received = pull.recv().unwrap();
+
+        assert_eq!(msg, received)
+    }
+
+    it "exchange requests and replies" {
+        let mut req = session.create_socket::<Req>().expect("Failed to create socket !");
+        let mut rep = session.create_socket::<Rep>().expect("Failed to create socket !");
+
+        req.set_recv_timeout(timeout).unwrap();
+        rep.set_recv_timeout(timeout).unwrap();
+        rep.bind(&url).unwrap();
+        req.connect(&url).unwrap();
+
+        req.send(vec![65, 66, 67]).unwrap();
+        let request = rep.recv().unwrap();
+        rep.send(request).unwrap();
+        let reply = req.recv().unwrap();
+
+        assert_eq!(vec![65, 66, 67], reply)
+    }
+}
+
+describe! cannot {
+
+    before_each {
+        let _ = ::env_logger::init();
+        let mut session = make_session();
+        let mut push = session.create_socket::<Push>().expect("Failed to create socket !");
+        let url = urls::ws::get();
+        let timeout = make_timeout();
+
+        push.set_send_timeout(timeout).expect("Failed to set send timeout !");
+    }
+
+    it "talk to a peer bound on another path" {
+        let mut pull = session.create_socket::<Pull>().expect("Failed to create socket !");
+
+        pull.bind(&format!("{}/here", url)).unwrap();
+        push.connect(&format!("{}/there", url)).unwrap();
+        sleep_some();
+
+        let err = push.try_send(vec![65, 66, 67]).unwrap_err();
+
+        assert_eq!(io::ErrorKind::WouldBlock, err.kind());
+    }
+
+    it "talk to a peer of the wrong protocol" {
+        let mut sub = session.create_socket::<Sub>().expect("Failed to create socket !");
+
+        push.bind(&url).unwrap();
+        sub.connect(&url).unwrap();
+        sleep_some();
+
+        let err = push.try_send(vec![65, 66, 67]).unwrap_err();
+
+        assert_eq!(io::ErrorKind::WouldBlock, err.kind());
+    }
+}